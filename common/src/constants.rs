@@ -32,6 +32,7 @@ pub const OP_MOVE2  : u32 = 0b0100_0000_0000_0000;
 pub const OP_MOVEM  : u32 = 0b0100_1000_1000_0000;
 pub const OP_MOVEP  : u32 = 0b0000_0000_0000_1000;
 pub const OP_MOVEQ  : u32 = 0b0111_0000_0000_0000;
+pub const OP_MOVES  : u32 = 0b0000_1110_0000_0000; // Only 010+
 pub const OP_MULS   : u32 = 0b1100_0001_1100_0000;
 pub const OP_MULU   : u32 = 0b1100_0000_1100_0000;
 pub const OP_NBCD   : u32 = 0b0100_1000_0000_0000;
@@ -56,6 +57,27 @@ pub const OP_TAS    : u32 = 0b0100_1010_1100_0000;
 pub const OP_TST    : u32 = 0b0100_1010_0000_0000;
 pub const OP_UNLK   : u32 = 0b0100_1110_0101_1000;
 
+// 68020 and later
+pub const OP_BITFIELD: u32 = 0b1110_1000_1100_0000;
+pub const OP_CALLM  : u32 = 0b0000_0110_1100_0000; // Only 020
+pub const OP_CAS    : u32 = 0b0000_1000_1100_0000;
+pub const OP_CHK2   : u32 = 0b0000_0000_1100_0000; // also CMP2
+pub const OP_DIVL   : u32 = 0b0100_1100_0100_0000;
+pub const OP_LINK32 : u32 = 0b0100_1000_0000_1000;
+pub const OP_MULL   : u32 = 0b0100_1100_0000_0000;
+pub const OP_PACK   : u32 = 0b1000_0001_0100_0000;
+pub const OP_CONDTRAP: u32 = 0b0101_0000_1111_1000;
+pub const OP_UNPK   : u32 = 0b1000_0001_1000_0000;
+
+// 68881/68882 floating-point coprocessor (coprocessor id 1)
+pub const OP_FPU    : u32 = 0b1111_0010_0000_0000;
+pub const FPU_GENERAL : u32 = 0x000;
+pub const FPU_SCC     : u32 = 0x040; // also FDBcc and FTRAPcc
+pub const FPU_BCC_16  : u32 = 0x080;
+pub const FPU_BCC_32  : u32 = 0x0c0;
+pub const FPU_SAVE    : u32 = 0x100;
+pub const FPU_RESTORE : u32 = 0x140;
+
 pub const BYTE_SIZED: u32 = 0x00;
 #[allow(dead_code)]
 pub const WORD_SIZED: u32 = 0x40;
@@ -70,6 +92,7 @@ pub const MASK_LOBYTE : u32 = 0b1111111100000000; // masks out low byte
 pub const MASK_LOBYTX : u32 = 0b1111000100000000; // masks out low byte and X register bits
 pub const MASK_LO3NIB : u32 = 0b1111000000000000; // masks out lower three nibbles
 pub const MASK_LONIB  : u32 = 0b1111111111110000; // masks out low nibble
+pub const MASK_OUT_EA : u32 = 0b1111111111000000; // masks out the effective address (??????????mmmrrr)
 
 pub const IF_T : u32 = 0b0000_0000_0000; // True            1
 pub const IF_F : u32 = 0b0001_0000_0000; // False           0
@@ -125,8 +148,31 @@ pub const LOGI_MEM_SHIFT  : u32 = 0xC0 | (LOGI_REG_SHIFT << 6);
 pub const ROTX_MEM_SHIFT  : u32 = 0xC0 | (ROTX_REG_SHIFT << 6);
 pub const ROTA_MEM_SHIFT  : u32 = 0xC0 | (ROTA_REG_SHIFT << 6);
 
+// CAS and CHK2/CMP2 have their size in bits 10-9, but encode it
+// differently
+pub const CAS_BYTE_SIZED : u32 = 0x200;
+pub const CAS_WORD_SIZED : u32 = 0x400;
+pub const CAS_LONG_SIZED : u32 = 0x600;
+pub const CHK2_BYTE_SIZED: u32 = 0x000;
+pub const CHK2_WORD_SIZED: u32 = 0x200;
+pub const CHK2_LONG_SIZED: u32 = 0x400;
+
+pub const BF_TST : u32 = 0x000;
+pub const BF_EXTU: u32 = 0x100;
+pub const BF_CHG : u32 = 0x200;
+pub const BF_EXTS: u32 = 0x300;
+pub const BF_CLR : u32 = 0x400;
+pub const BF_FFO : u32 = 0x500;
+pub const BF_SET : u32 = 0x600;
+pub const BF_INS : u32 = 0x700;
+
+// TRAPcc has an optional word or long operand
+pub const TRAPCC_16  : u32 = 0x2;
+pub const TRAPCC_32  : u32 = 0x3;
+pub const TRAPCC_NONE: u32 = 0x4;
+
 pub const MOVE_FROM_SR : u32 = 0x0c0;
-// pub const MOVE_FROM_CCR : u32 = 0x2c0; // Only 010+
+pub const MOVE_FROM_CCR: u32 = 0x2c0; // Only 010+
 pub const MOVE_TO_CCR  : u32 = 0x4c0;
 pub const MOVE_TO_SR   : u32 = 0x6c0;
 
@@ -192,7 +238,7 @@ pub const EXG_DATA_ADDR: u32 = 0x88; // Exchange a data register and an address
 // DEST_AX_LONG, perhaps there's a better common name somewhere)
 pub const BYTE_TO_WORD: u32 = 0x080;
 pub const WORD_TO_LONG: u32 = 0x0C0;
-// const BYTE_TO_LONG: u32 = 0x1C0; // 020+
+pub const BYTE_TO_LONG: u32 = 0x1C0; // 020+
 
 // CHK constants
 pub const WORD_OP: u32 = 0x180;
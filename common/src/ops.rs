@@ -5,3 +5,9 @@ pub const OP_RESET  : u32 = 0b0100_1110_0111_0000;
 pub const OP_STOP   : u32 = 0b0100_1110_0111_0010;
 pub const OP_TRAP   : u32 = 0b0100_1110_0100_0000;
 pub const OP_TRAPV  : u32 = 0b0100_1110_0111_0110;
+
+// 68010 and later
+pub const OP_BKPT   : u32 = 0b0100_1000_0100_1000;
+pub const OP_MOVEC_CR: u32 = 0b0100_1110_0111_1010; // control register to general register
+pub const OP_MOVEC_RC: u32 = 0b0100_1110_0111_1011; // general register to control register
+pub const OP_RTD    : u32 = 0b0100_1110_0111_0100;
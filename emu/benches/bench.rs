@@ -4,25 +4,36 @@ extern crate test;
 extern crate r68k_emu;
 use test::Bencher;
 
-use r68k_emu::cpu::{ConfiguredCore, Core, ProcessingState, Result, Cycles, Exception, Callbacks};
-use r68k_emu::cpu::ops::opcodes;
-use r68k_emu::ram::PagedMem;
+use r68k_emu::cpu::{ConfiguredCore, Core, ProcessingState, Result, Cycles, Exception, Callbacks, BeforeInstruction, CpuModel};
+use r68k_emu::cpu::ops::{self, opcodes};
+use r68k_emu::ram::{AddressBus, FlatMem, PagedMem, SUPERVISOR_DATA};
 use r68k_emu::interrupts::AutoInterruptController;
 
 struct LogAllExceptions {
-    count: isize
+    count: isize,
+    // counted by a before_instruction hook, if Some
+    instructions: Option<u64>,
 }
-impl Callbacks for LogAllExceptions {
-    fn exception_callback(&mut self, _: &mut impl Core, ex: Exception) -> Result<Cycles> {
+impl<C: Core> Callbacks<C> for LogAllExceptions {
+    fn exception_callback(&mut self, _: &mut C, ex: Exception) -> Result<Cycles> {
         println!("{:?}", ex);
         self.count += 1;
         Err(ex)
     }
+    fn before_instruction(&mut self, _: &mut C, _pc: u32, _opcode: u16) -> BeforeInstruction {
+        if let Some(ref mut instructions) = self.instructions {
+            *instructions += 1;
+        }
+        BeforeInstruction::Execute
+    }
 }
 
-#[bench]
-fn bench_100k_cycles(b: &mut Bencher) {
-    let mut cpu = ConfiguredCore::new_with(0, AutoInterruptController::new(), PagedMem::new(0xAAAAAAAA));
+fn bench_100k_cycles_with<A: AddressBus>(b: &mut Bencher, mem: A, block_cache: bool) {
+    bench_100k_cycles_hooked(b, mem, block_cache, None);
+}
+
+fn bench_100k_cycles_hooked<A: AddressBus>(b: &mut Bencher, mem: A, block_cache: bool, instructions: Option<u64>) {
+    let mut cpu = ConfiguredCore::new_with(0, AutoInterruptController::new(), mem);
     let regregops = [opcodes::OP_ADD_16_ER_DN, opcodes::OP_SUB_16_ER_DN, opcodes::OP_AND_16_ER_DN, opcodes::OP_OR_16_ER_DN];
     let pc_base = 0x1000;
     // write an instruction sequence of simple reg-to-reg operations
@@ -40,7 +51,8 @@ fn bench_100k_cycles(b: &mut Bencher) {
     let cycles_per_instruction = 4;
     let num_instructions = 25_000;
     let bytes_per_instruction = 2;
-    let mut handler = LogAllExceptions { count: 0 };
+    let mut handler = LogAllExceptions { count: 0, instructions };
+    cpu.set_block_cache(block_cache);
     cpu.reset();
     assert_eq!(pc_base, cpu.pc);
     b.iter(|| {
@@ -50,4 +62,82 @@ fn bench_100k_cycles(b: &mut Bencher) {
     assert_eq!(0, handler.count);
     assert_eq!(ProcessingState::Normal, cpu.processing_state);
     assert_eq!(pc_base + (num_instructions * bytes_per_instruction) as u32, cpu.pc);
-}
\ No newline at end of file
+}
+
+#[bench]
+fn bench_100k_cycles(b: &mut Bencher) {
+    bench_100k_cycles_with(b, PagedMem::new(0xAAAAAAAA), false);
+}
+
+// the same, run from the block cache after the first iteration
+#[bench]
+fn bench_100k_cycles_block_cache(b: &mut Bencher) {
+    bench_100k_cycles_with(b, PagedMem::new(0xAAAAAAAA), true);
+}
+
+// The instruction hooks of Callbacks left out cost nothing, as they are
+// inlined away, so bench_100k_cycles is as fast as it was without them.
+// Even a hook counting the instructions hardly shows.
+#[bench]
+fn bench_100k_cycles_before_instruction_hook(b: &mut Bencher) {
+    bench_100k_cycles_hooked(b, PagedMem::new(0xAAAAAAAA), false, Some(0));
+}
+
+#[bench]
+fn bench_100k_cycles_flatmem(b: &mut Bencher) {
+    bench_100k_cycles_with(b, FlatMem::new(0xAAAAAAAA), false);
+}
+
+// the block cache skips even FlatMem's prefetch reads, and the interrupt
+// checks within a block
+#[bench]
+fn bench_100k_cycles_flatmem_block_cache(b: &mut Bencher) {
+    bench_100k_cycles_with(b, FlatMem::new(0xAAAAAAAA), true);
+}
+
+// reads and writes 64 KiB as longs, and a word in between each
+fn bench_mem_with<A: AddressBus>(b: &mut Bencher, mut mem: A) {
+    for address in (0..0x10000).step_by(4) {
+        mem.write_long(SUPERVISOR_DATA, 0x10000 + address, address).unwrap();
+    }
+    b.iter(|| {
+        let mut sum = 0u32;
+        for address in (0x10000..0x20000).step_by(4) {
+            let long = mem.read_long(SUPERVISOR_DATA, address).unwrap();
+            sum = sum.wrapping_add(long).wrapping_add(mem.read_word(SUPERVISOR_DATA, address + 1).unwrap());
+            mem.write_long(SUPERVISOR_DATA, address, long.wrapping_add(1)).unwrap();
+        }
+        sum
+    });
+}
+
+#[bench]
+fn bench_mem_pagedmem(b: &mut Bencher) {
+    bench_mem_with(b, PagedMem::new(0xAAAAAAAA));
+}
+
+#[bench]
+fn bench_mem_flatmem(b: &mut Bencher) {
+    bench_mem_with(b, FlatMem::new(0xAAAAAAAA));
+}
+
+// Each core used to build its own 64K entry handler table, as this
+// does, 512 KiB of function pointers on a 64 bit host. Now that cores of
+// a kind share the table, it is built once per process, so compare this
+// with bench_new_core.
+#[bench]
+fn bench_new_instruction_set(b: &mut Bencher) {
+    b.iter(|| ops::instruction_set_for::<ConfiguredCore<AutoInterruptController, PagedMem>>(CpuModel::M68000));
+}
+
+#[bench]
+fn bench_new_core(b: &mut Bencher) {
+    b.iter(|| ConfiguredCore::new_with(0, AutoInterruptController::new(), PagedMem::new(0)));
+}
+
+#[bench]
+fn bench_new_100_cores(b: &mut Bencher) {
+    b.iter(|| {
+        (0..100).map(|_| ConfiguredCore::new_with(0, AutoInterruptController::new(), PagedMem::new(0))).collect::<Vec<_>>()
+    });
+}
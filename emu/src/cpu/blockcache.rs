@@ -0,0 +1,374 @@
+use super::Handler;
+
+// The most instructions in a block, which also bounds how long a block
+// can hold off an interrupt
+pub const MAX_BLOCK_INSTRUCTIONS: usize = 32;
+// writes invalidate the blocks on the same page
+const PAGE_SHIFT: u32 = 8;
+// Blocks are found through tables of page slots, by the page they start
+// on and by the pages they were fetched from; pages PAGE_SLOTS pages
+// apart share a slot
+const PAGE_SLOTS: usize = 1 << 12;
+
+fn page_slot(page: u32) -> usize {
+    page as usize & (PAGE_SLOTS - 1)
+}
+
+struct Op<C> {
+    pc: u32,
+    opcode: u16,
+    handler: Handler<C>,
+}
+
+struct Block<C> {
+    start: u32,
+    supervisor: bool,
+    // the program words from start on, as fetched
+    words: Vec<u16>,
+    ops: Vec<Op<C>>,
+    // the pages, as addressed on the bus, the words were fetched from
+    pages: Vec<u32>,
+}
+
+impl<C> Block<C> {
+    fn end(&self) -> u32 {
+        self.start.wrapping_add(2 * self.words.len() as u32)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct BlockCacheStats {
+    // blocks recorded, entered from the cache, and thrown away by writes
+    pub recorded: u64,
+    pub entered: u64,
+    pub invalidated: u64,
+}
+
+// Caches straight-line runs of instructions (blocks), as they were first
+// executed: the program words they were fetched from, and the opcode and
+// handler of each instruction. Running from a block skips the decoding,
+// the prefetch and the memory reads, and interrupts are only checked on
+// entering a block. A block ends at an instruction that leaves the PC
+// anywhere but at the next one, an exception, a change of supervisor
+// mode or after MAX_BLOCK_INSTRUCTIONS.
+//
+// Writes by the core invalidate the blocks on the pages written to, and
+// the whole cache is flushed when the bus reports a change of its
+// mapping, but the cache cannot see memory changed behind the core's
+// back otherwise (by the host or another core on a shared bus), so
+// flush it after that.
+pub struct BlockCache<C> {
+    blocks: Vec<Option<Block<C>>>,
+    free: Vec<usize>,
+    // the blocks starting on the pages of each slot, by the program's
+    // addresses
+    starts: Vec<Vec<usize>>,
+    // the blocks fetched from the pages of each slot, by the addresses
+    // put on the bus
+    pages: Vec<Vec<usize>>,
+    // the block being run or recorded, and its next instruction
+    current: Option<usize>,
+    next_op: usize,
+    recording: bool,
+    stats: BlockCacheStats,
+    // the map generation of the bus the blocks were recorded from
+    generation: u32,
+}
+
+impl<C> Default for BlockCache<C> {
+    fn default() -> BlockCache<C> {
+        BlockCache {
+            blocks: Vec::new(), free: Vec::new(),
+            starts: (0..PAGE_SLOTS).map(|_| Vec::new()).collect(), pages: (0..PAGE_SLOTS).map(|_| Vec::new()).collect(),
+            current: None, next_op: 0, recording: false, stats: BlockCacheStats::default(), generation: 0
+        }
+    }
+}
+
+impl<C> BlockCache<C> {
+    pub fn new() -> BlockCache<C> {
+        BlockCache::default()
+    }
+    pub fn flush(&mut self) {
+        // only the slots of cached blocks are in use
+        for block in self.blocks.drain(..).flatten() {
+            self.starts[page_slot(block.start >> PAGE_SHIFT)].clear();
+            for page in block.pages {
+                self.pages[page_slot(page)].clear();
+            }
+        }
+        self.free.clear();
+        self.current = None;
+        self.recording = false;
+    }
+    // blocks currently cached
+    pub fn len(&self) -> usize {
+        self.blocks.len() - self.free.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    pub fn stats(&self) -> BlockCacheStats {
+        self.stats
+    }
+    pub fn is_recording(&self) -> bool {
+        self.recording
+    }
+    // flushes the cache if the bus was remapped since it was last told
+    pub fn follow_map(&mut self, generation: u32) {
+        if generation != self.generation {
+            self.flush();
+            self.generation = generation;
+        }
+    }
+
+    // the next instruction of the block being run, if it is at pc
+    pub fn next_op(&mut self, pc: u32, supervisor: bool) -> Option<(u16, Handler<C>)> {
+        if self.recording {
+            return None;
+        }
+        let block = self.blocks[self.current?].as_ref().unwrap();
+        match block.ops.get(self.next_op) {
+            Some(op) if op.pc == pc && block.supervisor == supervisor => {
+                self.next_op += 1;
+                Some((op.opcode, op.handler))
+            }
+            _ => {
+                self.current = None;
+                None
+            }
+        }
+    }
+    // enters the cached block starting at pc, if there is one
+    pub fn enter(&mut self, pc: u32, supervisor: bool) -> Option<(u16, Handler<C>)> {
+        let blocks = &self.blocks;
+        let index = *self.starts[page_slot(pc >> PAGE_SHIFT)].iter().find(|&&index| {
+            let block = blocks[index].as_ref().unwrap();
+            block.start == pc && block.supervisor == supervisor
+        })?;
+        self.current = Some(index);
+        self.next_op = 0;
+        self.stats.entered += 1;
+        self.next_op(pc, supervisor)
+    }
+    // starts recording a new block at pc
+    pub fn record(&mut self, pc: u32, supervisor: bool) {
+        let block = Block { start: pc, supervisor, words: Vec::new(), ops: Vec::new(), pages: Vec::new() };
+        let index = match self.free.pop() {
+            Some(index) => {
+                self.blocks[index] = Some(block);
+                index
+            }
+            None => {
+                self.blocks.push(Some(block));
+                self.blocks.len() - 1
+            }
+        };
+        self.starts[page_slot(pc >> PAGE_SHIFT)].push(index);
+        self.current = Some(index);
+        self.recording = true;
+    }
+    pub fn record_op(&mut self, pc: u32, opcode: u16, handler: Handler<C>) {
+        if let (true, Some(index)) = (self.recording, self.current) {
+            self.blocks[index].as_mut().unwrap().ops.push(Op { pc, opcode, handler });
+        }
+    }
+
+    // a program word of the current block
+    pub fn word(&self, pc: u32, supervisor: bool) -> Option<u16> {
+        let block = self.blocks[self.current?].as_ref().unwrap();
+        let offset = pc.wrapping_sub(block.start) as usize;
+        if block.supervisor == supervisor && offset < 2 * block.words.len() {
+            Some(block.words[offset / 2])
+        } else {
+            None
+        }
+    }
+    // Adds a word fetched from memory to the block being recorded, given
+    // its address as seen by the program and as put on the bus
+    pub fn record_word(&mut self, pc: u32, address: u32, supervisor: bool, word: u16) {
+        let index = match (self.recording, self.current) {
+            (true, Some(index)) => index,
+            _ => return,
+        };
+        let page = address >> PAGE_SHIFT;
+        {
+            let block = self.blocks[index].as_mut().unwrap();
+            if block.end() != pc || block.supervisor != supervisor {
+                return self.leave();
+            }
+            block.words.push(word);
+            if block.pages.contains(&page) {
+                return;
+            }
+            block.pages.push(page);
+        }
+        self.pages[page_slot(page)].push(index);
+    }
+
+    // Called after each instruction, with where it left the PC, and
+    // whether the core took an exception or stopped
+    pub fn end_instruction(&mut self, pc: u32, supervisor: bool, interrupted: bool) {
+        if interrupted {
+            return self.leave();
+        }
+        if let (true, Some(index)) = (self.recording, self.current) {
+            let block = self.blocks[index].as_ref().unwrap();
+            if block.end() != pc || block.supervisor != supervisor || block.ops.len() >= MAX_BLOCK_INSTRUCTIONS {
+                self.leave();
+            }
+        }
+    }
+    fn leave(&mut self) {
+        if let (true, Some(index)) = (self.recording, self.current) {
+            // a block without an instruction is no use
+            if self.blocks[index].as_ref().unwrap().ops.is_empty() {
+                self.remove(index);
+            } else {
+                self.stats.recorded += 1;
+            }
+        }
+        self.current = None;
+        self.recording = false;
+    }
+
+    // Throws away the blocks on the pages written to, given the address
+    // as put on the bus
+    pub fn invalidate(&mut self, address: u32, size: u32) {
+        let first = address >> PAGE_SHIFT;
+        let last = address.wrapping_add(size - 1) >> PAGE_SHIFT;
+        self.invalidate_page(first);
+        if last != first {
+            self.invalidate_page(last);
+        }
+    }
+    fn invalidate_page(&mut self, page: u32) {
+        let slot = page_slot(page);
+        // removing a block takes it out of the slot
+        let mut i = 0;
+        while i < self.pages[slot].len() {
+            let index = self.pages[slot][i];
+            if self.blocks[index].as_ref().unwrap().pages.contains(&page) {
+                self.remove(index);
+                self.stats.invalidated += 1;
+            } else {
+                i += 1;
+            }
+        }
+    }
+    fn remove(&mut self, index: usize) {
+        let block = match self.blocks[index].take() {
+            Some(block) => block,
+            None => return,
+        };
+        self.starts[page_slot(block.start >> PAGE_SHIFT)].retain(|&i| i != index);
+        for page in block.pages {
+            self.pages[page_slot(page)].retain(|&i| i != index);
+        }
+        self.free.push(index);
+        if self.current == Some(index) {
+            self.current = None;
+            self.recording = false;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cpu::TestCore;
+    use ram::{AddressBus, SUPERVISOR_DATA, SUPERVISOR_PROGRAM};
+
+    // counts D0 up in a loop, summing it into D1, and keeping the sum at
+    // $1000 from time to time:
+    // 0100 moveq #0, d0
+    // 0102 addq.w #1, d0
+    // 0104 add.l d0, d1
+    // 0106 btst #3, d0
+    // 010a beq.s $0102
+    // 010c move.l d1, $1000.w
+    // 0110 bra.s $0102
+    const LOOP: [u16; 9] = [0x7000, 0x5240, 0xd280, 0x0800, 0x0003, 0x67f6, 0x21c1, 0x1000, 0x60f0];
+
+    fn core(code: &[u16], block_cache: bool) -> TestCore {
+        let mut cpu = TestCore::new_mem(0x100, &[]);
+        for (i, word) in code.iter().enumerate() {
+            cpu.mem.write_word(SUPERVISOR_PROGRAM, 0x100 + 2 * i as u32, u32::from(*word)).unwrap();
+        }
+        cpu.dar[15] = 0x800;
+        cpu.set_block_cache(block_cache);
+        cpu
+    }
+
+    #[test]
+    fn runs_the_same_as_without_the_cache() {
+        let mut plain = core(&LOOP, false);
+        let mut cached = core(&LOOP, true);
+        for _ in 0..20 {
+            assert_eq!(plain.execute(100), cached.execute(100));
+            assert_eq!((plain.pc, plain.dar), (cached.pc, cached.dar));
+        }
+        assert_eq!(plain.read_data_long(0x1000).unwrap(), cached.read_data_long(0x1000).unwrap());
+        let stats = cached.block_cache().unwrap().stats();
+        assert!(stats.entered > 10 * stats.recorded);
+    }
+
+    #[test]
+    fn writes_to_cached_code_invalidate_it() {
+        // 0100 moveq #1, d0
+        // 0102 move.w #$7002, $0100.w
+        // 0108 bra.s $0100
+        let mut cpu = core(&[0x7001, 0x31fc, 0x7002, 0x0100, 0x60f6], true);
+        cpu.execute1();
+        assert_eq!(1, cpu.dar[0]);
+        cpu.execute1();
+        cpu.execute1();
+        assert_eq!(0x100, cpu.pc);
+        cpu.execute1();
+        assert_eq!(2, cpu.dar[0]);
+        assert_eq!(1, cpu.block_cache().unwrap().stats().invalidated);
+    }
+
+    #[test]
+    fn writes_to_pages_sharing_a_slot_keep_the_blocks() {
+        let mut cpu = core(&[0x7001, 0x60fc], true);
+        cpu.execute(4 + 10);
+        assert_eq!(1, cpu.block_cache().unwrap().len());
+        let elsewhere = 0x100 + ((super::PAGE_SLOTS as u32) << super::PAGE_SHIFT);
+        cpu.write_data_word(elsewhere, 0x7002).unwrap();
+        assert_eq!(1, cpu.block_cache().unwrap().len());
+        cpu.write_data_word(0x100, 0x7002).unwrap();
+        assert_eq!(0, cpu.block_cache().unwrap().len());
+    }
+
+    #[test]
+    fn interrupts_are_taken_between_blocks() {
+        // a block of NOPs, ending in a jump back to the start
+        let mut code = [0x4e71u16; 10];
+        code[8] = 0x60ee; // bra.s $0100
+        let mut cpu = core(&code, true);
+        cpu.mem.write_long(SUPERVISOR_DATA, 0x74, 0x400).unwrap(); // level 5 autovector
+        cpu.int_mask = 0;
+        cpu.execute(8 * 4 + 10);
+        assert_eq!(0x100, cpu.pc);
+        cpu.execute(4);
+        cpu.int_ctrl.request_interrupt(5);
+        cpu.execute(4);
+        assert_eq!(0x104, cpu.pc);
+        cpu.execute(6 * 4 + 10);
+        assert_eq!(0x100, cpu.pc);
+        cpu.execute1();
+        assert_eq!(0x400, cpu.pc);
+    }
+
+    #[test]
+    fn host_writes_need_a_flush() {
+        let mut cpu = core(&[0x7001, 0x60fc], true);
+        cpu.execute(4 + 10);
+        cpu.mem.write_word(SUPERVISOR_PROGRAM, 0x100, 0x7002).unwrap();
+        cpu.execute(4 + 10);
+        assert_eq!(1, cpu.dar[0]);
+        cpu.flush_block_cache();
+        cpu.execute(4 + 10);
+        assert_eq!(2, cpu.dar[0]);
+    }
+}
@@ -0,0 +1,345 @@
+use std::collections::HashSet;
+use interrupts::InterruptController;
+use ram::{AddressBus, AddressSpace};
+use super::{ConfiguredCore, Callbacks, Cycles, AccessType};
+
+// What kind of data access triggers a watchpoint
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Watch {
+    Read,
+    Write,
+    Access, // read or write
+}
+
+// Watches the addresses as put on the bus, so a mirror of them on a
+// narrower bus triggers it as well. Only accesses that got through
+// trigger it, not those ending in an address or bus error.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Watchpoint {
+    pub address: u32,
+    pub len: u32,
+    pub watch: Watch,
+    // only trigger on accesses in these address spaces, or in any
+    // address space if empty
+    pub address_spaces: Vec<AddressSpace>,
+}
+
+impl Watchpoint {
+    pub fn new(address: u32, len: u32, watch: Watch) -> Watchpoint {
+        Watchpoint { address, len, watch, address_spaces: Vec::new() }
+    }
+    // whether an access of bytes at address touches the watched range,
+    // which may end at the top of the bus
+    pub fn overlaps(&self, address: u32, bytes: u32) -> bool {
+        address.wrapping_sub(self.address) < self.len || self.address.wrapping_sub(address) < bytes
+    }
+    fn triggers(&self, address_space: AddressSpace, address: u32, bytes: u32, access_type: AccessType) -> bool {
+        let kind = matches!((self.watch, access_type),
+            (Watch::Access, _) | (Watch::Read, AccessType::Read) | (Watch::Write, AccessType::Write));
+        kind && self.overlaps(address, bytes) && (self.address_spaces.is_empty() || self.address_spaces.contains(&address_space))
+    }
+}
+
+// The access that triggered a watchpoint, and the address of the
+// instruction making it
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct WatchHit {
+    pub pc: u32,
+    pub address: u32,
+    pub access_type: AccessType,
+    pub address_space: AddressSpace,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StopReason {
+    CyclesConsumed,     // executed (at least) the requested cycles
+    Step,               // a step helper completed its step
+    Breakpoint(u32),    // about to execute the instruction at a breakpoint
+    Watchpoint(WatchHit), // an instruction accessed watched memory
+    Stopped,            // STOP instruction, waiting for an interrupt
+    Halted,             // double fault, waiting for an external reset
+    Requested,          // a Callbacks hook asked to stop
+}
+
+#[derive(Clone, Default)]
+pub struct Debugger {
+    breakpoints: HashSet<u32>,
+    watchpoints: Vec<Watchpoint>,
+    hit: Option<WatchHit>,
+}
+
+impl Debugger {
+    pub fn new() -> Debugger {
+        Debugger { breakpoints: HashSet::new(), watchpoints: Vec::new(), hit: None }
+    }
+    pub fn add_breakpoint(&mut self, pc: u32) -> bool {
+        self.breakpoints.insert(pc)
+    }
+    pub fn remove_breakpoint(&mut self, pc: u32) -> bool {
+        self.breakpoints.remove(&pc)
+    }
+    pub fn is_breakpoint(&self, pc: u32) -> bool {
+        !self.breakpoints.is_empty() && self.breakpoints.contains(&pc)
+    }
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) {
+        self.watchpoints.push(watchpoint);
+    }
+    pub fn remove_watchpoint(&mut self, watchpoint: &Watchpoint) -> bool {
+        let before = self.watchpoints.len();
+        self.watchpoints.retain(|w| w != watchpoint);
+        before != self.watchpoints.len()
+    }
+    pub fn clear(&mut self) {
+        self.breakpoints.clear();
+        self.watchpoints.clear();
+        self.hit = None;
+    }
+    // called by the core for every data access; the first hit is kept
+    // until the current instruction has completed
+    pub fn check_access(&mut self, address_space: AddressSpace, address: u32, bytes: u32, access_type: AccessType) {
+        if self.watchpoints.is_empty() || self.hit.is_some() {
+            return;
+        }
+        if self.watchpoints.iter().any(|w| w.triggers(address_space, address, bytes, access_type)) {
+            self.hit = Some(WatchHit { pc: 0, address, access_type, address_space });
+        }
+    }
+    pub fn take_hit(&mut self, pc: u32) -> Option<WatchHit> {
+        self.hit.take().map(|hit| WatchHit { pc, ..hit })
+    }
+}
+
+// JSR <ea> and BSR
+fn is_call(opcode: u16) -> bool {
+    opcode & 0xffc0 == 0x4e80 || opcode & 0xff00 == 0x6100
+}
+
+// RTE, RTS and RTR
+fn is_return(opcode: u16) -> bool {
+    opcode == 0x4e73 || opcode == 0x4e75 || opcode == 0x4e77
+}
+
+impl<T: InterruptController, A: AddressBus> ConfiguredCore<T, A> {
+    // Executes a single instruction, or the exception processing that
+    // preempts it, even if there is a breakpoint at the current PC
+    pub fn step_instruction<S: Callbacks<Self>>(&mut self, state: &mut S) -> (Cycles, StopReason) {
+        match self.execute_with_state(1, state) {
+            (cycles, StopReason::CyclesConsumed) => (cycles, StopReason::Step),
+            other => other,
+        }
+    }
+
+    // Steps one instruction, but if it is a subroutine call, keeps
+    // running until the subroutine has returned, or max_cycles have been
+    // spent, or a breakpoint or watchpoint hits
+    pub fn step_over<S: Callbacks<Self>>(&mut self, max_cycles: i32, state: &mut S) -> (Cycles, StopReason) {
+        let sp = self.dar[15];
+        let (cycles, reason) = self.step_instruction(state);
+        if reason != StopReason::Step || !is_call(self.ir) || self.dar[15] != sp.wrapping_sub(4) {
+            return (cycles, reason);
+        }
+        let address_space = self.data_space();
+        let return_address = match self.mem.read_long(address_space, self.dar[15]) {
+            Ok(address) => address,
+            Err(_) => return (cycles, reason),
+        };
+        self.run_until(cycles, max_cycles, state, |core| core.pc == return_address && core.dar[15] == sp)
+    }
+
+    // Runs until the current subroutine or exception handler returns to
+    // its caller, or max_cycles have been spent, or a breakpoint or
+    // watchpoint hits
+    pub fn step_out<S: Callbacks<Self>>(&mut self, max_cycles: i32, state: &mut S) -> (Cycles, StopReason) {
+        let sp = self.dar[15];
+        let (cycles, reason) = self.step_instruction(state);
+        if reason != StopReason::Step || (is_return(self.ir) && self.dar[15] > sp) {
+            return (cycles, reason);
+        }
+        self.run_until(cycles, max_cycles, state, |core| is_return(core.ir) && core.dar[15] > sp)
+    }
+
+    fn run_until<S: Callbacks<Self>, F>(&mut self, spent: Cycles, max_cycles: i32, state: &mut S, done: F) -> (Cycles, StopReason)
+        where F: Fn(&Self) -> bool
+    {
+        let mut cycles = spent;
+        while cycles.0 < max_cycles {
+            if self.debugger.is_breakpoint(self.pc) {
+                return (cycles, StopReason::Breakpoint(self.pc));
+            }
+            let (used, reason) = self.step_instruction(state);
+            cycles = cycles + used;
+            if reason != StopReason::Step || done(self) {
+                return (cycles, reason);
+            }
+        }
+        (cycles, StopReason::CyclesConsumed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{StopReason, Watch, Watchpoint, WatchHit};
+    use cpu::{TestCore, Cycles, AccessType, EmulateAllExceptions};
+    use ram::{USER_DATA, SUPERVISOR_DATA, SUPERVISOR_PROGRAM};
+
+    // 7001 is MOVEQ #1, D0
+    // 7202 is MOVEQ #2, D1
+    // 6100 0006 is BSR +6
+    // 4e71 is NOP
+    // 31c0 0100 is MOVE.W D0, $100.W
+    // 3238 0100 is MOVE.W $100.W, D1
+    // 4e75 is RTS
+    const PROGRAM: [u8; 22] = [0x70, 0x01, 0x72, 0x02, 0x61, 0x00, 0x00, 0x06,
+                               0x4e, 0x71, 0x4e, 0x71, 0x31, 0xc0, 0x01, 0x00,
+                               0x32, 0x38, 0x01, 0x00, 0x4e, 0x75];
+
+    fn debugged_core() -> TestCore {
+        let mut cpu = TestCore::new_mem(0x40, &PROGRAM);
+        cpu.dar[15] = 0x400;
+        cpu.sr_to_flags(0x2700);
+        cpu
+    }
+
+    #[test]
+    fn runs_until_cycles_consumed_without_breakpoints() {
+        let mut cpu = debugged_core();
+        assert_eq!((Cycles(8), StopReason::CyclesConsumed), cpu.execute_with_state(8, &mut EmulateAllExceptions));
+        assert_eq!(0x44, cpu.pc);
+    }
+
+    #[test]
+    fn stops_at_breakpoint() {
+        let mut cpu = debugged_core();
+        cpu.debugger.add_breakpoint(0x42);
+        assert_eq!((Cycles(4), StopReason::Breakpoint(0x42)), cpu.execute_with_state(1000, &mut EmulateAllExceptions));
+        assert_eq!(0x42, cpu.pc);
+        // resuming from the breakpoint executes the instruction at it
+        assert_eq!((Cycles(4), StopReason::CyclesConsumed), cpu.execute_with_state(4, &mut EmulateAllExceptions));
+        assert_eq!(0x44, cpu.pc);
+        assert!(cpu.debugger.remove_breakpoint(0x42));
+        assert!(!cpu.debugger.remove_breakpoint(0x42));
+    }
+
+    #[test]
+    fn stops_after_instruction_writing_watched_memory() {
+        let mut cpu = debugged_core();
+        cpu.debugger.add_watchpoint(Watchpoint::new(0x101, 1, Watch::Write));
+        let (_, reason) = cpu.execute_with_state(1000, &mut EmulateAllExceptions);
+        assert_eq!(StopReason::Watchpoint(WatchHit { pc: 0x4c, address: 0x100, access_type: AccessType::Write, address_space: SUPERVISOR_DATA }), reason);
+        assert_eq!(0x50, cpu.pc);
+        assert_eq!(0x0001, cpu.read_data_word(0x100).unwrap());
+    }
+
+    #[test]
+    fn read_watchpoint_ignores_writes_and_filters_on_address_space() {
+        let mut cpu = debugged_core();
+        let user_only = Watchpoint { address_spaces: vec![USER_DATA], ..Watchpoint::new(0x100, 2, Watch::Read) };
+        cpu.debugger.add_watchpoint(user_only.clone());
+        cpu.debugger.add_watchpoint(Watchpoint { address_spaces: vec![SUPERVISOR_DATA], ..Watchpoint::new(0x100, 2, Watch::Read) });
+        assert!(cpu.debugger.remove_watchpoint(&user_only));
+        let (_, reason) = cpu.execute_with_state(1000, &mut EmulateAllExceptions);
+        assert_eq!(StopReason::Watchpoint(WatchHit { pc: 0x50, address: 0x100, access_type: AccessType::Read, address_space: SUPERVISOR_DATA }), reason);
+        assert_eq!(0x54, cpu.pc);
+        assert_eq!(1, cpu.dar[1]);
+    }
+
+    #[test]
+    fn watchpoint_sees_mirrored_addresses() {
+        // 33c0 ff00 0100 is MOVE.W D0, $ff000100.L
+        let mut cpu = TestCore::new_mem(0x40, &[0x33, 0xc0, 0xff, 0x00, 0x01, 0x00]);
+        cpu.debugger.add_watchpoint(Watchpoint::new(0x100, 2, Watch::Write));
+        let (_, reason) = cpu.execute_with_state(1000, &mut EmulateAllExceptions);
+        assert_eq!(StopReason::Watchpoint(WatchHit { pc: 0x40, address: 0x100, access_type: AccessType::Write, address_space: SUPERVISOR_DATA }), reason);
+    }
+
+    #[test]
+    fn watchpoint_can_end_at_the_top_of_the_bus() {
+        let top = Watchpoint::new(0xffff_fffc, 4, Watch::Write);
+        assert!(top.triggers(SUPERVISOR_DATA, 0xffff_fffe, 2, AccessType::Write));
+        assert!(top.triggers(SUPERVISOR_DATA, 0xffff_fffa, 4, AccessType::Write));
+        assert!(!top.triggers(SUPERVISOR_DATA, 0xffff_fff8, 4, AccessType::Write));
+        assert!(!top.triggers(SUPERVISOR_DATA, 0, 4, AccessType::Write));
+    }
+
+    #[test]
+    fn failed_access_does_not_trigger_watchpoint() {
+        // 31c0 0101 is MOVE.W D0, $101.W, an address error on the 68000
+        let mut cpu = TestCore::new_mem(0x40, &[0x31, 0xc0, 0x01, 0x01]);
+        cpu.dar[15] = 0x400;
+        cpu.debugger.add_watchpoint(Watchpoint::new(0x100, 4, Watch::Write));
+        assert_eq!(StopReason::CyclesConsumed, cpu.execute_with_state(1, &mut EmulateAllExceptions).1);
+    }
+
+    #[test]
+    fn instruction_fetch_does_not_trigger_data_watchpoint() {
+        let mut cpu = debugged_core();
+        cpu.debugger.add_watchpoint(Watchpoint { address_spaces: vec![SUPERVISOR_PROGRAM], ..Watchpoint::new(0x40, 0x20, Watch::Access) });
+        assert_eq!(StopReason::CyclesConsumed, cpu.execute_with_state(8, &mut EmulateAllExceptions).1);
+    }
+
+    #[test]
+    fn step_instruction_ignores_breakpoint_at_pc() {
+        let mut cpu = debugged_core();
+        cpu.debugger.add_breakpoint(0x40);
+        assert_eq!((Cycles(4), StopReason::Step), cpu.step_instruction(&mut EmulateAllExceptions));
+        assert_eq!(0x42, cpu.pc);
+    }
+
+    #[test]
+    fn step_over_runs_subroutine() {
+        let mut cpu = debugged_core();
+        cpu.step_instruction(&mut EmulateAllExceptions);
+        cpu.step_instruction(&mut EmulateAllExceptions);
+        assert_eq!(0x44, cpu.pc);
+        let (cycles, reason) = cpu.step_over(1000, &mut EmulateAllExceptions);
+        assert_eq!(StopReason::Step, reason);
+        assert_eq!(0x48, cpu.pc);
+        assert_eq!(0x400, cpu.dar[15]);
+        assert_eq!(1, cpu.dar[1]);
+        // BSR, MOVE.W D0 to memory, MOVE.W memory to D1, RTS
+        assert_eq!(Cycles(18 + 12 + 12 + 16), cycles);
+        // not a call, so just a step
+        assert_eq!((Cycles(4), StopReason::Step), cpu.step_over(1000, &mut EmulateAllExceptions));
+    }
+
+    #[test]
+    fn step_over_stops_at_breakpoint_in_subroutine() {
+        let mut cpu = debugged_core();
+        cpu.jump(0x44);
+        cpu.debugger.add_breakpoint(0x50);
+        let (_, reason) = cpu.step_over(1000, &mut EmulateAllExceptions);
+        assert_eq!(StopReason::Breakpoint(0x50), reason);
+        assert_eq!(0x50, cpu.pc);
+    }
+
+    #[test]
+    fn step_out_returns_to_caller() {
+        let mut cpu = debugged_core();
+        cpu.jump(0x44);
+        cpu.step_instruction(&mut EmulateAllExceptions); // BSR
+        assert_eq!(0x4c, cpu.pc);
+        let (cycles, reason) = cpu.step_out(1000, &mut EmulateAllExceptions);
+        assert_eq!(StopReason::Step, reason);
+        assert_eq!(Cycles(12 + 12 + 16), cycles);
+        assert_eq!(0x48, cpu.pc);
+        assert_eq!(0x400, cpu.dar[15]);
+    }
+
+    #[test]
+    fn step_out_gives_up_after_max_cycles() {
+        let mut cpu = debugged_core();
+        cpu.jump(0x44);
+        cpu.step_instruction(&mut EmulateAllExceptions); // BSR
+        assert_eq!((Cycles(12 + 12), StopReason::CyclesConsumed), cpu.step_out(20, &mut EmulateAllExceptions));
+        assert_eq!(0x54, cpu.pc);
+    }
+
+    #[test]
+    fn reports_stopped_and_halted() {
+        let mut cpu = TestCore::new_mem(0x40, &[0x4e, 0x72, 0x27, 0x00]); // STOP #$2700
+        cpu.sr_to_flags(0x2700);
+        assert_eq!(StopReason::Stopped, cpu.execute_with_state(100, &mut EmulateAllExceptions).1);
+        cpu.processing_state = ::cpu::ProcessingState::Halted;
+        assert_eq!(StopReason::Halted, cpu.execute_with_state(100, &mut EmulateAllExceptions).1);
+    }
+}
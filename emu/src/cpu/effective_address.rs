@@ -1,4 +1,4 @@
-use super::{Core, Result};
+use super::{Core, CpuModel, Result};
 use std::num::Wrapping;
 
 pub fn absolute_word<T: Core>(core: &mut T) -> Result<u32> {
@@ -132,22 +132,78 @@ pub fn displacement<T: Core>(core: &mut T, reg_val: u32) -> Result<u32> {
 }
 // Brief Extension Word format (see M68000 PRM section 2.1)
 const LONG_INDEX_MASK: u16 = 0x0800;
+// The 68020 scales the index by 1, 2, 4 or 8, and has a full extension
+// word format too
+const FULL_FORMAT_MASK: u16 = 0x0100;
 fn index<T: Core>(core: &mut T, reg_val: u32) -> Result<u32> {
     let extension = try!(core.read_imm_u16());
     // top four bits = (D/A RRR) matches our register array layout
     let xreg_ndx = (extension>>12) as usize;
     let xn = dar!(core)[xreg_ndx];
     let xn = if (extension & LONG_INDEX_MASK) > 0 {xn} else {(xn as i16) as u32};
-
-      let index = extension as i8;
-    let ea = (Wrapping(reg_val) + Wrapping(xn) + Wrapping(index as u32)).0;
-    Ok(ea)
+    if core.model() != CpuModel::M68020 {
+        let index = extension as i8;
+        let ea = (Wrapping(reg_val) + Wrapping(xn) + Wrapping(index as u32)).0;
+        return Ok(ea)
+    }
+    let xn = xn << ((extension >> 9) & 3);
+    if extension & FULL_FORMAT_MASK == 0 {
+        let index = extension as i8;
+        let ea = (Wrapping(reg_val) + Wrapping(xn) + Wrapping(index as u32)).0;
+        return Ok(ea)
+    }
+    full_format_index(core, extension, reg_val, xn)
+}
+// Full Extension Word format (see M68000 PRM section 2.1), following
+// Musashi in treating the reserved encodings as their nearest neighbour
+//   BS  base register suppressed (bit 7)
+//   IS  index suppressed (bit 6)
+//   BD  base displacement size, null, word or long (bits 5-4)
+//   I/IS no memory indirection, or the outer displacement size and
+//        whether the index is added before or after the indirection
+//        (bits 2-0)
+fn full_format_index<T: Core>(core: &mut T, extension: u16, reg_val: u32, xn: u32) -> Result<u32> {
+    let base = if extension & 0x0080 > 0 {0} else {reg_val};
+    let xn = if extension & 0x0040 > 0 {0} else {xn};
+    let bd = if extension & 0x0020 > 0 {
+        if extension & 0x0010 > 0 {try!(core.read_imm_u32())} else {try!(core.read_imm_i16()) as u32}
+    } else {
+        0
+    };
+    if extension & 7 == 0 {
+        return Ok(base.wrapping_add(bd).wrapping_add(xn))
+    }
+    let od = if extension & 0x0002 > 0 {
+        if extension & 0x0001 > 0 {try!(core.read_imm_u32())} else {try!(core.read_imm_i16()) as u32}
+    } else {
+        0
+    };
+    if extension & 0x0004 > 0 {
+        // postindexed
+        let pointer = try!(core.read_data_long(base.wrapping_add(bd)));
+        Ok(pointer.wrapping_add(xn).wrapping_add(od))
+    } else {
+        // preindexed
+        let pointer = try!(core.read_data_long(base.wrapping_add(bd).wrapping_add(xn)));
+        Ok(pointer.wrapping_add(od))
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::super::TestCore;
-    use super::super::effective_address::{predecrement_8, postincrement_8};
+    use super::super::{TestCore, CpuModel};
+    use super::super::effective_address::{predecrement_8, postincrement_8, index};
+    use ram::{AddressBus, SUPERVISOR_DATA, SUPERVISOR_PROGRAM};
+
+    fn core_with_extension(words: &[u16]) -> TestCore {
+        let mut core = TestCore::new_mem(0x40, &[]);
+        for (i, word) in words.iter().enumerate() {
+            core.mem.write_word(SUPERVISOR_PROGRAM, 0x40 + 2 * i as u32, u32::from(*word)).unwrap();
+        }
+        core.dar[1] = 0x10;
+        core.dar[8] = 0x1000;
+        core
+    }
 
     #[test]
     fn predecrement_wraps() {
@@ -193,4 +249,48 @@ mod tests {
         assert_eq!(0xFFFFFFFE, ea);
         assert_eq!(0x0, core.dar[8+7]);
     }
+    #[test]
+    fn index_is_not_scaled_on_68000() {
+        // (8,A0,D1.L*4) on the 68020
+        let mut core = core_with_extension(&[0x1c08]);
+        assert_eq!(0x1000 + 0x10 + 8, index(&mut core, 0x1000).unwrap());
+    }
+    #[test]
+    fn brief_index_is_scaled_on_68020() {
+        let mut core = core_with_extension(&[0x1c08]);
+        core.set_model(CpuModel::M68020);
+        assert_eq!(0x1000 + 0x40 + 8, index(&mut core, 0x1000).unwrap());
+    }
+    #[test]
+    fn full_format_with_long_base_displacement() {
+        // (bd.L,A0,D1.W*2), no memory indirection
+        let mut core = core_with_extension(&[0x1330, 0x0001, 0x0000]);
+        core.set_model(CpuModel::M68020);
+        assert_eq!(0x1_0000 + 0x1000 + 0x20, index(&mut core, 0x1000).unwrap());
+        assert_eq!(0x46, core.pc);
+    }
+    #[test]
+    fn full_format_suppressing_base_and_index() {
+        // (bd.W), with the base register and index suppressed
+        let mut core = core_with_extension(&[0x11e0, 0xfff0]);
+        core.set_model(CpuModel::M68020);
+        assert_eq!(0xffff_fff0, index(&mut core, 0x1000).unwrap());
+    }
+    #[test]
+    fn full_format_memory_indirect_preindexed() {
+        // ([bd.W,A0,D1.L],od.W)
+        let mut core = core_with_extension(&[0x1922, 0x0100, 0x0004]);
+        core.set_model(CpuModel::M68020);
+        core.mem.write_long(SUPERVISOR_DATA, 0x1000 + 0x100 + 0x10, 0x2000).unwrap();
+        assert_eq!(0x2004, index(&mut core, 0x1000).unwrap());
+    }
+    #[test]
+    fn full_format_memory_indirect_postindexed() {
+        // ([bd.W,A0],D1.L,od.L)
+        let mut core = core_with_extension(&[0x1927, 0x0100, 0x0000, 0x0004]);
+        core.set_model(CpuModel::M68020);
+        core.mem.write_long(SUPERVISOR_DATA, 0x1000 + 0x100, 0x2000).unwrap();
+        assert_eq!(0x2000 + 0x10 + 4, index(&mut core, 0x1000).unwrap());
+        assert_eq!(0x48, core.pc);
+    }
 }
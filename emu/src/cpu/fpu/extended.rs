@@ -0,0 +1,1014 @@
+// 80-bit extended precision values, as held in the FP data registers and
+// stored to memory: the sign and a 15-bit biased exponent in the upper
+// word, followed by a 64-bit mantissa with an explicit integer bit.
+//
+// Unlike the x87, the 68881 does not special-case a zero exponent; a
+// denormal is just a number with exponent 0 whose integer bit is clear,
+// so every finite value is mantissa * 2^(exponent - 16383 - 63).
+//
+// The arithmetic keeps exact intermediate results as a u128 significand
+// and the exponent of its bit 0, and rounds once, according to the
+// rounding mode and precision of the FPCR.
+use std::cmp::Ordering;
+use super::{FPSR_SNAN, FPSR_OPERR, FPSR_OVFL, FPSR_UNFL, FPSR_DZ, FPSR_INEX2};
+
+const EXP_BIAS: i32 = 16383;
+const EXP_SPECIAL: u16 = 0x7fff;
+const SIGN_BIT: u16 = 0x8000;
+const INTEGER_BIT: u64 = 1 << 63;
+const QUIET_BIT: u64 = 1 << 62;
+const HALF: u128 = 1 << 127;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RoundingMode {
+    Nearest,
+    Zero,
+    Minus,
+    Plus,
+}
+
+// The rounding mode and precision (mantissa bits) to use, collecting the
+// exceptions raised along the way as FPSR exception byte bits
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Env {
+    pub mode: RoundingMode,
+    pub precision: u32,
+    pub exceptions: u32,
+}
+
+impl Env {
+    pub fn new(mode: RoundingMode, precision: u32) -> Env {
+        Env { mode, precision, exceptions: 0 }
+    }
+    fn raise(&mut self, exceptions: u32) {
+        self.exceptions |= exceptions;
+    }
+}
+
+// The exponent range is that of the leading bit of normalized numbers,
+// denormals have their leading bit below emin
+struct Format {
+    bits: u32,
+    exp_bits: u32,
+    emin: i32,
+    emax: i32,
+}
+const EXTENDED: Format = Format { bits: 64, exp_bits: 15, emin: -16383, emax: 16383 };
+const DOUBLE: Format = Format { bits: 53, exp_bits: 11, emin: -1022, emax: 1023 };
+const SINGLE: Format = Format { bits: 24, exp_bits: 8, emin: -126, emax: 127 };
+
+// A mantissa of the rounding precision and the exponent of its leading
+// bit position, or an overflow to infinity or the largest finite number
+enum Rounded {
+    Finite { mant: u64, exp: i32 },
+    Overflow { max: bool },
+}
+
+fn shift_right_sticky(m: u128, shift: u32) -> u128 {
+    match shift {
+        0 => m,
+        1..=127 => (m >> shift) | ((m << (128 - shift)) != 0) as u128,
+        _ => (m != 0) as u128,
+    }
+}
+
+// Keeps the upper `keep` bits of a normalized significand, rounding by the
+// bits below them. The result may carry into bit `keep`.
+fn round_bits(m: u128, keep: u32, sign: bool, mode: RoundingMode) -> (u128, bool) {
+    let (kept, rest) = if keep == 0 { (0, m) } else { (m >> (128 - keep), m << keep) };
+    if rest == 0 {
+        return (kept, false);
+    }
+    let up = match mode {
+        RoundingMode::Nearest => rest > HALF || (rest == HALF && kept & 1 == 1),
+        RoundingMode::Zero => false,
+        RoundingMode::Minus => sign,
+        RoundingMode::Plus => !sign,
+    };
+    (kept + up as u128, true)
+}
+
+// Rounds sign * mant * 2^exp to `bits` of precision within the exponent
+// range of the format
+fn round(sign: bool, mant: u128, exp: i32, bits: u32, format: &Format, env: &mut Env) -> Rounded {
+    if mant == 0 {
+        return Rounded::Finite { mant: 0, exp: format.emin };
+    }
+    let lz = mant.leading_zeros();
+    let mut m = mant << lz;
+    let mut e = exp + 127 - lz as i32;
+    let tiny = e < format.emin;
+    if tiny {
+        m = shift_right_sticky(m, (format.emin - e) as u32);
+        e = format.emin;
+    }
+    let (mut kept, inexact) = round_bits(m, bits, sign, env.mode);
+    if kept >> bits != 0 {
+        kept >>= 1;
+        e += 1;
+    }
+    if inexact {
+        env.raise(FPSR_INEX2);
+        if tiny {
+            env.raise(FPSR_UNFL);
+        }
+    }
+    if e > format.emax {
+        env.raise(FPSR_OVFL | FPSR_INEX2);
+        let max = match env.mode {
+            RoundingMode::Nearest => false,
+            RoundingMode::Zero => true,
+            RoundingMode::Minus => !sign,
+            RoundingMode::Plus => sign,
+        };
+        return Rounded::Overflow { max };
+    }
+    Rounded::Finite { mant: kept as u64, exp: e }
+}
+
+fn pack_ieee(sign: bool, rounded: Rounded, format: &Format) -> u64 {
+    let frac_bits = format.bits - 1;
+    let frac_mask = (1u64 << frac_bits) - 1;
+    let exp_max = (1u64 << format.exp_bits) - 1;
+    let sign = (sign as u64) << (frac_bits + format.exp_bits);
+    match rounded {
+        Rounded::Overflow { max: false } => sign | exp_max << frac_bits,
+        Rounded::Overflow { max: true } => sign | (exp_max - 1) << frac_bits | frac_mask,
+        // zero or denormal
+        Rounded::Finite { mant, .. } if mant >> frac_bits == 0 => sign | mant,
+        Rounded::Finite { mant, exp } =>
+            sign | ((exp + format.emax) as u64) << frac_bits | (mant & frac_mask),
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Extended {
+    pub sign_exp: u16,
+    pub mantissa: u64,
+}
+
+impl Extended {
+    pub fn new(sign_exp: u16, mantissa: u64) -> Extended {
+        Extended { sign_exp, mantissa }
+    }
+    pub fn zero(sign: bool) -> Extended {
+        Extended::new(if sign { SIGN_BIT } else { 0 }, 0)
+    }
+    pub fn infinity(sign: bool) -> Extended {
+        Extended::new(if sign { SIGN_BIT | EXP_SPECIAL } else { EXP_SPECIAL }, 0)
+    }
+    // the NaN produced by invalid operations
+    pub fn default_nan() -> Extended {
+        Extended::new(EXP_SPECIAL, 0xffff_ffff_ffff_ffff)
+    }
+    pub fn from_i32(value: i32) -> Extended {
+        Extended::exact(value < 0, u128::from(value.unsigned_abs()), 0)
+    }
+    pub fn from_f32_bits(bits: u32) -> Extended {
+        Extended::from_ieee(u64::from(bits), &SINGLE)
+    }
+    pub fn from_f64_bits(bits: u64) -> Extended {
+        Extended::from_ieee(bits, &DOUBLE)
+    }
+    pub fn from_f64(value: f64) -> Extended {
+        Extended::from_f64_bits(value.to_bits())
+    }
+    fn from_ieee(bits: u64, format: &Format) -> Extended {
+        let frac_bits = format.bits - 1;
+        let exp_max = (1u64 << format.exp_bits) - 1;
+        let sign = (bits >> (frac_bits + format.exp_bits)) & 1 == 1;
+        let exp = (bits >> frac_bits) & exp_max;
+        let frac = bits & ((1u64 << frac_bits) - 1);
+        if exp == exp_max {
+            if frac == 0 {
+                Extended::infinity(sign)
+            } else {
+                Extended::infinity(sign).with_mantissa(INTEGER_BIT | frac << (63 - frac_bits))
+            }
+        } else if exp == 0 {
+            Extended::exact(sign, u128::from(frac), format.emin - frac_bits as i32)
+        } else {
+            Extended::exact(sign, u128::from(frac | 1 << frac_bits), exp as i32 - format.emax - frac_bits as i32)
+        }
+    }
+    // for values known to fit in 64 mantissa bits
+    fn exact(sign: bool, mant: u128, exp: i32) -> Extended {
+        Extended::round_from(sign, mant, exp, &mut Env::new(RoundingMode::Nearest, 64))
+    }
+    fn round_from(sign: bool, mant: u128, exp: i32, env: &mut Env) -> Extended {
+        let bits = env.precision;
+        let sign_bit = if sign { SIGN_BIT } else { 0 };
+        match round(sign, mant, exp, bits, &EXTENDED, env) {
+            Rounded::Overflow { max: false } => Extended::infinity(sign),
+            Rounded::Overflow { max: true } =>
+                Extended::new(sign_bit | (EXP_SPECIAL - 1), !0u64 << (64 - bits)),
+            Rounded::Finite { mant: 0, .. } => Extended::zero(sign),
+            Rounded::Finite { mant, exp } =>
+                Extended::new(sign_bit | (exp + EXP_BIAS) as u16, mant << (64 - bits)),
+        }
+    }
+
+    pub fn sign(self) -> bool {
+        self.sign_exp & SIGN_BIT != 0
+    }
+    fn biased_exp(self) -> u16 {
+        self.sign_exp & EXP_SPECIAL
+    }
+    pub fn is_nan(self) -> bool {
+        self.biased_exp() == EXP_SPECIAL && self.mantissa & !INTEGER_BIT != 0
+    }
+    pub fn is_signaling_nan(self) -> bool {
+        self.is_nan() && self.mantissa & QUIET_BIT == 0
+    }
+    pub fn is_infinite(self) -> bool {
+        self.biased_exp() == EXP_SPECIAL && self.mantissa & !INTEGER_BIT == 0
+    }
+    // unnormalized numbers with a zero mantissa are zeros too
+    pub fn is_zero(self) -> bool {
+        self.biased_exp() != EXP_SPECIAL && self.mantissa == 0
+    }
+    pub fn negate(self) -> Extended {
+        Extended::new(self.sign_exp ^ SIGN_BIT, self.mantissa)
+    }
+    pub fn abs(self) -> Extended {
+        Extended::new(self.sign_exp & !SIGN_BIT, self.mantissa)
+    }
+    fn with_mantissa(self, mantissa: u64) -> Extended {
+        Extended::new(self.sign_exp, mantissa)
+    }
+    fn quiet(self) -> Extended {
+        self.with_mantissa(self.mantissa | QUIET_BIT)
+    }
+    // sign, exponent of the leading bit, and the mantissa shifted so that
+    // the leading bit is bit 63. Only for finite non-zero values.
+    fn normalized(self) -> (bool, i32, u64) {
+        let lz = self.mantissa.leading_zeros();
+        (self.sign(), i32::from(self.biased_exp()) - EXP_BIAS - lz as i32, self.mantissa << lz)
+    }
+
+    // Rounds to the precision of the environment, which is what moving a
+    // value into an FP register does
+    pub fn rounded(self, env: &mut Env) -> Extended {
+        if self.is_nan() {
+            return nan_operand(self, self, env);
+        }
+        if self.is_infinite() || self.is_zero() {
+            return self;
+        }
+        let (sign, exp, mant) = self.normalized();
+        Extended::round_from(sign, u128::from(mant), exp - 63, env)
+    }
+    // Keeps only the upper bits of the (normalized) mantissa, as the
+    // single precision multiply and divide do with their operands
+    pub fn truncated(self, bits: u32) -> Extended {
+        if self.is_nan() || self.is_infinite() || self.is_zero() {
+            return self;
+        }
+        let (sign, exp, mant) = self.normalized();
+        let mut env = Env::new(RoundingMode::Zero, 64);
+        Extended::round_from(sign, u128::from(mant & !0u64 << (64 - bits)), exp - 63, &mut env)
+    }
+
+    pub fn to_f32_bits(self, env: &mut Env) -> u32 {
+        self.to_ieee(&SINGLE, env) as u32
+    }
+    pub fn to_f64_bits(self, env: &mut Env) -> u64 {
+        self.to_ieee(&DOUBLE, env)
+    }
+    pub fn to_f64(self) -> f64 {
+        f64::from_bits(self.to_f64_bits(&mut Env::new(RoundingMode::Nearest, 64)))
+    }
+    fn to_ieee(self, format: &Format, env: &mut Env) -> u64 {
+        let frac_bits = format.bits - 1;
+        let exp_max = (1u64 << format.exp_bits) - 1;
+        let sign = self.sign();
+        if self.is_nan() {
+            if self.is_signaling_nan() {
+                env.raise(FPSR_SNAN);
+            }
+            let payload = (self.mantissa & !INTEGER_BIT) >> (63 - frac_bits);
+            let quiet = 1u64 << (frac_bits - 1);
+            (sign as u64) << (frac_bits + format.exp_bits) | exp_max << frac_bits | payload | quiet
+        } else if self.is_infinite() {
+            pack_ieee(sign, Rounded::Overflow { max: false }, format)
+        } else if self.is_zero() {
+            (sign as u64) << (frac_bits + format.exp_bits)
+        } else {
+            let (sign, exp, mant) = self.normalized();
+            pack_ieee(sign, round(sign, u128::from(mant), exp - 63, format.bits, format, env), format)
+        }
+    }
+    // Converts to a signed integer of the given number of bits, rounding
+    // by the mode of the environment. Out of range values (and NaNs) are
+    // an operand error, and give the largest integer of their sign.
+    pub fn to_int(self, bits: u32, env: &mut Env) -> i32 {
+        let limit = 1i64 << (bits - 1);
+        let saturated = (if self.sign() { -limit } else { limit - 1 }) as i32;
+        if self.is_nan() || self.is_infinite() {
+            if self.is_signaling_nan() {
+                env.raise(FPSR_SNAN);
+            }
+            env.raise(FPSR_OPERR);
+            return saturated;
+        }
+        if self.is_zero() {
+            return 0;
+        }
+        let (sign, exp, mant) = self.normalized();
+        if exp >= 63 {
+            env.raise(FPSR_OPERR);
+            return saturated;
+        }
+        let (value, inexact) = integer_part(sign, exp, mant, env.mode);
+        let value = value as i64;
+        if (!sign && value >= limit) || (sign && value > limit) {
+            env.raise(FPSR_OPERR);
+            return saturated;
+        }
+        if inexact {
+            env.raise(FPSR_INEX2);
+        }
+        (if sign { -value } else { value }) as i32
+    }
+
+    pub fn add(self, src: Extended, env: &mut Env) -> Extended {
+        if self.is_nan() || src.is_nan() {
+            return nan_operand(self, src, env);
+        }
+        if self.is_infinite() {
+            if src.is_infinite() && src.sign() != self.sign() {
+                return operand_error(env);
+            }
+            return self;
+        }
+        if src.is_infinite() {
+            return src;
+        }
+        if self.is_zero() && src.is_zero() {
+            let sign = if self.sign() == src.sign() { self.sign() } else { env.mode == RoundingMode::Minus };
+            return Extended::zero(sign);
+        }
+        if src.is_zero() {
+            return self.rounded(env);
+        }
+        if self.is_zero() {
+            return src.rounded(env);
+        }
+        let (big, small) = if magnitude(self) >= magnitude(src) { (self, src) } else { (src, self) };
+        let (big_sign, big_exp, big_mant) = big.normalized();
+        let (small_sign, small_exp, small_mant) = small.normalized();
+        // 62 guard bits, leaving room for the carry
+        let big_mant = u128::from(big_mant) << 62;
+        let small_mant = shift_right_sticky(u128::from(small_mant) << 62, (big_exp - small_exp) as u32);
+        let exp = big_exp - 125;
+        if big_sign == small_sign {
+            Extended::round_from(big_sign, big_mant + small_mant, exp, env)
+        } else if big_mant == small_mant {
+            Extended::zero(env.mode == RoundingMode::Minus)
+        } else {
+            Extended::round_from(big_sign, big_mant - small_mant, exp, env)
+        }
+    }
+    pub fn sub(self, src: Extended, env: &mut Env) -> Extended {
+        if self.is_nan() || src.is_nan() {
+            return nan_operand(self, src, env);
+        }
+        self.add(src.negate(), env)
+    }
+    pub fn mul(self, src: Extended, env: &mut Env) -> Extended {
+        if self.is_nan() || src.is_nan() {
+            return nan_operand(self, src, env);
+        }
+        if (self.is_infinite() && src.is_zero()) || (self.is_zero() && src.is_infinite()) {
+            return operand_error(env);
+        }
+        let sign = self.sign() != src.sign();
+        if self.is_infinite() || src.is_infinite() {
+            return Extended::infinity(sign);
+        }
+        if self.is_zero() || src.is_zero() {
+            return Extended::zero(sign);
+        }
+        let (_, dst_exp, dst_mant) = self.normalized();
+        let (_, src_exp, src_mant) = src.normalized();
+        let product = u128::from(dst_mant) * u128::from(src_mant);
+        Extended::round_from(sign, product, dst_exp + src_exp - 126, env)
+    }
+    pub fn div(self, src: Extended, env: &mut Env) -> Extended {
+        if self.is_nan() || src.is_nan() {
+            return nan_operand(self, src, env);
+        }
+        if (self.is_zero() && src.is_zero()) || (self.is_infinite() && src.is_infinite()) {
+            return operand_error(env);
+        }
+        let sign = self.sign() != src.sign();
+        if self.is_infinite() {
+            return Extended::infinity(sign);
+        }
+        if src.is_infinite() || self.is_zero() {
+            return Extended::zero(sign);
+        }
+        if src.is_zero() {
+            env.raise(FPSR_DZ);
+            return Extended::infinity(sign);
+        }
+        let (_, dst_exp, dst_mant) = self.normalized();
+        let (_, src_exp, src_mant) = src.normalized();
+        let divisor = u128::from(src_mant);
+        // two 64 bit quotient digits, the first with its leading bit at
+        // bit 63 by pre-shifting the dividend by 63 or 64
+        let shift = if dst_mant >= src_mant { 63 } else { 64 };
+        let dividend = u128::from(dst_mant) << shift;
+        let high = dividend / divisor;
+        let rem = dividend % divisor;
+        let low = (rem << 64) / divisor;
+        let sticky = (rem << 64) % divisor != 0;
+        let quotient = high << 64 | low | sticky as u128;
+        Extended::round_from(sign, quotient, dst_exp - src_exp - shift - 64, env)
+    }
+    pub fn sqrt(self, env: &mut Env) -> Extended {
+        if self.is_nan() {
+            return nan_operand(self, self, env);
+        }
+        if self.is_zero() {
+            return self;
+        }
+        if self.sign() {
+            return operand_error(env);
+        }
+        if self.is_infinite() {
+            return self;
+        }
+        let (_, exp, mant) = self.normalized();
+        let mut exp = exp - 63;
+        let mut radicand = u128::from(mant);
+        if exp & 1 != 0 {
+            radicand <<= 1;
+            exp -= 1;
+        }
+        // bit by bit square root of radicand * 4^EXTRA, giving 67 bits
+        const EXTRA: u32 = 34;
+        let mut root: u128 = 0;
+        let mut rem: u128 = 0;
+        for pair in (0..33 + EXTRA).rev() {
+            let bits = if pair >= EXTRA { (radicand >> (2 * (pair - EXTRA))) & 3 } else { 0 };
+            rem = rem << 2 | bits;
+            let trial = root << 2 | 1;
+            root <<= 1;
+            if rem >= trial {
+                rem -= trial;
+                root |= 1;
+            }
+        }
+        Extended::round_from(false, root << 1 | (rem != 0) as u128, exp / 2 - EXTRA as i32 - 1, env)
+    }
+    // FMOD (quotient rounded towards zero) and FREM (rounded to nearest),
+    // also returning the quotient byte for the FPSR: the sign of the
+    // quotient and its seven least significant bits
+    pub fn rem(self, src: Extended, nearest: bool, env: &mut Env) -> (Extended, u8) {
+        if self.is_nan() || src.is_nan() {
+            return (nan_operand(self, src, env), 0);
+        }
+        if self.is_infinite() || src.is_zero() {
+            return (operand_error(env), 0);
+        }
+        let quotient_sign = ((self.sign() != src.sign()) as u8) << 7;
+        if src.is_infinite() || self.is_zero() {
+            return (self.rounded(env), quotient_sign);
+        }
+        let (sign, dst_exp, dst_mant) = self.normalized();
+        let (_, src_exp, src_mant) = src.normalized();
+        let diff = dst_exp - src_exp;
+        if diff < -1 {
+            // less than half the divisor
+            return (self.rounded(env), quotient_sign);
+        }
+        let mut quotient: u32 = 0;
+        let (mut rem, divisor, exp) = if diff >= 0 {
+            let divisor = u128::from(src_mant);
+            let mut rem = u128::from(dst_mant);
+            if rem >= divisor {
+                rem -= divisor;
+                quotient = 1;
+            }
+            for _ in 0..diff {
+                rem <<= 1;
+                quotient = quotient.wrapping_shl(1);
+                if rem >= divisor {
+                    rem -= divisor;
+                    quotient |= 1;
+                }
+            }
+            (rem, divisor, src_exp - 63)
+        } else {
+            (u128::from(dst_mant), u128::from(src_mant) << 1, dst_exp - 63)
+        };
+        let mut sign = sign;
+        if nearest {
+            let twice = rem << 1;
+            if twice > divisor || (twice == divisor && quotient & 1 == 1) {
+                rem = divisor - rem;
+                quotient = quotient.wrapping_add(1);
+                sign = !sign;
+            }
+        }
+        let result = if rem == 0 {
+            Extended::zero(self.sign())
+        } else {
+            Extended::round_from(sign, rem, exp, env)
+        };
+        (result, quotient_sign | (quotient & 0x7f) as u8)
+    }
+    // FINT and FINTRZ
+    pub fn round_to_integer(self, mode: RoundingMode, env: &mut Env) -> Extended {
+        if self.is_nan() {
+            return nan_operand(self, self, env);
+        }
+        if self.is_infinite() || self.is_zero() {
+            return self;
+        }
+        let (sign, exp, mant) = self.normalized();
+        if exp >= 63 {
+            return self.rounded(env);
+        }
+        let (value, inexact) = integer_part(sign, exp, mant, mode);
+        if inexact {
+            env.raise(FPSR_INEX2);
+        }
+        if value == 0 {
+            return Extended::zero(sign);
+        }
+        Extended::round_from(sign, value, 0, env)
+    }
+    pub fn get_exponent(self, env: &mut Env) -> Extended {
+        if self.is_nan() {
+            return nan_operand(self, self, env);
+        }
+        if self.is_infinite() {
+            return operand_error(env);
+        }
+        if self.is_zero() {
+            return self;
+        }
+        let (_, exp, _) = self.normalized();
+        Extended::from_i32(exp)
+    }
+    pub fn get_mantissa(self, env: &mut Env) -> Extended {
+        if self.is_nan() {
+            return nan_operand(self, self, env);
+        }
+        if self.is_infinite() {
+            return operand_error(env);
+        }
+        if self.is_zero() {
+            return self;
+        }
+        let (sign, _, mant) = self.normalized();
+        Extended::new(if sign { SIGN_BIT } else { 0 } | EXP_BIAS as u16, mant)
+    }
+    // FSCALE, by the integer part of src
+    pub fn scale(self, src: Extended, env: &mut Env) -> Extended {
+        if self.is_nan() || src.is_nan() {
+            return nan_operand(self, src, env);
+        }
+        if src.is_infinite() {
+            return operand_error(env);
+        }
+        if self.is_infinite() || self.is_zero() {
+            return self;
+        }
+        let factor = if src.is_zero() {
+            0
+        } else {
+            // anything beyond 2^17 over- or underflows anyway
+            let (src_sign, src_exp, src_mant) = src.normalized();
+            let factor = match src_exp {
+                exp if exp < 0 => 0,
+                exp if exp > 17 => 1 << 18,
+                exp => (src_mant >> (63 - exp)) as i32,
+            };
+            if src_sign { -factor } else { factor }
+        };
+        let (sign, exp, mant) = self.normalized();
+        Extended::round_from(sign, u128::from(mant), exp - 63 + factor, env)
+    }
+    // The ordering of FCMP, None for unordered (NaN) operands
+    pub fn compare(self, src: Extended) -> Option<Ordering> {
+        if self.is_nan() || src.is_nan() {
+            return None;
+        }
+        if self.is_zero() && src.is_zero() {
+            return Some(Ordering::Equal);
+        }
+        Some(match (self.sign(), src.sign()) {
+            (false, true) => Ordering::Greater,
+            (true, false) => Ordering::Less,
+            (false, false) => magnitude(self).cmp(&magnitude(src)),
+            (true, true) => magnitude(src).cmp(&magnitude(self)),
+        })
+    }
+
+    // Packed decimal real, as three longs: the signs of mantissa and
+    // exponent, a 3 digit BCD exponent (with a 4th digit on output), and
+    // a 17 digit BCD mantissa with one digit before the decimal point.
+    // Exponent digits of all ones mark infinities and NaNs.
+    pub fn from_packed(words: [u32; 3], env: &mut Env) -> Extended {
+        let sign = words[0] & 0x8000_0000 != 0;
+        let fraction = u64::from(words[1]) << 32 | u64::from(words[2]);
+        if (words[0] >> 16) & 0xfff == 0xfff {
+            let integer = words[0] & 0xf;
+            return match (integer, fraction) {
+                (0, 0) => Extended::infinity(sign),
+                (_, 0) => Extended::infinity(sign).with_mantissa(QUIET_BIT),
+                _ => Extended::infinity(sign).with_mantissa(fraction),
+            };
+        }
+        let mut mant = u128::from(words[0] & 0xf);
+        for digit in (0..16).rev() {
+            mant = mant * 10 + u128::from((fraction >> (4 * digit)) & 0xf);
+        }
+        let mut exp = bcd_to_binary((words[0] >> 16) & 0xfff) as i32;
+        if words[0] & 0x4000_0000 != 0 {
+            exp = -exp;
+        }
+        if mant == 0 {
+            return Extended::zero(sign);
+        }
+        Extended::round_from(sign, mant, 0, &mut Env::new(RoundingMode::Nearest, 64))
+            .scale10(exp - 16, env)
+    }
+    // k > 0 gives that many significant digits (at most 17), k <= 0 that
+    // many digits to the right of the decimal point
+    pub fn to_packed(self, k: i32, env: &mut Env) -> [u32; 3] {
+        let sign = if self.sign() { 0x8000_0000 } else { 0 };
+        if self.is_nan() || self.is_infinite() {
+            let mantissa = if self.is_nan() { self.mantissa } else { 0 };
+            return [sign | 0x7fff_0000, (mantissa >> 32) as u32, mantissa as u32];
+        }
+        if self.is_zero() {
+            return [sign, 0, 0];
+        }
+        let k = if k > 17 {
+            env.raise(FPSR_OPERR);
+            17
+        } else {
+            k
+        };
+        let (_, exp, mant) = self.normalized();
+        let log2 = f64::from(exp) + ((mant >> 11) as f64 / (1u64 << 52) as f64).log2();
+        let mut exp10 = (log2 * ::std::f64::consts::LOG10_2).floor() as i32;
+        let mut scratch = *env;
+        let mut value = 0;
+        // the estimate of the decimal exponent may be one off
+        for _ in 0..3 {
+            let digits = (if k > 0 { k } else { exp10 + 1 - k }).clamp(1, 17);
+            scratch = *env;
+            let scaled = self.abs().scale10(digits - 1 - exp10, &mut scratch);
+            let integer = scaled.round_to_integer(env.mode, &mut scratch);
+            value = integer.integer_value();
+            if value >= 10u64.pow(digits as u32) {
+                exp10 += 1;
+            } else if value < 10u64.pow(digits as u32 - 1) {
+                exp10 -= 1;
+            } else {
+                break;
+            }
+        }
+        env.raise(scratch.exceptions & FPSR_INEX2);
+        let mut bcd: u128 = 0;
+        for digit in value.to_string().bytes().chain(::std::iter::repeat(b'0')).take(17) {
+            bcd = bcd << 4 | u128::from(digit - b'0');
+        }
+        let exp_sign = if exp10 < 0 { 0x4000_0000 } else { 0 };
+        let exp_bcd = binary_to_bcd(exp10.unsigned_abs());
+        [sign | exp_sign | (exp_bcd & 0xfff) << 16 | (exp_bcd & 0xf000) | (bcd >> 64) as u32,
+         (bcd >> 32) as u32, bcd as u32]
+    }
+    // Multiplies by 10^exp10
+    fn scale10(self, exp10: i32, env: &mut Env) -> Extended {
+        if exp10.abs() > 4096 {
+            let half = exp10 / 2;
+            return self.scale10(half, env).scale10(exp10 - half, env);
+        }
+        let power = power_of_ten(exp10.unsigned_abs());
+        if exp10 < 0 {
+            self.div(power, env)
+        } else {
+            self.mul(power, env)
+        }
+    }
+    // the value of a non-negative integral extended below 2^64
+    fn integer_value(self) -> u64 {
+        if self.is_zero() {
+            return 0;
+        }
+        let (_, exp, mant) = self.normalized();
+        match exp {
+            exp if exp < 0 => 0,
+            exp if exp >= 63 => mant,
+            exp => mant >> (63 - exp),
+        }
+    }
+}
+
+// The integer part of a normalized value below 2^63 and whether that lost
+// any bits, rounding by the given mode
+fn integer_part(sign: bool, exp: i32, mant: u64, mode: RoundingMode) -> (u128, bool) {
+    let shift = if exp < -1 { (-1 - exp) as u32 } else { 0 };
+    let keep = if exp < 0 { 0 } else { (exp + 1) as u32 };
+    round_bits(shift_right_sticky(u128::from(mant) << 64, shift), keep, sign, mode)
+}
+
+// Orders finite and infinite values by magnitude
+fn magnitude(value: Extended) -> (u8, i32, u64) {
+    if value.is_zero() {
+        (0, 0, 0)
+    } else if value.is_infinite() {
+        (2, 0, 0)
+    } else {
+        let (_, exp, mant) = value.normalized();
+        (1, exp, mant)
+    }
+}
+
+// The destination NaN wins if both operands are NaNs. A signaling NaN is
+// quieted, and raises SNAN.
+fn nan_operand(dst: Extended, src: Extended, env: &mut Env) -> Extended {
+    if dst.is_signaling_nan() || src.is_signaling_nan() {
+        env.raise(FPSR_SNAN);
+    }
+    if dst.is_nan() { dst.quiet() } else { src.quiet() }
+}
+
+fn operand_error(env: &mut Env) -> Extended {
+    env.raise(FPSR_OPERR);
+    Extended::default_nan()
+}
+
+// 10^n, exact up to 10^27, and within an ulp or so beyond that
+fn power_of_ten(n: u32) -> Extended {
+    let mut env = Env::new(RoundingMode::Nearest, 64);
+    let mut result = Extended::from_i32(1);
+    let mut square = Extended::from_i32(10);
+    let mut n = n;
+    while n > 0 {
+        if n & 1 == 1 {
+            result = result.mul(square, &mut env);
+        }
+        n >>= 1;
+        if n > 0 {
+            square = square.mul(square, &mut env);
+        }
+    }
+    result
+}
+
+fn bcd_to_binary(bcd: u32) -> u32 {
+    (bcd >> 8) * 100 + ((bcd >> 4) & 0xf) * 10 + (bcd & 0xf)
+}
+
+fn binary_to_bcd(value: u32) -> u32 {
+    let mut bcd = 0;
+    for shift in 0..4 {
+        bcd |= (value / 10u32.pow(shift) % 10) << (4 * shift);
+    }
+    bcd
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cmp::Ordering;
+    use super::{Extended, Env, RoundingMode};
+    use super::super::{FPSR_SNAN, FPSR_OPERR, FPSR_OVFL, FPSR_UNFL, FPSR_DZ, FPSR_INEX2};
+
+    fn nearest() -> Env {
+        Env::new(RoundingMode::Nearest, 64)
+    }
+    fn ext(value: f64) -> Extended {
+        Extended::from_f64(value)
+    }
+
+    #[test]
+    fn converts_doubles_exactly() {
+        assert_eq!(Extended::new(0x3fff, 0x8000_0000_0000_0000), ext(1.0));
+        assert_eq!(Extended::new(0xc000, 0xc000_0000_0000_0000), ext(-3.0));
+        assert_eq!(Extended::new(0x3fff - 1074, 0x8000_0000_0000_0000), Extended::from_f64_bits(1));
+        for &value in &[0.1, -2.5e-300, 1.7976931348623157e308, 123456.789] {
+            assert_eq!(value, ext(value).to_f64());
+        }
+        assert!(Extended::from_f64_bits(0x7ff0_0000_0000_0001).is_signaling_nan());
+        assert!(ext(::std::f64::NEG_INFINITY).is_infinite());
+    }
+
+    #[test]
+    fn converts_singles() {
+        assert_eq!(ext(1.5), Extended::from_f32_bits(1.5f32.to_bits()));
+        let mut env = nearest();
+        assert_eq!(0.1f32.to_bits(), ext(0.1).to_f32_bits(&mut env));
+        assert_eq!(FPSR_INEX2, env.exceptions);
+        let mut overflow = nearest();
+        assert_eq!(0x7f80_0000, ext(1e300).to_f32_bits(&mut overflow));
+        assert_eq!(FPSR_OVFL | FPSR_INEX2, overflow.exceptions);
+    }
+
+    #[test]
+    fn stores_single_denormals() {
+        let mut env = nearest();
+        assert_eq!(1, ext(1.0e-45).to_f32_bits(&mut env));
+        assert_eq!(FPSR_UNFL | FPSR_INEX2, env.exceptions);
+    }
+
+    #[test]
+    fn overflow_depends_on_rounding_mode() {
+        let mut env = Env::new(RoundingMode::Zero, 64);
+        assert_eq!(0x7f7f_ffff, ext(1e300).to_f32_bits(&mut env));
+        let mut env = Env::new(RoundingMode::Minus, 64);
+        assert_eq!(0xff80_0000, ext(-1e300).to_f32_bits(&mut env));
+    }
+
+    #[test]
+    fn converts_to_integers() {
+        let mut env = nearest();
+        assert_eq!(2, ext(2.5).to_int(32, &mut env));
+        assert_eq!(4, ext(3.5).to_int(32, &mut env));
+        assert_eq!(-3, ext(-2.7).to_int(8, &mut env));
+        assert_eq!(FPSR_INEX2, env.exceptions);
+        let mut env = Env::new(RoundingMode::Zero, 64);
+        assert_eq!(-2, ext(-2.7).to_int(16, &mut env));
+        let mut env = Env::new(RoundingMode::Plus, 64);
+        assert_eq!(1, ext(0.001).to_int(16, &mut env));
+    }
+
+    #[test]
+    fn integer_overflow_is_an_operand_error() {
+        let mut env = nearest();
+        assert_eq!(127, ext(300.0).to_int(8, &mut env));
+        assert_eq!(-32768, ext(-1e10).to_int(16, &mut env));
+        assert_eq!(-128, ext(-128.0).to_int(8, &mut env));
+        assert_eq!(FPSR_OPERR, env.exceptions);
+    }
+
+    #[test]
+    fn adds_with_extended_precision() {
+        let mut env = nearest();
+        // 1 + 2^-63 is representable in extended, but not in double
+        let tiny = Extended::new(0x3fff - 63, 0x8000_0000_0000_0000);
+        let sum = ext(1.0).add(tiny, &mut env);
+        assert_eq!(Extended::new(0x3fff, 0x8000_0000_0000_0001), sum);
+        assert_eq!(0, env.exceptions);
+        assert_eq!(ext(1.0), sum.sub(tiny, &mut env));
+    }
+
+    #[test]
+    fn rounds_to_nearest_even() {
+        let mut env = nearest();
+        let half_ulp = Extended::new(0x3fff - 64, 0x8000_0000_0000_0000);
+        assert_eq!(ext(1.0), ext(1.0).add(half_ulp, &mut env));
+        assert_eq!(FPSR_INEX2, env.exceptions);
+        let odd = Extended::new(0x3fff, 0x8000_0000_0000_0001);
+        assert_eq!(Extended::new(0x3fff, 0x8000_0000_0000_0002), odd.add(half_ulp, &mut env));
+    }
+
+    #[test]
+    fn rounds_to_precision() {
+        let mut env = Env::new(RoundingMode::Nearest, 24);
+        assert_eq!(ext(f64::from(0.1f32)), ext(0.1).rounded(&mut env));
+        let mut env = Env::new(RoundingMode::Nearest, 53);
+        assert_eq!(ext(1.0 / 3.0), ext(1.0).div(ext(3.0), &mut env));
+    }
+
+    #[test]
+    fn subtracting_equal_values_gives_positive_zero() {
+        let mut env = nearest();
+        assert_eq!(Extended::zero(false), ext(2.0).sub(ext(2.0), &mut env));
+        let mut env = Env::new(RoundingMode::Minus, 64);
+        assert_eq!(Extended::zero(true), ext(2.0).sub(ext(2.0), &mut env));
+    }
+
+    #[test]
+    fn infinities_and_zeros() {
+        let mut env = nearest();
+        let inf = Extended::infinity(false);
+        assert_eq!(inf, inf.add(ext(1.0), &mut env));
+        assert_eq!(Extended::zero(true), ext(-1.0).div(inf, &mut env));
+        assert_eq!(0, env.exceptions);
+        assert!(inf.sub(inf, &mut env).is_nan());
+        assert_eq!(FPSR_OPERR, env.exceptions);
+        let mut env = nearest();
+        assert!(inf.mul(Extended::zero(false), &mut env).is_nan());
+        assert_eq!(FPSR_OPERR, env.exceptions);
+    }
+
+    #[test]
+    fn divides_by_zero() {
+        let mut env = nearest();
+        assert_eq!(Extended::infinity(true), ext(-1.0).div(Extended::zero(false), &mut env));
+        assert_eq!(FPSR_DZ, env.exceptions);
+    }
+
+    #[test]
+    fn multiplies_and_divides() {
+        let mut env = nearest();
+        assert_eq!(ext(-7.5), ext(2.5).mul(ext(-3.0), &mut env));
+        assert_eq!(ext(0.75), ext(3.0).div(ext(4.0), &mut env));
+        assert_eq!(0, env.exceptions);
+        let third = ext(1.0).div(ext(3.0), &mut env);
+        assert_eq!(Extended::new(0x3ffd, 0xaaaa_aaaa_aaaa_aaab), third);
+        assert_eq!(FPSR_INEX2, env.exceptions);
+    }
+
+    #[test]
+    fn takes_square_roots() {
+        let mut env = nearest();
+        assert_eq!(ext(12.0), ext(144.0).sqrt(&mut env));
+        assert_eq!(ext(0.5), ext(0.25).sqrt(&mut env));
+        assert_eq!(0, env.exceptions);
+        // sqrt(2) correctly rounded to 64 bits
+        assert_eq!(Extended::new(0x3fff, 0xb504_f333_f9de_6484), ext(2.0).sqrt(&mut env));
+        assert!(ext(-1.0).sqrt(&mut env).is_nan());
+        assert_eq!(FPSR_INEX2 | FPSR_OPERR, env.exceptions);
+    }
+
+    #[test]
+    fn remainders_and_quotients() {
+        let mut env = nearest();
+        assert_eq!((ext(1.0), 3), ext(7.0).rem(ext(2.0), false, &mut env));
+        assert_eq!((ext(-1.0), 4), ext(7.0).rem(ext(2.0), true, &mut env));
+        assert_eq!((ext(-1.5), 0x80 | 1), ext(-5.5).rem(ext(4.0), false, &mut env));
+        assert_eq!((ext(1.5), 0x80 | 2), ext(-6.5).rem(ext(4.0), true, &mut env));
+        assert_eq!((ext(0.25), 0), ext(0.25).rem(ext(1.0), true, &mut env));
+        assert_eq!((ext(-0.25), 1), ext(0.75).rem(ext(1.0), true, &mut env));
+        assert_eq!(0, env.exceptions);
+    }
+
+    #[test]
+    fn rounds_to_integers() {
+        let mut env = nearest();
+        assert_eq!(ext(2.0), ext(2.5).round_to_integer(RoundingMode::Nearest, &mut env));
+        assert_eq!(ext(-3.0), ext(-2.5).round_to_integer(RoundingMode::Minus, &mut env));
+        assert_eq!(ext(2.0), ext(2.9).round_to_integer(RoundingMode::Zero, &mut env));
+        assert_eq!(Extended::zero(true), ext(-0.3).round_to_integer(RoundingMode::Nearest, &mut env));
+        assert_eq!(ext(1.0), ext(0.0001).round_to_integer(RoundingMode::Plus, &mut env));
+        assert_eq!(FPSR_INEX2, env.exceptions);
+    }
+
+    #[test]
+    fn splits_exponent_and_mantissa() {
+        let mut env = nearest();
+        assert_eq!(ext(3.0), ext(12.0).get_exponent(&mut env));
+        assert_eq!(ext(-4.0), ext(0.1).get_exponent(&mut env));
+        assert_eq!(ext(-1.5), ext(-12.0).get_mantissa(&mut env));
+        assert_eq!(ext(-24.0), ext(-1.5).scale(ext(4.9), &mut env));
+        assert_eq!(0, env.exceptions);
+    }
+
+    #[test]
+    fn compares_values() {
+        assert_eq!(Some(Ordering::Less), ext(-2.0).compare(ext(1.0)));
+        assert_eq!(Some(Ordering::Less), ext(-2.0).compare(ext(-1.0)));
+        assert_eq!(Some(Ordering::Greater), Extended::infinity(false).compare(ext(1e300)));
+        assert_eq!(Some(Ordering::Equal), Extended::zero(true).compare(Extended::zero(false)));
+        assert_eq!(None, Extended::default_nan().compare(ext(1.0)));
+    }
+
+    #[test]
+    fn propagates_nans() {
+        let mut env = nearest();
+        let snan = Extended::new(0x7fff, 0x8000_0000_0000_0001);
+        let quieted = Extended::new(0x7fff, 0xc000_0000_0000_0001);
+        assert_eq!(quieted, ext(1.0).add(snan, &mut env));
+        assert_eq!(FPSR_SNAN, env.exceptions);
+        let qnan = Extended::new(0xffff, 0xc000_0000_0000_0000);
+        assert_eq!(qnan, qnan.mul(quieted, &mut env));
+    }
+
+    #[test]
+    fn reads_packed_decimals() {
+        let mut env = nearest();
+        // +1.5E+1
+        assert_eq!(ext(15.0), Extended::from_packed([0x0001_0001, 0x5000_0000, 0], &mut env));
+        // -1.25E-2
+        assert_eq!(ext(-125.0).div(ext(10000.0), &mut env), Extended::from_packed([0xc002_0001, 0x2500_0000, 0], &mut env));
+        assert!(Extended::from_packed([0x7fff_0000, 0, 0], &mut env).is_infinite());
+    }
+
+    #[test]
+    fn writes_packed_decimals() {
+        let mut env = nearest();
+        assert_eq!([0x0001_0001, 0x5000_0000, 0], ext(15.0).to_packed(17, &mut env));
+        assert_eq!([0xc002_0001, 0x2500_0000, 0], ext(-0.0125).to_packed(3, &mut env));
+        assert_eq!([0x0000_0003, 0x1415_9265, 0x3589_7931], ext(::std::f64::consts::PI).to_packed(17, &mut env));
+        // two digits right of the decimal point
+        assert_eq!([0x0002_0001, 0x2346_0000, 0], ext(123.456).to_packed(-2, &mut env));
+        assert_eq!([0x8000_0000, 0, 0], Extended::zero(true).to_packed(17, &mut env));
+    }
+
+    #[test]
+    fn packed_decimals_roundtrip() {
+        let mut env = nearest();
+        for &value in &[1.0, -3.0e-200, 6.02214076e23, 1.0e300] {
+            let value = ext(value);
+            assert_eq!(value.to_f64(), Extended::from_packed(value.to_packed(17, &mut env), &mut env).to_f64());
+        }
+    }
+}
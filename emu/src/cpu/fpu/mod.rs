@@ -0,0 +1,599 @@
+// A 68881/68882 floating-point coprocessor. The instructions reach it
+// through line F (coprocessor id 1), see cpu::ops::fpu, and it keeps the
+// FP registers, performs the arithmetic and evaluates the FP conditions.
+//
+// The basic arithmetic is done in extended precision with IEEE rounding,
+// the transcendental functions are computed in double precision. Timing
+// is the register to register execution time of the 68881, ignoring the
+// overlap of FPU and CPU execution.
+use std::cmp::Ordering;
+use super::{EXCEPTION_FP_UNORDERED_CONDITION, EXCEPTION_FP_INEXACT, EXCEPTION_FP_DIVIDE_BY_ZERO,
+    EXCEPTION_FP_UNDERFLOW, EXCEPTION_FP_OPERAND_ERROR, EXCEPTION_FP_OVERFLOW, EXCEPTION_FP_SIGNALING_NAN};
+pub use self::extended::{Extended, Env, RoundingMode};
+
+mod extended;
+
+// FPSR exception byte, also used for the exception enable byte of FPCR
+pub const FPSR_BSUN: u32  = 0x8000;
+pub const FPSR_SNAN: u32  = 0x4000;
+pub const FPSR_OPERR: u32 = 0x2000;
+pub const FPSR_OVFL: u32  = 0x1000;
+pub const FPSR_UNFL: u32  = 0x0800;
+pub const FPSR_DZ: u32    = 0x0400;
+pub const FPSR_INEX2: u32 = 0x0200;
+pub const FPSR_INEX1: u32 = 0x0100;
+const FPSR_EXCEPTIONS: u32 = 0xff00;
+
+// FPSR accrued exception byte
+pub const FPSR_AEXC_IOP: u32  = 0x80;
+pub const FPSR_AEXC_OVFL: u32 = 0x40;
+pub const FPSR_AEXC_UNFL: u32 = 0x20;
+pub const FPSR_AEXC_DZ: u32   = 0x10;
+pub const FPSR_AEXC_INEX: u32 = 0x08;
+
+// FPSR condition code byte
+pub const FPSR_CC_N: u32   = 0x0800_0000;
+pub const FPSR_CC_Z: u32   = 0x0400_0000;
+pub const FPSR_CC_I: u32   = 0x0200_0000;
+pub const FPSR_CC_NAN: u32 = 0x0100_0000;
+const FPSR_CC: u32 = 0x0f00_0000;
+const FPSR_QUOTIENT: u32 = 0x00ff_0000;
+
+const FPCR_MASK: u32 = 0x0000_fff0;
+const FPSR_MASK: u32 = 0x0fff_fff8;
+
+// FSAVE frame format word: version number and size of the internal state
+const FRAME_VERSION: u32 = 0x1f;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FpuModel {
+    M68881,
+    M68882,
+}
+
+impl FpuModel {
+    fn idle_frame_size(self) -> u32 {
+        match self {
+            FpuModel::M68881 => 0x18,
+            FpuModel::M68882 => 0x38,
+        }
+    }
+    fn busy_frame_size(self) -> u32 {
+        match self {
+            FpuModel::M68881 => 0xb4,
+            FpuModel::M68882 => 0xd4,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Fpu {
+    pub model: FpuModel,
+    pub fp: [Extended; 8],
+    pub fpcr: u32,
+    pub fpsr: u32,
+    pub fpiar: u32,
+    pub null_state: bool, // reset, and not used since; FSAVE stores a null frame
+}
+
+impl Fpu {
+    pub fn new(model: FpuModel) -> Fpu {
+        let mut fpu = Fpu {
+            model, fp: [Extended::default_nan(); 8], fpcr: 0, fpsr: 0, fpiar: 0, null_state: true
+        };
+        fpu.reset();
+        fpu
+    }
+    pub fn reset(&mut self) {
+        self.fp = [Extended::default_nan(); 8];
+        self.fpcr = 0;
+        self.fpsr = 0;
+        self.fpiar = 0;
+        self.null_state = true;
+    }
+    pub fn set_fpcr(&mut self, value: u32) {
+        self.fpcr = value & FPCR_MASK;
+    }
+    pub fn set_fpsr(&mut self, value: u32) {
+        self.fpsr = value & FPSR_MASK;
+    }
+    // The rounding mode and precision of the FPCR
+    pub fn env(&self) -> Env {
+        let mode = match (self.fpcr >> 4) & 3 {
+            0 => RoundingMode::Nearest,
+            1 => RoundingMode::Zero,
+            2 => RoundingMode::Minus,
+            _ => RoundingMode::Plus,
+        };
+        let precision = match (self.fpcr >> 6) & 3 {
+            1 => 24,
+            2 => 53,
+            _ => 64,
+        };
+        Env::new(mode, precision)
+    }
+    // Arithmetic instructions, including FMOVE, start out with a clear
+    // exception byte, and leave their address in FPIAR
+    pub fn begin(&mut self, address: u32) {
+        self.fpsr &= !FPSR_EXCEPTIONS;
+        self.fpiar = address;
+        self.null_state = false;
+    }
+    pub fn raise(&mut self, exceptions: u32) {
+        let exceptions = exceptions & FPSR_EXCEPTIONS;
+        let mut accrued = 0;
+        if exceptions & (FPSR_BSUN | FPSR_SNAN | FPSR_OPERR) != 0 {
+            accrued |= FPSR_AEXC_IOP;
+        }
+        if exceptions & FPSR_OVFL != 0 {
+            accrued |= FPSR_AEXC_OVFL;
+        }
+        if exceptions & FPSR_UNFL != 0 && exceptions & FPSR_INEX2 != 0 {
+            accrued |= FPSR_AEXC_UNFL;
+        }
+        if exceptions & FPSR_DZ != 0 {
+            accrued |= FPSR_AEXC_DZ;
+        }
+        if exceptions & (FPSR_INEX1 | FPSR_INEX2 | FPSR_OVFL) != 0 {
+            accrued |= FPSR_AEXC_INEX;
+        }
+        self.fpsr |= exceptions | accrued;
+    }
+    fn set_condition_codes(&mut self, cc: u32) {
+        self.fpsr = (self.fpsr & !FPSR_CC) | cc;
+    }
+    // The vector of the highest priority exception that is both raised
+    // and enabled
+    pub fn pending_exception(&self) -> Option<u8> {
+        let enabled = self.fpsr & self.fpcr & FPSR_EXCEPTIONS;
+        [(FPSR_BSUN, EXCEPTION_FP_UNORDERED_CONDITION),
+         (FPSR_SNAN, EXCEPTION_FP_SIGNALING_NAN),
+         (FPSR_OPERR, EXCEPTION_FP_OPERAND_ERROR),
+         (FPSR_OVFL, EXCEPTION_FP_OVERFLOW),
+         (FPSR_UNFL, EXCEPTION_FP_UNDERFLOW),
+         (FPSR_DZ, EXCEPTION_FP_DIVIDE_BY_ZERO),
+         (FPSR_INEX2 | FPSR_INEX1, EXCEPTION_FP_INEXACT)].iter()
+            .find(|&&(bits, _)| enabled & bits != 0)
+            .map(|&(_, vector)| vector)
+    }
+    // Performs the operation selected by the opmode of a general FPU
+    // instruction on the source operand and FPn, with the exceptions of
+    // the source conversion already in env. Returns the execution time,
+    // or None for undefined opmodes.
+    pub fn arithmetic(&mut self, opmode: u16, src: Extended, dst: usize, env: Env) -> Option<i32> {
+        let cycles = execution_cycles(opmode)?;
+        let mut env = env;
+        let mode = env.mode;
+        let dst_value = self.fp[dst];
+        let result = match opmode {
+            0x00 => src.rounded(&mut env),
+            0x01 => src.round_to_integer(mode, &mut env),
+            0x02 => transcendental(src, f64::sinh, false, &mut env),
+            0x03 => src.round_to_integer(RoundingMode::Zero, &mut env),
+            0x04 => src.sqrt(&mut env),
+            0x06 => transcendental(src, f64::ln_1p, true, &mut env),
+            0x08 => transcendental(src, f64::exp_m1, false, &mut env),
+            0x09 => transcendental(src, f64::tanh, false, &mut env),
+            0x0a => transcendental(src, f64::atan, false, &mut env),
+            0x0c => transcendental(src, f64::asin, false, &mut env),
+            0x0d => transcendental(src, f64::atanh, true, &mut env),
+            0x0e => transcendental(src, f64::sin, false, &mut env),
+            0x0f => transcendental(src, f64::tan, false, &mut env),
+            0x10 => transcendental(src, f64::exp, false, &mut env),
+            0x11 => transcendental(src, f64::exp2, false, &mut env),
+            0x12 => transcendental(src, |x| 10f64.powf(x), false, &mut env),
+            0x14 => transcendental(src, f64::ln, true, &mut env),
+            0x15 => transcendental(src, f64::log10, true, &mut env),
+            0x16 => transcendental(src, f64::log2, true, &mut env),
+            0x18 => src.abs().rounded(&mut env),
+            0x19 => transcendental(src, f64::cosh, false, &mut env),
+            0x1a => src.negate().rounded(&mut env),
+            0x1c => transcendental(src, f64::acos, false, &mut env),
+            0x1d => transcendental(src, f64::cos, false, &mut env),
+            0x1e => src.get_exponent(&mut env),
+            0x1f => src.get_mantissa(&mut env),
+            0x20 => dst_value.div(src, &mut env),
+            0x21 | 0x25 => {
+                let (result, quotient) = dst_value.rem(src, opmode == 0x25, &mut env);
+                self.fpsr = (self.fpsr & !FPSR_QUOTIENT) | u32::from(quotient) << 16;
+                result
+            }
+            0x22 => dst_value.add(src, &mut env),
+            0x23 => dst_value.mul(src, &mut env),
+            0x24 | 0x27 => {
+                // the single precision operations only use the upper 24
+                // bits of the mantissas, and round to single precision
+                let mut single = Env::new(mode, 24);
+                let (dst_value, src) = (dst_value.truncated(24), src.truncated(24));
+                let result = if opmode == 0x24 { dst_value.div(src, &mut single) } else { dst_value.mul(src, &mut single) };
+                env.exceptions |= single.exceptions;
+                result
+            }
+            0x26 => dst_value.scale(src, &mut env),
+            0x28 => dst_value.sub(src, &mut env),
+            0x30..=0x37 => {
+                self.fp[usize::from(opmode & 7)] = transcendental(src, f64::cos, false, &mut env);
+                transcendental(src, f64::sin, false, &mut env)
+            }
+            0x38 => {
+                // FCMP sets the condition codes of FPn - source only
+                if dst_value.is_signaling_nan() || src.is_signaling_nan() {
+                    env.exceptions |= FPSR_SNAN;
+                }
+                let cc = match dst_value.compare(src) {
+                    None => FPSR_CC_NAN,
+                    Some(Ordering::Less) => FPSR_CC_N,
+                    Some(Ordering::Equal) if dst_value.sign() => FPSR_CC_N | FPSR_CC_Z,
+                    Some(Ordering::Equal) => FPSR_CC_Z,
+                    Some(Ordering::Greater) => 0,
+                };
+                self.raise(env.exceptions);
+                self.set_condition_codes(cc);
+                return Some(cycles);
+            }
+            _ => {
+                // FTST
+                if src.is_signaling_nan() {
+                    env.exceptions |= FPSR_SNAN;
+                }
+                self.raise(env.exceptions);
+                self.set_condition_codes(condition_codes(src));
+                return Some(cycles);
+            }
+        };
+        self.raise(env.exceptions);
+        self.set_condition_codes(condition_codes(result));
+        self.fp[dst] = result;
+        Some(cycles)
+    }
+    // Evaluates one of the 32 conditional predicates of FBcc, FScc,
+    // FDBcc and FTRAPcc. The upper 16 are not IEEE-aware, and raise BSUN
+    // when tested for an unordered result. Otherwise BSUN is cleared, and
+    // the rest of the exception byte is left alone.
+    pub fn condition(&mut self, predicate: u16) -> Option<bool> {
+        if predicate > 0x1f {
+            return None;
+        }
+        self.null_state = false;
+        self.fpsr &= !FPSR_BSUN;
+        let n = self.fpsr & FPSR_CC_N != 0;
+        let z = self.fpsr & FPSR_CC_Z != 0;
+        let nan = self.fpsr & FPSR_CC_NAN != 0;
+        if predicate & 0x10 != 0 && nan {
+            self.raise(FPSR_BSUN);
+        }
+        Some(match predicate & 0xf {
+            0x0 => false,                   // F, SF
+            0x1 => z,                       // EQ, SEQ
+            0x2 => !(nan || z || n),        // OGT, GT
+            0x3 => z || !(nan || n),        // OGE, GE
+            0x4 => n && !(nan || z),        // OLT, LT
+            0x5 => z || (n && !nan),        // OLE, LE
+            0x6 => !(nan || z),             // OGL, GL
+            0x7 => !nan,                    // OR, GLE
+            0x8 => nan,                     // UN, NGLE
+            0x9 => nan || z,                // UEQ, NGL
+            0xa => nan || !(n || z),        // UGT, NLE
+            0xb => nan || z || !n,          // UGE, NLT
+            0xc => nan || (n && !z),        // ULT, NGE
+            0xd => nan || z || n,           // ULE, NGT
+            0xe => !z,                      // NE, SNE
+            _ => true,                      // T, ST
+        })
+    }
+    // A branch on an unordered condition traps before branching, if BSUN
+    // is enabled
+    pub fn unordered_condition_exception(&self) -> Option<u8> {
+        if self.fpsr & self.fpcr & FPSR_BSUN != 0 {
+            Some(EXCEPTION_FP_UNORDERED_CONDITION)
+        } else {
+            None
+        }
+    }
+    // The format word of an FSAVE frame, and the number of bytes of
+    // internal state following it. A reset FPU saves a null frame, and
+    // otherwise an idle frame, as instructions always complete here.
+    pub fn save_frame(&self) -> (u32, u32) {
+        if self.null_state {
+            (0, 0)
+        } else {
+            let size = self.model.idle_frame_size();
+            (FRAME_VERSION << 24 | size << 16, size)
+        }
+    }
+    // Restores from the format word of an FSAVE frame, returning the size
+    // of the internal state following it, or None for an invalid frame.
+    // A null frame resets the FPU.
+    pub fn restore_frame(&mut self, format: u32) -> Option<u32> {
+        let version = format >> 24;
+        let size = (format >> 16) & 0xff;
+        if version == 0 {
+            self.reset();
+            return Some(0);
+        }
+        if version == FRAME_VERSION && (size == self.model.idle_frame_size() || size == self.model.busy_frame_size()) {
+            self.null_state = false;
+            Some(size)
+        } else {
+            None
+        }
+    }
+}
+
+fn condition_codes(value: Extended) -> u32 {
+    let mut cc = 0;
+    if value.sign() {
+        cc |= FPSR_CC_N;
+    }
+    if value.is_zero() {
+        cc |= FPSR_CC_Z;
+    }
+    if value.is_infinite() {
+        cc |= FPSR_CC_I;
+    }
+    if value.is_nan() {
+        cc |= FPSR_CC_NAN;
+    }
+    cc
+}
+
+// The transcendental functions are computed in double precision. Infinite
+// results of finite operands are divisions by zero for the poles of the
+// logarithms and FATANH, and overflows otherwise.
+fn transcendental(src: Extended, function: fn(f64) -> f64, pole: bool, env: &mut Env) -> Extended {
+    if src.is_nan() {
+        return src.rounded(env);
+    }
+    let result = function(src.to_f64());
+    if result.is_nan() {
+        env.exceptions |= FPSR_OPERR;
+        return Extended::default_nan();
+    }
+    if result.is_infinite() && !src.is_infinite() {
+        env.exceptions |= if pole { FPSR_DZ } else { FPSR_OVFL | FPSR_INEX2 };
+    } else if result.is_finite() && result != 0.0 {
+        env.exceptions |= FPSR_INEX2;
+    }
+    Extended::from_f64(result).rounded(env)
+}
+
+// Register to register execution times of the MC68881
+fn execution_cycles(opmode: u16) -> Option<i32> {
+    Some(match opmode {
+        0x00 => 33,         // FMOVE
+        0x01 | 0x03 => 55,  // FINT, FINTRZ
+        0x02 => 687,        // FSINH
+        0x04 => 107,        // FSQRT
+        0x06 => 569,        // FLOGNP1
+        0x08 => 589,        // FETOXM1
+        0x09 => 661,        // FTANH
+        0x0a => 403,        // FATAN
+        0x0c => 581,        // FASIN
+        0x0d => 693,        // FATANH
+        0x0e | 0x1d => 391, // FSIN, FCOS
+        0x0f => 473,        // FTAN
+        0x10 => 497,        // FETOX
+        0x11 | 0x12 => 567, // FTWOTOX, FTENTOX
+        0x14 => 525,        // FLOGN
+        0x15 | 0x16 => 581, // FLOG10, FLOG2
+        0x18 | 0x1a => 35,  // FABS, FNEG
+        0x19 => 607,        // FCOSH
+        0x1c => 625,        // FACOS
+        0x1e => 45,         // FGETEXP
+        0x1f => 31,         // FGETMAN
+        0x20 => 103,        // FDIV
+        0x21 => 70,         // FMOD
+        0x22 | 0x28 => 51,  // FADD, FSUB
+        0x23 => 71,         // FMUL
+        0x24 => 69,         // FSGLDIV
+        0x25 => 100,        // FREM
+        0x26 => 41,         // FSCALE
+        0x27 => 59,         // FSGLMUL
+        0x30..=0x37 => 451, // FSINCOS
+        0x38 | 0x3a => 33,  // FCMP, FTST
+        _ => return None,
+    })
+}
+
+// The on-chip constant ROM of FMOVECR, rounded to nearest. Undefined
+// offsets read as zero.
+pub fn rom_constant(offset: u16) -> Extended {
+    let (sign_exp, mantissa) = match offset {
+        0x00 => (0x4000, 0xc90f_daa2_2168_c235), // pi
+        0x0b => (0x3ffd, 0x9a20_9a84_fbcf_f799), // log10(2)
+        0x0c => (0x4000, 0xadf8_5458_a2bb_4a9b), // e
+        0x0d => (0x3fff, 0xb8aa_3b29_5c17_f0bc), // log2(e)
+        0x0e => (0x3ffd, 0xde5b_d8a9_3728_7195), // log10(e)
+        0x30 => (0x3ffe, 0xb172_17f7_d1cf_79ac), // ln(2)
+        0x31 => (0x4000, 0x935d_8ddd_aaa8_ac17), // ln(10)
+        0x32 => (0x3fff, 0x8000_0000_0000_0000), // 10^0
+        0x33 => (0x4002, 0xa000_0000_0000_0000), // 10^1
+        0x34 => (0x4005, 0xc800_0000_0000_0000), // 10^2
+        0x35 => (0x400c, 0x9c40_0000_0000_0000), // 10^4
+        0x36 => (0x4019, 0xbebc_2000_0000_0000), // 10^8
+        0x37 => (0x4034, 0x8e1b_c9bf_0400_0000), // 10^16
+        0x38 => (0x4069, 0x9dc5_ada8_2b70_b59e), // 10^32
+        0x39 => (0x40d3, 0xc278_1f49_ffcf_a6d5), // 10^64
+        0x3a => (0x41a8, 0x93ba_47c9_80e9_8ce0), // 10^128
+        0x3b => (0x4351, 0xaa7e_ebfb_9df9_de8e), // 10^256
+        0x3c => (0x46a3, 0xe319_a0ae_a60e_91c7), // 10^512
+        0x3d => (0x4d48, 0xc976_7586_8175_0c17), // 10^1024
+        0x3e => (0x5a92, 0x9e8b_3b5d_c53d_5de5), // 10^2048
+        0x3f => (0x7525, 0xc460_5202_8a20_979b), // 10^4096
+        _ => (0, 0),                             // includes 0.0 at 0x0f
+    };
+    Extended::new(sign_exp, mantissa)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ext(value: f64) -> Extended {
+        Extended::from_f64(value)
+    }
+    fn fpu() -> Fpu {
+        Fpu::new(FpuModel::M68881)
+    }
+
+    #[test]
+    fn resets_to_nans() {
+        let fpu = fpu();
+        assert!(fpu.fp.iter().all(|fp| fp.is_nan()));
+        assert!(fpu.null_state);
+        assert_eq!((0, 0), fpu.save_frame());
+    }
+
+    #[test]
+    fn arithmetic_sets_condition_codes() {
+        let mut fpu = fpu();
+        let env = fpu.env();
+        fpu.fp[1] = ext(2.0);
+        fpu.arithmetic(0x28, ext(5.0), 1, env);
+        assert_eq!(ext(-3.0), fpu.fp[1]);
+        assert_eq!(FPSR_CC_N, fpu.fpsr);
+        fpu.arithmetic(0x23, ext(0.0), 1, env);
+        assert_eq!(FPSR_CC_N | FPSR_CC_Z, fpu.fpsr);
+        fpu.arithmetic(0x20, ext(0.0), 1, env);
+        assert_eq!(FPSR_CC_NAN | FPSR_OPERR | FPSR_AEXC_IOP, fpu.fpsr);
+    }
+
+    #[test]
+    fn accrues_exceptions() {
+        let mut fpu = fpu();
+        let env = fpu.env();
+        fpu.fp[0] = ext(1.0);
+        fpu.arithmetic(0x20, ext(0.0), 0, env);
+        assert_eq!(FPSR_CC_I | FPSR_DZ | FPSR_AEXC_DZ, fpu.fpsr);
+        fpu.begin(0x100);
+        fpu.arithmetic(0x20, ext(3.0), 0, env);
+        assert_eq!(FPSR_CC_I | FPSR_AEXC_DZ, fpu.fpsr);
+        fpu.begin(0x104);
+        fpu.fp[0] = ext(1.0);
+        fpu.arithmetic(0x20, ext(3.0), 0, env);
+        assert_eq!(FPSR_INEX2 | FPSR_AEXC_DZ | FPSR_AEXC_INEX, fpu.fpsr);
+        assert_eq!(0x104, fpu.fpiar);
+    }
+
+    #[test]
+    fn honours_rounding_precision_and_mode() {
+        let mut fpu = fpu();
+        fpu.set_fpcr(0x0050); // single precision, round to zero
+        let env = fpu.env();
+        fpu.fp[0] = ext(1.0);
+        fpu.arithmetic(0x20, ext(3.0), 0, env);
+        assert_eq!(Extended::new(0x3ffd, 0xaaaa_aa00_0000_0000), fpu.fp[0]);
+    }
+
+    #[test]
+    fn compares_without_storing() {
+        let mut fpu = fpu();
+        let env = fpu.env();
+        fpu.fp[2] = ext(1.0);
+        fpu.arithmetic(0x38, ext(2.0), 2, env);
+        assert_eq!(FPSR_CC_N, fpu.fpsr);
+        assert_eq!(ext(1.0), fpu.fp[2]);
+        fpu.arithmetic(0x38, Extended::default_nan(), 2, env);
+        assert_eq!(FPSR_CC_NAN, fpu.fpsr);
+        fpu.arithmetic(0x3a, ext(-0.0), 2, env);
+        assert_eq!(FPSR_CC_N | FPSR_CC_Z, fpu.fpsr);
+    }
+
+    #[test]
+    fn sincos_writes_two_registers() {
+        let mut fpu = fpu();
+        let env = fpu.env();
+        fpu.arithmetic(0x33, ext(0.5), 1, env);
+        assert_eq!(0.5f64.sin(), fpu.fp[1].to_f64());
+        assert_eq!(0.5f64.cos(), fpu.fp[3].to_f64());
+    }
+
+    #[test]
+    fn transcendental_poles_divide_by_zero() {
+        let mut fpu = fpu();
+        let env = fpu.env();
+        fpu.arithmetic(0x14, ext(0.0), 0, env);
+        assert_eq!(Extended::infinity(true), fpu.fp[0]);
+        assert_eq!(FPSR_DZ, fpu.fpsr & FPSR_DZ);
+        fpu.begin(0);
+        fpu.arithmetic(0x14, ext(-1.0), 0, env);
+        assert_eq!(FPSR_OPERR, fpu.fpsr & FPSR_OPERR);
+    }
+
+    #[test]
+    fn modulo_sets_quotient_byte() {
+        let mut fpu = fpu();
+        let env = fpu.env();
+        fpu.fp[0] = ext(-11.0);
+        fpu.arithmetic(0x21, ext(4.0), 0, env);
+        assert_eq!(ext(-3.0), fpu.fp[0]);
+        assert_eq!(0x82, (fpu.fpsr >> 16) & 0xff);
+    }
+
+    #[test]
+    fn undefined_opmodes_are_rejected() {
+        let mut fpu = fpu();
+        let env = fpu.env();
+        assert_eq!(None, fpu.arithmetic(0x05, ext(1.0), 0, env));
+        assert_eq!(None, fpu.arithmetic(0x40, ext(1.0), 0, env));
+    }
+
+    #[test]
+    fn evaluates_ieee_aware_conditions() {
+        let mut fpu = fpu();
+        fpu.fpsr = FPSR_CC_NAN;
+        assert_eq!(Some(true), fpu.condition(0x08)); // UN
+        assert_eq!(Some(false), fpu.condition(0x02)); // OGT
+        assert_eq!(Some(true), fpu.condition(0x0e)); // NE
+        assert_eq!(FPSR_CC_NAN, fpu.fpsr);
+        fpu.fpsr = FPSR_CC_N;
+        assert_eq!(Some(true), fpu.condition(0x04)); // OLT
+        assert_eq!(Some(true), fpu.condition(0x05)); // OLE
+        assert_eq!(Some(false), fpu.condition(0x03)); // OGE
+        assert_eq!(None, fpu.condition(0x20));
+    }
+
+    #[test]
+    fn non_aware_conditions_signal_unordered() {
+        let mut fpu = fpu();
+        fpu.fpsr = FPSR_CC_NAN;
+        assert_eq!(Some(false), fpu.condition(0x12)); // GT
+        assert_eq!(FPSR_BSUN | FPSR_AEXC_IOP, fpu.fpsr & !FPSR_CC);
+        assert_eq!(None, fpu.unordered_condition_exception());
+        fpu.set_fpcr(FPSR_BSUN);
+        assert_eq!(Some(EXCEPTION_FP_UNORDERED_CONDITION), fpu.unordered_condition_exception());
+    }
+
+    #[test]
+    fn reports_highest_priority_enabled_exception() {
+        let mut fpu = fpu();
+        fpu.raise(FPSR_INEX2 | FPSR_OVFL);
+        assert_eq!(None, fpu.pending_exception());
+        fpu.set_fpcr(FPSR_INEX2);
+        assert_eq!(Some(EXCEPTION_FP_INEXACT), fpu.pending_exception());
+        fpu.set_fpcr(FPSR_INEX2 | FPSR_OVFL);
+        assert_eq!(Some(EXCEPTION_FP_OVERFLOW), fpu.pending_exception());
+    }
+
+    #[test]
+    fn saves_and_restores_frames() {
+        let mut fpu = Fpu::new(FpuModel::M68882);
+        fpu.begin(0);
+        assert_eq!((0x1f38_0000, 0x38), fpu.save_frame());
+        assert_eq!(Some(0x38), fpu.restore_frame(0x1f38_0000));
+        assert_eq!(Some(0xd4), fpu.restore_frame(0x1fd4_0000));
+        assert_eq!(None, fpu.restore_frame(0x1f18_0000));
+        fpu.fpcr = 0x10;
+        assert_eq!(Some(0), fpu.restore_frame(0));
+        assert_eq!(0, fpu.fpcr);
+        assert!(fpu.null_state);
+    }
+
+    #[test]
+    fn rom_has_pi_and_powers_of_ten() {
+        assert_eq!(::std::f64::consts::PI, rom_constant(0).to_f64());
+        assert_eq!(1e16, rom_constant(0x37).to_f64());
+        assert_eq!(1e256, rom_constant(0x3b).to_f64());
+        assert!(rom_constant(0x0f).is_zero());
+    }
+}
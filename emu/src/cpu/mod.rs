@@ -6,8 +6,21 @@ use ram::loggingmem::{LoggingMem, OpsLogger};
 pub type TestCore = ConfiguredCore<AutoInterruptController, LoggingMem<OpsLogger>>;
 pub type Handler<T> = fn(&mut T) -> Result<Cycles>;
 pub type InstructionSet<T> = Vec<Handler<T>>;
-use ram::{AddressBus, SUPERVISOR_PROGRAM, SUPERVISOR_DATA, USER_PROGRAM, USER_DATA};
+use ram::{AddressBus, SUPERVISOR_PROGRAM, SUPERVISOR_DATA, USER_PROGRAM, USER_DATA, ADDRBUS_MASK, ADDRBUS_MASK_20, ADDRBUS_MASK_22, ADDRBUS_MASK_32};
+use self::debugger::{Debugger, StopReason};
+use self::tracer::{Trace, TraceLine, TRACED_WORDS};
+use self::profiler::Profiler;
+use self::blockcache::BlockCache;
+use self::state::CpuState;
+use self::fpu::Fpu;
 pub mod ops;
+pub mod debugger;
+pub mod tracer;
+pub mod state;
+pub mod recorder;
+pub mod profiler;
+pub mod blockcache;
+pub mod fpu;
 mod effective_address;
 mod operator;
 
@@ -64,17 +77,32 @@ pub trait Core {
     fn read_imm_u16(&mut self) -> Result<u16>;
     fn read_imm_u32(&mut self) -> Result<u32>;
     fn jump(&mut self, pc: u32);
-    fn push_32(&mut self, value: u32) -> u32;
-    fn pop_32(&mut self) -> u32;
-    fn push_16(&mut self, value: u16) -> u32;
-    fn pop_16(&mut self) -> u16;
-    fn push_sp(&mut self) -> u32;
+    fn push_32(&mut self, value: u32) -> Result<u32>;
+    fn pop_32(&mut self) -> Result<u32>;
+    fn push_16(&mut self, value: u16) -> Result<u32>;
+    fn pop_16(&mut self) -> Result<u16>;
+    fn push_sp(&mut self) -> Result<u32>;
     fn inactive_ssp(&self) -> u32;
     fn inactive_usp(&mut self) -> &mut u32;
     fn reset_external_devices(&mut self);
     fn resume_normal_processing(&mut self);
     fn stop_instruction_processing(&mut self);
     fn allow_tas_writeback(&mut self) -> bool;
+    fn model(&self) -> CpuModel;
+    fn vbr(&mut self) -> &mut u32;
+    fn sfc(&mut self) -> &mut u32;
+    fn dfc(&mut self) -> &mut u32;
+    fn read_fc_byte(&mut self, fc: u32, address: u32) -> Result<u32>;
+    fn read_fc_word(&mut self, fc: u32, address: u32) -> Result<u32>;
+    fn read_fc_long(&mut self, fc: u32, address: u32) -> Result<u32>;
+    fn write_fc_byte(&mut self, fc: u32, address: u32, value: u32) -> Result<()>;
+    fn write_fc_word(&mut self, fc: u32, address: u32, value: u32) -> Result<()>;
+    fn write_fc_long(&mut self, fc: u32, address: u32, value: u32) -> Result<()>;
+    fn msp(&mut self) -> &mut u32;
+    fn isp(&mut self) -> &mut u32;
+    fn cacr(&mut self) -> &mut u32;
+    fn caar(&mut self) -> &mut u32;
+    fn fpu(&mut self) -> Option<&mut Fpu>;
 }
 
 pub struct ConfiguredCore<T: InterruptController, A: AddressBus> {
@@ -83,8 +111,10 @@ pub struct ConfiguredCore<T: InterruptController, A: AddressBus> {
     pub inactive_usp: u32, // when in supervisor mode
     pub ir: u16,
     pub dar: [u32; 16],
-    instruction_set: InstructionSet<ConfiguredCore<T, A>>,
+    model: CpuModel,
+    instruction_set: Arc<InstructionSet<ConfiguredCore<T, A>>>,
     pub s_flag: u32,
+    pub t1_flag: u32,
     pub irq_level: u8,
     pub int_mask: u32,
     pub int_ctrl: T,
@@ -95,8 +125,27 @@ pub struct ConfiguredCore<T: InterruptController, A: AddressBus> {
     pub prefetch_addr: u32,
     pub prefetch_data: u32,
     pub not_z_flag: u32,
+    pub vbr: u32, // 68010 only
+    pub sfc: u32, // 68010 only
+    pub dfc: u32, // 68010 only
+    pub m_flag: u32, // 68020 only
+    pub inactive_msp: u32, // 68020 only, unless in master mode
+    pub cacr: u32, // 68020 only
+    pub caar: u32, // 68020 only
+    fpu: Option<Fpu>,
+    bus: BusConfig,
+    bus_cycles: i32, // extra cycles of the current instruction on a narrow bus
+    exception_taken: bool, // whether the current instruction led to exception processing
+    // the words of the current instruction, as fetched, for the tracer
+    fetched: [u16; TRACED_WORDS],
+    fetched_words: usize,
+    block_cache: Option<BlockCache<ConfiguredCore<T, A>>>,
+    pub ppc: u32, // address of the current instruction
     pub processing_state: ProcessingState,
     pub mem: A,
+    pub debugger: Debugger,
+    pub tracer: Option<Box<dyn Trace + Send>>,
+    pub profiler: Option<Profiler>,
 }
 impl<T: InterruptController, A: AddressBus> Core for ConfiguredCore<T, A> {
     fn dar(&mut self) -> &mut [u32; 16] {
@@ -273,19 +322,19 @@ impl<T: InterruptController, A: AddressBus> Core for ConfiguredCore<T, A> {
     fn jump(&mut self, pc: u32) {
         self.jump(pc)
     }
-    fn push_32(&mut self, value: u32) -> u32 {
+    fn push_32(&mut self, value: u32) -> Result<u32> {
         self.push_32(value)
     }
-    fn pop_32(&mut self) -> u32 {
+    fn pop_32(&mut self) -> Result<u32> {
         self.pop_32()
     }
-    fn push_16(&mut self, value: u16) -> u32 {
+    fn push_16(&mut self, value: u16) -> Result<u32> {
         self.push_16(value)
     }
-    fn pop_16(&mut self) -> u16 {
+    fn pop_16(&mut self) -> Result<u16> {
         self.pop_16()
     }
-    fn push_sp(&mut self) -> u32 {
+    fn push_sp(&mut self) -> Result<u32> {
         self.push_sp()
     }
     fn inactive_ssp(&self) -> u32 {
@@ -306,6 +355,65 @@ impl<T: InterruptController, A: AddressBus> Core for ConfiguredCore<T, A> {
     fn allow_tas_writeback(&mut self) -> bool {
         true
     }
+    fn model(&self) -> CpuModel {
+        self.model
+    }
+    fn vbr(&mut self) -> &mut u32 {
+        &mut self.vbr
+    }
+    fn sfc(&mut self) -> &mut u32 {
+        &mut self.sfc
+    }
+    fn dfc(&mut self) -> &mut u32 {
+        &mut self.dfc
+    }
+    fn read_fc_byte(&mut self, fc: u32, address: u32) -> Result<u32> {
+        let address_space = self.fc_space(fc, address, AccessType::Read)?;
+        self.read_byte(address_space, address)
+    }
+    fn read_fc_word(&mut self, fc: u32, address: u32) -> Result<u32> {
+        let address_space = self.fc_space(fc, address, AccessType::Read)?;
+        self.read_word(address_space, address)
+    }
+    fn read_fc_long(&mut self, fc: u32, address: u32) -> Result<u32> {
+        let address_space = self.fc_space(fc, address, AccessType::Read)?;
+        self.read_long(address_space, address)
+    }
+    fn write_fc_byte(&mut self, fc: u32, address: u32, value: u32) -> Result<()> {
+        let address_space = self.fc_space(fc, address, AccessType::Write)?;
+        self.write_byte(address_space, address, value)
+    }
+    fn write_fc_word(&mut self, fc: u32, address: u32, value: u32) -> Result<()> {
+        let address_space = self.fc_space(fc, address, AccessType::Write)?;
+        self.write_word(address_space, address, value)
+    }
+    fn write_fc_long(&mut self, fc: u32, address: u32, value: u32) -> Result<()> {
+        let address_space = self.fc_space(fc, address, AccessType::Write)?;
+        self.write_long(address_space, address, value)
+    }
+    fn msp(&mut self) -> &mut u32 {
+        if self.s_flag != SFLAG_CLEAR && self.m_flag != MFLAG_CLEAR {
+            &mut self.dar[15]
+        } else {
+            &mut self.inactive_msp
+        }
+    }
+    fn isp(&mut self) -> &mut u32 {
+        if self.s_flag != SFLAG_CLEAR && self.m_flag == MFLAG_CLEAR {
+            &mut self.dar[15]
+        } else {
+            &mut self.inactive_ssp
+        }
+    }
+    fn cacr(&mut self) -> &mut u32 {
+        &mut self.cacr
+    }
+    fn caar(&mut self) -> &mut u32 {
+        &mut self.caar
+    }
+    fn fpu(&mut self) -> Option<&mut Fpu> {
+        self.fpu.as_mut()
+    }
 }
 pub const STACK_POINTER_REG: usize = 15;
 
@@ -334,13 +442,58 @@ impl Cycles {
     }
 }
 
-pub trait Callbacks {
-    fn exception_callback(&mut self, core: &mut impl Core, ex: Exception) -> Result<Cycles>;
+// What to do with an instruction about to execute, as decided by
+// Callbacks::before_instruction
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BeforeInstruction {
+    Execute,
+    // execute the given opcode instead, such as to patch code in ROM
+    Replace(u16),
+    // don't execute it, as the hook has done what it should instead
+    // (leaving the PC past the opcode, unless it jumped), taking the given
+    // cycles
+    Skip(Cycles),
+    // stop execution before it, leaving the PC at it
+    Stop,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Flow {
+    Continue,
+    Stop,
+}
+
+// Hooks into execute_with_state, for cores of type C. All but
+// exception_callback default to doing nothing, and as execute_with_state
+// is generic over the callbacks, hooks left out cost nothing. Callbacks
+// for any core implement this for all C: Core, while those needing more
+// than Core, such as the core's memory, implement it for a concrete
+// ConfiguredCore.
+pub trait Callbacks<C: Core> {
+    fn exception_callback(&mut self, core: &mut C, ex: Exception) -> Result<Cycles>;
+    // called once the opcode at pc is fetched (so the PC is past it),
+    // but not for exceptions taken instead of an instruction
+    fn before_instruction(&mut self, _core: &mut C, _pc: u32, _opcode: u16) -> BeforeInstruction {
+        BeforeInstruction::Execute
+    }
+    // called after each instruction before_instruction was called for,
+    // with the cycles it took, including any exception processing
+    fn after_instruction(&mut self, _core: &mut C, _cycles: Cycles) -> Flow {
+        Flow::Continue
+    }
+    // called after a RESET instruction has reset the external devices
+    fn reset_instruction(&mut self, _core: &mut C) -> Flow {
+        Flow::Continue
+    }
+    // called when a STOP instruction stops the core, or a double fault
+    // halts it, which ends execution unless the hook gets it going again
+    fn stopped(&mut self, _core: &mut C) {}
+    fn halted(&mut self, _core: &mut C) {}
 }
 
-struct EmulateAllExceptions;
-impl Callbacks for EmulateAllExceptions {
-    fn exception_callback(&mut self, _: &mut impl Core, ex: Exception) -> Result<Cycles> {
+pub struct EmulateAllExceptions;
+impl<C: Core> Callbacks<C> for EmulateAllExceptions {
+    fn exception_callback(&mut self, _: &mut C, ex: Exception) -> Result<Cycles> {
         Err(ex)
     }
 }
@@ -376,18 +529,136 @@ impl ProcessingState {
     }
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum CpuModel {
+    M68000,
+    M68010,
+    M68020,
+}
+
+impl CpuModel {
+    // Exception processing time, including the instruction for TRAP and
+    // friends, which also add their EA calculation time. The 68010 times
+    // are taken from Musashi's exception cycle table.
+    pub fn exception_cycles(self, vector: u8) -> i32 {
+        match self {
+            CpuModel::M68000 => match vector {
+                EXCEPTION_BUS_ERROR | EXCEPTION_ADDRESS_ERROR => 50,
+                EXCEPTION_ZERO_DIVIDE => 38,
+                EXCEPTION_CHK => 40,
+                EXCEPTION_FORMAT_ERROR => 4,
+                _ => 34,
+            },
+            CpuModel::M68010 => match vector {
+                EXCEPTION_BUS_ERROR | EXCEPTION_ADDRESS_ERROR => 126,
+                EXCEPTION_ZERO_DIVIDE | EXCEPTION_CHK => 44,
+                EXCEPTION_TRAPV => 34,
+                EXCEPTION_FORMAT_ERROR => 4,
+                _ => 38,
+            },
+            CpuModel::M68020 => match vector {
+                EXCEPTION_BUS_ERROR | EXCEPTION_ADDRESS_ERROR => 50,
+                EXCEPTION_ZERO_DIVIDE => 38,
+                EXCEPTION_CHK => 40,
+                EXCEPTION_PRIVILEGE_VIOLATION => 34,
+                EXCEPTION_TRACE => 25,
+                EXCEPTION_FORMAT_ERROR => 4,
+                _ => 20,
+            },
+        }
+    }
+    // 44 cycles for an interrupt according to MC68000UM, Table 8-14
+    // The interrupt acknowledge cycle is assumed to take four clock periods
+    pub fn interrupt_cycles(self) -> i32 {
+        match self {
+            CpuModel::M68000 => 44,
+            CpuModel::M68010 => 46,
+            CpuModel::M68020 => 30,
+        }
+    }
+    // The 68000 and 68010 only bring out 24 address lines. Memory
+    // implementations take the mask to know how much to decode.
+    pub fn address_mask(self) -> u32 {
+        match self {
+            CpuModel::M68020 => ADDRBUS_MASK_32,
+            _ => ADDRBUS_MASK,
+        }
+    }
+    // Word and long data at odd addresses cause an address error, while
+    // the 68020 just takes more bus cycles. Instructions must be at even
+    // addresses on all models.
+    pub fn aligns_data(self) -> bool {
+        self != CpuModel::M68020
+    }
+    fn sr_mask(self) -> u16 {
+        match self {
+            CpuModel::M68020 => CPU_SR_MASK | MFLAG_SET as u16,
+            _ => CPU_SR_MASK,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DataBus {
+    Bits8,
+    Bits16,
+    Bits32,
+}
+
+// How the core is wired to the outside world: the width of the data bus
+// and the address lines brought out. The 68008 and the 68EC000 in 8-bit
+// mode run the 68000 instruction set unchanged, but transfer words a byte
+// at a time, which costs an extra bus cycle per word.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BusConfig {
+    pub data_bus: DataBus,
+    pub address_mask: u32,
+}
+
+impl BusConfig {
+    pub fn for_model(model: CpuModel) -> BusConfig {
+        let data_bus = match model {
+            CpuModel::M68020 => DataBus::Bits32,
+            _ => DataBus::Bits16,
+        };
+        BusConfig { data_bus, address_mask: model.address_mask() }
+    }
+    // the 48-pin 68008, as found in the Sinclair QL
+    pub fn m68008() -> BusConfig {
+        BusConfig { data_bus: DataBus::Bits8, address_mask: ADDRBUS_MASK_20 }
+    }
+    // the 52-pin 68008
+    pub fn m68008_plcc() -> BusConfig {
+        BusConfig { data_bus: DataBus::Bits8, address_mask: ADDRBUS_MASK_22 }
+    }
+    // the 68EC000 selects an 8 or 16-bit data bus at reset
+    pub fn m68ec000(data_bus: DataBus) -> BusConfig {
+        BusConfig { data_bus, address_mask: ADDRBUS_MASK }
+    }
+    // The instruction timings assume each word takes one bus cycle of
+    // four clocks, an 8-bit bus needs two
+    fn extra_cycles(self, bytes: u32) -> i32 {
+        match self.data_bus {
+            DataBus::Bits8 => 4 * (bytes as i32 / 2),
+            _ => 0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum AccessType {Read, Write}
 use ram::AddressSpace;
 
 #[derive(Clone, Copy, Debug)]
 pub enum Exception {
     AddressError { address: u32, access_type: AccessType, processing_state: ProcessingState, address_space: AddressSpace},
+    BusError { address: u32, access_type: AccessType, processing_state: ProcessingState, address_space: AddressSpace},
     IllegalInstruction(u16, u32), // ir, pc
     Trap(u8, i32),                // trap no, exception cycles
     PrivilegeViolation(u16, u32), // ir, pc
     UnimplementedInstruction(u16, u32, u8), // ir, pc, vector no
     Interrupt(u8, u8), // irq, vector no
+    Trace,
 }
 use std::fmt;
 impl fmt::Display for Exception {
@@ -396,11 +667,15 @@ impl fmt::Display for Exception {
             Exception::AddressError {
                 address, access_type, processing_state, address_space
                 } => write!(f, "Address Error: {:?} {:?} at {:08x} during {:?} processing", access_type, address_space, address, processing_state),
+            Exception::BusError {
+                address, access_type, processing_state, address_space
+                } => write!(f, "Bus Error: {:?} {:?} at {:08x} during {:?} processing", access_type, address_space, address, processing_state),
             Exception::IllegalInstruction(ir, pc) => write!(f, "Illegal Instruction {:04x} at {:08x}", ir, pc),
             Exception::Trap(num, ea_cyc) => write!(f, "Trap: {:02x} (ea cyc {})", num, ea_cyc),
             Exception::PrivilegeViolation(ir, pc) => write!(f, "Privilege Violation {:04x} at {:08x}", ir, pc),
             Exception::UnimplementedInstruction(ir, pc, _) => write!(f, "Unimplemented Instruction {:04x} at {:08x}", ir, pc),
             Exception::Interrupt(irq, vec) => write!(f, "Interrupt {:1x} (vector {:02x})", irq, vec),
+            Exception::Trace => write!(f, "Trace"),
         }
     }
 }
@@ -409,11 +684,13 @@ impl error::Error for Exception {
     fn description(&self) -> &str {
          match *self {
             Exception::AddressError{..} => "Address Error",
+            Exception::BusError{..} => "Bus Error",
             Exception::IllegalInstruction(_, _) => "Illegal Instruction",
             Exception::Trap(_, _) => "Trap",
             Exception::PrivilegeViolation(_, _) => "PrivilegeViolation",
             Exception::UnimplementedInstruction(_, _, _) => "UnimplementedInstruction",
             Exception::Interrupt(_, _) => "Interrupt",
+            Exception::Trace => "Trace",
          }
     }
     fn cause(&self) -> Option<&error::Error> {
@@ -421,15 +698,19 @@ impl error::Error for Exception {
     }
 }
 use std::num::Wrapping;
+use std::any::{Any, TypeId};
+use std::sync::{Arc, Mutex};
 
 // these values are borrowed from Musashi
 // and not yet fully understood
 const SFLAG_SET: u32 =  0x04;
+const TFLAG_SET: u32 = 0x8000;
 const XFLAG_SET: u32 = 0x100;
 const ZFLAG_SET: u32 = 0x00;
 const NFLAG_SET: u32 =  0x80;
 const VFLAG_SET: u32 =  0x80;
 const CFLAG_SET: u32 = 0x100;
+const MFLAG_SET: u32 = 0x1000;
 const CPU_SR_MASK: u16 = 0xa71f; /* T1 -- S  -- -- I2 I1 I0 -- -- -- X  N  Z  V  C  */
 const CPU_SR_INT_MASK: u32 = 0x0700;
 
@@ -438,32 +719,67 @@ const XFLAG_CLEAR: u32 =  0x00;
 const NFLAG_CLEAR: u32 =  0x00;
 const CFLAG_CLEAR: u32 =  0x00;
 const SFLAG_CLEAR: u32 =  0x00;
+const TFLAG_CLEAR: u32 =  0x00;
+const MFLAG_CLEAR: u32 =  0x00;
 const ZFLAG_CLEAR: u32 =  0xffff_ffff; // used as "non-z-flag"
 
+// an Arc<InstructionSet<T>> for the core type T, model and FPU
+type SharedInstructionSet = ((TypeId, CpuModel, bool), Box<dyn Any + Send + Sync>);
+// few enough to be looked up one by one
+static INSTRUCTION_SETS: Mutex<Vec<SharedInstructionSet>> = Mutex::new(Vec::new());
+
+// Instruction sets are built on first use, once per core type, model and
+// FPU, and then shared by all cores of that kind, as with hundreds of
+// cores the 64K entry tables would take a while to build and a lot of
+// memory to keep
+fn instruction_set<T: Core + 'static>(model: CpuModel, fpu: bool) -> Arc<InstructionSet<T>> {
+    let key = (TypeId::of::<T>(), model, fpu);
+    let mut sets = INSTRUCTION_SETS.lock().unwrap();
+    if let Some((_, set)) = sets.iter().find(|(k, _)| *k == key) {
+        return set.downcast_ref::<Arc<InstructionSet<T>>>().unwrap().clone();
+    }
+    let set: Arc<InstructionSet<T>> = Arc::new(if fpu {
+        ops::instruction_set_with_fpu(model)
+    } else {
+        ops::instruction_set_for(model)
+    });
+    sets.push((key, Box::new(set.clone())));
+    set
+}
+
 // Exception Vectors
-//pub const EXCEPTION_BUS_ERROR: u8               =  2;
+pub const EXCEPTION_BUS_ERROR: u8               =  2;
 pub const EXCEPTION_ADDRESS_ERROR: u8           =  3;
 pub const EXCEPTION_ILLEGAL_INSTRUCTION: u8     =  4;
 pub const EXCEPTION_ZERO_DIVIDE: u8             =  5;
 pub const EXCEPTION_CHK: u8                     =  6;
 pub const EXCEPTION_TRAPV: u8                   =  7;
 pub const EXCEPTION_PRIVILEGE_VIOLATION: u8     =  8;
-// pub const EXCEPTION_TRACE: u8                   =  9;
+pub const EXCEPTION_TRACE: u8                   =  9;
 pub const EXCEPTION_UNIMPLEMENTED_1010: u8      = 10;
 pub const EXCEPTION_UNIMPLEMENTED_1111: u8      = 11;
-// pub const EXCEPTION_FORMAT_ERROR: u8            = 14;
+pub const EXCEPTION_FORMAT_ERROR: u8            = 14;
 // pub const EXCEPTION_UNINITIALIZED_INTERRUPT: u8 = 15;
 // pub const EXCEPTION_SPURIOUS_INTERRUPT: u8      = 24;
 // pub const EXCEPTION_INTERRUPT_AUTOVECTOR: u8    = 24;
 pub const EXCEPTION_TRAP_BASE: u8               = 32;
+pub const EXCEPTION_FP_UNORDERED_CONDITION: u8  = 48;
+pub const EXCEPTION_FP_INEXACT: u8              = 49;
+pub const EXCEPTION_FP_DIVIDE_BY_ZERO: u8       = 50;
+pub const EXCEPTION_FP_UNDERFLOW: u8            = 51;
+pub const EXCEPTION_FP_OPERAND_ERROR: u8        = 52;
+pub const EXCEPTION_FP_OVERFLOW: u8             = 53;
+pub const EXCEPTION_FP_SIGNALING_NAN: u8        = 54;
 
 impl TestCore {
     pub fn new(base: u32) -> TestCore {
         TestCore {
             pc: base, prefetch_addr: 0, prefetch_data: 0, inactive_ssp: 0, inactive_usp: 0, ir: 0, processing_state: ProcessingState::Group0Exception,
-            dar: [0u32; 16], mem: LoggingMem::new(0xaaaa_aaaa, OpsLogger::new()), instruction_set: ops::instruction_set(),
-            irq_level: 0, int_ctrl: AutoInterruptController::new(),
-            s_flag: SFLAG_SET, int_mask: CPU_SR_INT_MASK, x_flag: 0, v_flag: 0, c_flag: 0, n_flag: 0, not_z_flag: 0xffff_ffff
+            dar: [0u32; 16], mem: LoggingMem::new(0xaaaa_aaaa, OpsLogger::new()), instruction_set: instruction_set(CpuModel::M68000, false),
+            irq_level: 0, int_ctrl: AutoInterruptController::new(), model: CpuModel::M68000, vbr: 0, sfc: 0, dfc: 0,
+            m_flag: 0, inactive_msp: 0, cacr: 0, caar: 0, fpu: None, bus: BusConfig::for_model(CpuModel::M68000), bus_cycles: 0, exception_taken: false, fetched: [0; TRACED_WORDS], fetched_words: 0, block_cache: None, ppc: 0,
+            s_flag: SFLAG_SET, t1_flag: TFLAG_CLEAR, int_mask: CPU_SR_INT_MASK, x_flag: 0, v_flag: 0, c_flag: 0, n_flag: 0, not_z_flag: 0xffff_ffff,
+            debugger: Debugger::new(), tracer: None, profiler: None
         }
     }
     pub fn new_auto() -> TestCore {
@@ -479,9 +795,11 @@ impl TestCore {
         }
         TestCore {
             pc: base, prefetch_addr: 0, prefetch_data: 0, inactive_ssp: 0, inactive_usp: 0, ir: 0, processing_state: ProcessingState::Normal,
-            dar: [0u32; 16], mem: lm, instruction_set: ops::instruction_set(),
-            irq_level: 0, int_ctrl: AutoInterruptController::new(),
-            s_flag: SFLAG_SET, int_mask: CPU_SR_INT_MASK, x_flag: 0, v_flag: 0, c_flag: 0, n_flag: 0, not_z_flag: 0xffff_ffff
+            dar: [0u32; 16], mem: lm, instruction_set: instruction_set(CpuModel::M68000, false),
+            irq_level: 0, int_ctrl: AutoInterruptController::new(), model: CpuModel::M68000, vbr: 0, sfc: 0, dfc: 0,
+            m_flag: 0, inactive_msp: 0, cacr: 0, caar: 0, fpu: None, bus: BusConfig::for_model(CpuModel::M68000), bus_cycles: 0, exception_taken: false, fetched: [0; TRACED_WORDS], fetched_words: 0, block_cache: None, ppc: 0,
+            s_flag: SFLAG_SET, t1_flag: TFLAG_CLEAR, int_mask: CPU_SR_INT_MASK, x_flag: 0, v_flag: 0, c_flag: 0, n_flag: 0, not_z_flag: 0xffff_ffff,
+            debugger: Debugger::new(), tracer: None, profiler: None
         }
     }
 }
@@ -490,22 +808,146 @@ impl<T: InterruptController, A: AddressBus> ConfiguredCore<T, A> {
     pub fn new_with(base: u32, int_ctrl: T, memory: A) -> ConfiguredCore<T, A> {
         ConfiguredCore {
             pc: base, prefetch_addr: 0, prefetch_data: 0, inactive_ssp: 0, inactive_usp: 0, ir: 0, processing_state: ProcessingState::Group0Exception,
-            dar: [0u32; 16], mem: memory, instruction_set: ops::instruction_set(),
-            irq_level: 0, int_ctrl,
-            s_flag: SFLAG_SET, int_mask: CPU_SR_INT_MASK, x_flag: 0, v_flag: 0, c_flag: 0, n_flag: 0, not_z_flag: 0xffff_ffff
+            dar: [0u32; 16], mem: memory, instruction_set: instruction_set(CpuModel::M68000, false),
+            irq_level: 0, int_ctrl, model: CpuModel::M68000, vbr: 0, sfc: 0, dfc: 0,
+            m_flag: 0, inactive_msp: 0, cacr: 0, caar: 0, fpu: None, bus: BusConfig::for_model(CpuModel::M68000), bus_cycles: 0, exception_taken: false, fetched: [0; TRACED_WORDS], fetched_words: 0, block_cache: None, ppc: 0,
+            s_flag: SFLAG_SET, t1_flag: TFLAG_CLEAR, int_mask: CPU_SR_INT_MASK, x_flag: 0, v_flag: 0, c_flag: 0, n_flag: 0, not_z_flag: 0xffff_ffff,
+            debugger: Debugger::new(), tracer: None, profiler: None
+        }
+    }
+    pub fn model(&self) -> CpuModel {
+        self.model
+    }
+    // Switches the instruction set, exception frames and timing to those
+    // of the given model, leaving the registers as they are. The bus is
+    // reset to that of the model, so set_bus goes after set_model.
+    pub fn set_model(&mut self, model: CpuModel) {
+        if model != self.model {
+            self.model = model;
+            self.instruction_set = instruction_set(model, self.fpu.is_some());
+            self.bus = BusConfig::for_model(model);
+            self.flush_block_cache();
+        }
+    }
+    pub fn bus(&self) -> BusConfig {
+        self.bus
+    }
+    // Narrows the data or address bus, as for the 68008. The memory
+    // implementation still needs a matching address mask to mirror the
+    // address space the same way.
+    pub fn set_bus(&mut self, bus: BusConfig) {
+        self.bus = bus;
+        self.flush_block_cache();
+    }
+    // Turns the block cache on or off. It makes straight-line code run
+    // faster, at the cost of checking for interrupts only between blocks
+    // of up to blockcache::MAX_BLOCK_INSTRUCTIONS instructions, and of
+    // having to flush it after changing memory other than through this
+    // core.
+    pub fn set_block_cache(&mut self, enabled: bool) {
+        if enabled != self.block_cache.is_some() {
+            self.block_cache = if enabled { Some(BlockCache::new()) } else { None };
+            // the cache reads around the prefetch, which is stale by now
+            self.prefetch_addr = 1;
+        }
+    }
+    pub fn block_cache(&self) -> Option<&BlockCache<ConfiguredCore<T, A>>> {
+        self.block_cache.as_ref()
+    }
+    pub fn flush_block_cache(&mut self) {
+        if let Some(ref mut cache) = self.block_cache {
+            cache.flush();
+        }
+    }
+    pub fn fpu(&self) -> Option<&Fpu> {
+        self.fpu.as_ref()
+    }
+    pub fn fpu_mut(&mut self) -> Option<&mut Fpu> {
+        self.fpu.as_mut()
+    }
+    // Attaches (or with None, removes) a floating-point coprocessor. Its
+    // instructions are on line F, so without one they trap as
+    // unimplemented instructions, which is what FPU emulation software
+    // expects on a 68000.
+    pub fn set_fpu(&mut self, fpu: Option<Fpu>) {
+        let changed = fpu.is_some() != self.fpu.is_some();
+        self.fpu = fpu;
+        if changed {
+            self.instruction_set = instruction_set(self.model, self.fpu.is_some());
+            self.flush_block_cache();
+        }
+    }
+    pub fn save_state(&self) -> CpuState {
+        CpuState {
+            pc: self.pc, ir: self.ir, dar: self.dar,
+            inactive_ssp: self.inactive_ssp, inactive_usp: self.inactive_usp,
+            s_flag: self.s_flag, t1_flag: self.t1_flag, x_flag: self.x_flag, c_flag: self.c_flag,
+            v_flag: self.v_flag, n_flag: self.n_flag, not_z_flag: self.not_z_flag,
+            int_mask: self.int_mask, irq_level: self.irq_level,
+            prefetch_addr: self.prefetch_addr, prefetch_data: self.prefetch_data,
+            processing_state: self.processing_state,
+            model: self.model, vbr: self.vbr, sfc: self.sfc, dfc: self.dfc,
+            m_flag: self.m_flag, inactive_msp: self.inactive_msp, cacr: self.cacr, caar: self.caar,
+            fpu: self.fpu.clone(), bus: self.bus,
         }
     }
+    // memory, the interrupt controller, debugger and tracer are left as is
+    pub fn load_state(&mut self, state: &CpuState) {
+        self.pc = state.pc;
+        self.ir = state.ir;
+        self.dar = state.dar;
+        self.inactive_ssp = state.inactive_ssp;
+        self.inactive_usp = state.inactive_usp;
+        self.s_flag = state.s_flag;
+        self.t1_flag = state.t1_flag;
+        self.x_flag = state.x_flag;
+        self.c_flag = state.c_flag;
+        self.v_flag = state.v_flag;
+        self.n_flag = state.n_flag;
+        self.not_z_flag = state.not_z_flag;
+        self.int_mask = state.int_mask;
+        self.irq_level = state.irq_level;
+        self.prefetch_addr = state.prefetch_addr;
+        self.prefetch_data = state.prefetch_data;
+        self.processing_state = state.processing_state;
+        self.set_model(state.model);
+        self.vbr = state.vbr;
+        self.sfc = state.sfc;
+        self.dfc = state.dfc;
+        self.m_flag = state.m_flag;
+        self.inactive_msp = state.inactive_msp;
+        self.cacr = state.cacr;
+        self.caar = state.caar;
+        self.set_fpu(state.fpu.clone());
+        self.bus = state.bus;
+        // the memory may well have changed since
+        self.flush_block_cache();
+    }
     pub fn reset(&mut self) {
         self.processing_state = ProcessingState::Group0Exception;
         self.s_flag = SFLAG_SET;
+        self.t1_flag = TFLAG_CLEAR;
+        self.m_flag = MFLAG_CLEAR;
         self.int_mask = CPU_SR_INT_MASK;
+        self.vbr = 0;
+        self.cacr = 0;
+        if let Some(ref mut fpu) = self.fpu {
+            fpu.reset();
+        }
         self.prefetch_addr = 1; // non-zero, or the prefetch won't kick in
+        self.flush_block_cache();
         self.jump(0);
-        // these reads cannot possibly cause AddressError, as we forced PC to 0
-        self.dar[15] = self.read_imm_u32().unwrap();
-        let new_pc = self.read_imm_u32().unwrap();
-        self.jump(new_pc);
-        self.processing_state = ProcessingState::Normal;
+        // these reads cannot possibly cause AddressError, as we forced PC
+        // to 0, but a bus error while reading the reset vector halts the
+        // processor
+        match self.read_imm_u32().and_then(|ssp| self.read_imm_u32().map(|pc| (ssp, pc))) {
+            Ok((ssp, new_pc)) => {
+                self.dar[15] = ssp;
+                self.jump(new_pc);
+                self.processing_state = ProcessingState::Normal;
+            }
+            Err(_) => self.processing_state = ProcessingState::Halted,
+        }
     }
     pub fn x_flag_as_1(&self) -> u32 {
         (self.x_flag>>8)&1
@@ -514,8 +956,10 @@ impl<T: InterruptController, A: AddressBus> ConfiguredCore<T, A> {
     // which I don't fully understand (they are not matching their
     // positions in the SR/CCR)
     pub fn status_register(&self) -> u16 {
-        ((self.s_flag << 11)                |
-        self.int_mask                        |
+        (self.t1_flag                       |
+        (self.s_flag << 11)                 |
+        self.m_flag                         |
+        self.int_mask                       |
         ((self.x_flag & XFLAG_SET) >> 4)    |
         ((self.n_flag & NFLAG_SET) >> 4)    |
         ((not1!(self.not_z_flag))  << 2)    |
@@ -532,35 +976,59 @@ impl<T: InterruptController, A: AddressBus> ConfiguredCore<T, A> {
             self.dar[15]
         }
     }
+    // the interrupt stack pointer, which is the only supervisor stack
+    // pointer before the 68020
     pub fn ssp(&self) -> u32 {
-        if self.s_flag > 0 {
+        if self.s_flag > 0 && self.m_flag == MFLAG_CLEAR {
             self.dar[15]
         } else {
             self.inactive_ssp
         }
     }
+    pub fn msp(&self) -> u32 {
+        if self.s_flag > 0 && self.m_flag != MFLAG_CLEAR {
+            self.dar[15]
+        } else {
+            self.inactive_msp
+        }
+    }
+    // A7 is the USP in user mode, and the ISP in supervisor mode, or on
+    // the 68020 the MSP if the M flag is set too
+    fn stack_pointer_slot(&mut self, s_flag: u32, m_flag: u32) -> &mut u32 {
+        if s_flag == SFLAG_CLEAR {
+            &mut self.inactive_usp
+        } else if m_flag == MFLAG_CLEAR {
+            &mut self.inactive_ssp
+        } else {
+            &mut self.inactive_msp
+        }
+    }
+    fn switch_stack_pointer(&mut self, old_s_flag: u32, old_m_flag: u32) {
+        let old_m_flag = if old_s_flag == SFLAG_CLEAR { MFLAG_CLEAR } else { old_m_flag };
+        let new_m_flag = if self.s_flag == SFLAG_CLEAR { MFLAG_CLEAR } else { self.m_flag };
+        if (old_s_flag, old_m_flag) != (self.s_flag, new_m_flag) {
+            let sp = self.dar[15];
+            *self.stack_pointer_slot(old_s_flag, old_m_flag) = sp;
+            let s_flag = self.s_flag;
+            self.dar[15] = *self.stack_pointer_slot(s_flag, new_m_flag);
+        }
+    }
     // admittely I've chosen to reuse Musashi's representation of flags
     // which I don't fully understand (they are not matching their
     // positions in the SR/CCR)
     pub fn sr_to_flags(&mut self, sr: u16) {
-        let sr = u32::from(sr & CPU_SR_MASK);
-        let old_sflag = self.s_flag;
+        let sr = u32::from(sr & self.model.sr_mask());
+        let (old_sflag, old_mflag) = (self.s_flag, self.m_flag);
         self.int_mask = sr & CPU_SR_INT_MASK;
+        self.t1_flag =           sr & TFLAG_SET;
         self.s_flag =           (sr >> 11) & SFLAG_SET;
+        self.m_flag =            sr & MFLAG_SET;
         self.x_flag =            (sr <<  4) & XFLAG_SET;
         self.n_flag =            (sr <<  4) & NFLAG_SET;
         self.not_z_flag = not1!(sr & 0b00100);
         self.v_flag =            (sr <<  6) & VFLAG_SET;
         self.c_flag =            (sr <<  8) & CFLAG_SET;
-        if old_sflag != self.s_flag {
-            if self.s_flag == SFLAG_SET {
-                self.inactive_usp = self.dar[15];
-                self.dar[15] = self.inactive_ssp;
-            } else {
-                self.inactive_ssp = self.dar[15];
-                self.dar[15] = self.inactive_usp;
-            }
-        }
+        self.switch_stack_pointer(old_sflag, old_mflag);
         // println!("{} {:016b} {} {}", self.flags(), sr, self.not_z_flag, sr & 0b00100);
     }
     pub fn ccr_to_flags(&mut self, ccr: u16) {
@@ -573,7 +1041,8 @@ impl<T: InterruptController, A: AddressBus> ConfiguredCore<T, A> {
         let supervisor = (sr >> 13) & 1;
         let irq_mask = (0x700 & sr) >> 8;
 
-        format!("-{}{}{}{}{}{}{}",
+        format!("{}{}{}{}{}{}{}{}",
+        if 0 < (sr >> 15) & 1 {'T'} else {'-'},
         if supervisor > 0 {'S'} else {'U'},
         irq_mask,
         if 0 < (sr >> 4) & 1 {'X'} else {'-'},
@@ -582,31 +1051,62 @@ impl<T: InterruptController, A: AddressBus> ConfiguredCore<T, A> {
         if 0 < (sr >> 1) & 1 {'V'} else {'-'},
         if 0 < (sr     ) & 1 {'C'} else {'-'})
     }
-    fn prefetch_if_needed(&mut self) -> bool {
+    fn program_space(&self) -> AddressSpace {
+        if self.s_flag != 0 {SUPERVISOR_PROGRAM} else {USER_PROGRAM}
+    }
+    fn data_space(&self) -> AddressSpace {
+        if self.s_flag != 0 {SUPERVISOR_DATA} else {USER_DATA}
+    }
+    fn address_error(&self, address: u32, access_type: AccessType, address_space: AddressSpace) -> Exception {
+        Exception::AddressError{address, access_type, address_space, processing_state: self.processing_state}
+    }
+    fn bus_error(&self, address: u32, access_type: AccessType, address_space: AddressSpace) -> Exception {
+        Exception::BusError{address, access_type, address_space, processing_state: self.processing_state}
+    }
+    // The AddressBus only knows the user/supervisor program/data spaces,
+    // so MOVES using any other function code (such as CPU space) ends in
+    // a bus error, reported as a data space access
+    fn fc_space(&self, fc: u32, address: u32, access_type: AccessType) -> Result<AddressSpace> {
+        AddressSpace::from_fc(fc).ok_or_else(|| self.bus_error(address, access_type, self.data_space()))
+    }
+    fn prefetch_if_needed(&mut self) -> Result<bool> {
         // does current PC overlap with fetched data
         let fetched = if self.pc & !3 != self.prefetch_addr {
-            self.prefetch_addr = self.pc & !3;
-            let address_space = if self.s_flag != 0 {SUPERVISOR_PROGRAM} else {USER_PROGRAM};
-            self.prefetch_data = self.mem.read_long(address_space, self.prefetch_addr);
+            let address = self.pc & !3;
+            let address_space = self.program_space();
+            match self.mem.read_long(address_space, address & self.bus.address_mask) {
+                Ok(data) => self.prefetch_data = data,
+                Err(_) => return Err(self.bus_error(address, AccessType::Read, address_space)),
+            }
+            self.prefetch_addr = address;
             true
         } else {
             false
         };
         self.pc = self.pc.wrapping_add(2);
-        fetched
+        Ok(fetched)
     }
     pub fn read_imm_u32(&mut self) -> Result<u32> {
         if self.pc & 1 > 0 {
-            let address_space = if self.s_flag != 0 {SUPERVISOR_PROGRAM} else {USER_PROGRAM};
-            return Err(Exception::AddressError{address: self.pc, access_type: AccessType::Read, address_space, processing_state: self.processing_state})
+            let address_space = self.program_space();
+            return Err(self.address_error(self.pc, AccessType::Read, address_space))
+        }
+        if self.block_cache.is_some() {
+            let high = u32::from(self.read_imm_u16()?);
+            let low = u32::from(self.read_imm_u16()?);
+            return Ok(high << 16 | low);
         }
-        self.prefetch_if_needed();
+        self.bus_cycles += self.bus.extra_cycles(4);
+        self.prefetch_if_needed()?;
         let prev_prefetch_data = self.prefetch_data;
-        Ok(if self.prefetch_if_needed() {
+        let value = if self.prefetch_if_needed()? {
             ((prev_prefetch_data << 16) | (self.prefetch_data >> 16))
         } else {
             prev_prefetch_data
-        })
+        };
+        self.record_fetch((value >> 16) as u16);
+        self.record_fetch(value as u16);
+        Ok(value)
     }
     pub fn read_imm_i16(&mut self) -> Result<i16> {
         self.read_imm_u16().map(|val| val as i16)
@@ -614,147 +1114,249 @@ impl<T: InterruptController, A: AddressBus> ConfiguredCore<T, A> {
     pub fn read_imm_u16(&mut self) -> Result<u16> {
         // the Musashi read_imm_16 calls cpu_read_long as part of prefetch
         if self.pc & 1 > 0 {
-            let address_space = if self.s_flag != 0 {SUPERVISOR_PROGRAM} else {USER_PROGRAM};
-            return Err(Exception::AddressError{address: self.pc, access_type: AccessType::Read, address_space, processing_state: self.processing_state})
+            let address_space = self.program_space();
+            return Err(self.address_error(self.pc, AccessType::Read, address_space))
+        }
+        self.bus_cycles += self.bus.extra_cycles(2);
+        let word = if self.block_cache.is_some() {
+            self.read_imm_u16_cached()?
+        } else {
+            self.prefetch_if_needed()?;
+            ((self.prefetch_data >> ((2 - ((self.pc.wrapping_sub(2)) & 2))<<3)) & 0xffff) as u16
+        };
+        self.record_fetch(word);
+        Ok(word)
+    }
+    // keeps the words of the instruction for the tracer, which cannot
+    // read them back from memory without side effects
+    fn record_fetch(&mut self, word: u16) {
+        if self.fetched_words < TRACED_WORDS {
+            self.fetched[self.fetched_words] = word;
+            self.fetched_words += 1;
         }
-        self.prefetch_if_needed();
-        Ok(((self.prefetch_data >> ((2 - ((self.pc.wrapping_sub(2)) & 2))<<3)) & 0xffff) as u16)
     }
-    pub fn push_sp(&mut self) -> u32 {
+    // Reads a program word from the current block, or from memory if it
+    // is not there, recording it if a block is being recorded. Memory
+    // reads go around the prefetch, which would otherwise keep stale data
+    // after a write the cache has seen.
+    fn read_imm_u16_cached(&mut self) -> Result<u16> {
+        let pc = self.pc;
+        let supervisor = self.s_flag != SFLAG_CLEAR;
+        let cached = self.block_cache.as_ref().and_then(|cache| cache.word(pc, supervisor));
+        let word = match cached {
+            Some(word) => word,
+            None => {
+                let address_space = self.program_space();
+                let address = pc & self.bus.address_mask;
+                let word = match self.mem.read_word(address_space, address) {
+                    Ok(word) => word as u16,
+                    Err(_) => return Err(self.bus_error(pc, AccessType::Read, address_space)),
+                };
+                if let Some(ref mut cache) = self.block_cache {
+                    cache.record_word(pc, address, supervisor, word);
+                }
+                word
+            }
+        };
+        self.prefetch_addr = 1;
+        self.pc = pc.wrapping_add(2);
+        Ok(word)
+    }
+    pub fn push_sp(&mut self) -> Result<u32> {
          let new_sp = (Wrapping(self.dar[15]) - Wrapping(4)).0;
          self.dar[15] = new_sp;
-         self.write_data_long(new_sp, new_sp).unwrap();
-         new_sp
+         self.write_data_long(new_sp, new_sp)?;
+         Ok(new_sp)
     }
-    pub fn push_32(&mut self, value: u32) -> u32 {
+    pub fn push_32(&mut self, value: u32) -> Result<u32> {
          let new_sp = (Wrapping(self.dar[15]) - Wrapping(4)).0;
          self.dar[15] = new_sp;
-         self.write_data_long(new_sp, value).unwrap();
-         new_sp
+         self.write_data_long(new_sp, value)?;
+         Ok(new_sp)
     }
-    pub fn pop_32(&mut self) -> u32 {
+    pub fn pop_32(&mut self) -> Result<u32> {
         let sp = self.dar[15];
-        let data = self.read_data_long(sp).unwrap();
+        let data = self.read_data_long(sp)?;
         self.dar[15] = sp.wrapping_add(4);
-        data
+        Ok(data)
     }
-    pub fn push_16(&mut self, value: u16) -> u32 {
+    pub fn push_16(&mut self, value: u16) -> Result<u32> {
          let new_sp = (Wrapping(self.dar[15]) - Wrapping(2)).0;
          self.dar[15] = new_sp;
-         self.write_data_word(new_sp, u32::from(value)).unwrap();
-         new_sp
+         self.write_data_word(new_sp, u32::from(value))?;
+         Ok(new_sp)
     }
-    pub fn pop_16(&mut self) -> u16 {
+    pub fn pop_16(&mut self) -> Result<u16> {
         let sp = self.dar[15];
-        let data = self.read_data_word(sp).unwrap() as u16;
+        let data = self.read_data_word(sp)? as u16;
         self.dar[15] = sp.wrapping_add(2);
-        data
+        Ok(data)
+    }
+    // Watchpoints only see accesses that got through, at the address put
+    // on the bus
+    fn read_byte(&mut self, address_space: AddressSpace, address: u32) -> Result<u32> {
+        self.bus_cycles += self.bus.extra_cycles(1);
+        let masked = address & self.bus.address_mask;
+        let value = self.mem.read_byte(address_space, masked)
+            .map_err(|_| self.bus_error(address, AccessType::Read, address_space))?;
+        self.debugger.check_access(address_space, masked, 1, AccessType::Read);
+        Ok(value)
+    }
+    fn read_word(&mut self, address_space: AddressSpace, address: u32) -> Result<u32> {
+        if address & 1 > 0 && self.model.aligns_data() {
+            return Err(self.address_error(address, AccessType::Read, address_space))
+        }
+        self.bus_cycles += self.bus.extra_cycles(2);
+        let masked = address & self.bus.address_mask;
+        let value = self.mem.read_word(address_space, masked)
+            .map_err(|_| self.bus_error(address, AccessType::Read, address_space))?;
+        self.debugger.check_access(address_space, masked, 2, AccessType::Read);
+        Ok(value)
+    }
+    fn read_long(&mut self, address_space: AddressSpace, address: u32) -> Result<u32> {
+        if address & 1 > 0 && self.model.aligns_data() {
+            return Err(self.address_error(address, AccessType::Read, address_space))
+        }
+        self.bus_cycles += self.bus.extra_cycles(4);
+        let masked = address & self.bus.address_mask;
+        let value = self.mem.read_long(address_space, masked)
+            .map_err(|_| self.bus_error(address, AccessType::Read, address_space))?;
+        self.debugger.check_access(address_space, masked, 4, AccessType::Read);
+        Ok(value)
+    }
+    fn write_byte(&mut self, address_space: AddressSpace, address: u32, value: u32) -> Result<()> {
+        self.bus_cycles += self.bus.extra_cycles(1);
+        let masked = address & self.bus.address_mask;
+        match self.mem.write_byte(address_space, masked, value) {
+            Ok(()) => {
+                self.written(address_space, masked, 1);
+                Ok(())
+            }
+            Err(_) => Err(self.bus_error(address, AccessType::Write, address_space)),
+        }
+    }
+    fn write_word(&mut self, address_space: AddressSpace, address: u32, value: u32) -> Result<()> {
+        if address & 1 > 0 && self.model.aligns_data() {
+            return Err(self.address_error(address, AccessType::Write, address_space))
+        }
+        self.bus_cycles += self.bus.extra_cycles(2);
+        let masked = address & self.bus.address_mask;
+        match self.mem.write_word(address_space, masked, value) {
+            Ok(()) => {
+                self.written(address_space, masked, 2);
+                Ok(())
+            }
+            Err(_) => Err(self.bus_error(address, AccessType::Write, address_space)),
+        }
+    }
+    fn write_long(&mut self, address_space: AddressSpace, address: u32, value: u32) -> Result<()> {
+        if address & 1 > 0 && self.model.aligns_data() {
+            return Err(self.address_error(address, AccessType::Write, address_space))
+        }
+        self.bus_cycles += self.bus.extra_cycles(4);
+        let masked = address & self.bus.address_mask;
+        match self.mem.write_long(address_space, masked, value) {
+            Ok(()) => {
+                self.written(address_space, masked, 4);
+                Ok(())
+            }
+            Err(_) => Err(self.bus_error(address, AccessType::Write, address_space)),
+        }
+    }
+    // after a write got through to the bus
+    fn written(&mut self, address_space: AddressSpace, address: u32, bytes: u32) {
+        self.debugger.check_access(address_space, address, bytes, AccessType::Write);
+        if let Some(ref mut cache) = self.block_cache {
+            cache.invalidate(address, bytes);
+        }
     }
     pub fn read_data_byte(&mut self, address: u32) -> Result<u32> {
-        let address_space = if self.s_flag != 0 {SUPERVISOR_DATA} else {USER_DATA};
-        Ok(self.mem.read_byte(address_space, address))
+        let address_space = self.data_space();
+        self.read_byte(address_space, address)
     }
     pub fn read_program_byte(&mut self, address: u32) -> Result<u32> {
-        let address_space = if self.s_flag != 0 {SUPERVISOR_PROGRAM} else {USER_PROGRAM};
-        Ok(self.mem.read_byte(address_space, address))
+        let address_space = self.program_space();
+        self.read_byte(address_space, address)
     }
     pub fn write_data_byte(&mut self, address: u32, value: u32) -> Result<()> {
-        let address_space = if self.s_flag != 0 {SUPERVISOR_DATA} else {USER_DATA};
-        self.mem.write_byte(address_space, address, value);
-        Ok(())
+        let address_space = self.data_space();
+        self.write_byte(address_space, address, value)
     }
     pub fn write_program_byte(&mut self, address: u32, value: u32) -> Result<()> {
-        let address_space = if self.s_flag != 0 {SUPERVISOR_PROGRAM} else {USER_PROGRAM};
-        self.mem.write_byte(address_space, address, value);
-        Ok(())
+        let address_space = self.program_space();
+        self.write_byte(address_space, address, value)
     }
     pub fn read_data_word(&mut self, address: u32) -> Result<u32> {
-        let address_space = if self.s_flag != 0 {SUPERVISOR_DATA} else {USER_DATA};
-        if address & 1 > 0 {
-            Err(Exception::AddressError{address, access_type: AccessType::Read, address_space, processing_state: self.processing_state})
-        } else {
-            Ok(self.mem.read_word(address_space, address))
-        }
+        let address_space = self.data_space();
+        self.read_word(address_space, address)
     }
     pub fn read_program_word(&mut self, address: u32) -> Result<u32> {
-        let address_space = if self.s_flag != 0 {SUPERVISOR_PROGRAM} else {USER_PROGRAM};
-        if address & 1 > 0 {
-            Err(Exception::AddressError {address, access_type: AccessType::Read, address_space, processing_state: self.processing_state})
-        } else {
-            Ok(self.mem.read_word(address_space, address))
-        }
+        let address_space = self.program_space();
+        self.read_word(address_space, address)
     }
     pub fn write_data_word(&mut self, address: u32, value: u32) -> Result<()> {
-        let address_space = if self.s_flag != 0 {SUPERVISOR_DATA} else {USER_DATA};
-        if address & 1 > 0 {
-            Err(Exception::AddressError{address, access_type: AccessType::Write, address_space, processing_state: self.processing_state})
-        } else {
-            self.mem.write_word(address_space, address, value);
-            Ok(())
-        }
+        let address_space = self.data_space();
+        self.write_word(address_space, address, value)
     }
     pub fn write_program_word(&mut self, address: u32, value: u32) -> Result<()> {
-        let address_space = if self.s_flag != 0 {SUPERVISOR_PROGRAM} else {USER_PROGRAM};
-        if address & 1 > 0 {
-            Err(Exception::AddressError{address, access_type: AccessType::Write, address_space, processing_state: self.processing_state})
-        } else {
-            self.mem.write_word(address_space, address, value);
-            Ok(())
-        }
+        let address_space = self.program_space();
+        self.write_word(address_space, address, value)
     }
     pub fn read_data_long(&mut self, address: u32) -> Result<u32> {
-        let address_space = if self.s_flag != 0 {SUPERVISOR_DATA} else {USER_DATA};
-        if address & 1 > 0 {
-            Err(Exception::AddressError{address, access_type: AccessType::Read, address_space, processing_state: self.processing_state})
-        } else {
-            Ok(self.mem.read_long(address_space, address))
-        }
+        let address_space = self.data_space();
+        self.read_long(address_space, address)
     }
     pub fn read_program_long(&mut self, address: u32) -> Result<u32> {
-        let address_space = if self.s_flag != 0 {SUPERVISOR_PROGRAM} else {USER_PROGRAM};
-        if address & 1 > 0 {
-            Err(Exception::AddressError{address, access_type: AccessType::Read, address_space, processing_state: self.processing_state})
-        } else {
-            Ok(self.mem.read_long(address_space, address))
-        }
+        let address_space = self.program_space();
+        self.read_long(address_space, address)
     }
     pub fn write_data_long(&mut self, address: u32, value: u32) -> Result<()> {
-        let address_space = if self.s_flag != 0 {SUPERVISOR_DATA} else {USER_DATA};
-        if address & 1 > 0 {
-            Err(Exception::AddressError{address, access_type: AccessType::Write, address_space, processing_state: self.processing_state})
-        } else {
-            self.mem.write_long(address_space, address, value);
-            Ok(())
-        }
+        let address_space = self.data_space();
+        self.write_long(address_space, address, value)
     }
     pub fn write_program_long(&mut self, address: u32, value: u32) -> Result<()> {
-        let address_space = if self.s_flag != 0 {SUPERVISOR_PROGRAM} else {USER_PROGRAM};
-        if address & 1 > 0 {
-            Err(Exception::AddressError{address, access_type: AccessType::Write, address_space, processing_state: self.processing_state})
-        } else {
-            self.mem.write_long(address_space, address, value);
-            Ok(())
-        }
+        let address_space = self.program_space();
+        self.write_long(address_space, address, value)
     }
     pub fn jump(&mut self, pc: u32) {
         self.pc = pc;
     }
-    pub fn jump_vector(&mut self, vector: u8) {
-        let vector_address = u32::from(vector) << 2;
-        self.pc = self.read_data_long(vector_address).unwrap();
+    pub fn jump_vector(&mut self, vector: u8) -> Result<()> {
+        let vector_address = self.vbr.wrapping_add(u32::from(vector) << 2);
+        self.pc = self.read_data_long(vector_address)?;
+        Ok(())
     }
     pub fn ensure_supervisor_mode(&mut self) -> u16 {
         let backup_sr = self.status_register();
-        // if in user mode, swap stack pointers!
-        if self.s_flag == SFLAG_CLEAR {
-            self.inactive_usp = self.dar[15];
-            self.dar[15] = self.inactive_ssp;
-        }
-        // enter supervisor mode
+        // enter supervisor mode, and as all exception processing
+        // does, turn off tracing. If in user mode, swap stack pointers!
+        let (old_sflag, old_mflag) = (self.s_flag, self.m_flag);
         self.s_flag = SFLAG_SET;
+        self.t1_flag = TFLAG_CLEAR;
+        self.switch_stack_pointer(old_sflag, old_mflag);
         backup_sr
     }
+    // A bus or address error while stacking an exception frame or
+    // fetching the exception vector is a double fault, which halts the
+    // processor until an external reset
+    fn halt_on_double_fault(&mut self, stacking: Result<()>, cycles: Cycles) -> Cycles {
+        match stacking {
+            Ok(()) => cycles,
+            Err(_) => {
+                self.processing_state = ProcessingState::Halted;
+                Cycles(0)
+            }
+        }
+    }
     pub fn handle_address_error(&mut self, bad_address: u32, access_type: AccessType, processing_state: ProcessingState, address_space: AddressSpace) -> Cycles
+    {
+        self.handle_group0_exception(EXCEPTION_ADDRESS_ERROR, bad_address, access_type, processing_state, address_space)
+    }
+    pub fn handle_bus_error(&mut self, bad_address: u32, access_type: AccessType, processing_state: ProcessingState, address_space: AddressSpace) -> Cycles
+    {
+        self.handle_group0_exception(EXCEPTION_BUS_ERROR, bad_address, access_type, processing_state, address_space)
+    }
+    fn handle_group0_exception(&mut self, vector: u8, bad_address: u32, access_type: AccessType, processing_state: ProcessingState, address_space: AddressSpace) -> Cycles
     {
         if processing_state == ProcessingState::Group0Exception {
             self.processing_state = ProcessingState::Halted;
@@ -762,13 +1364,22 @@ impl<T: InterruptController, A: AddressBus> ConfiguredCore<T, A> {
         }
         self.processing_state = ProcessingState::Group0Exception;
         let backup_sr = self.ensure_supervisor_mode();
-
+        let stacking = match self.model {
+            CpuModel::M68000 => self.stack_group0_frame(backup_sr, bad_address, access_type, processing_state, address_space),
+            CpuModel::M68010 => self.stack_bus_fault_frame(vector, backup_sr, bad_address, access_type, address_space),
+            CpuModel::M68020 => self.stack_short_bus_fault_frame(vector, backup_sr, bad_address, access_type, address_space),
+        }.and_then(|_| self.jump_vector(vector));
+        let cycles = self.model.exception_cycles(vector);
+        self.halt_on_double_fault(stacking, Cycles(cycles))
+    }
+    fn stack_group0_frame(&mut self, backup_sr: u16, bad_address: u32, access_type: AccessType, processing_state: ProcessingState, address_space: AddressSpace) -> Result<()>
+    {
         // Bus error stack frame (68000 only).
         let (pc, ir) = (self.pc, self.ir);
-        self.push_32(pc);
-        self.push_16(backup_sr);
-        self.push_16(ir);
-        self.push_32(bad_address);    /* access address */
+        self.push_32(pc)?;
+        self.push_16(backup_sr)?;
+        self.push_16(ir)?;
+        self.push_32(bad_address)?;    /* access address */
         /* 0 0 0 0 0 0 0 0 0 0 0 R/W I/N FC
          * R/W  0 = write, 1 = read
          * I/N  0 = instruction, 1 = not
@@ -777,9 +1388,72 @@ impl<T: InterruptController, A: AddressBus> ConfiguredCore<T, A> {
         let access_info = match access_type {AccessType::Read => 0b10000, _ => 0 } |
             if processing_state.instruction_processing() { 0 } else { 0b01000 } |
             (address_space.fc() as u16);
-        self.push_16(access_info);
-        self.jump_vector(EXCEPTION_ADDRESS_ERROR);
-        Cycles(50)
+        self.push_16(access_info)?;
+        Ok(())
+    }
+    fn stack_bus_fault_frame(&mut self, vector: u8, backup_sr: u16, bad_address: u32, access_type: AccessType, address_space: AddressSpace) -> Result<()>
+    {
+        // Long bus fault stack frame, format $8 (68010 only). We don't
+        // keep the internal state the processor saves to be able to
+        // rerun the faulted bus cycle, so it is stacked as zeroes.
+        for _ in 0..16 {
+            self.push_16(0)?;           /* internal information */
+        }
+        let ir = self.ir;
+        self.push_16(ir)?;              /* instruction input buffer */
+        self.push_16(0)?;
+        self.push_16(0)?;               /* data input buffer */
+        self.push_16(0)?;
+        self.push_16(0)?;               /* data output buffer */
+        self.push_16(0)?;
+        self.push_32(bad_address)?;     /* fault address */
+        /* RR * IF DF RM HB BY RW * * * * * FC
+         * IF   instruction fetch
+         * DF   data fetch
+         * RW   0 = write, 1 = read
+         * FC   3-bit function code
+         */
+        let fetch = if address_space == SUPERVISOR_PROGRAM || address_space == USER_PROGRAM { 1 << 13 } else { 1 << 12 };
+        let special_status = fetch |
+            match access_type {AccessType::Read => 1 << 8, _ => 0 } |
+            (address_space.fc() as u16);
+        self.push_16(special_status)?;
+        self.push_16(0x8000 | u16::from(vector) << 2)?;
+        let pc = self.pc;
+        self.push_32(pc)?;
+        self.push_16(backup_sr)?;
+        Ok(())
+    }
+    fn stack_short_bus_fault_frame(&mut self, vector: u8, backup_sr: u16, bad_address: u32, access_type: AccessType, address_space: AddressSpace) -> Result<()>
+    {
+        // Short bus cycle fault stack frame, format $A (68020 only).
+        // Again, the internal state is stacked as zeroes.
+        self.push_16(0)?;
+        self.push_16(0)?;               /* internal registers */
+        self.push_32(0)?;               /* data output buffer */
+        self.push_16(0)?;
+        self.push_16(0)?;               /* internal registers */
+        self.push_32(bad_address)?;     /* data cycle fault address */
+        self.push_16(0)?;               /* instruction pipe stage B */
+        let ir = self.ir;
+        self.push_16(ir)?;              /* instruction pipe stage C */
+        /* FC FB RC RB * * * DF RM RW SIZE * FC
+         * FC/FB fault on stage C/B of the instruction pipe
+         * DF   data fault
+         * RW   0 = write, 1 = read
+         * FC   3-bit function code
+         */
+        let fault = if address_space == SUPERVISOR_PROGRAM || address_space == USER_PROGRAM { 1 << 14 | 1 << 12 } else { 1 << 8 };
+        let special_status = fault |
+            match access_type {AccessType::Read => 1 << 6, _ => 0 } |
+            (address_space.fc() as u16);
+        self.push_16(special_status)?;
+        self.push_16(0)?;               /* internal register */
+        self.push_16(0xa000 | u16::from(vector) << 2)?;
+        let pc = self.pc;
+        self.push_32(pc)?;
+        self.push_16(backup_sr)?;
+        Ok(())
     }
     pub fn handle_unimplemented_instruction(&mut self, pc: u32, vector: u8) -> Cycles {
         // somewhat unclear if the unimplemented instruction exceptions
@@ -791,29 +1465,75 @@ impl<T: InterruptController, A: AddressBus> ConfiguredCore<T, A> {
         // G2 exception stack frames are identical, so maybe it doesn't
         // really matter. EASy68k considers them group 2 exceptions. For
         // the time being, we do too.
-        self.handle_exception(ProcessingState::Group2Exception, pc, vector, 34)
+        let cycles = self.model.exception_cycles(vector);
+        self.handle_exception(ProcessingState::Group2Exception, pc, vector, cycles)
     }
     pub fn handle_illegal_instruction(&mut self, pc: u32) -> Cycles {
-        self.handle_exception(ProcessingState::Group1Exception, pc, EXCEPTION_ILLEGAL_INSTRUCTION, 34)
+        let cycles = self.model.exception_cycles(EXCEPTION_ILLEGAL_INSTRUCTION);
+        self.handle_exception(ProcessingState::Group1Exception, pc, EXCEPTION_ILLEGAL_INSTRUCTION, cycles)
     }
     pub fn handle_privilege_violation(&mut self, pc: u32) -> Cycles {
-        self.handle_exception(ProcessingState::Group1Exception, pc, EXCEPTION_PRIVILEGE_VIOLATION, 34)
+        let cycles = self.model.exception_cycles(EXCEPTION_PRIVILEGE_VIOLATION);
+        self.handle_exception(ProcessingState::Group1Exception, pc, EXCEPTION_PRIVILEGE_VIOLATION, cycles)
     }
     pub fn handle_trap(&mut self, trap: u8, cycles: i32) -> Cycles {
         let pc = self.pc;
+        // the ops calculate 68000 cycles, keep their EA calculation time
+        let cycles = cycles + self.model.exception_cycles(trap) - CpuModel::M68000.exception_cycles(trap);
         self.handle_exception(ProcessingState::Group2Exception, pc, trap, cycles)
     }
+    pub fn handle_trace(&mut self) -> Cycles {
+        // the stacked PC is the address of the next instruction, which
+        // also makes the trace exception nullify a STOP instruction
+        let pc = self.pc;
+        let cycles = self.model.exception_cycles(EXCEPTION_TRACE);
+        self.handle_exception(ProcessingState::Group1Exception, pc, EXCEPTION_TRACE, cycles)
+    }
 
     pub fn handle_exception(&mut self, new_state: ProcessingState, pc: u32, vector: u8, cycles: i32) -> Cycles {
         self.processing_state = new_state;
         let backup_sr = self.ensure_supervisor_mode();
 
-        // Group 1 and 2 stack frame (68000 only).
-        self.push_32(pc);
-        self.push_16(backup_sr);
-
-        self.jump_vector(vector);
-        Cycles(cycles)
+        let stacking = self.stack_short_frame(pc, backup_sr, vector)
+            .and_then(|_| self.jump_vector(vector));
+        self.halt_on_double_fault(stacking, Cycles(cycles))
+    }
+    fn stack_short_frame(&mut self, pc: u32, backup_sr: u16, vector: u8) -> Result<()> {
+        // The 68020 stacks the six word frame for the exceptions caused
+        // by an instruction (and for tracing it)
+        let format = match (self.model, vector) {
+            (CpuModel::M68020, EXCEPTION_ZERO_DIVIDE) |
+            (CpuModel::M68020, EXCEPTION_CHK) |
+            (CpuModel::M68020, EXCEPTION_TRAPV) |
+            (CpuModel::M68020, EXCEPTION_TRACE) => 2,
+            _ => 0,
+        };
+        self.stack_frame(format, pc, backup_sr, vector)
+    }
+    fn stack_frame(&mut self, format: u16, pc: u32, backup_sr: u16, vector: u8) -> Result<()> {
+        // Group 1 and 2 stack frame. The 68010 adds a format/vector word,
+        // format $0 being the short frame, $1 the 68020 throwaway frame,
+        // and $2 also holding the address of the instruction.
+        if self.model != CpuModel::M68000 {
+            if format == 2 {
+                let ppc = self.ppc;
+                self.push_32(ppc)?;
+            }
+            self.push_16(format << 12 | u16::from(vector) << 2)?;
+        }
+        self.push_32(pc)?;
+        self.push_16(backup_sr)?;
+        Ok(())
+    }
+    // An interrupt taken in master mode (68020) stacks its frame on the
+    // MSP, then clears M and leaves a throwaway frame on the ISP
+    fn stack_throwaway_frame(&mut self, pc: u32, backup_sr: u16, vector: u8) -> Result<()> {
+        if self.m_flag == MFLAG_CLEAR {
+            return Ok(());
+        }
+        self.m_flag = MFLAG_CLEAR;
+        self.switch_stack_pointer(SFLAG_SET, MFLAG_SET);
+        self.stack_frame(1, pc, backup_sr | 0x2000, vector)
     }
 
     pub fn handle_interrupt(&mut self, irq_level: u8, vector: u8) -> Cycles {
@@ -826,15 +1546,12 @@ impl<T: InterruptController, A: AddressBus> ConfiguredCore<T, A> {
 
         // Musashi jumps first, and stacks later for interrupts,
         // but the other way around for exceptions
-        self.jump_vector(vector);
-
-        // Group 1 and 2 stack frame (68000 only).
-        self.push_32(pc);
-        self.push_16(backup_sr);
+        let stacking = self.jump_vector(vector)
+            .and_then(|_| self.stack_short_frame(pc, backup_sr, vector))
+            .and_then(|_| self.stack_throwaway_frame(pc, backup_sr, vector));
 
-        // 44 cycles for an interrupt according to MC68000UM, Table 8-14
-        // The interrupt acknowledge cycle is assumed to take four clock periods
-        Cycles(44)
+        let cycles = self.model.interrupt_cycles();
+        self.halt_on_double_fault(stacking, Cycles(cycles))
     }
     fn stopped_with_pending_interrups(&mut self) -> bool {
         self.processing_state == ProcessingState::Stopped && self.pending_interrupt().is_some()
@@ -852,74 +1569,250 @@ impl<T: InterruptController, A: AddressBus> ConfiguredCore<T, A> {
             None
         }
     }
-    pub fn read_instruction(&mut self) -> Result<u16> {
-        // first check for interrupts
+    fn check_interrupts(&mut self) -> Result<()> {
         if let Some(irq) = self.pending_interrupt() {
             let vector = self.int_ctrl.acknowledge_interrupt(irq).unwrap_or(SPURIOUS_INTERRUPT);
             Err(Exception::Interrupt(irq, vector))
         } else {
-            // not interrupted, read instruction from PC
-            self.read_imm_u16()
+            // keep track of the interrupt level, so that a level 7
+            // interrupt needs a new edge to be taken again
+            self.irq_level = self.int_ctrl.highest_priority();
+            Ok(())
+        }
+    }
+    pub fn read_instruction(&mut self) -> Result<u16> {
+        // first check for interrupts
+        self.check_interrupts()?;
+        // not interrupted, read instruction from PC
+        self.read_imm_u16()
+    }
+    // Reads the instruction at PC and its handler, from the block cache
+    // if there is one. Interrupts are only checked between blocks.
+    fn read_cached_instruction(&mut self) -> Result<(u16, Handler<ConfiguredCore<T, A>>)> {
+        let pc = self.pc;
+        let supervisor = self.s_flag != SFLAG_CLEAR;
+        let (mut cached, between_blocks) = match self.block_cache {
+            Some(ref mut cache) => {
+                let op = cache.next_op(pc, supervisor);
+                (op, op.is_none() && !cache.is_recording())
+            }
+            None => (None, false),
+        };
+        if between_blocks {
+            self.check_interrupts()?;
+            let generation = self.mem.map_generation();
+            if let Some(ref mut cache) = self.block_cache {
+                cache.follow_map(generation);
+                cached = cache.enter(pc, supervisor);
+                if cached.is_none() {
+                    cache.record(pc, supervisor);
+                }
+            }
+        }
+        if let Some((opcode, handler)) = cached {
+            if pc & 1 > 0 {
+                let address_space = self.program_space();
+                return Err(self.address_error(pc, AccessType::Read, address_space))
+            }
+            self.bus_cycles += self.bus.extra_cycles(2);
+            self.pc = pc.wrapping_add(2);
+            self.record_fetch(opcode);
+            return Ok((opcode, handler));
         }
+        let opcode = self.read_imm_u16()?;
+        let handler = self.instruction_set[opcode as usize];
+        if let Some(ref mut cache) = self.block_cache {
+            cache.record_op(pc, opcode, handler);
+        }
+        Ok((opcode, handler))
     }
     pub fn execute1(&mut self) -> Cycles {
         self.execute(1)
     }
     pub fn execute(&mut self, cycles: i32) -> Cycles {
-        self.execute_with_state(cycles, &mut EmulateAllExceptions)
+        self.execute_with_state(cycles, &mut EmulateAllExceptions).0
+    }
+    fn handle_result<S: Callbacks<Self>>(&mut self, result: Result<Cycles>, state: &mut S) -> Cycles {
+        match result {
+            Ok(cycles_used) => cycles_used,
+            Err(ex) => {
+                let handled = state.exception_callback(self, ex);
+                // exceptions handled by the callbacks are not processed
+                self.exception_taken |= handled.is_err();
+                match handled {
+                    Ok(cycles_used) => cycles_used,
+                    Err(Exception::AddressError { address, access_type, processing_state, address_space }) =>
+                        self.handle_address_error(address, access_type, processing_state, address_space),
+                    Err(Exception::BusError { address, access_type, processing_state, address_space }) =>
+                        self.handle_bus_error(address, access_type, processing_state, address_space),
+                    Err(Exception::IllegalInstruction(_, pc)) =>
+                        self.handle_illegal_instruction(pc),
+                    Err(Exception::UnimplementedInstruction(_, pc, vector)) =>
+                        self.handle_unimplemented_instruction(pc, vector),
+                    Err(Exception::Trap(num, ea_calculation_cycles)) =>
+                        self.handle_trap(num, ea_calculation_cycles),
+                    Err(Exception::PrivilegeViolation(_, pc)) =>
+                        self.handle_privilege_violation(pc),
+                    Err(Exception::Interrupt(irq, vec)) =>
+                        self.handle_interrupt(irq, vec),
+                    Err(Exception::Trace) =>
+                        self.handle_trace(),
+                }
+            }
+        }
+    }
+    fn trace_instruction(&mut self, pc: u32, supervisor: bool, cycles: Cycles) {
+        if let Some(mut tracer) = self.tracer.take() {
+            let mut code = [0u16; TRACED_WORDS];
+            code[..self.fetched_words].copy_from_slice(&self.fetched[..self.fetched_words]);
+            let line = TraceLine {
+                pc, supervisor, ir: self.ir, code, dar: self.dar,
+                sr: self.status_register(), flags: self.flags(), cycles
+            };
+            // a failing tracer is dropped
+            if tracer.trace(&line).is_ok() {
+                self.tracer = Some(tracer);
+            }
+        }
     }
-    pub fn execute_with_state<S: Callbacks>(&mut self, cycles: i32, state: &mut S) -> Cycles {
+    // Executes instructions until the given cycles are consumed, or a
+    // breakpoint or watchpoint hits, or the core stops or halts. A
+    // breakpoint at the PC execution starts from does not hit, so that
+    // execution can be resumed from it.
+    pub fn execute_with_state<S: Callbacks<Self>>(&mut self, cycles: i32, state: &mut S) -> (Cycles, StopReason) {
         let cycles = Cycles(cycles);
         let mut remaining_cycles = cycles;
+        let mut stop_reason = StopReason::CyclesConsumed;
+        let mut resuming = true;
         while remaining_cycles.any() && self.can_execute() {
+            let pc = self.pc;
+            if !resuming && self.debugger.is_breakpoint(pc) {
+                stop_reason = StopReason::Breakpoint(pc);
+                break;
+            }
+            resuming = false;
+            // Tracing is decided by the T bit at the start of the
+            // instruction, so an instruction that clears T is traced,
+            // while one that sets T is not
+            let tracing = self.t1_flag != TFLAG_CLEAR;
+            let supervisor = self.s_flag != SFLAG_CLEAR;
+            let cycles_before = remaining_cycles;
+            self.ppc = pc;
+            self.bus_cycles = 0;
+            self.exception_taken = false;
+            self.fetched_words = 0;
             // Read an instruction from PC (increments PC by 2)
-            let result = self.read_instruction().and_then(|opcode| {
+            let opcode = if self.block_cache.is_some() {
+                self.read_cached_instruction()
+            } else {
+                self.read_instruction().map(|opcode| (opcode, self.instruction_set[opcode as usize]))
+            };
+            let fetched = opcode.is_ok();
+            let mut skipped = false;
+            let result = match opcode {
+                Ok((opcode, handler)) => {
                     self.ir = opcode;
-                    // Call instruction handler to mutate Core accordingly
-                    self.instruction_set[opcode as usize](self)
-                });
-            remaining_cycles = remaining_cycles - match result {
-                Ok(cycles_used) => cycles_used,
-                Err(ex) => {
-                    match state.exception_callback(self, ex) {
-                        Ok(cycles_used) => cycles_used,
-                        Err(Exception::AddressError { address, access_type, processing_state, address_space }) =>
-                            self.handle_address_error(address, access_type, processing_state, address_space),
-                        Err(Exception::IllegalInstruction(_, pc)) =>
-                            self.handle_illegal_instruction(pc),
-                        Err(Exception::UnimplementedInstruction(_, pc, vector)) =>
-                            self.handle_unimplemented_instruction(pc, vector),
-                        Err(Exception::Trap(num, ea_calculation_cycles)) =>
-                            self.handle_trap(num, ea_calculation_cycles),
-                        Err(Exception::PrivilegeViolation(_, pc)) =>
-                            self.handle_privilege_violation(pc),
-                        Err(Exception::Interrupt(irq, vec)) =>
-                            self.handle_interrupt(irq, vec),
+                    match state.before_instruction(self, pc, opcode) {
+                        // Call instruction handler to mutate Core accordingly
+                        BeforeInstruction::Execute => handler(self),
+                        BeforeInstruction::Replace(opcode) => {
+                            self.ir = opcode;
+                            (self.instruction_set[opcode as usize])(self)
+                        }
+                        BeforeInstruction::Skip(cycles) => {
+                            skipped = true;
+                            Ok(cycles)
+                        }
+                        BeforeInstruction::Stop => {
+                            self.pc = pc;
+                            if let Some(ref mut cache) = self.block_cache {
+                                cache.end_instruction(pc, supervisor, true);
+                            }
+                            stop_reason = StopReason::Requested;
+                            break;
+                        }
                     }
                 }
+                Err(ex) => Err(ex),
+            };
+            let reset = !skipped && result.is_ok() && u32::from(self.ir) == ops::opcodes::OP_RESET;
+            // The trace exception is taken once the instruction has
+            // completed, which is also the case for instructions causing
+            // group 2 exceptions (TRAP, TRAPV, CHK, DIV by zero), after
+            // their exception processing. Group 0 and 1 exceptions
+            // (including interrupts) abort or preempt the instruction, and
+            // no trace exception occurs.
+            let completed = match result {
+                Ok(_) | Err(Exception::Trap(_, _)) => true,
+                Err(_) => false,
+            };
+            remaining_cycles = remaining_cycles - self.handle_result(result, state);
+            if tracing && completed {
+                remaining_cycles = remaining_cycles - self.handle_result(Err(Exception::Trace), state);
+            }
+            remaining_cycles = remaining_cycles - Cycles(self.bus_cycles);
+            if let Some(ref mut cache) = self.block_cache {
+                let interrupted = self.exception_taken || self.processing_state != ProcessingState::Normal;
+                cache.end_instruction(self.pc, self.s_flag != SFLAG_CLEAR, interrupted);
+            }
+            if fetched && self.tracer.is_some() {
+                self.trace_instruction(pc, supervisor, cycles_before - remaining_cycles);
+            }
+            if let Some(profiler) = self.profiler.as_mut() {
+                let opcode = if fetched { Some(self.ir) } else { None };
+                profiler.record(pc, opcode, cycles_before - remaining_cycles, self.pc, self.exception_taken);
+            }
+            let mut flow = if fetched {
+                state.after_instruction(self, cycles_before - remaining_cycles)
+            } else {
+                Flow::Continue
             };
+            if reset && state.reset_instruction(self) == Flow::Stop {
+                flow = Flow::Stop;
+            }
+            match self.processing_state {
+                ProcessingState::Stopped => state.stopped(self),
+                ProcessingState::Halted => state.halted(self),
+                _ => (),
+            }
+            if let Some(hit) = self.debugger.take_hit(pc) {
+                stop_reason = StopReason::Watchpoint(hit);
+                break;
+            }
+            if flow == Flow::Stop {
+                stop_reason = StopReason::Requested;
+                break;
+            }
         }
-        if self.processing_state.running() {
-            cycles - remaining_cycles
-        } else {
-            // if not running, consume all available cycles
-            // including overconsumed cycles
-            let adjust = if remaining_cycles.0 < 0 { remaining_cycles } else { Cycles(0) };
-            cycles - adjust
+        match self.processing_state {
+            ProcessingState::Stopped | ProcessingState::Halted => {
+                // if not running, consume all available cycles
+                // including overconsumed cycles
+                let adjust = if remaining_cycles.0 < 0 { remaining_cycles } else { Cycles(0) };
+                let stop_reason = if self.processing_state == ProcessingState::Halted {
+                    StopReason::Halted
+                } else {
+                    StopReason::Stopped
+                };
+                (cycles - adjust, stop_reason)
+            }
+            _ => (cycles - remaining_cycles, stop_reason)
         }
     }
 }
 
 impl Clone for TestCore {
     fn clone(&self) -> Self {
-        let mut lm = LoggingMem::new(self.mem.initializer, OpsLogger::new());
+        let mut lm = LoggingMem::with_address_mask(self.mem.initializer, OpsLogger::new(), self.mem.address_mask());
         lm.copy_from(&self.mem);
         assert_eq!(0, lm.logger.len());
         TestCore {
             pc: self.pc, prefetch_addr: 0, prefetch_data: 0, inactive_ssp: self.inactive_ssp, inactive_usp: self.inactive_usp, ir: self.ir, processing_state: self.processing_state,
-            dar: self.dar, mem: lm, instruction_set: ops::instruction_set(),
-            irq_level: 0, int_ctrl: AutoInterruptController::new(),
-            s_flag: self.s_flag, int_mask: self.int_mask, x_flag: self.x_flag, v_flag: self.v_flag, c_flag: self.c_flag, n_flag: self.n_flag, not_z_flag: self.not_z_flag
+            dar: self.dar, mem: lm, instruction_set: instruction_set(self.model, self.fpu.is_some()),
+            irq_level: self.irq_level, int_ctrl: self.int_ctrl.clone(), model: self.model, vbr: self.vbr, sfc: self.sfc, dfc: self.dfc,
+            m_flag: self.m_flag, inactive_msp: self.inactive_msp, cacr: self.cacr, caar: self.caar, fpu: self.fpu.clone(), bus: self.bus, bus_cycles: 0, exception_taken: false, fetched: [0; TRACED_WORDS], fetched_words: 0, block_cache: None, ppc: self.ppc,
+            s_flag: self.s_flag, t1_flag: self.t1_flag, int_mask: self.int_mask, x_flag: self.x_flag, v_flag: self.v_flag, c_flag: self.c_flag, n_flag: self.n_flag, not_z_flag: self.not_z_flag,
+            debugger: self.debugger.clone(), tracer: None, profiler: None
         }
     }
 }
@@ -928,11 +1821,25 @@ impl Clone for TestCore {
 mod tests {
     use super::{TestCore, Cycles};
     use super::ops; //::instruction_set;
+    use std::sync::Arc;
     use ram::{AddressBus, SUPERVISOR_PROGRAM, USER_PROGRAM, USER_DATA};
     use ram::loggingmem::Operation;
     use cpu::ops::opcodes;
+    use cpu::fpu::{Fpu, FpuModel, Extended, FPSR_CC_Z, FPSR_CC_N, FPSR_DZ};
     use r68k_common::constants;
 
+    #[test]
+    fn cores_of_a_kind_share_their_instruction_set() {
+        let cpu = TestCore::new(0);
+        let mut other = TestCore::new(0);
+        assert!(Arc::ptr_eq(&cpu.instruction_set, &other.instruction_set));
+        other.set_model(CpuModel::M68010);
+        assert!(!Arc::ptr_eq(&cpu.instruction_set, &other.instruction_set));
+        let mut clone = cpu.clone();
+        clone.set_model(CpuModel::M68010);
+        assert!(Arc::ptr_eq(&other.instruction_set, &clone.instruction_set));
+    }
+
     #[test]
     fn new_sets_pc() {
         let cpu = TestCore::new(256);
@@ -944,8 +1851,8 @@ mod tests {
         let base = 128;
         let cpu = TestCore::new_mem(base, &[1u8, 2u8, 3u8, 4u8, 5u8, 6u8]);
         assert_eq!(128, cpu.pc);
-        assert_eq!(1, cpu.mem.read_byte(SUPERVISOR_PROGRAM, 128));
-        assert_eq!(2, cpu.mem.read_byte(SUPERVISOR_PROGRAM, 129));
+        assert_eq!(1, cpu.mem.read_byte(SUPERVISOR_PROGRAM, 128).unwrap());
+        assert_eq!(2, cpu.mem.read_byte(SUPERVISOR_PROGRAM, 129).unwrap());
     }
 
     #[test]
@@ -1029,7 +1936,7 @@ mod tests {
     #[test]
     fn execute_can_execute_instruction_handler_0a() {
         let mut cpu = TestCore::new_mem(0xba, &[0x00, 0x0A, 1u8,0u8, 0u8,0u8,0u8,128u8]);
-        cpu.instruction_set = ops::fake::instruction_set();
+        cpu.instruction_set = Arc::new(ops::fake::instruction_set());
         cpu.execute1();
         assert_eq!(0xabcd, cpu.dar[0]);
         assert_eq!(0x0000, cpu.dar[1]);
@@ -1038,7 +1945,7 @@ mod tests {
     #[test]
     fn execute_can_execute_instruction_handler_0b() {
         let mut cpu = TestCore::new_mem(0xba, &[0x00, 0x0B, 1u8,0u8, 0u8,0u8,0u8,128u8]);
-        cpu.instruction_set = ops::fake::instruction_set();
+        cpu.instruction_set = Arc::new(ops::fake::instruction_set());
         cpu.execute1();
         assert_eq!(0x0000, cpu.dar[0]);
         assert_eq!(0xbcde, cpu.dar[1]);
@@ -1056,7 +1963,7 @@ mod tests {
         // 4c == D6
         // 4e == D7
         let mut cpu = TestCore::new_mem(0x40, &[0x4c, 0x00, 1u8, 0u8]);
-        cpu.instruction_set = ops::fake::instruction_set();
+        cpu.instruction_set = Arc::new(ops::fake::instruction_set());
         cpu.execute1();
         assert_eq!(0xcdef, cpu.dar[6]);
     }
@@ -1112,10 +2019,10 @@ mod tests {
 
         cpu.dar[8+0] = 0x160+1;
         cpu.dar[8+1] = 0x260+1;
-        cpu.mem.write_byte(USER_DATA, 0x160, 0x16);
-        cpu.mem.write_byte(USER_DATA, 0x260, 0x26);
+        cpu.mem.write_byte(USER_DATA, 0x160, 0x16).unwrap();
+        cpu.mem.write_byte(USER_DATA, 0x260, 0x26).unwrap();
         cpu.execute1();
-        let res = cpu.mem.read_byte(USER_DATA, 0x260);
+        let res = cpu.mem.read_byte(USER_DATA, 0x260).unwrap();
 
         // 16 + 26 is 42
         assert_eq!(0x42, res);
@@ -1148,7 +2055,7 @@ mod tests {
         let mut cpu = TestCore::new_mem(0x40, &[0xd2, 0x18]);
         let addr = 0x100;
         cpu.dar[8+0] = addr;
-        cpu.mem.write_byte(USER_DATA, addr, 16);
+        cpu.mem.write_byte(USER_DATA, addr, 16).unwrap();
         cpu.dar[1] = 26;
         cpu.execute1();
 
@@ -1167,7 +2074,7 @@ mod tests {
         let mut cpu = TestCore::new_mem(0x40, &[0xd2, 0x20]);
         let addr = 0x100;
         cpu.dar[8+0] = addr;
-        cpu.mem.write_byte(USER_DATA, addr-1, 16);
+        cpu.mem.write_byte(USER_DATA, addr-1, 16).unwrap();
         cpu.dar[1] = 26;
         cpu.execute1();
 
@@ -1187,7 +2094,7 @@ mod tests {
 
         let addr = 0x100;
         cpu.dar[8+0] = addr;
-        cpu.mem.write_byte(USER_DATA, addr, 16);
+        cpu.mem.write_byte(USER_DATA, addr, 16).unwrap();
         cpu.dar[1] = 26;
         cpu.execute1();
 
@@ -1207,7 +2114,7 @@ mod tests {
         let addr = 0x100;
         cpu.dar[8+0] = addr;
         let displaced_addr = addr + 0x108;
-        cpu.mem.write_byte(USER_DATA, displaced_addr, 16);
+        cpu.mem.write_byte(USER_DATA, displaced_addr, 16).unwrap();
         cpu.dar[1] = 26;
         cpu.execute1();
 
@@ -1227,7 +2134,7 @@ mod tests {
         let addr = 0x100;
         cpu.dar[8+0] = addr;
         let displaced_addr = addr - 2;
-        cpu.mem.write_byte(USER_DATA, displaced_addr, 16);
+        cpu.mem.write_byte(USER_DATA, displaced_addr, 16).unwrap();
         cpu.dar[1] = 26;
         cpu.execute1();
 
@@ -1250,7 +2157,7 @@ mod tests {
         cpu.dar[8+0] = addr;
         cpu.dar[8+1] = index;
         let effective_addr = addr + index + displacement;
-        cpu.mem.write_byte(USER_DATA, effective_addr, 16);
+        cpu.mem.write_byte(USER_DATA, effective_addr, 16).unwrap();
         cpu.dar[1] = 26;
         cpu.execute1();
 
@@ -1273,7 +2180,7 @@ mod tests {
         cpu.dar[8+0] = addr;
         cpu.dar[8+1] = index;
         let effective_addr = addr + index - displacement;
-        cpu.mem.write_byte(USER_DATA, effective_addr, 16);
+        cpu.mem.write_byte(USER_DATA, effective_addr, 16).unwrap();
         cpu.dar[1] = 26;
         cpu.execute1();
 
@@ -1290,7 +2197,7 @@ mod tests {
 
         // opcodes d238,0108 is ADD.B    $0108, D1
         let mut cpu = TestCore::new_mem(0x40, &[0xd2, 0x38, 0x01, 0x08]);
-        cpu.mem.write_byte(USER_DATA, 0x108, 16);
+        cpu.mem.write_byte(USER_DATA, 0x108, 16).unwrap();
         cpu.dar[1] = 26;
         cpu.execute1();
 
@@ -1308,7 +2215,7 @@ mod tests {
 
         // opcodes d239,0009,0000 is ADD.B    $90000, D1
         let mut cpu = TestCore::new_mem(0x40, &[0xd2, 0x39, 0x00, 0x09, 0x00, 0x00]);
-        cpu.mem.write_byte(USER_DATA, 0x90000, 16);
+        cpu.mem.write_byte(USER_DATA, 0x90000, 16).unwrap();
         cpu.dar[1] = 26;
         cpu.execute1();
 
@@ -1324,7 +2231,7 @@ mod tests {
         // opcodes d23a,0108 is ADD.B    ($0108, PC), D1
         let mut cpu = TestCore::new_mem(0x40, &[0xd2, 0x3a, 0x01, 0x08]);
         let addr = 0x40+2+0x0108;
-        cpu.mem.write_byte(USER_DATA, addr, 16);
+        cpu.mem.write_byte(USER_DATA, addr, 16).unwrap();
         cpu.dar[1] = 26;
         cpu.execute1();
 
@@ -1344,7 +2251,7 @@ mod tests {
         let displacement = 2;
         cpu.dar[8+1] = index;
         let effective_addr = addr + index + displacement;
-        cpu.mem.write_byte(USER_DATA, effective_addr, 16);
+        cpu.mem.write_byte(USER_DATA, effective_addr, 16).unwrap();
         cpu.dar[1] = 26;
         cpu.execute1();
 
@@ -1419,6 +2326,18 @@ mod tests {
         assert_eq!(0x32, clone.dar[1]);
     }
 
+    #[test]
+    fn clones_keep_pending_interrupts() {
+        let mut core = TestCore::new_mem(0x40, &[0xd2, 0x00]); // d200 is ADD.B D0, D1
+        core.sr_to_flags(1 << 13);
+        core.int_ctrl.request_interrupt(4);
+        let mut clone = core.clone();
+        assert_eq!(Some(4), clone.pending_interrupt());
+        assert_eq!(Cycles(44), clone.execute1());
+        assert_eq!(None, clone.pending_interrupt());
+        assert_eq!(Some(4), core.pending_interrupt());
+    }
+
     #[test]
     fn user_mode_chk_16_pd_with_trap_uses_sp_correctly() {
         let mut cpu = TestCore::new_mem(0x40, &[0x41, 0xa7]); // 0x41a7 CHK.W -(A7), D0
@@ -1467,7 +2386,7 @@ mod tests {
     fn core_can_stop() {
         let initial_pc = 0x40;
         let mut cpu = TestCore::new_mem_init(initial_pc, &[0x4e, 0x72, 0x00, 0x00], opcodes::OP_NOP);
-        cpu.sr_to_flags(0xffff); // Supa mode
+        cpu.sr_to_flags(0x7fff); // Supa mode, but not tracing (which would resume from STOP)
         cpu.execute1();
         assert_eq!(0x0000, cpu.status_register());
         let next_instruction = initial_pc + 2 + 2; // 40 + instruction word + immediate word
@@ -1580,9 +2499,9 @@ mod tests {
         let vec4handler = 0x2F0000;
         let autovector_base = 24;
         let irq = 5;
-        cpu.mem.write_long(SUPERVISOR_PROGRAM, (autovector_base + irq) * 4 , vec4handler);
+        cpu.mem.write_long(SUPERVISOR_PROGRAM, (autovector_base + irq) * 4 , vec4handler).unwrap();
         // opcodes d278,0108 is ADD.W    $0108, D1
-        cpu.mem.write_long(SUPERVISOR_PROGRAM, vec4handler, 0xd2780108);
+        cpu.mem.write_long(SUPERVISOR_PROGRAM, vec4handler, 0xd2780108).unwrap();
         cpu.execute1(); // will execute STOP
         assert_eq!(super::ProcessingState::Stopped, cpu.processing_state);
         cpu.int_ctrl.request_interrupt(irq as u8);
@@ -1614,7 +2533,7 @@ mod tests {
         // (except external reset) occurs in a group 0 exception handler
         let mut cpu = TestCore::new_mem(0x41, &[0xd2, 0x00]); // d200 is ADD.B D0, D1
         let address_error_handler = 0x2F0001;
-        cpu.mem.write_long(SUPERVISOR_PROGRAM, super::EXCEPTION_ADDRESS_ERROR as u32 * 4, address_error_handler);
+        cpu.mem.write_long(SUPERVISOR_PROGRAM, super::EXCEPTION_ADDRESS_ERROR as u32 * 4, address_error_handler).unwrap();
         // opcodes d278,0108 is ADD.W    $0108, D1
         cpu.execute1(); // will execute at an odd address, invoking the address error exception handler
         assert_eq!(super::ProcessingState::Group0Exception, cpu.processing_state);
@@ -1636,7 +2555,8 @@ mod tests {
         assert_eq!(super::ProcessingState::Halted, cpu.processing_state);
     }
 
-    use cpu::{Result, Callbacks, Exception, Core};
+    use cpu::{Result, Callbacks, Exception, Core, BeforeInstruction, Flow};
+    use cpu::debugger::StopReason;
 
     struct CustomExceptionHandler
     {
@@ -1645,8 +2565,8 @@ mod tests {
         ex: Option<Exception>
     }
 
-    impl Callbacks for CustomExceptionHandler {
-        fn exception_callback(&mut self, core: &mut impl Core, ex: Exception) -> Result<Cycles> {
+    impl<C: Core> Callbacks<C> for CustomExceptionHandler {
+        fn exception_callback(&mut self, core: &mut C, ex: Exception) -> Result<Cycles> {
             self.count += 1;
             self.ex = Some(ex);
 
@@ -1672,7 +2592,7 @@ mod tests {
         cpu.write_data_long(super::EXCEPTION_ADDRESS_ERROR as u32 * 4, address_error_handler).unwrap();
 
         let mut handler = CustomExceptionHandler { suppress: false, count: 0, ex: None };
-        let cycles = cpu.execute_with_state(1, &mut handler);
+        let cycles = cpu.execute_with_state(1, &mut handler).0;
         assert_eq!(1, handler.count);
         assert_eq!(Cycles(50), cycles); // expected number of cycles for initiating an AddressError exception
         assert_eq!(address_error_handler, cpu.pc);
@@ -1688,7 +2608,7 @@ mod tests {
         handler.suppress = true;
         // setup an odd PC, in order to cause another Address Error
         cpu.pc = odd_subsequent_address;
-        let cycles = cpu.execute_with_state(1, &mut handler);
+        let cycles = cpu.execute_with_state(1, &mut handler).0;
         assert_eq!(2, handler.count);
         assert_eq!(Cycles(1000), cycles); // expected number of cycles we faked in our exception_callback
         assert_eq!(odd_subsequent_address + 1, cpu.pc); // our exception_callback jumps to the next even address
@@ -1698,4 +2618,1370 @@ mod tests {
             assert!(false);
         }
     }
+
+    #[test]
+    fn trace_exception_follows_traced_instruction() {
+        let mut cpu = TestCore::new_mem(0x40, &[0xd2, 0x00]); // d200 is ADD.B D0, D1
+        let trace_handler = 0x1010;
+        cpu.write_data_long(super::EXCEPTION_TRACE as u32 * 4, trace_handler).unwrap();
+        cpu.dar[15] = 0x200;
+        cpu.sr_to_flags(0xa700); // trace, supervisor, mask 7
+        assert_eq!("TS7-----", cpu.flags());
+        cpu.dar[0] = 16;
+        cpu.dar[1] = 26;
+
+        // the ADD.B (4 cycles) is executed, followed by the trace exception (34 cycles)
+        assert_eq!(Cycles(4 + 34), cpu.execute1());
+        assert_eq!(42, cpu.dar[1]);
+        assert_eq!(trace_handler, cpu.pc);
+        assert_eq!(super::ProcessingState::Group1Exception, cpu.processing_state);
+        assert_eq!("-S7-----", cpu.flags()); // tracing is off in the handler
+        assert_eq!(0x200-6, cpu.dar[15]);
+        // the stacked PC points to the next instruction, and the stacked SR still has T set
+        assert_eq!(0x42, cpu.read_data_long(0x200-4).unwrap());
+        assert_eq!(0xa700, cpu.read_data_word(0x200-6).unwrap());
+    }
+
+    #[test]
+    fn rte_to_traced_code_single_steps() {
+        // d200 is ADD.B D0, D1
+        let mut cpu = TestCore::new_mem(0x40, &[0xd2, 0x00, 0xd2, 0x00, 0xd2, 0x00]);
+        let trace_handler = 0x1010;
+        cpu.write_data_long(super::EXCEPTION_TRACE as u32 * 4, trace_handler).unwrap();
+        cpu.write_data_word(trace_handler, opcodes::OP_RTE_32).unwrap(); // handler is just RTE
+        cpu.dar[15] = 0x200;
+        cpu.sr_to_flags(0xa700);
+        cpu.dar[0] = 1;
+
+        cpu.execute1(); // ADD.B and trace
+        assert_eq!(trace_handler, cpu.pc);
+        assert_eq!(1, cpu.dar[1]);
+        cpu.execute1(); // RTE is not traced, as T is clear in the handler
+        assert_eq!(0x42, cpu.pc);
+        assert_eq!(0xa700, cpu.status_register());
+        cpu.execute1(); // second ADD.B and trace
+        assert_eq!(trace_handler, cpu.pc);
+        assert_eq!(2, cpu.dar[1]);
+    }
+
+    #[test]
+    fn instruction_setting_trace_bit_is_not_traced() {
+        // 46fc a700 is MOVE #$a700, SR
+        // d200 is ADD.B D0, D1
+        let mut cpu = TestCore::new_mem(0x40, &[0x46, 0xfc, 0xa7, 0x00, 0xd2, 0x00]);
+        let trace_handler = 0x1010;
+        cpu.write_data_long(super::EXCEPTION_TRACE as u32 * 4, trace_handler).unwrap();
+        cpu.dar[15] = 0x200;
+
+        cpu.execute1(); // MOVE to SR sets T, but T was clear when it started
+        assert_eq!(0x44, cpu.pc);
+        assert_eq!("TS7-----", cpu.flags());
+        cpu.execute1(); // the ADD.B is traced
+        assert_eq!(trace_handler, cpu.pc);
+    }
+
+    #[test]
+    fn instruction_clearing_trace_bit_is_traced() {
+        // 46fc 2700 is MOVE #$2700, SR
+        let mut cpu = TestCore::new_mem(0x40, &[0x46, 0xfc, 0x27, 0x00]);
+        let trace_handler = 0x1010;
+        cpu.write_data_long(super::EXCEPTION_TRACE as u32 * 4, trace_handler).unwrap();
+        cpu.dar[15] = 0x200;
+        cpu.sr_to_flags(0xa700);
+
+        cpu.execute1();
+        assert_eq!(trace_handler, cpu.pc);
+        assert_eq!(0x2700, cpu.read_data_word(0x200-6).unwrap());
+    }
+
+    #[test]
+    fn illegal_instruction_is_not_traced() {
+        let mut cpu = TestCore::new_mem(0x40, &[0x4a, 0xfc]); // 4afc ILLEGAL
+        let illegal_handler = 0x1010;
+        cpu.write_data_long(super::EXCEPTION_ILLEGAL_INSTRUCTION as u32 * 4, illegal_handler).unwrap();
+        cpu.write_data_long(super::EXCEPTION_TRACE as u32 * 4, 0x2020).unwrap();
+        cpu.dar[15] = 0x200;
+        cpu.sr_to_flags(0xa700);
+
+        assert_eq!(Cycles(34), cpu.execute1());
+        assert_eq!(illegal_handler, cpu.pc);
+        assert_eq!(0x200-6, cpu.dar[15]);
+    }
+
+    #[test]
+    fn address_error_is_not_traced() {
+        let mut cpu = TestCore::new_mem(0x40, &[0x41, 0xa0]); // 0x41a0 CHK.W -(A0), D0
+        let address_error_handler = 0x1010;
+        cpu.write_data_long(super::EXCEPTION_ADDRESS_ERROR as u32 * 4, address_error_handler).unwrap();
+        cpu.write_data_long(super::EXCEPTION_TRACE as u32 * 4, 0x2020).unwrap();
+        cpu.dar[15] = 0x200;
+        cpu.dar[8] = 0x0023; // odd, will cause an address error
+        cpu.sr_to_flags(0xa700);
+
+        assert_eq!(Cycles(50), cpu.execute1());
+        assert_eq!(address_error_handler, cpu.pc);
+        assert_eq!(0x200-14, cpu.dar[15]);
+    }
+
+    #[test]
+    fn trap_is_followed_by_trace_of_trap_handler_entry() {
+        let mut cpu = TestCore::new_mem(0x40, &[0x4e, 0x41]); // 4e41 TRAP #1
+        let trap_handler = 0x1010;
+        let trace_handler = 0x2020;
+        cpu.write_data_long((super::EXCEPTION_TRAP_BASE as u32 + 1) * 4, trap_handler).unwrap();
+        cpu.write_data_long(super::EXCEPTION_TRACE as u32 * 4, trace_handler).unwrap();
+        cpu.dar[15] = 0x200;
+        cpu.sr_to_flags(0xa700);
+
+        assert_eq!(Cycles(34 + 34), cpu.execute1());
+        assert_eq!(trace_handler, cpu.pc);
+        // the trace frame is stacked on top of the trap frame, and
+        // returns to the first instruction of the trap handler
+        assert_eq!(0x200-12, cpu.dar[15]);
+        assert_eq!(0x2700, cpu.read_data_word(0x200-12).unwrap());
+        assert_eq!(trap_handler, cpu.read_data_long(0x200-10).unwrap());
+        assert_eq!(0xa700, cpu.read_data_word(0x200-6).unwrap());
+        assert_eq!(0x42, cpu.read_data_long(0x200-4).unwrap());
+    }
+
+    #[test]
+    fn trace_resumes_stopped_core() {
+        let mut cpu = TestCore::new_mem(0x40, &[0x4e, 0x72, 0xa7, 0x00]); // 4e72 a700 STOP #$a700
+        let trace_handler = 0x1010;
+        cpu.write_data_long(super::EXCEPTION_TRACE as u32 * 4, trace_handler).unwrap();
+        cpu.dar[15] = 0x200;
+        cpu.sr_to_flags(0xa700);
+
+        assert_eq!(Cycles(4 + 34), cpu.execute1());
+        assert_eq!(super::ProcessingState::Group1Exception, cpu.processing_state);
+        assert_eq!(trace_handler, cpu.pc);
+        assert_eq!(0x44, cpu.read_data_long(0x200-4).unwrap());
+    }
+
+    #[test]
+    fn trace_and_interrupt_priority() {
+        let mut cpu = TestCore::new_mem(0x40, &[0xd2, 0x00]); // d200 is ADD.B D0, D1
+        let trace_handler = 0x1010;
+        let autovector_base = 24;
+        let irq = 5;
+        let int_handler = 0x2020;
+        cpu.write_data_long(super::EXCEPTION_TRACE as u32 * 4, trace_handler).unwrap();
+        cpu.write_data_long((autovector_base + irq) * 4, int_handler).unwrap();
+        cpu.dar[15] = 0x200;
+        cpu.sr_to_flags(0xa000); // trace, supervisor, mask 0
+        cpu.int_ctrl.request_interrupt(irq as u8);
+
+        // the interrupt is taken first, as it is already pending when
+        // the instruction would start
+        assert_eq!(Cycles(44), cpu.execute1());
+        assert_eq!(int_handler, cpu.pc);
+
+        let mut cpu = TestCore::new_mem(0x40, &[0xd2, 0x00]); // d200 is ADD.B D0, D1
+        cpu.write_data_long(super::EXCEPTION_TRACE as u32 * 4, trace_handler).unwrap();
+        cpu.write_data_long((autovector_base + irq) * 4, int_handler).unwrap();
+        cpu.dar[15] = 0x200;
+        cpu.sr_to_flags(0xa000);
+        cpu.execute1(); // ADD.B and trace
+        assert_eq!(trace_handler, cpu.pc);
+        // an interrupt requested now is taken before the first
+        // instruction of the trace handler
+        cpu.int_ctrl.request_interrupt(irq as u8);
+        cpu.execute1();
+        assert_eq!(int_handler, cpu.pc);
+        assert_eq!(trace_handler, cpu.read_data_long(0x200-6-4).unwrap());
+    }
+
+    #[test]
+    fn exception_callback_can_intercept_trace() {
+        let mut cpu = TestCore::new_mem(0x40, &[0xd2, 0x00]); // d200 is ADD.B D0, D1
+        cpu.dar[15] = 0x200;
+        cpu.sr_to_flags(0xa700);
+        let mut handler = CustomExceptionHandler { suppress: true, count: 0, ex: None };
+        assert_eq!(Cycles(4 + 1000), cpu.execute_with_state(1, &mut handler).0);
+        assert_eq!(1, handler.count);
+        match handler.ex {
+            Some(Exception::Trace) => (),
+            ex => panic!("expected trace, got {:?}", ex),
+        }
+        assert_eq!(0x42, cpu.pc);
+        assert_eq!(0x200, cpu.dar[15]);
+    }
+
+    #[derive(Default)]
+    struct Hooks {
+        after: usize,
+        resets: usize,
+        stops: usize,
+        halts: usize,
+    }
+    impl<C: Core> Callbacks<C> for Hooks {
+        fn exception_callback(&mut self, _core: &mut C, ex: Exception) -> Result<Cycles> {
+            Err(ex)
+        }
+        // runs TRAP #15 as a system call setting D1, puts MOVEQ #5, D0 in
+        // place of ILLEGAL, and stops at $46
+        fn before_instruction(&mut self, core: &mut C, pc: u32, opcode: u16) -> BeforeInstruction {
+            match opcode {
+                0x4e4f => {
+                    core.dar()[1] = 42;
+                    BeforeInstruction::Skip(Cycles(10))
+                }
+                0x4afc => BeforeInstruction::Replace(0x7005),
+                _ if pc == 0x46 => BeforeInstruction::Stop,
+                _ => BeforeInstruction::Execute,
+            }
+        }
+        fn after_instruction(&mut self, _core: &mut C, _cycles: Cycles) -> Flow {
+            self.after += 1;
+            Flow::Continue
+        }
+        fn reset_instruction(&mut self, _core: &mut C) -> Flow {
+            self.resets += 1;
+            Flow::Stop
+        }
+        fn stopped(&mut self, _core: &mut C) {
+            self.stops += 1;
+        }
+        fn halted(&mut self, _core: &mut C) {
+            self.halts += 1;
+        }
+    }
+
+    #[test]
+    fn before_instruction_can_skip_replace_or_stop() {
+        // 4e4f is TRAP #15, 4afc ILLEGAL and 4e71 NOP
+        let mut cpu = TestCore::new_mem(0x40, &[0x4e, 0x4f, 0x4a, 0xfc, 0x4e, 0x71, 0x4e, 0x71]);
+        cpu.dar[15] = 0x200;
+        let mut hooks = Hooks::default();
+        assert_eq!((Cycles(10 + 4 + 4), StopReason::Requested), cpu.execute_with_state(1000, &mut hooks));
+        assert_eq!((42, 5), (cpu.dar[1], cpu.dar[0]));
+        assert_eq!(0x46, cpu.pc);
+        assert_eq!(3, hooks.after);
+        // and again on resuming
+        assert_eq!((Cycles(0), StopReason::Requested), cpu.execute_with_state(1000, &mut hooks));
+        assert_eq!(0x46, cpu.pc);
+    }
+
+    #[test]
+    fn hooks_see_reset_stop_and_halt() {
+        // 4e70 is RESET, 4e72 2700 STOP #$2700
+        let mut cpu = TestCore::new_mem(0x40, &[0x4e, 0x70, 0x4e, 0x72, 0x27, 0x00]);
+        let mut hooks = Hooks::default();
+        assert_eq!((Cycles(132), StopReason::Requested), cpu.execute_with_state(1000, &mut hooks));
+        assert_eq!(1, hooks.resets);
+        assert_eq!(StopReason::Stopped, cpu.execute_with_state(1000, &mut hooks).1);
+        assert_eq!((1, 0), (hooks.stops, hooks.halts));
+        // an illegal instruction, with an odd stack pointer to stack it on
+        let mut cpu = TestCore::new_mem(0x40, &[0x4a, 0xfc]);
+        cpu.dar[15] = 0x201;
+        assert_eq!(StopReason::Halted, cpu.execute_with_state(1000, &mut hooks).1);
+        assert_eq!((1, 1), (hooks.stops, hooks.halts));
+    }
+
+    use ram::SUPERVISOR_DATA;
+    use ram::pagedmem::PagedMem;
+    use interrupts::AutoInterruptController;
+    use super::ConfiguredCore;
+    use self::partial::PartialMem;
+
+    mod partial {
+        use ram::{AddressBus, AddressSpace, BusError};
+        use ram::pagedmem::PagedMem;
+
+        // memory with nothing mapped at or above 0x10000
+        pub struct PartialMem(pub PagedMem);
+
+        fn map(address: u32) -> Result<u32, BusError> {
+            if address & 0xffffff < 0x10000 { Ok(address) } else { Err(BusError) }
+        }
+
+        impl AddressBus for PartialMem {
+            fn copy_from(&mut self, other: &Self) {
+                self.0.copy_from(&other.0)
+            }
+            fn read_byte(&self, address_space: AddressSpace, address: u32) -> Result<u32, BusError> {
+                map(address).and_then(|a| self.0.read_byte(address_space, a))
+            }
+            fn read_word(&self, address_space: AddressSpace, address: u32) -> Result<u32, BusError> {
+                map(address).and_then(|a| self.0.read_word(address_space, a))
+            }
+            fn read_long(&self, address_space: AddressSpace, address: u32) -> Result<u32, BusError> {
+                map(address).and_then(|a| self.0.read_long(address_space, a))
+            }
+            fn write_byte(&mut self, address_space: AddressSpace, address: u32, value: u32) -> Result<(), BusError> {
+                map(address).and_then(|a| self.0.write_byte(address_space, a, value))
+            }
+            fn write_word(&mut self, address_space: AddressSpace, address: u32, value: u32) -> Result<(), BusError> {
+                map(address).and_then(|a| self.0.write_word(address_space, a, value))
+            }
+            fn write_long(&mut self, address_space: AddressSpace, address: u32, value: u32) -> Result<(), BusError> {
+                map(address).and_then(|a| self.0.write_long(address_space, a, value))
+            }
+        }
+    }
+
+    fn partial_mem_core(base: u32, code: &[u16]) -> ConfiguredCore<AutoInterruptController, PartialMem> {
+        let mut mem = PartialMem(PagedMem::new(0));
+        for (i, word) in code.iter().enumerate() {
+            mem.write_word(SUPERVISOR_PROGRAM, base + 2 * i as u32, u32::from(*word)).unwrap();
+        }
+        mem.write_long(SUPERVISOR_PROGRAM, 0, 0x800).unwrap(); // SSP
+        mem.write_long(SUPERVISOR_PROGRAM, 4, base).unwrap(); // PC
+        mem.write_long(SUPERVISOR_DATA, super::EXCEPTION_BUS_ERROR as u32 * 4, 0x2000).unwrap();
+        let mut cpu = ConfiguredCore::new_with(base, AutoInterruptController::new(), mem);
+        cpu.reset();
+        cpu
+    }
+
+    #[test]
+    fn unmapped_data_read_causes_bus_error() {
+        // 3038 8000 is MOVE.W $8000.W, D0 which reads from 0xffff8000
+        let mut cpu = partial_mem_core(0x1000, &[0x3038, 0x8000]);
+        assert_eq!(Cycles(50), cpu.execute1());
+        assert_eq!(0x2000, cpu.pc);
+        assert_eq!(super::ProcessingState::Group0Exception, cpu.processing_state);
+        assert_eq!(0x800 - 14, cpu.dar[15]);
+        // read, instruction processing, supervisor data
+        assert_eq!(0b10101, cpu.read_data_word(0x800 - 14).unwrap());
+        assert_eq!(0xffff8000, cpu.read_data_long(0x800 - 12).unwrap());
+        assert_eq!(0x3038, cpu.read_data_word(0x800 - 8).unwrap());
+        assert_eq!(0x2700, cpu.read_data_word(0x800 - 6).unwrap());
+    }
+
+    #[test]
+    fn unmapped_data_write_causes_bus_error() {
+        // 31c0 8000 is MOVE.W D0, $8000.W
+        let mut cpu = partial_mem_core(0x1000, &[0x31c0, 0x8000]);
+        assert_eq!(Cycles(50), cpu.execute1());
+        assert_eq!(0x2000, cpu.pc);
+        // write, instruction processing, supervisor data
+        assert_eq!(0b00101, cpu.read_data_word(0x800 - 14).unwrap());
+        assert_eq!(0xffff8000, cpu.read_data_long(0x800 - 12).unwrap());
+    }
+
+    #[test]
+    fn instruction_fetch_from_unmapped_memory_causes_bus_error() {
+        let mut cpu = partial_mem_core(0x1000, &[]);
+        cpu.jump(0x20000);
+        assert_eq!(Cycles(50), cpu.execute1());
+        assert_eq!(0x2000, cpu.pc);
+        // read, instruction processing, supervisor program
+        assert_eq!(0b10110, cpu.read_data_word(0x800 - 14).unwrap());
+        assert_eq!(0x20000, cpu.read_data_long(0x800 - 12).unwrap());
+    }
+
+    #[test]
+    fn bus_error_while_stacking_halts() {
+        let mut cpu = partial_mem_core(0x1000, &[0x3038, 0x8000]);
+        cpu.dar[15] = 0x20000;
+        cpu.execute1();
+        assert_eq!(super::ProcessingState::Halted, cpu.processing_state);
+    }
+
+    #[test]
+    fn bus_error_in_bus_error_handler_halts() {
+        let mut cpu = partial_mem_core(0x1000, &[0x3038, 0x8000]);
+        cpu.write_data_long(super::EXCEPTION_BUS_ERROR as u32 * 4, 0x20000).unwrap();
+        cpu.execute1();
+        assert_eq!(super::ProcessingState::Group0Exception, cpu.processing_state);
+        cpu.execute1(); // fetching the handler causes a second bus error
+        assert_eq!(super::ProcessingState::Halted, cpu.processing_state);
+    }
+
+    #[test]
+    fn bus_error_during_trap_stacking_halts() {
+        // 4e40 is TRAP #0
+        let mut cpu = partial_mem_core(0x1000, &[0x4e40]);
+        cpu.dar[15] = 0x10004;
+        cpu.execute1();
+        assert_eq!(super::ProcessingState::Halted, cpu.processing_state);
+    }
+
+    #[test]
+    fn core_can_run_on_mapped_bus() {
+        use ram::mappedbus::{MappedBus, Unmapped};
+        let mut bus = MappedBus::new(Unmapped::BusError);
+        // reset vectors and code in ROM, followed by RAM
+        // 33fc 1234 0000 0100 is MOVE.W #$1234, $100.L
+        bus.map_rom(0, &[0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x00, 0x08,
+                         0x33, 0xfc, 0x12, 0x34, 0x00, 0x00, 0x01, 0x00]);
+        bus.map_ram(0x100, 0x100);
+        let mut cpu = ConfiguredCore::new_with(0, AutoInterruptController::new(), bus);
+        cpu.reset();
+        assert_eq!(0x200, cpu.dar[15]);
+        cpu.execute1();
+        assert_eq!(0x1234, cpu.read_data_word(0x100).unwrap());
+    }
+
+    #[test]
+    fn user_write_to_supervisor_memory_is_a_bus_error() {
+        use ram::fcbus::FcBus;
+        use ram::{BusError, USER_DATA};
+        let mut bus = FcBus::new(PagedMem::new(0));
+        // 33c0 0000 0100 is MOVE.W D0, $100.L
+        bus.write_word(SUPERVISOR_DATA, 0x1000, 0x33c0).unwrap();
+        bus.write_long(SUPERVISOR_DATA, 0x1002, 0x0000_0100).unwrap();
+        bus.write_long(SUPERVISOR_DATA, super::EXCEPTION_BUS_ERROR as u32 * 4, 0x2000).unwrap();
+        bus.protect(0, 0x800, &[SUPERVISOR_DATA, SUPERVISOR_PROGRAM], &[SUPERVISOR_DATA, SUPERVISOR_PROGRAM]);
+        let mut cpu = ConfiguredCore::new_with(0x1000, AutoInterruptController::new(), bus);
+        cpu.processing_state = super::ProcessingState::Normal;
+        cpu.sr_to_flags(0x0000);
+        cpu.inactive_ssp = 0x4000;
+        cpu.execute1();
+        assert_eq!(0x2000, cpu.pc);
+        assert_eq!(0x100, cpu.read_data_long(0x4000 - 12).unwrap());
+        assert_eq!(Err(BusError), cpu.mem.read_word(USER_DATA, 0x100));
+    }
+
+    #[test]
+    fn user_program_runs_translated_by_mmu() {
+        use ram::mmu::{Mmu, Descriptor, SEGMENT_ENABLED};
+        use ram::{USER_DATA, USER_PROGRAM};
+        let mut mmu = Mmu::new(PagedMem::new(0), 0xff_f000);
+        // the supervisor keeps the one-to-one mapping of descriptor 0,
+        // the user process sees physical $4000-$4fff at 0
+        let mut identity = mmu.descriptor(0);
+        identity.address_space_mask = 0xff;
+        mmu.load_descriptor(0, identity);
+        mmu.load_descriptor(1, Descriptor { logical_base: 0, logical_mask: 0xfff0, physical_base: 0x40,
+            address_space_number: 1, address_space_mask: 0xff, status: SEGMENT_ENABLED });
+        mmu.set_address_space_number(USER_PROGRAM, 1);
+        mmu.set_address_space_number(USER_DATA, 1);
+        // 3038 0100 is MOVE.W $100.W, D0 and 3038 2000 is MOVE.W $2000.W, D0
+        mmu.bus_mut().write_long(USER_PROGRAM, 0x4010, 0x3038_0100).unwrap();
+        mmu.bus_mut().write_long(USER_PROGRAM, 0x4014, 0x3038_2000).unwrap();
+        mmu.bus_mut().write_word(USER_DATA, 0x4100, 0x1234).unwrap();
+        mmu.write_long(SUPERVISOR_DATA, super::EXCEPTION_BUS_ERROR as u32 * 4, 0x2000).unwrap();
+        let mut cpu = ConfiguredCore::new_with(0x10, AutoInterruptController::new(), mmu);
+        cpu.processing_state = super::ProcessingState::Normal;
+        cpu.sr_to_flags(0x0000);
+        cpu.inactive_ssp = 0x8000;
+        cpu.execute1();
+        assert_eq!(0x1234, cpu.dar[0]);
+        // $2000 is outside the segment, a translation fault
+        cpu.execute1();
+        assert_eq!(0x2000, cpu.pc);
+        assert_eq!(0x2000, cpu.read_data_long(0x8000 - 12).unwrap());
+    }
+
+    #[test]
+    fn takes_vector_supplied_by_device() {
+        use interrupts::VectoredInterruptController;
+        use ram::PagedMem;
+        use super::ProcessingState;
+        let mut mem = PagedMem::new(0);
+        // 4e71 is NOP
+        mem.write_word(SUPERVISOR_PROGRAM, 0x100, 0x4e71).unwrap();
+        mem.write_long(SUPERVISOR_PROGRAM, 0x40 * 4, 0x1000).unwrap();
+        mem.write_long(SUPERVISOR_PROGRAM, 15 * 4, 0x2000).unwrap();
+        let mut int_ctrl = VectoredInterruptController::new();
+        let mfp = int_ctrl.add_device(6);
+        let duart = int_ctrl.add_device(4);
+        int_ctrl.set_vector(mfp, Some(0x40));
+        let mut cpu = ConfiguredCore::new_with(0x100, int_ctrl, mem);
+        cpu.processing_state = ProcessingState::Normal;
+        cpu.dar[15] = 0x800;
+        cpu.int_mask = 0;
+        cpu.int_ctrl.request_interrupt(mfp);
+        cpu.int_ctrl.request_interrupt(duart);
+        cpu.execute1();
+        assert_eq!(0x1000, cpu.pc);
+        assert_eq!(0x600, cpu.int_mask);
+        // the DUART never had its vector register written
+        cpu.int_mask = 0;
+        cpu.execute1();
+        assert_eq!(0x2000, cpu.pc);
+    }
+
+    mod level {
+        use cpu::ConfiguredCore;
+        use interrupts::LevelInterruptController;
+        use ram::{AddressBus, PagedMem, SUPERVISOR_PROGRAM};
+
+        pub type LevelCore = ConfiguredCore<LevelInterruptController, PagedMem>;
+
+        // NOPs at 0x100, and RTE handlers at 0x1000 + level * 0x10 for
+        // every autovector
+        pub fn level_core(mask: u32) -> LevelCore {
+            let mut mem = PagedMem::new(0);
+            for offset in 0..8 {
+                mem.write_word(SUPERVISOR_PROGRAM, 0x100 + offset * 2, 0x4e71).unwrap();
+            }
+            for level in 1..8 {
+                let handler = 0x1000 + level * 0x10;
+                mem.write_long(SUPERVISOR_PROGRAM, (24 + level) * 4, handler).unwrap();
+                mem.write_word(SUPERVISOR_PROGRAM, handler, 0x4e73).unwrap();
+            }
+            let mut cpu = ConfiguredCore::new_with(0x100, LevelInterruptController::new(), mem);
+            cpu.processing_state = ::cpu::ProcessingState::Normal;
+            cpu.dar[15] = 0x800;
+            cpu.int_mask = mask << 8;
+            cpu
+        }
+    }
+    use self::level::level_core;
+
+    #[test]
+    fn held_line_reenters_handler_after_rte() {
+        let mut cpu = level_core(0);
+        let line = cpu.int_ctrl.add_line(5);
+        cpu.int_ctrl.assert_line(line);
+        cpu.execute1();
+        assert_eq!(0x1050, cpu.pc);
+        assert_eq!(0x500, cpu.int_mask);
+        cpu.execute1(); // RTE lowers the mask again
+        assert_eq!(0x100, cpu.pc);
+        cpu.execute1(); // and the line is still asserted
+        assert_eq!(0x1050, cpu.pc);
+        cpu.execute1();
+        cpu.int_ctrl.deassert_line(line);
+        cpu.execute1();
+        assert_eq!(0x102, cpu.pc);
+    }
+
+    #[test]
+    fn line_at_or_below_mask_is_ignored() {
+        let mut cpu = level_core(5);
+        let low = cpu.int_ctrl.add_line(5);
+        let high = cpu.int_ctrl.add_line(6);
+        cpu.int_ctrl.assert_line(low);
+        cpu.execute1();
+        assert_eq!(0x102, cpu.pc);
+        cpu.int_ctrl.assert_line(high);
+        cpu.execute1();
+        assert_eq!(0x1060, cpu.pc);
+        // still masked inside the level 6 handler
+        cpu.int_ctrl.deassert_line(high);
+        cpu.execute1();
+        assert_eq!(0x102, cpu.pc);
+        assert_eq!(0x500, cpu.int_mask);
+        cpu.execute1();
+        assert_eq!(0x104, cpu.pc);
+    }
+
+    #[test]
+    fn higher_line_nests_inside_lower_handler() {
+        let mut cpu = level_core(0);
+        let low = cpu.int_ctrl.add_line(2);
+        let high = cpu.int_ctrl.add_line(4);
+        cpu.int_ctrl.assert_line(low);
+        cpu.execute1();
+        assert_eq!(0x1020, cpu.pc);
+        cpu.int_ctrl.assert_line(high);
+        cpu.execute1();
+        assert_eq!(0x1040, cpu.pc);
+        cpu.int_ctrl.deassert_line(high);
+        cpu.execute1(); // RTE back to the level 2 handler
+        assert_eq!(0x1020, cpu.pc);
+        assert_eq!(0x200, cpu.int_mask);
+    }
+
+    #[test]
+    fn shared_level_stays_asserted_until_all_lines_deassert() {
+        let mut cpu = level_core(0);
+        let serial = cpu.int_ctrl.add_line(3);
+        let disk = cpu.int_ctrl.add_line(3);
+        cpu.int_ctrl.assert_line(serial);
+        cpu.int_ctrl.assert_line(disk);
+        cpu.execute1();
+        cpu.int_ctrl.deassert_line(serial);
+        cpu.execute1(); // RTE
+        cpu.execute1();
+        assert_eq!(0x1030, cpu.pc);
+        cpu.int_ctrl.deassert_line(disk);
+        cpu.execute1();
+        cpu.execute1();
+        assert_eq!(0x102, cpu.pc);
+    }
+
+    #[test]
+    fn held_level_7_line_needs_a_new_edge() {
+        let mut cpu = level_core(7);
+        let nmi = cpu.int_ctrl.add_line(7);
+        cpu.int_ctrl.assert_line(nmi);
+        cpu.execute1();
+        assert_eq!(0x1070, cpu.pc);
+        cpu.execute1(); // RTE
+        cpu.execute1();
+        assert_eq!(0x102, cpu.pc);
+        cpu.int_ctrl.deassert_line(nmi);
+        cpu.execute1();
+        assert_eq!(0x104, cpu.pc);
+        cpu.int_ctrl.assert_line(nmi);
+        cpu.execute1();
+        assert_eq!(0x1070, cpu.pc);
+    }
+
+    mod m68010 {
+        use cpu::{TestCore, CpuModel};
+
+        // code at 0x40, supervisor stack at 0x800, and vectors read
+        // relative to a VBR of 0x1000
+        pub fn m68010_core(code: &[u16]) -> TestCore {
+            let bytes: Vec<u8> = code.iter().flat_map(|w| vec![(w >> 8) as u8, *w as u8]).collect();
+            let mut cpu = TestCore::new_mem(0x40, &bytes);
+            cpu.set_model(CpuModel::M68010);
+            cpu.dar[15] = 0x800;
+            cpu.vbr = 0x1000;
+            cpu
+        }
+    }
+    use self::m68010::m68010_core;
+    use super::CpuModel;
+
+    #[test]
+    fn m68010_takes_vectors_relative_to_vbr_and_stacks_format_word() {
+        let mut cpu = m68010_core(&[0x4afc]); // ILLEGAL
+        cpu.write_data_long(0x1000 + super::EXCEPTION_ILLEGAL_INSTRUCTION as u32 * 4, 0x2000).unwrap();
+        let Cycles(used) = cpu.execute1();
+        assert_eq!(0x2000, cpu.pc);
+        assert_eq!(0x800 - 8, cpu.dar[15]);
+        assert_eq!(0x40, cpu.read_data_long(0x800 - 6).unwrap());
+        assert_eq!(u32::from(super::EXCEPTION_ILLEGAL_INSTRUCTION) << 2, cpu.read_data_word(0x800 - 2).unwrap());
+        assert_eq!(38, used);
+    }
+
+    #[test]
+    fn m68010_rte_pops_format_word() {
+        let mut cpu = m68010_core(&[0x4e73]); // RTE
+        cpu.dar[15] = 0x7f8;
+        cpu.write_data_word(0x7f8, 0x2704).unwrap(); // SR
+        cpu.write_data_long(0x7fa, 0x3000).unwrap(); // PC
+        cpu.write_data_word(0x7fe, 0x0010).unwrap(); // format $0, vector 4
+        let Cycles(used) = cpu.execute1();
+        assert_eq!(0x3000, cpu.pc);
+        assert_eq!(0x800, cpu.dar[15]);
+        assert_eq!(0x2704, cpu.status_register());
+        assert_eq!(24, used);
+    }
+
+    #[test]
+    fn m68010_rte_rejects_unknown_frame_format() {
+        let mut cpu = m68010_core(&[0x4e73]); // RTE
+        cpu.dar[15] = 0x7f8;
+        cpu.write_data_word(0x7fe, 0x8008).unwrap(); // format $8
+        cpu.write_data_long(0x1000 + super::EXCEPTION_FORMAT_ERROR as u32 * 4, 0x2000).unwrap();
+        cpu.execute1();
+        assert_eq!(0x2000, cpu.pc);
+        assert_eq!(u32::from(super::EXCEPTION_FORMAT_ERROR) << 2, cpu.read_data_word(0x7f8 - 2).unwrap());
+    }
+
+    #[test]
+    fn m68010_stacks_long_bus_fault_frame() {
+        let mut cpu = m68010_core(&[0x3010]); // MOVE.W (A0), D0
+        cpu.dar[8] = 0x3001;
+        cpu.write_data_long(0x1000 + super::EXCEPTION_ADDRESS_ERROR as u32 * 4, 0x2000).unwrap();
+        let Cycles(used) = cpu.execute1();
+        assert_eq!(0x2000, cpu.pc);
+        assert_eq!(0x800 - 58, cpu.dar[15]);
+        let sp = cpu.dar[15];
+        assert_eq!(0x8000 | u32::from(super::EXCEPTION_ADDRESS_ERROR) << 2, cpu.read_data_word(sp + 6).unwrap());
+        assert_eq!(0x1000 | 0x100 | 5, cpu.read_data_word(sp + 8).unwrap()); // DF, read, supervisor data
+        assert_eq!(0x3001, cpu.read_data_long(sp + 10).unwrap());
+        assert_eq!(126, used);
+    }
+
+    #[test]
+    fn m68010_movec_reads_and_writes_control_registers() {
+        // MOVEC D0, VBR; MOVEC D1, SFC; MOVEC VBR, A2; MOVEC SFC, D3
+        let mut cpu = m68010_core(&[0x4e7b, 0x0801, 0x4e7b, 0x1000, 0x4e7a, 0xa801, 0x4e7a, 0x3000]);
+        cpu.dar[0] = 0x4000;
+        cpu.dar[1] = 0xffff_fffd;
+        let Cycles(used) = cpu.execute(10 + 10 + 12 + 12);
+        assert_eq!(10 + 10 + 12 + 12, used);
+        assert_eq!(0x4000, cpu.vbr);
+        assert_eq!(5, cpu.sfc);
+        assert_eq!(0x4000, cpu.dar[10]);
+        assert_eq!(5, cpu.dar[3]);
+    }
+
+    #[test]
+    fn m68010_movec_with_unknown_control_register_is_illegal() {
+        let mut cpu = m68010_core(&[0x4e7a, 0x0002]); // MOVEC CACR, D0
+        cpu.write_data_long(0x1000 + super::EXCEPTION_ILLEGAL_INSTRUCTION as u32 * 4, 0x2000).unwrap();
+        cpu.execute1();
+        assert_eq!(0x2000, cpu.pc);
+        assert_eq!(0x40, cpu.read_data_long(0x800 - 6).unwrap());
+    }
+
+    #[test]
+    fn m68010_moves_uses_source_and_destination_function_codes() {
+        // MOVES.W (A0), A1; MOVES.B D2, (A0)
+        let mut cpu = m68010_core(&[0x0e50, 0x9000, 0x0e10, 0x2800]);
+        cpu.sfc = 1;
+        cpu.dfc = 1;
+        cpu.dar[8] = 0x3000;
+        cpu.dar[2] = 0x1234_5678;
+        cpu.write_data_word(0x3000, 0x8001).unwrap();
+        cpu.execute(18 + 18);
+        assert_eq!(0xffff_8001, cpu.dar[9]);
+        let ops = cpu.mem.logger.ops();
+        assert!(ops.contains(&Operation::ReadWord(USER_DATA, 0x3000, 0x8001)));
+        assert!(ops.contains(&Operation::WriteByte(USER_DATA, 0x3000, 0x78)));
+    }
+
+    #[test]
+    fn m68010_moves_with_cpu_space_function_code_is_a_bus_error() {
+        let mut cpu = m68010_core(&[0x0e90, 0x0000]); // MOVES.L (A0), D0
+        cpu.sfc = 7;
+        cpu.dar[8] = 0x3000;
+        cpu.write_data_long(0x1000 + super::EXCEPTION_BUS_ERROR as u32 * 4, 0x2000).unwrap();
+        cpu.execute1();
+        assert_eq!(0x2000, cpu.pc);
+    }
+
+    #[test]
+    fn m68010_rtd_deallocates_parameters() {
+        let mut cpu = m68010_core(&[0x4e74, 0x000c]); // RTD #12
+        cpu.dar[15] = 0x7fc;
+        cpu.write_data_long(0x7fc, 0x3000).unwrap();
+        let Cycles(used) = cpu.execute1();
+        assert_eq!(0x3000, cpu.pc);
+        assert_eq!(0x800 + 12, cpu.dar[15]);
+        assert_eq!(16, used);
+    }
+
+    #[test]
+    fn m68010_move_from_ccr_is_allowed_in_user_mode() {
+        let mut cpu = m68010_core(&[0x42c0]); // MOVE CCR, D0
+        cpu.sr_to_flags(0x001f);
+        cpu.dar[0] = 0xffff_ffff;
+        cpu.execute1();
+        assert_eq!(0xffff_001f, cpu.dar[0]);
+    }
+
+    #[test]
+    fn m68010_move_from_sr_is_privileged() {
+        let mut cpu = m68010_core(&[0x40c0]); // MOVE SR, D0
+        cpu.write_data_long(0x1000 + super::EXCEPTION_PRIVILEGE_VIOLATION as u32 * 4, 0x2000).unwrap();
+        cpu.sr_to_flags(0x0000);
+        cpu.execute1();
+        assert_eq!(0x2000, cpu.pc);
+
+        let mut cpu = TestCore::new_mem(0x40, &[0x40, 0xc0]);
+        cpu.sr_to_flags(0x0000);
+        cpu.execute1();
+        assert_eq!(0x42, cpu.pc);
+    }
+
+    #[test]
+    fn m68010_bkpt_is_illegal() {
+        let mut cpu = m68010_core(&[0x484b]); // BKPT #3
+        cpu.write_data_long(0x1000 + super::EXCEPTION_ILLEGAL_INSTRUCTION as u32 * 4, 0x2000).unwrap();
+        cpu.execute1();
+        assert_eq!(0x2000, cpu.pc);
+    }
+
+    #[test]
+    fn m68010_has_its_own_instruction_timing() {
+        // CLR.L (A0); MULU.W D0, D1; MOVE.L D0, -(A1)
+        let code = [0x4290, 0xc2c0, 0x2300];
+        let mut cpu = m68010_core(&code);
+        cpu.dar[8] = 0x100;
+        cpu.dar[9] = 0x200;
+        assert_eq!(Cycles(12), cpu.execute1());
+        assert_eq!(Cycles(30), cpu.execute1());
+        assert_eq!(Cycles(14), cpu.execute1());
+
+        let mut cpu = m68010_core(&code);
+        cpu.set_model(CpuModel::M68000);
+        cpu.dar[8] = 0x100;
+        cpu.dar[9] = 0x200;
+        assert_eq!(Cycles(20), cpu.execute1());
+        assert_eq!(Cycles(54), cpu.execute1());
+        assert_eq!(Cycles(12), cpu.execute1());
+    }
+
+    #[test]
+    fn m68010_divide_by_zero_takes_m68010_exception_time() {
+        let mut cpu = m68010_core(&[0x80c1]); // DIVU.W D1, D0
+        cpu.write_data_long(0x1000 + super::EXCEPTION_ZERO_DIVIDE as u32 * 4, 0x2000).unwrap();
+        assert_eq!(Cycles(44), cpu.execute1());
+        assert_eq!(0x2000, cpu.pc);
+    }
+
+    #[test]
+    fn m68000_does_not_know_m68010_instructions() {
+        let mut cpu = TestCore::new_mem(0x40, &[0x4e, 0x7a, 0x08, 0x01]); // MOVEC VBR, D0
+        cpu.write_data_long(super::EXCEPTION_ILLEGAL_INSTRUCTION as u32 * 4, 0x2000).unwrap();
+        cpu.execute1();
+        assert_eq!(0x2000, cpu.pc);
+        assert_eq!(CpuModel::M68000, cpu.model());
+    }
+
+    mod m68020 {
+        use cpu::{TestCore, CpuModel};
+
+        // code at 0x40, interrupt stack at 0x800, and vectors read
+        // relative to a VBR of 0x1000
+        pub fn m68020_core(code: &[u16]) -> TestCore {
+            let bytes: Vec<u8> = code.iter().flat_map(|w| vec![(w >> 8) as u8, *w as u8]).collect();
+            let mut cpu = TestCore::new_mem(0x40, &bytes);
+            cpu.set_model(CpuModel::M68020);
+            cpu.dar[15] = 0x800;
+            cpu.vbr = 0x1000;
+            cpu
+        }
+    }
+    use self::m68020::m68020_core;
+
+    #[test]
+    fn m68020_only_instructions_take_m68020_time() {
+        // BFTST (A0){0:8}; TRAPF.W #$1234; MULS.L D1, D0
+        let mut cpu = m68020_core(&[0xe8d0, 0x0008, 0x51fa, 0x1234, 0x4c01, 0x0800]);
+        cpu.dar[8] = 0x100;
+        assert_eq!(Cycles(17), cpu.execute1());
+        assert_eq!(Cycles(6), cpu.execute1());
+        assert_eq!(Cycles(43), cpu.execute1());
+    }
+
+    #[test]
+    fn m68020_stacks_format_2_frame_for_chk() {
+        let mut cpu = m68020_core(&[0x4181]); // CHK.W D1, D0
+        cpu.dar[0] = 0xffff;
+        cpu.dar[1] = 0x10;
+        cpu.write_data_long(0x1000 + super::EXCEPTION_CHK as u32 * 4, 0x2000).unwrap();
+        cpu.execute1();
+        assert_eq!(0x2000, cpu.pc);
+        assert_eq!(0x800 - 12, cpu.dar[15]);
+        assert_eq!(0x42, cpu.read_data_long(0x800 - 10).unwrap());
+        assert_eq!(0x2000 | u32::from(super::EXCEPTION_CHK) << 2, cpu.read_data_word(0x800 - 6).unwrap());
+        assert_eq!(0x40, cpu.read_data_long(0x800 - 4).unwrap());
+    }
+
+    #[test]
+    fn m68020_rte_pops_format_2_frame() {
+        let mut cpu = m68020_core(&[0x4e73]); // RTE
+        cpu.dar[15] = 0x7f4;
+        cpu.write_data_word(0x7f4, 0x2704).unwrap(); // SR
+        cpu.write_data_long(0x7f6, 0x3000).unwrap(); // PC
+        cpu.write_data_word(0x7fa, 0x2018).unwrap(); // format $2, vector 6
+        cpu.write_data_long(0x7fc, 0x2ffe).unwrap(); // instruction address
+        cpu.execute1();
+        assert_eq!(0x3000, cpu.pc);
+        assert_eq!(0x800, cpu.dar[15]);
+        assert_eq!(0x2704, cpu.status_register());
+    }
+
+    #[test]
+    fn m68020_interrupt_on_master_stack_stacks_throwaway_frame() {
+        let mut cpu = m68020_core(&[0x4e71]); // NOP
+        cpu.inactive_msp = 0x600;
+        cpu.sr_to_flags(0x3000); // supervisor, master stack
+        assert_eq!(0x600, cpu.dar[15]);
+        cpu.write_data_long(0x1000 + 28 * 4, 0x2000).unwrap(); // level 4 autovector
+        cpu.write_data_word(0x2000, opcodes::OP_RTE_32).unwrap();
+        cpu.int_ctrl.request_interrupt(4);
+        cpu.execute1();
+        assert_eq!(0x2000, cpu.pc);
+        assert_eq!(0x2400, cpu.status_register());
+        // the normal frame goes on the master stack
+        assert_eq!(0x5f8, cpu.msp());
+        assert_eq!(0x3000, cpu.read_data_word(0x5f8).unwrap());
+        assert_eq!(0x0070, cpu.read_data_word(0x5fe).unwrap());
+        // and a throwaway frame on the interrupt stack
+        assert_eq!(0x7f8, cpu.dar[15]);
+        assert_eq!(0x3000, cpu.read_data_word(0x7f8).unwrap());
+        assert_eq!(0x1070, cpu.read_data_word(0x7fe).unwrap());
+
+        cpu.execute1(); // RTE unwinds both frames
+        assert_eq!(0x40, cpu.pc);
+        assert_eq!(0x3000, cpu.status_register());
+        assert_eq!(0x600, cpu.dar[15]);
+        assert_eq!(0x800, cpu.ssp());
+    }
+
+    #[test]
+    fn m68020_movec_accesses_stack_pointers_and_cache_registers() {
+        // MOVEC D0, MSP; MOVEC ISP, D1; MOVEC D2, CACR; MOVEC CACR, D3
+        let mut cpu = m68020_core(&[0x4e7b, 0x0803, 0x4e7a, 0x1804, 0x4e7b, 0x2002, 0x4e7a, 0x3002]);
+        cpu.dar[0] = 0x600;
+        cpu.dar[2] = 0xff;
+        cpu.execute(10 + 12 + 10 + 12);
+        assert_eq!(0x600, cpu.msp());
+        assert_eq!(0x800, cpu.dar[1]);
+        assert_eq!(0xf, cpu.cacr);
+        assert_eq!(0xf, cpu.dar[3]);
+    }
+
+    #[test]
+    fn m68020_allows_misaligned_data_access() {
+        let mut cpu = m68020_core(&[0x3010]); // MOVE.W (A0), D0
+        cpu.dar[8] = 0x3001;
+        cpu.write_data_word(0x3001, 0x1234).unwrap();
+        cpu.execute1();
+        assert_eq!(0x42, cpu.pc);
+        assert_eq!(0x1234, cpu.dar[0] & 0xffff);
+    }
+
+    #[test]
+    fn m68020_extb_sign_extends_byte_to_long() {
+        let mut cpu = m68020_core(&[0x49c0]); // EXTB.L D0
+        cpu.dar[0] = 0x1234_5680;
+        cpu.execute1();
+        assert_eq!(0xffff_ff80, cpu.dar[0]);
+        assert_eq!(0x8, cpu.condition_code_register());
+    }
+
+    #[test]
+    fn m68020_link_long_allocates_32_bit_frame() {
+        let mut cpu = m68020_core(&[0x480e, 0xffff, 0xffe0]); // LINK.L A6, #-32
+        cpu.dar[14] = 0x1234;
+        cpu.execute1();
+        assert_eq!(0x7fc, cpu.dar[14]);
+        assert_eq!(0x7dc, cpu.dar[15]);
+        assert_eq!(0x1234, cpu.read_data_long(0x7fc).unwrap());
+        assert_eq!(0x46, cpu.pc);
+    }
+
+    #[test]
+    fn m68020_trapcc_skips_operand_and_traps_on_condition() {
+        let mut cpu = m68020_core(&[0x57fa, 0x1234]); // TRAPEQ.W #$1234
+        cpu.write_data_long(0x1000 + super::EXCEPTION_TRAPV as u32 * 4, 0x2000).unwrap();
+        cpu.sr_to_flags(0x2700);
+        cpu.execute1();
+        assert_eq!(0x44, cpu.pc);
+
+        let mut cpu = m68020_core(&[0x57fa, 0x1234]);
+        cpu.write_data_long(0x1000 + super::EXCEPTION_TRAPV as u32 * 4, 0x2000).unwrap();
+        cpu.sr_to_flags(0x2704);
+        cpu.execute1();
+        assert_eq!(0x2000, cpu.pc);
+        assert_eq!(0x44, cpu.read_data_long(0x800 - 10).unwrap());
+    }
+
+    #[test]
+    fn m68020_mull_gives_64_bit_product_or_overflow() {
+        // MULU.L D1, D2:D0; MULS.L D1, D3
+        let mut cpu = m68020_core(&[0x4c01, 0x0402, 0x4c01, 0x3800]);
+        cpu.dar[0] = 0xffff_ffff;
+        cpu.dar[1] = 2;
+        cpu.dar[3] = 0x4000_0000;
+        cpu.execute1();
+        assert_eq!(1, cpu.dar[2]);
+        assert_eq!(0xffff_fffe, cpu.dar[0]);
+        assert_eq!(0x0, cpu.condition_code_register());
+        cpu.execute1();
+        assert_eq!(0x8000_0000, cpu.dar[3]);
+        assert_eq!(0xa, cpu.condition_code_register()); // N, V
+    }
+
+    #[test]
+    fn m68020_divl_gives_quotient_and_remainder() {
+        // DIVS.L D1, D3:D0 of a 64 bit dividend
+        let mut cpu = m68020_core(&[0x4c41, 0x0c03]);
+        cpu.dar[3] = 0xffff_ffff;
+        cpu.dar[0] = 0xffff_fff9;
+        cpu.dar[1] = 2;
+        cpu.execute1();
+        assert_eq!(0xffff_fffd, cpu.dar[0]);
+        assert_eq!(0xffff_ffff, cpu.dar[3]);
+    }
+
+    #[test]
+    fn m68020_divl_overflow_leaves_registers_unchanged() {
+        // DIVU.L D1, D3:D0 with a quotient of 2^32
+        let mut cpu = m68020_core(&[0x4c41, 0x0403]);
+        cpu.dar[3] = 2;
+        cpu.dar[0] = 0;
+        cpu.dar[1] = 2;
+        cpu.execute1();
+        assert_eq!(0, cpu.dar[0]);
+        assert_eq!(2, cpu.dar[3]);
+        assert_eq!(0x2, cpu.condition_code_register() & 0x3);
+    }
+
+    #[test]
+    fn m68020_divl_by_zero_traps() {
+        let mut cpu = m68020_core(&[0x4c41, 0x0000]); // DIVU.L D1, D0
+        cpu.write_data_long(0x1000 + super::EXCEPTION_ZERO_DIVIDE as u32 * 4, 0x2000).unwrap();
+        cpu.execute1();
+        assert_eq!(0x2000, cpu.pc);
+        assert_eq!(0x2000 | u32::from(super::EXCEPTION_ZERO_DIVIDE) << 2, cpu.read_data_word(0x800 - 6).unwrap());
+    }
+
+    #[test]
+    fn m68020_pack_and_unpk_convert_between_bcd_and_digits() {
+        // PACK D0, D1, #0; UNPK D1, D2, #$3030
+        let mut cpu = m68020_core(&[0x8340, 0x0000, 0x8581, 0x3030]);
+        cpu.dar[0] = 0x0305;
+        cpu.dar[1] = 0xffff_ffff;
+        cpu.execute1();
+        assert_eq!(0xffff_ff35, cpu.dar[1]);
+        cpu.execute1();
+        assert_eq!(0x3335, cpu.dar[2] & 0xffff);
+    }
+
+    #[test]
+    fn m68020_pack_memory_reads_low_byte_first() {
+        let mut cpu = m68020_core(&[0x8348, 0xcfd0]); // PACK -(A0), -(A1), #-$3030
+        cpu.dar[8] = 0x3002;
+        cpu.dar[9] = 0x3010;
+        cpu.write_data_word(0x3000, 0x3132).unwrap(); // "12"
+        cpu.execute1();
+        assert_eq!(0x3000, cpu.dar[8]);
+        assert_eq!(0x300f, cpu.dar[9]);
+        assert_eq!(0x12, cpu.read_data_byte(0x300f).unwrap());
+    }
+
+    #[test]
+    fn m68020_cas_swaps_only_on_match() {
+        let code = [0x0ed0, 0x0081]; // CAS.L D1, D2, (A0)
+        let mut cpu = m68020_core(&code);
+        cpu.dar[8] = 0x3000;
+        cpu.dar[1] = 0x1234_5678;
+        cpu.dar[2] = 0xcafe_babe;
+        cpu.write_data_long(0x3000, 0x1234_5678).unwrap();
+        cpu.execute1();
+        assert_eq!(0xcafe_babe, cpu.read_data_long(0x3000).unwrap());
+        assert_eq!(0x4, cpu.condition_code_register() & 0x4);
+
+        let mut cpu = m68020_core(&code);
+        cpu.dar[8] = 0x3000;
+        cpu.dar[1] = 0;
+        cpu.write_data_long(0x3000, 0x1234_5678).unwrap();
+        cpu.execute1();
+        assert_eq!(0x1234_5678, cpu.read_data_long(0x3000).unwrap());
+        assert_eq!(0x1234_5678, cpu.dar[1]);
+    }
+
+    #[test]
+    fn m68020_cas2_updates_both_operands() {
+        // CAS2.W D0:D1, D2:D3, (A0):(A1)
+        let mut cpu = m68020_core(&[0x0cfc, 0x8080, 0x90c1]);
+        cpu.dar[8] = 0x3000;
+        cpu.dar[9] = 0x3010;
+        cpu.dar[0] = 0x1111;
+        cpu.dar[1] = 0x2222;
+        cpu.dar[2] = 0xaaaa;
+        cpu.dar[3] = 0xbbbb;
+        cpu.write_data_word(0x3000, 0x1111).unwrap();
+        cpu.write_data_word(0x3010, 0x2222).unwrap();
+        cpu.execute1();
+        assert_eq!(0x46, cpu.pc);
+        assert_eq!(0xaaaa, cpu.read_data_word(0x3000).unwrap());
+        assert_eq!(0xbbbb, cpu.read_data_word(0x3010).unwrap());
+    }
+
+    #[test]
+    fn m68020_cmp2_checks_value_against_bounds() {
+        let code = [0x00d0, 0x0000]; // CMP2.B (A0), D0
+        for &(value, ccr) in &[(0x15, 0x0), (0x20, 0x4), (0x25, 0x1)] {
+            let mut cpu = m68020_core(&code);
+            cpu.dar[8] = 0x3000;
+            cpu.dar[0] = value;
+            cpu.write_data_word(0x3000, 0x1020).unwrap();
+            cpu.execute1();
+            assert_eq!(ccr, cpu.condition_code_register() & 0x5);
+        }
+    }
+
+    #[test]
+    fn m68020_cmp2_with_address_register_uses_sign_extended_bounds() {
+        let code = [0x02d0, 0x9000]; // CMP2.W (A0), A1
+        for &(value, ccr) in &[(0xffff_fff8, 0x0), (0x0000_fff8, 0x1)] {
+            let mut cpu = m68020_core(&code);
+            cpu.dar[8] = 0x3000;
+            cpu.dar[9] = value;
+            cpu.write_data_long(0x3000, 0xfff0_0010).unwrap();
+            cpu.execute1();
+            assert_eq!(ccr, cpu.condition_code_register() & 0x5);
+        }
+    }
+
+    #[test]
+    fn m68020_chk2_traps_when_out_of_bounds() {
+        let mut cpu = m68020_core(&[0x00d0, 0x0800]); // CHK2.B (A0), D0
+        cpu.dar[8] = 0x3000;
+        cpu.dar[0] = 0x25;
+        cpu.write_data_word(0x3000, 0x1020).unwrap();
+        cpu.write_data_long(0x1000 + super::EXCEPTION_CHK as u32 * 4, 0x2000).unwrap();
+        cpu.execute1();
+        assert_eq!(0x2000, cpu.pc);
+    }
+
+    #[test]
+    fn m68020_bitfields_in_data_registers_wrap_around() {
+        // BFEXTU D0{4:8}, D1; BFEXTS D0{28:8}, D2
+        let mut cpu = m68020_core(&[0xe9c0, 0x1108, 0xebc0, 0x2708]);
+        cpu.dar[0] = 0x8234_567f;
+        cpu.execute1();
+        assert_eq!(0x23, cpu.dar[1]);
+        cpu.execute1();
+        assert_eq!(0xffff_fff8, cpu.dar[2]);
+        assert_eq!(0x8, cpu.condition_code_register());
+    }
+
+    #[test]
+    fn m68020_bfins_writes_field_to_memory() {
+        let mut cpu = m68020_core(&[0xefd0, 0x1310]); // BFINS D1, (A0){12:16}
+        cpu.dar[8] = 0x3000;
+        cpu.dar[1] = 0xabcd;
+        cpu.write_data_long(0x3000, 0).unwrap();
+        cpu.execute1();
+        assert_eq!(0x000a_bcd0, cpu.read_data_long(0x3000).unwrap());
+        assert_eq!(0x8, cpu.condition_code_register());
+    }
+
+    #[test]
+    fn m68020_bfset_can_span_five_bytes() {
+        let mut cpu = m68020_core(&[0xeed0, 0x01de]); // BFSET (A0){7:30}
+        cpu.dar[8] = 0x3000;
+        cpu.write_data_long(0x3000, 0).unwrap();
+        cpu.write_data_byte(0x3004, 0).unwrap();
+        cpu.execute1();
+        assert_eq!(0x01ff_ffff, cpu.read_data_long(0x3000).unwrap());
+        assert_eq!(0xf8, cpu.read_data_byte(0x3004).unwrap());
+        assert_eq!(0x4, cpu.condition_code_register());
+    }
+
+    #[test]
+    fn m68020_bitfield_offset_from_register_can_be_negative() {
+        let mut cpu = m68020_core(&[0xe8d0, 0x0844]); // BFTST (A0){D1:4}
+        cpu.dar[8] = 0x3000;
+        cpu.dar[1] = 0xffff_fffc;
+        cpu.write_data_byte(0x2fff, 0x0f).unwrap();
+        cpu.execute1();
+        assert_eq!(0x8, cpu.condition_code_register());
+    }
+
+    #[test]
+    fn m68020_bfffo_finds_first_set_bit() {
+        let code = [0xedc0, 0x2210]; // BFFFO D0{8:16}, D2
+        for &(value, offset) in &[(0x0000_1000, 19), (0, 24)] {
+            let mut cpu = m68020_core(&code);
+            cpu.dar[0] = value;
+            cpu.execute1();
+            assert_eq!(offset, cpu.dar[2]);
+        }
+    }
+
+    #[test]
+    fn m68020_callm_and_rtm_enter_and_leave_module() {
+        let mut cpu = m68020_core(&[0x06d0, 0x0004]); // CALLM #4, (A0)
+        cpu.dar[8] = 0x3000;
+        cpu.dar[13] = 0x1234;
+        cpu.write_data_long(0x3000, 0).unwrap(); // type 0 descriptor
+        cpu.write_data_long(0x3004, 0x2000).unwrap(); // entry
+        cpu.write_data_long(0x3008, 0x5000).unwrap(); // module data area
+        cpu.write_data_word(0x2000, 0xd000).unwrap(); // entry word, A5
+        cpu.write_data_word(0x2002, 0x06cd).unwrap(); // RTM A5
+        cpu.execute1();
+        assert_eq!(0x2002, cpu.pc);
+        assert_eq!(0x5000, cpu.dar[13]);
+        assert_eq!(0x800 - 20, cpu.dar[15]);
+        assert_eq!(4, cpu.read_data_word(0x800 - 16).unwrap());
+        cpu.execute1();
+        assert_eq!(0x44, cpu.pc);
+        assert_eq!(0x1234, cpu.dar[13]);
+        assert_eq!(0x800 + 4, cpu.dar[15]);
+    }
+
+    #[test]
+    fn m68010_does_not_know_m68020_instructions() {
+        let mut cpu = m68010_core(&[0x49c0]); // EXTB.L D0
+        cpu.write_data_long(0x1000 + super::EXCEPTION_ILLEGAL_INSTRUCTION as u32 * 4, 0x2000).unwrap();
+        cpu.execute1();
+        assert_eq!(0x2000, cpu.pc);
+    }
+
+    fn m68020_fpu_core(code: &[u16]) -> TestCore {
+        let mut cpu = m68020_core(code);
+        cpu.set_fpu(Some(Fpu::new(FpuModel::M68882)));
+        cpu
+    }
+
+    #[test]
+    fn line_f_traps_without_an_fpu() {
+        let mut cpu = m68020_core(&[0xf200, 0x4080]); // FMOVE.L D0, FP1
+        cpu.write_data_long(0x1000 + super::EXCEPTION_UNIMPLEMENTED_1111 as u32 * 4, 0x2000).unwrap();
+        cpu.execute1();
+        assert_eq!(0x2000, cpu.pc);
+        assert_eq!(None, cpu.fpu());
+    }
+
+    #[test]
+    fn fpu_converts_adds_and_stores() {
+        let mut cpu = m68020_fpu_core(&[
+            0xf200, 0x4080, // FMOVE.L D0, FP1
+            0xf200, 0x0522, // FADD.X FP1, FP2
+            0xf210, 0x7500, // FMOVE.D FP2, (A0)
+        ]);
+        cpu.dar[0] = 3;
+        cpu.dar[8] = 0x3000;
+        cpu.fpu_mut().unwrap().fp[2] = Extended::from_f64(1.5);
+        cpu.execute1();
+        assert_eq!(Extended::from_i32(3), cpu.fpu().unwrap().fp[1]);
+        cpu.execute1();
+        assert_eq!(Extended::from_f64(4.5), cpu.fpu().unwrap().fp[2]);
+        assert_eq!(0x44, cpu.fpu().unwrap().fpiar);
+        cpu.execute1();
+        assert_eq!(0x4012_0000, cpu.read_data_long(0x3000).unwrap());
+        assert_eq!(0, cpu.read_data_long(0x3004).unwrap());
+    }
+
+    #[test]
+    fn fpu_is_available_to_m68000_software() {
+        let mut cpu = TestCore::new_mem(0x40, &[0xf2, 0x00, 0x5c, 0x00]); // FMOVECR #0, FP0
+        cpu.set_fpu(Some(Fpu::new(FpuModel::M68881)));
+        cpu.execute1();
+        assert_eq!(0x44, cpu.pc);
+        assert_eq!(::std::f64::consts::PI, cpu.fpu().unwrap().fp[0].to_f64());
+    }
+
+    #[test]
+    fn fbcc_branches_on_fpu_condition() {
+        let code = [0xf281, 0x0010]; // FBEQ.W *+$12
+        let mut cpu = m68020_fpu_core(&code);
+        cpu.fpu_mut().unwrap().fpsr = FPSR_CC_Z;
+        cpu.execute1();
+        assert_eq!(0x52, cpu.pc);
+        let mut cpu = m68020_fpu_core(&code);
+        cpu.fpu_mut().unwrap().fpsr = FPSR_CC_N;
+        cpu.execute1();
+        assert_eq!(0x44, cpu.pc);
+    }
+
+    #[test]
+    fn fscc_and_fdbcc_test_fpu_condition() {
+        let mut cpu = m68020_fpu_core(&[
+            0xf241, 0x0001, // FSEQ D1
+            0xf249, 0x0000, 0xfffc, // FDBF D1, *
+        ]);
+        cpu.fpu_mut().unwrap().fpsr = FPSR_CC_Z;
+        cpu.dar[1] = 0x1234_0000;
+        cpu.execute1();
+        assert_eq!(0x1234_00ff, cpu.dar[1]);
+        cpu.execute1();
+        assert_eq!(0x1234_00fe, cpu.dar[1]);
+        assert_eq!(0x44, cpu.pc);
+    }
+
+    #[test]
+    fn fmovem_saves_and_restores_data_registers() {
+        let mut cpu = m68020_fpu_core(&[
+            0xf220, 0xe003, // FMOVEM.X FP0/FP1, -(A0)
+            0xf218, 0xd030, // FMOVEM.X (A0)+, FP2/FP3
+            0xf200, 0xb800, // FMOVEM.L FPCR/FPSR, D0
+        ]);
+        cpu.dar[8] = 0x3000;
+        cpu.fpu_mut().unwrap().fp[0] = Extended::from_f64(-1.0);
+        cpu.fpu_mut().unwrap().fp[1] = Extended::from_f64(2.0);
+        cpu.execute1();
+        assert_eq!(0x3000 - 24, cpu.dar[8]);
+        assert_eq!(0xbfff_0000, cpu.read_data_long(0x3000 - 24).unwrap());
+        assert_eq!(0x4000_0000, cpu.read_data_long(0x3000 - 12).unwrap());
+        cpu.execute1();
+        assert_eq!(0x3000, cpu.dar[8]);
+        assert_eq!(Extended::from_f64(-1.0), cpu.fpu().unwrap().fp[2]);
+        assert_eq!(Extended::from_f64(2.0), cpu.fpu().unwrap().fp[3]);
+        // only one control register fits in a data register
+        cpu.write_data_long(0x1000 + super::EXCEPTION_UNIMPLEMENTED_1111 as u32 * 4, 0x2000).unwrap();
+        cpu.execute1();
+        assert_eq!(0x2000, cpu.pc);
+    }
+
+    #[test]
+    fn fsave_and_frestore_fpu_frames() {
+        let mut cpu = m68020_fpu_core(&[
+            0xf327, // FSAVE -(A7)
+            0xf200, 0x4080, // FMOVE.L D0, FP1
+            0xf327, // FSAVE -(A7)
+            0xf35f, // FRESTORE (A7)+
+            0xf35f, // FRESTORE (A7)+
+        ]);
+        cpu.execute1();
+        assert_eq!(0x800 - 4, cpu.dar[15]);
+        assert_eq!(0, cpu.read_data_long(0x800 - 4).unwrap());
+        cpu.execute1();
+        cpu.execute1();
+        assert_eq!(0x800 - 4 - 0x3c, cpu.dar[15]);
+        assert_eq!(0x1f38_0000, cpu.read_data_long(0x800 - 4 - 0x3c).unwrap());
+        cpu.execute1();
+        assert_eq!(0x800 - 4, cpu.dar[15]);
+        assert_eq!(Extended::from_i32(0), cpu.fpu().unwrap().fp[1]);
+        cpu.execute1();
+        assert_eq!(0x800, cpu.dar[15]);
+        assert!(cpu.fpu().unwrap().null_state);
+        assert!(cpu.fpu().unwrap().fp[1].is_nan());
+    }
+
+    #[test]
+    fn fsave_is_privileged() {
+        let mut cpu = m68020_fpu_core(&[0xf327]); // FSAVE -(A7)
+        cpu.write_data_long(0x1000 + super::EXCEPTION_PRIVILEGE_VIOLATION as u32 * 4, 0x2000).unwrap();
+        cpu.sr_to_flags(0x0000);
+        cpu.execute1();
+        assert_eq!(0x2000, cpu.pc);
+    }
+
+    #[test]
+    fn enabled_fpu_exception_traps() {
+        let mut cpu = m68020_fpu_core(&[0xf200, 0x0420]); // FDIV.X FP1, FP0
+        cpu.write_data_long(0x1000 + super::EXCEPTION_FP_DIVIDE_BY_ZERO as u32 * 4, 0x2000).unwrap();
+        cpu.fpu_mut().unwrap().fp[0] = Extended::from_i32(1);
+        cpu.fpu_mut().unwrap().fp[1] = Extended::from_i32(0);
+        cpu.fpu_mut().unwrap().set_fpcr(FPSR_DZ);
+        cpu.execute1();
+        assert_eq!(0x2000, cpu.pc);
+        assert_eq!(Extended::infinity(false), cpu.fpu().unwrap().fp[0]);
+        assert_eq!(FPSR_DZ, cpu.fpu().unwrap().fpsr & FPSR_DZ);
+    }
+
+    mod m68008 {
+        use cpu::{TestCore, BusConfig};
+
+        pub fn m68008_core(code: &[u16]) -> TestCore {
+            let bytes: Vec<u8> = code.iter().flat_map(|w| vec![(w >> 8) as u8, *w as u8]).collect();
+            let mut cpu = TestCore::new_mem(0x40, &bytes);
+            cpu.set_bus(BusConfig::m68008());
+            cpu.dar[15] = 0x800;
+            cpu
+        }
+    }
+    use self::m68008::m68008_core;
+    use super::{BusConfig, DataBus};
+
+    #[test]
+    fn m68008_fetches_opcodes_a_byte_at_a_time() {
+        let mut cpu = m68008_core(&[0x7001]); // MOVEQ #1, D0
+        let Cycles(used) = cpu.execute1();
+        assert_eq!(1, cpu.dar[0]);
+        assert_eq!(8, used);
+    }
+
+    #[test]
+    fn m68008_reads_operands_a_byte_at_a_time() {
+        let mut cpu = m68008_core(&[0x3010]); // MOVE.W (A0), D0
+        cpu.dar[8] = 0x100;
+        cpu.write_data_word(0x100, 0x1234).unwrap();
+        let Cycles(used) = cpu.execute1();
+        assert_eq!(0x1234, cpu.dar[0]);
+        assert_eq!(16, used);
+    }
+
+    #[test]
+    fn m68008_has_twenty_address_lines() {
+        let mut cpu = m68008_core(&[0x3010]); // MOVE.W (A0), D0
+        cpu.dar[8] = 0x10_0100;
+        cpu.write_data_word(0x100, 0x1234).unwrap();
+        cpu.execute1();
+        assert_eq!(0x1234, cpu.dar[0]);
+    }
+
+    #[test]
+    fn m68ec000_on_a_16_bit_bus_keeps_68000_timing() {
+        let mut cpu = m68008_core(&[0x3010]); // MOVE.W (A0), D0
+        cpu.set_bus(BusConfig::m68ec000(DataBus::Bits16));
+        cpu.dar[8] = 0x100;
+        let Cycles(used) = cpu.execute1();
+        assert_eq!(8, used);
+    }
+
+    #[test]
+    fn changing_model_resets_bus() {
+        let mut cpu = m68008_core(&[]);
+        cpu.set_model(CpuModel::M68020);
+        assert_eq!(BusConfig { data_bus: DataBus::Bits32, address_mask: 0xffff_ffff }, cpu.bus());
+    }
 }
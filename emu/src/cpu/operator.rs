@@ -265,7 +265,7 @@ mod tests {
             core.dar[8+i as usize] = addr;
             // write just before where A0-A7 points
             let adjustment = if i == 7 {2} else {1};
-            core.mem.write_byte(SUPERVISOR_DATA, addr - adjustment, 0x11*i);
+            core.mem.write_byte(SUPERVISOR_DATA, addr - adjustment, 0x11*i).unwrap();
         }
         core.ir = 0b1111_1001_1111_1010; // X=4, Y=2
         let core = &mut core;
@@ -292,7 +292,7 @@ mod tests {
             core.dar[8+i as usize] = addr;
             // write just before where A0-A7 points
             let adjustment = if i == 7 {2} else {1};
-            core.mem.write_byte(SUPERVISOR_DATA, addr - adjustment, 0x11*i);
+            core.mem.write_byte(SUPERVISOR_DATA, addr - adjustment, 0x11*i).unwrap();
         }
 
         core.ir = 0b1111_1001_1111_1010; // X=4, Y=2
@@ -1,6 +1,6 @@
 #![macro_use]
 use super::super::Core;
-use cpu::{CFLAG_SET, ZFLAG_SET, XFLAG_SET, NFLAG_SET, ZFLAG_CLEAR, VFLAG_CLEAR, CFLAG_CLEAR, XFLAG_CLEAR, NFLAG_CLEAR};
+use cpu::{CFLAG_SET, ZFLAG_SET, XFLAG_SET, NFLAG_SET, VFLAG_SET, ZFLAG_CLEAR, VFLAG_CLEAR, CFLAG_CLEAR, XFLAG_CLEAR, NFLAG_CLEAR};
 use std::num::Wrapping;
 
 macro_rules! ir_dx {
@@ -589,6 +589,47 @@ pub fn divu_16<T: Core>(core: &mut T, dst: u32, src: u16) {
     }
 }
 
+// Put common implementation of DIVS.L and DIVU.L here (68020+)
+pub fn divl_32<T: Core>(core: &mut T, word2: u16, divisor: u32) {
+    let dq = ((word2 >> 12) & 7) as usize;
+    let dr = (word2 & 7) as usize;
+    let signed = word2 & 0x0800 != 0;
+    let dividend = if word2 & 0x0400 != 0 {
+        u64::from(dar!(core)[dr]) << 32 | u64::from(dar!(core)[dq])
+    } else if signed {
+        i64::from(dar!(core)[dq] as i32) as u64
+    } else {
+        u64::from(dar!(core)[dq])
+    };
+    let result = if signed {
+        let (dividend, divisor) = (dividend as i64, i64::from(divisor as i32));
+        match (dividend.checked_div(divisor), dividend.checked_rem(divisor)) {
+            (Some(quotient), Some(remainder)) if quotient == i64::from(quotient as i32) =>
+                Some((quotient as u32, remainder as u32)),
+            _ => None,
+        }
+    } else {
+        let quotient = dividend / u64::from(divisor);
+        let remainder = dividend % u64::from(divisor);
+        if quotient <= 0xffff_ffff {
+            Some((quotient as u32, remainder as u32))
+        } else {
+            None
+        }
+    };
+    c_flag!(core) = 0;
+    if let Some((quotient, remainder)) = result {
+        not_z_flag!(core) = quotient;
+        n_flag!(core) = quotient >> 24;
+        v_flag!(core) = 0;
+        // when Dr and Dq are the same register, only the quotient is kept
+        dar!(core)[dr] = remainder;
+        dar!(core)[dq] = quotient;
+    } else {
+        v_flag!(core) = VFLAG_SET;
+    }
+}
+
 // Put common implementation of EOR here
 pub fn eor_8<T: Core>(core: &mut T, dst: u32, src: u32) -> u32 {
     let dst = mask_out_above_8!(dst);
@@ -841,6 +882,33 @@ pub fn mulu_16<T: Core>(core: &mut T, dst: u16, src: u16) -> u32 {
     c_flag!(core) = 0;
     res
 }
+// Put common implementation of MULS.L and MULU.L here (68020+)
+pub fn mull_32<T: Core>(core: &mut T, word2: u16, src: u32) {
+    let dl = ((word2 >> 12) & 7) as usize;
+    let dh = (word2 & 7) as usize;
+    let dst = dar!(core)[dl];
+    let signed = word2 & 0x0800 != 0;
+    let res = if signed {
+        i64::from(dst as i32).wrapping_mul(i64::from(src as i32)) as u64
+    } else {
+        u64::from(dst) * u64::from(src)
+    };
+    let (hi, lo) = ((res >> 32) as u32, res as u32);
+    c_flag!(core) = 0;
+    if word2 & 0x0400 != 0 {
+        not_z_flag!(core) = hi | lo;
+        n_flag!(core) = hi >> 24;
+        v_flag!(core) = 0;
+        dar!(core)[dh] = hi;
+        dar!(core)[dl] = lo;
+    } else {
+        let overflow = if signed { hi != ((lo as i32) >> 31) as u32 } else { hi != 0 };
+        not_z_flag!(core) = lo;
+        n_flag!(core) = lo >> 24;
+        v_flag!(core) = if overflow { VFLAG_SET } else { 0 };
+        dar!(core)[dl] = lo;
+    }
+}
 // Put common implementation of NBCD here
 pub fn nbcd<T: Core>(core: &mut T, dst: u32) -> Option<u32> {
     let mut res = mask_out_above_8!((0x9a as u32).wrapping_sub(dst).wrapping_sub(core.x_flag_as_1()));
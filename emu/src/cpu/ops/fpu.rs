@@ -0,0 +1,502 @@
+// The line F instructions of the 68881/68882 floating-point coprocessor,
+// coprocessor id 1. They are only part of the instruction set of cores
+// with an FPU attached (see ConfiguredCore::set_fpu), and otherwise
+// trap as unimplemented instructions.
+//
+// The operand size depends on the command word following the opcode, so
+// like Musashi, the effective address is decoded here at run time rather
+// than by one handler per addressing mode. Exceptions enabled in the FPCR
+// are taken right after the instruction that raised them, instead of on
+// the next FPU instruction, and the cycle counts are those of the FPU
+// alone.
+use super::super::{Core, Cycles, Result, EXCEPTION_FORMAT_ERROR, EXCEPTION_TRAPV, EXCEPTION_UNIMPLEMENTED_1111};
+use super::super::Exception;
+use super::super::Exception::*;
+use super::super::effective_address;
+use super::super::fpu::{Extended, rom_constant};
+
+macro_rules! fpu {
+    ($core:ident) => ($core.fpu().expect("FPU instruction without an FPU"))
+}
+
+// An operand of an FPU instruction
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Location {
+    DataRegister(usize),
+    AddressRegister(usize),
+    Memory(u32),
+    Program(u32), // PC relative
+    Immediate,
+}
+
+// Operand formats, as in the source specifier of the command word
+const FORMAT_LONG: u16 = 0;
+const FORMAT_SINGLE: u16 = 1;
+const FORMAT_EXTENDED: u16 = 2;
+const FORMAT_PACKED: u16 = 3;
+const FORMAT_WORD: u16 = 4;
+const FORMAT_DOUBLE: u16 = 5;
+const FORMAT_BYTE: u16 = 6;
+const FORMAT_PACKED_DYNAMIC: u16 = 7; // FMOVE to memory only
+
+fn format_size(format: u16) -> u32 {
+    match format {
+        FORMAT_LONG | FORMAT_SINGLE => 4,
+        FORMAT_WORD => 2,
+        FORMAT_DOUBLE => 8,
+        FORMAT_BYTE => 1,
+        _ => 12,
+    }
+}
+
+// approximate timing of FMOVE FPn,<ea>, the FMOVEM register transfers
+// and FSAVE/FRESTORE, as they really depend on the data and the bus
+const FMOVE_OUT_CYCLES: i32 = 40;
+const FMOVEM_CYCLES: i32 = 12;
+const FMOVEM_PER_REGISTER_CYCLES: i32 = 24;
+const FSAVE_CYCLES: i32 = 16;
+const FRESTORE_CYCLES: i32 = 24;
+
+fn unimplemented<T: Core>(core: &mut T, pc: u32) -> Exception {
+    UnimplementedInstruction(ir!(core), pc, EXCEPTION_UNIMPLEMENTED_1111)
+}
+
+// Decodes the effective address of the opcode for an operand of `size`
+// bytes, updating the address register of the postincrement and
+// predecrement modes. Returns None for undefined addressing modes.
+fn decode_ea<T: Core>(core: &mut T, size: u32) -> Result<Option<Location>> {
+    let reg = ir!(core) as usize & 7;
+    // A7 is kept even
+    let size = if size == 1 && reg == 7 { 2 } else { size };
+    Ok(Some(match (ir!(core) >> 3) & 7 {
+        0 => Location::DataRegister(reg),
+        1 => Location::AddressRegister(reg),
+        2 => Location::Memory(effective_address::address_indirect_ay(core)?),
+        3 => {
+            let address = ay!(core);
+            ay!(core) = address.wrapping_add(size);
+            Location::Memory(address)
+        }
+        4 => {
+            let address = ay!(core).wrapping_sub(size);
+            ay!(core) = address;
+            Location::Memory(address)
+        }
+        5 => Location::Memory(effective_address::displacement_ay(core)?),
+        6 => Location::Memory(effective_address::index_ay(core)?),
+        _ => match reg {
+            0 => Location::Memory(effective_address::absolute_word(core)?),
+            1 => Location::Memory(effective_address::absolute_long(core)?),
+            2 => Location::Program(effective_address::displacement_pc(core)?),
+            3 => Location::Program(effective_address::index_pc(core)?),
+            4 => Location::Immediate,
+            _ => return Ok(None),
+        },
+    }))
+}
+
+fn is_register(location: Location) -> bool {
+    matches!(location, Location::DataRegister(_) | Location::AddressRegister(_))
+}
+
+// Reads `size` (1, 2 or 4) bytes at `offset` into the operand. Immediate
+// data is read in sequence.
+fn read<T: Core>(core: &mut T, location: Location, offset: u32, size: u32) -> Result<u32> {
+    match location {
+        Location::DataRegister(reg) => Ok(dar!(core)[reg]),
+        Location::AddressRegister(reg) => Ok(dar!(core)[8 + reg]),
+        Location::Memory(address) => {
+            let address = address.wrapping_add(offset);
+            match size {
+                1 => core.read_data_byte(address),
+                2 => core.read_data_word(address),
+                _ => core.read_data_long(address),
+            }
+        }
+        Location::Program(address) => {
+            let address = address.wrapping_add(offset);
+            match size {
+                1 => core.read_program_byte(address),
+                2 => core.read_program_word(address),
+                _ => core.read_program_long(address),
+            }
+        }
+        Location::Immediate => match size {
+            // byte immediates take a whole extension word
+            1 => core.read_imm_u16().map(|word| u32::from(word) & 0xff),
+            2 => core.read_imm_u16().map(u32::from),
+            _ => core.read_imm_u32(),
+        },
+    }
+}
+
+fn write<T: Core>(core: &mut T, location: Location, offset: u32, size: u32, value: u32) -> Result<()> {
+    match location {
+        Location::DataRegister(reg) => {
+            let mask = match size {
+                1 => 0xff,
+                2 => 0xffff,
+                _ => 0xffff_ffff,
+            };
+            dar!(core)[reg] = (dar!(core)[reg] & !mask) | (value & mask);
+            Ok(())
+        }
+        Location::AddressRegister(reg) => {
+            dar!(core)[8 + reg] = value;
+            Ok(())
+        }
+        Location::Memory(address) => {
+            let address = address.wrapping_add(offset);
+            match size {
+                1 => core.write_data_byte(address, value),
+                2 => core.write_data_word(address, value),
+                _ => core.write_data_long(address, value),
+            }
+        }
+        // not alterable, and rejected before getting here
+        Location::Program(_) | Location::Immediate => unreachable!(),
+    }
+}
+
+// Extended precision values in memory take three longs, the sign and
+// exponent in the upper word of the first
+fn read_extended<T: Core>(core: &mut T, location: Location, offset: u32) -> Result<Extended> {
+    let sign_exp = read(core, location, offset, 4)? >> 16;
+    let high = read(core, location, offset + 4, 4)?;
+    let low = read(core, location, offset + 8, 4)?;
+    Ok(Extended::new(sign_exp as u16, u64::from(high) << 32 | u64::from(low)))
+}
+
+fn write_extended<T: Core>(core: &mut T, location: Location, offset: u32, value: Extended) -> Result<()> {
+    write(core, location, offset, 4, u32::from(value.sign_exp) << 16)?;
+    write(core, location, offset + 4, 4, (value.mantissa >> 32) as u32)?;
+    write(core, location, offset + 8, 4, value.mantissa as u32)
+}
+
+// Performs the arithmetic operation of the opmode, trapping if it raised
+// an enabled exception
+fn arithmetic<T: Core>(core: &mut T, pc: u32, opmode: u16, src: Extended, dst: usize, exceptions: u32) -> Result<Cycles> {
+    let mut env = fpu!(core).env();
+    env.exceptions = exceptions;
+    match fpu!(core).arithmetic(opmode, src, dst, env) {
+        Some(cycles) => match fpu!(core).pending_exception() {
+            Some(vector) => Err(Trap(vector, 34 + cycles)),
+            None => Ok(Cycles(cycles)),
+        },
+        None => Err(unimplemented(core, pc)),
+    }
+}
+
+// General FPU instructions, selected by the upper three bits of the
+// command word:
+//   000 FPm to FPn operations
+//   010 <ea> to FPn operations, or FMOVECR
+//   011 FMOVE FPn to <ea>
+//   10x FMOVEM control registers
+//   11x FMOVEM data registers
+pub fn fgen<T: Core>(core: &mut T) -> Result<Cycles> {
+    let pc = pc!(core).wrapping_sub(2);
+    let command = core.read_imm_u16()?;
+    match command >> 13 {
+        0b000 => {
+            fpu!(core).begin(pc);
+            let src = fpu!(core).fp[usize::from((command >> 10) & 7)];
+            arithmetic(core, pc, command & 0x7f, src, usize::from((command >> 7) & 7), 0)
+        }
+        0b010 if (command >> 10) & 7 == 7 => {
+            fpu!(core).begin(pc);
+            let constant = rom_constant(command & 0x7f);
+            arithmetic(core, pc, 0, constant, usize::from((command >> 7) & 7), 0)
+        }
+        0b010 => fop_ea(core, pc, command),
+        0b011 => fmove_out(core, pc, command),
+        0b100 | 0b101 => fmovem_control(core, pc, command),
+        _ => fmovem_data(core, pc, command),
+    }
+}
+
+fn fop_ea<T: Core>(core: &mut T, pc: u32, command: u16) -> Result<Cycles> {
+    let format = (command >> 10) & 7;
+    let location = match decode_ea(core, format_size(format))? {
+        Some(Location::AddressRegister(_)) | None => return Err(unimplemented(core, pc)),
+        Some(Location::DataRegister(_)) if format_size(format) > 4 => return Err(unimplemented(core, pc)),
+        Some(location) => location,
+    };
+    fpu!(core).begin(pc);
+    let mut env = fpu!(core).env();
+    let src = match format {
+        FORMAT_LONG => Extended::from_i32(read(core, location, 0, 4)? as i32),
+        FORMAT_SINGLE => Extended::from_f32_bits(read(core, location, 0, 4)?),
+        FORMAT_EXTENDED => read_extended(core, location, 0)?,
+        FORMAT_PACKED => {
+            let words = [read(core, location, 0, 4)?, read(core, location, 4, 4)?, read(core, location, 8, 4)?];
+            Extended::from_packed(words, &mut env)
+        }
+        FORMAT_WORD => Extended::from_i32(i32::from(read(core, location, 0, 2)? as i16)),
+        FORMAT_DOUBLE => {
+            let high = read(core, location, 0, 4)?;
+            let low = read(core, location, 4, 4)?;
+            Extended::from_f64_bits(u64::from(high) << 32 | u64::from(low))
+        }
+        _ => Extended::from_i32(i32::from(read(core, location, 0, 1)? as i8)),
+    };
+    arithmetic(core, pc, command & 0x7f, src, usize::from((command >> 7) & 7), env.exceptions)
+}
+
+// FMOVE FPn,<ea> converts to the destination format, leaving the
+// condition codes alone
+fn fmove_out<T: Core>(core: &mut T, pc: u32, command: u16) -> Result<Cycles> {
+    let format = (command >> 10) & 7;
+    let location = match decode_ea(core, format_size(format))? {
+        Some(Location::DataRegister(_)) if format_size(format) > 4 => return Err(unimplemented(core, pc)),
+        Some(Location::AddressRegister(_)) | Some(Location::Program(_)) | Some(Location::Immediate) | None =>
+            return Err(unimplemented(core, pc)),
+        Some(location) => location,
+    };
+    fpu!(core).begin(pc);
+    let value = fpu!(core).fp[usize::from((command >> 7) & 7)];
+    let mut env = fpu!(core).env();
+    match format {
+        FORMAT_LONG => write(core, location, 0, 4, value.to_int(32, &mut env) as u32)?,
+        FORMAT_SINGLE => write(core, location, 0, 4, value.to_f32_bits(&mut env))?,
+        FORMAT_EXTENDED => write_extended(core, location, 0, value)?,
+        FORMAT_WORD => write(core, location, 0, 2, value.to_int(16, &mut env) as u32)?,
+        FORMAT_DOUBLE => {
+            let bits = value.to_f64_bits(&mut env);
+            write(core, location, 0, 4, (bits >> 32) as u32)?;
+            write(core, location, 4, 4, bits as u32)?;
+        }
+        FORMAT_BYTE => write(core, location, 0, 1, value.to_int(8, &mut env) as u32)?,
+        _ => {
+            // the k-factor is a signed 7-bit number, in the command word
+            // or a data register
+            let k = if format == FORMAT_PACKED_DYNAMIC {
+                dar!(core)[usize::from((command >> 4) & 7)]
+            } else {
+                u32::from(command)
+            };
+            let k = ((k as i8) << 1) >> 1;
+            let words = value.to_packed(i32::from(k), &mut env);
+            for (i, &word) in words.iter().enumerate() {
+                write(core, location, 4 * i as u32, 4, word)?;
+            }
+        }
+    }
+    fpu!(core).raise(env.exceptions);
+    match fpu!(core).pending_exception() {
+        Some(vector) => Err(Trap(vector, 34 + FMOVE_OUT_CYCLES)),
+        None => Ok(Cycles(FMOVE_OUT_CYCLES)),
+    }
+}
+
+// FMOVEM of FPCR, FPSR and FPIAR, in that order. A single register may
+// be moved to or from a data register, and only FPIAR to or from an
+// address register.
+fn fmovem_control<T: Core>(core: &mut T, pc: u32, command: u16) -> Result<Cycles> {
+    let to_memory = command & 0x2000 != 0;
+    let list = match (command >> 10) & 7 {
+        0 => 1, // FPIAR
+        list => list,
+    };
+    let count = list.count_ones();
+    let location = match decode_ea(core, 4 * count)? {
+        Some(Location::DataRegister(_)) if count > 1 => return Err(unimplemented(core, pc)),
+        Some(Location::AddressRegister(_)) if list != 1 => return Err(unimplemented(core, pc)),
+        Some(Location::Program(_)) | Some(Location::Immediate) if to_memory => return Err(unimplemented(core, pc)),
+        None => return Err(unimplemented(core, pc)),
+        Some(location) => location,
+    };
+    let mut offset = 0;
+    for register in [4, 2, 1].iter().filter(|&&register| list & register != 0) {
+        if to_memory {
+            let value = match *register {
+                4 => fpu!(core).fpcr,
+                2 => fpu!(core).fpsr,
+                _ => fpu!(core).fpiar,
+            };
+            write(core, location, offset, 4, value)?;
+        } else {
+            let value = read(core, location, offset, 4)?;
+            match *register {
+                4 => fpu!(core).set_fpcr(value),
+                2 => fpu!(core).set_fpsr(value),
+                _ => fpu!(core).fpiar = value,
+            }
+        }
+        if !is_register(location) {
+            offset += 4;
+        }
+    }
+    Ok(Cycles(FMOVEM_CYCLES + FMOVEM_PER_REGISTER_CYCLES * count as i32))
+}
+
+// FMOVEM of FP data registers, always stored with FP0 at the lowest
+// address. In the predecrement mode, the register list has FP0 in bit 0,
+// otherwise in bit 7. The list is either static, or in a data register.
+fn fmovem_data<T: Core>(core: &mut T, pc: u32, command: u16) -> Result<Cycles> {
+    let to_memory = command & 0x2000 != 0;
+    let predecrement_mode = command & 0x1000 == 0;
+    let list = if command & 0x0800 != 0 {
+        dar!(core)[usize::from((command >> 4) & 7)] as u8
+    } else {
+        command as u8
+    };
+    let list = if predecrement_mode { list } else { list.reverse_bits() };
+    let count = list.count_ones();
+    let mode = (ir!(core) >> 3) & 7;
+    // only stores predecrement, and only loads postincrement
+    if predecrement_mode != (mode == 4) || to_memory && mode == 3 || !to_memory && mode == 4 {
+        return Err(unimplemented(core, pc));
+    }
+    let location = match decode_ea(core, 12 * count)? {
+        Some(Location::Program(_)) if to_memory => return Err(unimplemented(core, pc)),
+        Some(Location::Memory(address)) => Location::Memory(address),
+        Some(Location::Program(address)) => Location::Program(address),
+        _ => return Err(unimplemented(core, pc)),
+    };
+    let mut offset = 0;
+    for reg in (0..8).filter(|reg| list & (1 << reg) != 0) {
+        if to_memory {
+            let value = fpu!(core).fp[reg];
+            write_extended(core, location, offset, value)?;
+        } else {
+            let value = read_extended(core, location, offset)?;
+            fpu!(core).fp[reg] = value;
+        }
+        offset += 12;
+    }
+    Ok(Cycles(FMOVEM_CYCLES + FMOVEM_PER_REGISTER_CYCLES * count as i32))
+}
+
+// Evaluates the predicate for the conditional instructions, which take
+// the BSUN trap before doing anything else
+fn condition<T: Core>(core: &mut T, pc: u32, predicate: u16) -> Result<bool> {
+    match fpu!(core).condition(predicate) {
+        Some(condition) => match fpu!(core).unordered_condition_exception() {
+            Some(vector) => Err(Trap(vector, 34)),
+            None => Ok(condition),
+        },
+        None => Err(unimplemented(core, pc)),
+    }
+}
+
+// FScc, and in the modes where it cannot write, FDBcc and FTRAPcc
+pub fn fscc<T: Core>(core: &mut T) -> Result<Cycles> {
+    let pc = pc!(core).wrapping_sub(2);
+    let predicate = core.read_imm_u16()? & 0x3f;
+    match ((ir!(core) >> 3) & 7, ir!(core) & 7) {
+        (1, _) => {
+            let displacement = core.read_imm_i16()?;
+            if condition(core, pc, predicate)? {
+                return Ok(Cycles(12));
+            }
+            let counter = dy!(core).wrapping_sub(1) & 0xffff;
+            dy!(core) = (dy!(core) & 0xffff_0000) | counter;
+            if counter != 0xffff {
+                // relative to the address of the displacement
+                pc!(core) = pc!(core).wrapping_sub(2).wrapping_add(displacement as u32);
+                Ok(Cycles(10))
+            } else {
+                Ok(Cycles(14))
+            }
+        }
+        (7, operand_mode @ 2..=4) => {
+            // the operand is only there for the trap handler to look at,
+            // the stacked PC points past it
+            let operand_bytes = match operand_mode {
+                2 => 2,
+                3 => 4,
+                _ => 0,
+            };
+            pc!(core) = pc!(core).wrapping_add(operand_bytes);
+            if condition(core, pc, predicate)? {
+                Err(Trap(EXCEPTION_TRAPV, 34))
+            } else {
+                Ok(Cycles(4))
+            }
+        }
+        _ => {
+            let location = match decode_ea(core, 1)? {
+                Some(Location::DataRegister(reg)) => Location::DataRegister(reg),
+                Some(Location::Memory(address)) => Location::Memory(address),
+                _ => return Err(unimplemented(core, pc)),
+            };
+            let value = if condition(core, pc, predicate)? { 0xff } else { 0 };
+            write(core, location, 0, 1, value)?;
+            Ok(Cycles(if is_register(location) { 4 } else { 8 }))
+        }
+    }
+}
+
+// FBcc, with the displacement relative to its own address. FBF.W with a
+// zero displacement is FNOP.
+fn fbcc<T: Core>(core: &mut T, long: bool) -> Result<Cycles> {
+    let pc = pc!(core).wrapping_sub(2);
+    let base = pc!(core);
+    let displacement = if long {
+        core.read_imm_u32()?
+    } else {
+        core.read_imm_i16()? as u32
+    };
+    let predicate = ir!(core) & 0x3f;
+    if condition(core, pc, predicate)? {
+        pc!(core) = base.wrapping_add(displacement);
+        Ok(Cycles(10))
+    } else {
+        Ok(Cycles(12))
+    }
+}
+
+pub fn fbcc_16<T: Core>(core: &mut T) -> Result<Cycles> {
+    fbcc(core, false)
+}
+
+pub fn fbcc_32<T: Core>(core: &mut T) -> Result<Cycles> {
+    fbcc(core, true)
+}
+
+// FSAVE stores the frame format word followed by the internal state,
+// which is all zeroes here, as instructions run to completion
+pub fn fsave<T: Core>(core: &mut T) -> Result<Cycles> {
+    let pc = pc!(core).wrapping_sub(2);
+    if s_flag!(core) == 0 {
+        return Err(PrivilegeViolation(ir!(core), pc));
+    }
+    let (format, size) = fpu!(core).save_frame();
+    let mode = (ir!(core) >> 3) & 7;
+    let location = match decode_ea(core, 4 + size)? {
+        Some(Location::Memory(address)) if mode != 3 => Location::Memory(address),
+        _ => return Err(unimplemented(core, pc)),
+    };
+    write(core, location, 0, 4, format)?;
+    for offset in (0..size).step_by(4) {
+        write(core, location, 4 + offset, 4, 0)?;
+    }
+    Ok(Cycles(FSAVE_CYCLES + size as i32))
+}
+
+// FRESTORE of a null frame resets the FPU, and of an idle or busy frame
+// of the same FPU model, just skips its internal state
+pub fn frestore<T: Core>(core: &mut T) -> Result<Cycles> {
+    let pc = pc!(core).wrapping_sub(2);
+    if s_flag!(core) == 0 {
+        return Err(PrivilegeViolation(ir!(core), pc));
+    }
+    let mode = (ir!(core) >> 3) & 7;
+    // the frame size isn't known until the format word is read
+    let location = match decode_ea(core, 0)? {
+        Some(Location::Memory(address)) if mode != 4 => Location::Memory(address),
+        Some(Location::Program(address)) => Location::Program(address),
+        _ => return Err(unimplemented(core, pc)),
+    };
+    let format = read(core, location, 0, 4)?;
+    match fpu!(core).restore_frame(format) {
+        Some(size) => {
+            if mode == 3 {
+                ay!(core) = ay!(core).wrapping_add(4 + size);
+            }
+            Ok(Cycles(FRESTORE_CYCLES + size as i32))
+        }
+        None => Err(Trap(EXCEPTION_FORMAT_ERROR, 4)),
+    }
+}
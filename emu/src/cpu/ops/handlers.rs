@@ -2,7 +2,7 @@ use std::collections::HashMap;
 use r68k_common::constants::*;
 use super::super::Handler;
 use super::opcodes::*;
-use super::super::InstructionSet;
+use super::super::{InstructionSet, CpuModel};
 use super::*;
 
 #[allow(dead_code)]
@@ -28,6 +28,28 @@ impl<T: Core> InstructionSetGenerator<T> {
         }
     }
 
+    // later entries override earlier ones, so the additional instructions
+    // of the 68010 and 68020 are simply appended to the 68000 optable
+    pub fn for_model(model: CpuModel) -> InstructionSetGenerator<T> {
+        let mut optable = generate_optable();
+        if model != CpuModel::M68000 {
+            optable.extend(generate_optable_010());
+        }
+        if model == CpuModel::M68020 {
+            optable.extend(generate_optable_020());
+        }
+        InstructionSetGenerator {
+            optable
+        }
+    }
+
+    // the FPU instructions take over part of line F, which otherwise
+    // traps as unimplemented
+    pub fn with_fpu(mut self) -> InstructionSetGenerator<T> {
+        self.optable.extend(generate_optable_fpu());
+        self
+    }
+
     pub fn generate(&self) -> InstructionSet<T> {
         self.generate_with(illegal, |ref op| op.handler)
     }
@@ -1896,6 +1918,401 @@ fn generate_optable<T: Core>() -> Vec<OpcodeHandler<T>> {
     ]
 }
 
+fn generate_optable_010<T: Core>() -> Vec<OpcodeHandler<T>> {
+    vec![
+        op_entry!(MASK_OUT_Y, OP_BKPT, bkpt),
+
+        op_entry!(MASK_OUT_Y, OP_MOVE_16_FRC_DN, move_16_frc_dn),
+        op_entry!(MASK_OUT_Y, OP_MOVE_16_FRC_AI, move_16_frc_ai),
+        op_entry!(MASK_OUT_Y, OP_MOVE_16_FRC_PI, move_16_frc_pi),
+        op_entry!(MASK_OUT_Y, OP_MOVE_16_FRC_PD, move_16_frc_pd),
+        op_entry!(MASK_OUT_Y, OP_MOVE_16_FRC_DI, move_16_frc_di),
+        op_entry!(MASK_OUT_Y, OP_MOVE_16_FRC_IX, move_16_frc_ix),
+        op_entry!(MASK_EXACT, OP_MOVE_16_FRC_AW, move_16_frc_aw),
+        op_entry!(MASK_EXACT, OP_MOVE_16_FRC_AL, move_16_frc_al),
+
+        op_entry!(MASK_EXACT, OP_MOVEC_32_CR, movec_32_cr),
+        op_entry!(MASK_EXACT, OP_MOVEC_32_RC, movec_32_rc),
+
+        op_entry!(MASK_OUT_Y, OP_MOVES_8_AI, moves_8_ai),
+        op_entry!(MASK_OUT_Y, OP_MOVES_8_PI, moves_8_pi),
+        op_entry!(MASK_OUT_Y, OP_MOVES_8_PD, moves_8_pd),
+        op_entry!(MASK_OUT_Y, OP_MOVES_8_DI, moves_8_di),
+        op_entry!(MASK_OUT_Y, OP_MOVES_8_IX, moves_8_ix),
+        op_entry!(MASK_EXACT, OP_MOVES_8_AW, moves_8_aw),
+        op_entry!(MASK_EXACT, OP_MOVES_8_AL, moves_8_al),
+
+        op_entry!(MASK_OUT_Y, OP_MOVES_16_AI, moves_16_ai),
+        op_entry!(MASK_OUT_Y, OP_MOVES_16_PI, moves_16_pi),
+        op_entry!(MASK_OUT_Y, OP_MOVES_16_PD, moves_16_pd),
+        op_entry!(MASK_OUT_Y, OP_MOVES_16_DI, moves_16_di),
+        op_entry!(MASK_OUT_Y, OP_MOVES_16_IX, moves_16_ix),
+        op_entry!(MASK_EXACT, OP_MOVES_16_AW, moves_16_aw),
+        op_entry!(MASK_EXACT, OP_MOVES_16_AL, moves_16_al),
+
+        op_entry!(MASK_OUT_Y, OP_MOVES_32_AI, moves_32_ai),
+        op_entry!(MASK_OUT_Y, OP_MOVES_32_PI, moves_32_pi),
+        op_entry!(MASK_OUT_Y, OP_MOVES_32_PD, moves_32_pd),
+        op_entry!(MASK_OUT_Y, OP_MOVES_32_DI, moves_32_di),
+        op_entry!(MASK_OUT_Y, OP_MOVES_32_IX, moves_32_ix),
+        op_entry!(MASK_EXACT, OP_MOVES_32_AW, moves_32_aw),
+        op_entry!(MASK_EXACT, OP_MOVES_32_AL, moves_32_al),
+
+        op_entry!(MASK_EXACT, OP_RTD_32, rtd_32),
+
+        // the same instructions as on the 68000, in 68010 cycles
+op_entry!(MASK_OUT_X, OP_ADD_8_ER_IMM, add_8_er_imm_010),
+        op_entry!(MASK_OUT_X, OP_ADD_16_ER_IMM, add_16_er_imm_010),
+        op_entry!(MASK_OUT_X, OP_ADD_32_ER_IMM, add_32_er_imm_010),
+        op_entry!(MASK_OUT_X, OP_ADDA_16_IMM, adda_16_imm_010),
+        op_entry!(MASK_OUT_X, OP_ADDA_32_IMM, adda_32_imm_010),
+        op_entry!(MASK_OUT_Y, OP_ADDI_32_DN, addi_32_dn_010),
+        op_entry!(MASK_OUT_X_Y, OP_ADDX_32_RR, addx_32_rr_010),
+        op_entry!(MASK_OUT_X, OP_AND_8_ER_IMM, and_8_er_imm_010),
+        op_entry!(MASK_OUT_X, OP_AND_16_ER_IMM, and_16_er_imm_010),
+        op_entry!(MASK_OUT_X, OP_AND_32_ER_IMM, and_32_er_imm_010),
+        op_entry!(MASK_EXACT, OP_ANDI_8_TOC, andi_8_toc_010),
+        op_entry!(MASK_EXACT, OP_ANDI_16_TOS, andi_16_tos_010),
+        op_entry!(MASK_OUT_X_Y, OP_BCLR_8_R_AI, bclr_8_r_ai_010),
+        op_entry!(MASK_OUT_X, OP_BCLR_8_R_AL, bclr_8_r_al_010),
+        op_entry!(MASK_OUT_X, OP_BCLR_8_R_AW, bclr_8_r_aw_010),
+        op_entry!(MASK_OUT_X_Y, OP_BCLR_8_R_DI, bclr_8_r_di_010),
+        op_entry!(MASK_OUT_X_Y, OP_BCLR_8_R_IX, bclr_8_r_ix_010),
+        op_entry!(MASK_OUT_X_Y, OP_BCLR_8_R_PD, bclr_8_r_pd_010),
+        op_entry!(MASK_OUT_X_Y, OP_BCLR_8_R_PI, bclr_8_r_pi_010),
+        op_entry!(MASK_OUT_X_Y, OP_CHK_16_AI, chk_16_ai_010),
+        op_entry!(MASK_OUT_X, OP_CHK_16_AL, chk_16_al_010),
+        op_entry!(MASK_OUT_X, OP_CHK_16_AW, chk_16_aw_010),
+        op_entry!(MASK_OUT_X_Y, OP_CHK_16_DN, chk_16_dn_010),
+        op_entry!(MASK_OUT_X_Y, OP_CHK_16_DI, chk_16_di_010),
+        op_entry!(MASK_OUT_X, OP_CHK_16_IMM, chk_16_imm_010),
+        op_entry!(MASK_OUT_X_Y, OP_CHK_16_IX, chk_16_ix_010),
+        op_entry!(MASK_OUT_X, OP_CHK_16_PCDI, chk_16_pcdi_010),
+        op_entry!(MASK_OUT_X, OP_CHK_16_PCIX, chk_16_pcix_010),
+        op_entry!(MASK_OUT_X_Y, OP_CHK_16_PD, chk_16_pd_010),
+        op_entry!(MASK_OUT_X_Y, OP_CHK_16_PI, chk_16_pi_010),
+        op_entry!(MASK_OUT_Y, OP_CLR_8_AI, clr_8_ai_010),
+        op_entry!(MASK_EXACT, OP_CLR_8_AL, clr_8_al_010),
+        op_entry!(MASK_EXACT, OP_CLR_8_AW, clr_8_aw_010),
+        op_entry!(MASK_OUT_Y, OP_CLR_8_DI, clr_8_di_010),
+        op_entry!(MASK_OUT_Y, OP_CLR_8_IX, clr_8_ix_010),
+        op_entry!(MASK_OUT_Y, OP_CLR_8_PD, clr_8_pd_010),
+        op_entry!(MASK_OUT_Y, OP_CLR_8_PI, clr_8_pi_010),
+        op_entry!(MASK_OUT_Y, OP_CLR_16_AI, clr_16_ai_010),
+        op_entry!(MASK_EXACT, OP_CLR_16_AL, clr_16_al_010),
+        op_entry!(MASK_EXACT, OP_CLR_16_AW, clr_16_aw_010),
+        op_entry!(MASK_OUT_Y, OP_CLR_16_DI, clr_16_di_010),
+        op_entry!(MASK_OUT_Y, OP_CLR_16_IX, clr_16_ix_010),
+        op_entry!(MASK_OUT_Y, OP_CLR_16_PD, clr_16_pd_010),
+        op_entry!(MASK_OUT_Y, OP_CLR_16_PI, clr_16_pi_010),
+        op_entry!(MASK_OUT_Y, OP_CLR_32_AI, clr_32_ai_010),
+        op_entry!(MASK_EXACT, OP_CLR_32_AL, clr_32_al_010),
+        op_entry!(MASK_EXACT, OP_CLR_32_AW, clr_32_aw_010),
+        op_entry!(MASK_OUT_Y, OP_CLR_32_DI, clr_32_di_010),
+        op_entry!(MASK_OUT_Y, OP_CLR_32_IX, clr_32_ix_010),
+        op_entry!(MASK_OUT_Y, OP_CLR_32_PD, clr_32_pd_010),
+        op_entry!(MASK_OUT_Y, OP_CLR_32_PI, clr_32_pi_010),
+        op_entry!(MASK_OUT_Y, OP_CMPI_32_DN, cmpi_32_dn_010),
+        op_entry!(MASK_OUT_X_Y, OP_DIVS_16_AI, divs_16_ai_010),
+        op_entry!(MASK_OUT_X, OP_DIVS_16_AL, divs_16_al_010),
+        op_entry!(MASK_OUT_X, OP_DIVS_16_AW, divs_16_aw_010),
+        op_entry!(MASK_OUT_X_Y, OP_DIVS_16_DN, divs_16_dn_010),
+        op_entry!(MASK_OUT_X_Y, OP_DIVS_16_DI, divs_16_di_010),
+        op_entry!(MASK_OUT_X, OP_DIVS_16_IMM, divs_16_imm_010),
+        op_entry!(MASK_OUT_X_Y, OP_DIVS_16_IX, divs_16_ix_010),
+        op_entry!(MASK_OUT_X, OP_DIVS_16_PCDI, divs_16_pcdi_010),
+        op_entry!(MASK_OUT_X, OP_DIVS_16_PCIX, divs_16_pcix_010),
+        op_entry!(MASK_OUT_X_Y, OP_DIVS_16_PD, divs_16_pd_010),
+        op_entry!(MASK_OUT_X_Y, OP_DIVS_16_PI, divs_16_pi_010),
+        op_entry!(MASK_OUT_X_Y, OP_DIVU_16_AI, divu_16_ai_010),
+        op_entry!(MASK_OUT_X, OP_DIVU_16_AL, divu_16_al_010),
+        op_entry!(MASK_OUT_X, OP_DIVU_16_AW, divu_16_aw_010),
+        op_entry!(MASK_OUT_X_Y, OP_DIVU_16_DN, divu_16_dn_010),
+        op_entry!(MASK_OUT_X_Y, OP_DIVU_16_DI, divu_16_di_010),
+        op_entry!(MASK_OUT_X, OP_DIVU_16_IMM, divu_16_imm_010),
+        op_entry!(MASK_OUT_X_Y, OP_DIVU_16_IX, divu_16_ix_010),
+        op_entry!(MASK_OUT_X, OP_DIVU_16_PCDI, divu_16_pcdi_010),
+        op_entry!(MASK_OUT_X, OP_DIVU_16_PCIX, divu_16_pcix_010),
+        op_entry!(MASK_OUT_X_Y, OP_DIVU_16_PD, divu_16_pd_010),
+        op_entry!(MASK_OUT_X_Y, OP_DIVU_16_PI, divu_16_pi_010),
+        op_entry!(MASK_OUT_X_Y, OP_EOR_32_DN, eor_32_dn_010),
+        op_entry!(MASK_EXACT, OP_EORI_8_TOC, eori_8_toc_010),
+        op_entry!(MASK_EXACT, OP_EORI_16_TOS, eori_16_tos_010),
+        op_entry!(MASK_OUT_Y, OP_EORI_32_DN, eori_32_dn_010),
+        op_entry!(MASK_OUT_Y, OP_MOVE_32_FRU, move_32_fru_010),
+        op_entry!(MASK_OUT_X_Y, OP_MOVE_32_PD_AN, move_32_pd_an_010),
+        op_entry!(MASK_OUT_X_Y, OP_MOVE_32_PD_AI, move_32_pd_ai_010),
+        op_entry!(MASK_OUT_X, OP_MOVE_32_PD_AL, move_32_pd_al_010),
+        op_entry!(MASK_OUT_X, OP_MOVE_32_PD_AW, move_32_pd_aw_010),
+        op_entry!(MASK_OUT_X_Y, OP_MOVE_32_PD_DN, move_32_pd_dn_010),
+        op_entry!(MASK_OUT_X_Y, OP_MOVE_32_PD_DI, move_32_pd_di_010),
+        op_entry!(MASK_OUT_X, OP_MOVE_32_PD_IMM, move_32_pd_imm_010),
+        op_entry!(MASK_OUT_X_Y, OP_MOVE_32_PD_IX, move_32_pd_ix_010),
+        op_entry!(MASK_OUT_X, OP_MOVE_32_PD_PCDI, move_32_pd_pcdi_010),
+        op_entry!(MASK_OUT_X, OP_MOVE_32_PD_PCIX, move_32_pd_pcix_010),
+        op_entry!(MASK_OUT_X_Y, OP_MOVE_32_PD_PD, move_32_pd_pd_010),
+        op_entry!(MASK_OUT_X_Y, OP_MOVE_32_PD_PI, move_32_pd_pi_010),
+        op_entry!(MASK_OUT_Y, OP_MOVE_32_TOU, move_32_tou_010),
+        op_entry!(MASK_OUT_X_Y, OP_MULS_16_AI, muls_16_ai_010),
+        op_entry!(MASK_OUT_X, OP_MULS_16_AL, muls_16_al_010),
+        op_entry!(MASK_OUT_X, OP_MULS_16_AW, muls_16_aw_010),
+        op_entry!(MASK_OUT_X_Y, OP_MULS_16_DN, muls_16_dn_010),
+        op_entry!(MASK_OUT_X_Y, OP_MULS_16_DI, muls_16_di_010),
+        op_entry!(MASK_OUT_X, OP_MULS_16_IMM, muls_16_imm_010),
+        op_entry!(MASK_OUT_X_Y, OP_MULS_16_IX, muls_16_ix_010),
+        op_entry!(MASK_OUT_X, OP_MULS_16_PCDI, muls_16_pcdi_010),
+        op_entry!(MASK_OUT_X, OP_MULS_16_PCIX, muls_16_pcix_010),
+        op_entry!(MASK_OUT_X_Y, OP_MULS_16_PD, muls_16_pd_010),
+        op_entry!(MASK_OUT_X_Y, OP_MULS_16_PI, muls_16_pi_010),
+        op_entry!(MASK_OUT_X_Y, OP_MULU_16_AI, mulu_16_ai_010),
+        op_entry!(MASK_OUT_X, OP_MULU_16_AL, mulu_16_al_010),
+        op_entry!(MASK_OUT_X, OP_MULU_16_AW, mulu_16_aw_010),
+        op_entry!(MASK_OUT_X_Y, OP_MULU_16_DN, mulu_16_dn_010),
+        op_entry!(MASK_OUT_X_Y, OP_MULU_16_DI, mulu_16_di_010),
+        op_entry!(MASK_OUT_X, OP_MULU_16_IMM, mulu_16_imm_010),
+        op_entry!(MASK_OUT_X_Y, OP_MULU_16_IX, mulu_16_ix_010),
+        op_entry!(MASK_OUT_X, OP_MULU_16_PCDI, mulu_16_pcdi_010),
+        op_entry!(MASK_OUT_X, OP_MULU_16_PCIX, mulu_16_pcix_010),
+        op_entry!(MASK_OUT_X_Y, OP_MULU_16_PD, mulu_16_pd_010),
+        op_entry!(MASK_OUT_X_Y, OP_MULU_16_PI, mulu_16_pi_010),
+        op_entry!(MASK_OUT_X, OP_OR_8_ER_IMM, or_8_er_imm_010),
+        op_entry!(MASK_OUT_X, OP_OR_16_ER_IMM, or_16_er_imm_010),
+        op_entry!(MASK_OUT_X, OP_OR_32_ER_IMM, or_32_er_imm_010),
+        op_entry!(MASK_EXACT, OP_ORI_8_TOC, ori_8_toc_010),
+        op_entry!(MASK_EXACT, OP_ORI_16_TOS, ori_16_tos_010),
+        op_entry!(MASK_OUT_Y, OP_ORI_32_DN, ori_32_dn_010),
+        op_entry!(MASK_OUT_X, OP_SUB_8_ER_IMM, sub_8_er_imm_010),
+        op_entry!(MASK_OUT_X, OP_SUB_16_ER_IMM, sub_16_er_imm_010),
+        op_entry!(MASK_OUT_X, OP_SUB_32_ER_IMM, sub_32_er_imm_010),
+        op_entry!(MASK_OUT_X, OP_SUBA_16_IMM, suba_16_imm_010),
+        op_entry!(MASK_OUT_X, OP_SUBA_32_IMM, suba_32_imm_010),
+        op_entry!(MASK_OUT_Y, OP_SUBI_32_DN, subi_32_dn_010),
+        op_entry!(MASK_OUT_X_Y, OP_SUBQ_16_AN, subq_16_an_010),
+        op_entry!(MASK_OUT_X_Y, OP_SUBX_32_RR, subx_32_rr_010),
+    ]
+}
+
+// The 68020 additionally has all the instructions of the 68010
+fn generate_optable_020<T: Core>() -> Vec<OpcodeHandler<T>> {
+    vec![
+        op_entry!(MASK_OUT_Y, OP_BFTST_32_DN, bftst_32_dn),
+        op_entry!(MASK_OUT_Y, OP_BFTST_32_AI, bftst_32_ai),
+        op_entry!(MASK_OUT_Y, OP_BFTST_32_DI, bftst_32_di),
+        op_entry!(MASK_OUT_Y, OP_BFTST_32_IX, bftst_32_ix),
+        op_entry!(MASK_EXACT, OP_BFTST_32_AW, bftst_32_aw),
+        op_entry!(MASK_EXACT, OP_BFTST_32_AL, bftst_32_al),
+        op_entry!(MASK_EXACT, OP_BFTST_32_PCDI, bftst_32_pcdi),
+        op_entry!(MASK_EXACT, OP_BFTST_32_PCIX, bftst_32_pcix),
+
+        op_entry!(MASK_OUT_Y, OP_BFEXTU_32_DN, bfextu_32_dn),
+        op_entry!(MASK_OUT_Y, OP_BFEXTU_32_AI, bfextu_32_ai),
+        op_entry!(MASK_OUT_Y, OP_BFEXTU_32_DI, bfextu_32_di),
+        op_entry!(MASK_OUT_Y, OP_BFEXTU_32_IX, bfextu_32_ix),
+        op_entry!(MASK_EXACT, OP_BFEXTU_32_AW, bfextu_32_aw),
+        op_entry!(MASK_EXACT, OP_BFEXTU_32_AL, bfextu_32_al),
+        op_entry!(MASK_EXACT, OP_BFEXTU_32_PCDI, bfextu_32_pcdi),
+        op_entry!(MASK_EXACT, OP_BFEXTU_32_PCIX, bfextu_32_pcix),
+
+        op_entry!(MASK_OUT_Y, OP_BFCHG_32_DN, bfchg_32_dn),
+        op_entry!(MASK_OUT_Y, OP_BFCHG_32_AI, bfchg_32_ai),
+        op_entry!(MASK_OUT_Y, OP_BFCHG_32_DI, bfchg_32_di),
+        op_entry!(MASK_OUT_Y, OP_BFCHG_32_IX, bfchg_32_ix),
+        op_entry!(MASK_EXACT, OP_BFCHG_32_AW, bfchg_32_aw),
+        op_entry!(MASK_EXACT, OP_BFCHG_32_AL, bfchg_32_al),
+
+        op_entry!(MASK_OUT_Y, OP_BFEXTS_32_DN, bfexts_32_dn),
+        op_entry!(MASK_OUT_Y, OP_BFEXTS_32_AI, bfexts_32_ai),
+        op_entry!(MASK_OUT_Y, OP_BFEXTS_32_DI, bfexts_32_di),
+        op_entry!(MASK_OUT_Y, OP_BFEXTS_32_IX, bfexts_32_ix),
+        op_entry!(MASK_EXACT, OP_BFEXTS_32_AW, bfexts_32_aw),
+        op_entry!(MASK_EXACT, OP_BFEXTS_32_AL, bfexts_32_al),
+        op_entry!(MASK_EXACT, OP_BFEXTS_32_PCDI, bfexts_32_pcdi),
+        op_entry!(MASK_EXACT, OP_BFEXTS_32_PCIX, bfexts_32_pcix),
+
+        op_entry!(MASK_OUT_Y, OP_BFCLR_32_DN, bfclr_32_dn),
+        op_entry!(MASK_OUT_Y, OP_BFCLR_32_AI, bfclr_32_ai),
+        op_entry!(MASK_OUT_Y, OP_BFCLR_32_DI, bfclr_32_di),
+        op_entry!(MASK_OUT_Y, OP_BFCLR_32_IX, bfclr_32_ix),
+        op_entry!(MASK_EXACT, OP_BFCLR_32_AW, bfclr_32_aw),
+        op_entry!(MASK_EXACT, OP_BFCLR_32_AL, bfclr_32_al),
+
+        op_entry!(MASK_OUT_Y, OP_BFFFO_32_DN, bfffo_32_dn),
+        op_entry!(MASK_OUT_Y, OP_BFFFO_32_AI, bfffo_32_ai),
+        op_entry!(MASK_OUT_Y, OP_BFFFO_32_DI, bfffo_32_di),
+        op_entry!(MASK_OUT_Y, OP_BFFFO_32_IX, bfffo_32_ix),
+        op_entry!(MASK_EXACT, OP_BFFFO_32_AW, bfffo_32_aw),
+        op_entry!(MASK_EXACT, OP_BFFFO_32_AL, bfffo_32_al),
+        op_entry!(MASK_EXACT, OP_BFFFO_32_PCDI, bfffo_32_pcdi),
+        op_entry!(MASK_EXACT, OP_BFFFO_32_PCIX, bfffo_32_pcix),
+
+        op_entry!(MASK_OUT_Y, OP_BFSET_32_DN, bfset_32_dn),
+        op_entry!(MASK_OUT_Y, OP_BFSET_32_AI, bfset_32_ai),
+        op_entry!(MASK_OUT_Y, OP_BFSET_32_DI, bfset_32_di),
+        op_entry!(MASK_OUT_Y, OP_BFSET_32_IX, bfset_32_ix),
+        op_entry!(MASK_EXACT, OP_BFSET_32_AW, bfset_32_aw),
+        op_entry!(MASK_EXACT, OP_BFSET_32_AL, bfset_32_al),
+
+        op_entry!(MASK_OUT_Y, OP_BFINS_32_DN, bfins_32_dn),
+        op_entry!(MASK_OUT_Y, OP_BFINS_32_AI, bfins_32_ai),
+        op_entry!(MASK_OUT_Y, OP_BFINS_32_DI, bfins_32_di),
+        op_entry!(MASK_OUT_Y, OP_BFINS_32_IX, bfins_32_ix),
+        op_entry!(MASK_EXACT, OP_BFINS_32_AW, bfins_32_aw),
+        op_entry!(MASK_EXACT, OP_BFINS_32_AL, bfins_32_al),
+
+        op_entry!(MASK_OUT_Y, OP_CALLM_32_AI, callm_32_ai),
+        op_entry!(MASK_OUT_Y, OP_CALLM_32_DI, callm_32_di),
+        op_entry!(MASK_OUT_Y, OP_CALLM_32_IX, callm_32_ix),
+        op_entry!(MASK_EXACT, OP_CALLM_32_AW, callm_32_aw),
+        op_entry!(MASK_EXACT, OP_CALLM_32_AL, callm_32_al),
+        op_entry!(MASK_EXACT, OP_CALLM_32_PCDI, callm_32_pcdi),
+        op_entry!(MASK_EXACT, OP_CALLM_32_PCIX, callm_32_pcix),
+        op_entry!(MASK_LONIB, OP_RTM_32, rtm_32),
+
+        op_entry!(MASK_OUT_Y, OP_CAS_8_AI, cas_8_ai),
+        op_entry!(MASK_OUT_Y, OP_CAS_8_PI, cas_8_pi),
+        op_entry!(MASK_OUT_Y, OP_CAS_8_PD, cas_8_pd),
+        op_entry!(MASK_OUT_Y, OP_CAS_8_DI, cas_8_di),
+        op_entry!(MASK_OUT_Y, OP_CAS_8_IX, cas_8_ix),
+        op_entry!(MASK_EXACT, OP_CAS_8_AW, cas_8_aw),
+        op_entry!(MASK_EXACT, OP_CAS_8_AL, cas_8_al),
+
+        op_entry!(MASK_OUT_Y, OP_CAS_16_AI, cas_16_ai),
+        op_entry!(MASK_OUT_Y, OP_CAS_16_PI, cas_16_pi),
+        op_entry!(MASK_OUT_Y, OP_CAS_16_PD, cas_16_pd),
+        op_entry!(MASK_OUT_Y, OP_CAS_16_DI, cas_16_di),
+        op_entry!(MASK_OUT_Y, OP_CAS_16_IX, cas_16_ix),
+        op_entry!(MASK_EXACT, OP_CAS_16_AW, cas_16_aw),
+        op_entry!(MASK_EXACT, OP_CAS_16_AL, cas_16_al),
+
+        op_entry!(MASK_OUT_Y, OP_CAS_32_AI, cas_32_ai),
+        op_entry!(MASK_OUT_Y, OP_CAS_32_PI, cas_32_pi),
+        op_entry!(MASK_OUT_Y, OP_CAS_32_PD, cas_32_pd),
+        op_entry!(MASK_OUT_Y, OP_CAS_32_DI, cas_32_di),
+        op_entry!(MASK_OUT_Y, OP_CAS_32_IX, cas_32_ix),
+        op_entry!(MASK_EXACT, OP_CAS_32_AW, cas_32_aw),
+        op_entry!(MASK_EXACT, OP_CAS_32_AL, cas_32_al),
+
+        op_entry!(MASK_EXACT, OP_CAS2_16, cas2_16),
+        op_entry!(MASK_EXACT, OP_CAS2_32, cas2_32),
+
+        op_entry!(MASK_OUT_Y, OP_CHK2_8_AI, chk2_8_ai),
+        op_entry!(MASK_OUT_Y, OP_CHK2_8_DI, chk2_8_di),
+        op_entry!(MASK_OUT_Y, OP_CHK2_8_IX, chk2_8_ix),
+        op_entry!(MASK_EXACT, OP_CHK2_8_AW, chk2_8_aw),
+        op_entry!(MASK_EXACT, OP_CHK2_8_AL, chk2_8_al),
+        op_entry!(MASK_EXACT, OP_CHK2_8_PCDI, chk2_8_pcdi),
+        op_entry!(MASK_EXACT, OP_CHK2_8_PCIX, chk2_8_pcix),
+
+        op_entry!(MASK_OUT_Y, OP_CHK2_16_AI, chk2_16_ai),
+        op_entry!(MASK_OUT_Y, OP_CHK2_16_DI, chk2_16_di),
+        op_entry!(MASK_OUT_Y, OP_CHK2_16_IX, chk2_16_ix),
+        op_entry!(MASK_EXACT, OP_CHK2_16_AW, chk2_16_aw),
+        op_entry!(MASK_EXACT, OP_CHK2_16_AL, chk2_16_al),
+        op_entry!(MASK_EXACT, OP_CHK2_16_PCDI, chk2_16_pcdi),
+        op_entry!(MASK_EXACT, OP_CHK2_16_PCIX, chk2_16_pcix),
+
+        op_entry!(MASK_OUT_Y, OP_CHK2_32_AI, chk2_32_ai),
+        op_entry!(MASK_OUT_Y, OP_CHK2_32_DI, chk2_32_di),
+        op_entry!(MASK_OUT_Y, OP_CHK2_32_IX, chk2_32_ix),
+        op_entry!(MASK_EXACT, OP_CHK2_32_AW, chk2_32_aw),
+        op_entry!(MASK_EXACT, OP_CHK2_32_AL, chk2_32_al),
+        op_entry!(MASK_EXACT, OP_CHK2_32_PCDI, chk2_32_pcdi),
+        op_entry!(MASK_EXACT, OP_CHK2_32_PCIX, chk2_32_pcix),
+
+        op_entry!(MASK_OUT_Y, OP_MULL_32_DN, mull_32_dn),
+        op_entry!(MASK_OUT_Y, OP_MULL_32_AI, mull_32_ai),
+        op_entry!(MASK_OUT_Y, OP_MULL_32_PI, mull_32_pi),
+        op_entry!(MASK_OUT_Y, OP_MULL_32_PD, mull_32_pd),
+        op_entry!(MASK_OUT_Y, OP_MULL_32_DI, mull_32_di),
+        op_entry!(MASK_OUT_Y, OP_MULL_32_IX, mull_32_ix),
+        op_entry!(MASK_EXACT, OP_MULL_32_AW, mull_32_aw),
+        op_entry!(MASK_EXACT, OP_MULL_32_AL, mull_32_al),
+        op_entry!(MASK_EXACT, OP_MULL_32_PCDI, mull_32_pcdi),
+        op_entry!(MASK_EXACT, OP_MULL_32_PCIX, mull_32_pcix),
+        op_entry!(MASK_EXACT, OP_MULL_32_IMM, mull_32_imm),
+
+        op_entry!(MASK_OUT_Y, OP_DIVL_32_DN, divl_32_dn),
+        op_entry!(MASK_OUT_Y, OP_DIVL_32_AI, divl_32_ai),
+        op_entry!(MASK_OUT_Y, OP_DIVL_32_PI, divl_32_pi),
+        op_entry!(MASK_OUT_Y, OP_DIVL_32_PD, divl_32_pd),
+        op_entry!(MASK_OUT_Y, OP_DIVL_32_DI, divl_32_di),
+        op_entry!(MASK_OUT_Y, OP_DIVL_32_IX, divl_32_ix),
+        op_entry!(MASK_EXACT, OP_DIVL_32_AW, divl_32_aw),
+        op_entry!(MASK_EXACT, OP_DIVL_32_AL, divl_32_al),
+        op_entry!(MASK_EXACT, OP_DIVL_32_PCDI, divl_32_pcdi),
+        op_entry!(MASK_EXACT, OP_DIVL_32_PCIX, divl_32_pcix),
+        op_entry!(MASK_EXACT, OP_DIVL_32_IMM, divl_32_imm),
+
+        op_entry!(MASK_OUT_Y, OP_EXT_BL, ext_bl),
+        op_entry!(MASK_OUT_Y, OP_LINK_32, link_32),
+
+        op_entry!(MASK_OUT_X_Y, OP_PACK_16_RR, pack_16_rr),
+        op_entry!(MASK_OUT_X_Y, OP_PACK_16_MM, pack_16_mm),
+        op_entry!(MASK_OUT_X_Y, OP_UNPK_16_RR, unpk_16_rr),
+        op_entry!(MASK_OUT_X_Y, OP_UNPK_16_MM, unpk_16_mm),
+
+        op_entry!(MASK_EXACT, OP_TRAPT, trapt),
+        op_entry!(MASK_EXACT, OP_TRAPT_16, trapt_16),
+        op_entry!(MASK_EXACT, OP_TRAPT_32, trapt_32),
+        op_entry!(MASK_EXACT, OP_TRAPF, trapf),
+        op_entry!(MASK_EXACT, OP_TRAPF_16, trapf_16),
+        op_entry!(MASK_EXACT, OP_TRAPF_32, trapf_32),
+        op_entry!(MASK_EXACT, OP_TRAPHI, traphi),
+        op_entry!(MASK_EXACT, OP_TRAPHI_16, traphi_16),
+        op_entry!(MASK_EXACT, OP_TRAPHI_32, traphi_32),
+        op_entry!(MASK_EXACT, OP_TRAPLS, trapls),
+        op_entry!(MASK_EXACT, OP_TRAPLS_16, trapls_16),
+        op_entry!(MASK_EXACT, OP_TRAPLS_32, trapls_32),
+        op_entry!(MASK_EXACT, OP_TRAPCC, trapcc),
+        op_entry!(MASK_EXACT, OP_TRAPCC_16, trapcc_16),
+        op_entry!(MASK_EXACT, OP_TRAPCC_32, trapcc_32),
+        op_entry!(MASK_EXACT, OP_TRAPCS, trapcs),
+        op_entry!(MASK_EXACT, OP_TRAPCS_16, trapcs_16),
+        op_entry!(MASK_EXACT, OP_TRAPCS_32, trapcs_32),
+        op_entry!(MASK_EXACT, OP_TRAPNE, trapne),
+        op_entry!(MASK_EXACT, OP_TRAPNE_16, trapne_16),
+        op_entry!(MASK_EXACT, OP_TRAPNE_32, trapne_32),
+        op_entry!(MASK_EXACT, OP_TRAPEQ, trapeq),
+        op_entry!(MASK_EXACT, OP_TRAPEQ_16, trapeq_16),
+        op_entry!(MASK_EXACT, OP_TRAPEQ_32, trapeq_32),
+        op_entry!(MASK_EXACT, OP_TRAPVC, trapvc),
+        op_entry!(MASK_EXACT, OP_TRAPVC_16, trapvc_16),
+        op_entry!(MASK_EXACT, OP_TRAPVC_32, trapvc_32),
+        op_entry!(MASK_EXACT, OP_TRAPVS, trapvs),
+        op_entry!(MASK_EXACT, OP_TRAPVS_16, trapvs_16),
+        op_entry!(MASK_EXACT, OP_TRAPVS_32, trapvs_32),
+        op_entry!(MASK_EXACT, OP_TRAPPL, trappl),
+        op_entry!(MASK_EXACT, OP_TRAPPL_16, trappl_16),
+        op_entry!(MASK_EXACT, OP_TRAPPL_32, trappl_32),
+        op_entry!(MASK_EXACT, OP_TRAPMI, trapmi),
+        op_entry!(MASK_EXACT, OP_TRAPMI_16, trapmi_16),
+        op_entry!(MASK_EXACT, OP_TRAPMI_32, trapmi_32),
+        op_entry!(MASK_EXACT, OP_TRAPGE, trapge),
+        op_entry!(MASK_EXACT, OP_TRAPGE_16, trapge_16),
+        op_entry!(MASK_EXACT, OP_TRAPGE_32, trapge_32),
+        op_entry!(MASK_EXACT, OP_TRAPLT, traplt),
+        op_entry!(MASK_EXACT, OP_TRAPLT_16, traplt_16),
+        op_entry!(MASK_EXACT, OP_TRAPLT_32, traplt_32),
+        op_entry!(MASK_EXACT, OP_TRAPGT, trapgt),
+        op_entry!(MASK_EXACT, OP_TRAPGT_16, trapgt_16),
+        op_entry!(MASK_EXACT, OP_TRAPGT_32, trapgt_32),
+        op_entry!(MASK_EXACT, OP_TRAPLE, traple),
+        op_entry!(MASK_EXACT, OP_TRAPLE_16, traple_16),
+        op_entry!(MASK_EXACT, OP_TRAPLE_32, traple_32),
+    ]
+}
+
+fn generate_optable_fpu<T: Core>() -> Vec<OpcodeHandler<T>> {
+    vec![
+        op_entry!(MASK_OUT_EA, OP_FGEN, fgen),
+        op_entry!(MASK_OUT_EA, OP_FSCC, fscc),
+        op_entry!(MASK_OUT_EA, OP_FBCC_16, fbcc_16),
+        op_entry!(MASK_OUT_EA, OP_FBCC_32, fbcc_32),
+        op_entry!(MASK_OUT_EA, OP_FSAVE, fsave),
+        op_entry!(MASK_OUT_EA, OP_FRESTORE, frestore),
+    ]
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -1903,7 +2320,10 @@ mod tests {
     
     #[test]
     fn optable_mask_and_matching_makes_sense() {
-        let optable = super::generate_optable::<TestCore>();
+        let mut optable = super::generate_optable::<TestCore>();
+        optable.extend(super::generate_optable_010::<TestCore>());
+        optable.extend(super::generate_optable_020::<TestCore>());
+        optable.extend(super::generate_optable_fpu::<TestCore>());
 
         for op in optable {
             if op.mask & op.matching != op.matching {
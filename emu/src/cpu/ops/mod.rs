@@ -1,8 +1,9 @@
 #![macro_use]
-use super::{Core, Cycles, Result, EXCEPTION_CHK, EXCEPTION_UNIMPLEMENTED_1010, EXCEPTION_UNIMPLEMENTED_1111, EXCEPTION_ZERO_DIVIDE, EXCEPTION_TRAP_BASE, EXCEPTION_TRAPV};
+use super::{Core, CpuModel, Cycles, Result, CFLAG_SET, EXCEPTION_CHK, EXCEPTION_FORMAT_ERROR, EXCEPTION_UNIMPLEMENTED_1010, EXCEPTION_UNIMPLEMENTED_1111, EXCEPTION_ZERO_DIVIDE, EXCEPTION_TRAP_BASE, EXCEPTION_TRAPV};
 use super::Exception::*;
 
 mod common;
+mod fpu;
 pub mod handlers;
 pub mod opcodes;
 
@@ -175,8 +176,15 @@ use super::InstructionSet;
 pub fn instruction_set<T: Core>() -> InstructionSet<T> {
     handlers::InstructionSetGenerator::new().generate()
 }
+pub fn instruction_set_for<T: Core>(model: CpuModel) -> InstructionSet<T> {
+    handlers::InstructionSetGenerator::for_model(model).generate()
+}
+pub fn instruction_set_with_fpu<T: Core>(model: CpuModel) -> InstructionSet<T> {
+    handlers::InstructionSetGenerator::for_model(model).with_fpu().generate()
+}
 use std::num::Wrapping;
 use super::operator;
+pub use self::fpu::{fgen, fscc, fbcc_16, fbcc_32, fsave, frestore};
 
 pub fn unimplemented_1010<T: Core>(core: &mut T) -> Result<Cycles> {
     Err(UnimplementedInstruction(ir!(core), pc!(core).wrapping_sub(2), EXCEPTION_UNIMPLEMENTED_1010))
@@ -675,8 +683,10 @@ macro_rules! branch {
                 let offset = mask_out_above_8!(ir!(core)) as i8;
                 core.branch_8(offset);
                 Cycles(10)
-            } else {
+            } else if core.model() == CpuModel::M68000 {
                 Cycles(8)
+            } else {
+                Cycles(6)
             })
         }
     };
@@ -690,7 +700,7 @@ macro_rules! branch {
                 Cycles(10)
             } else {
                 pc!(core) = pc!(core).wrapping_add(2);
-                Cycles(12)
+                Cycles(if core.model() == CpuModel::M68000 { 12 } else { 10 })
             })
         }
     };
@@ -708,7 +718,7 @@ macro_rules! branch {
                     Cycles(10)
                 } else {
                     pc!(core) = pc!(core).wrapping_add(2);
-                    Cycles(14)
+                    Cycles(if core.model() == CpuModel::M68000 { 14 } else { 16 })
                 }
             } else {
                 pc!(core) = pc!(core).wrapping_add(2);
@@ -957,7 +967,7 @@ pub fn bra_16<T: Core>(core: &mut T) -> Result<Cycles> {
 pub fn bsr_8<T: Core>(core: &mut T) -> Result<Cycles> {
     let offset = mask_out_above_8!(ir!(core)) as i8;
     let pc = pc!(core);
-    core.push_32(pc);
+    try!(core.push_32(pc));
     core.branch_8(offset);
     Ok(Cycles(18))
 }
@@ -965,7 +975,7 @@ pub fn bsr_8<T: Core>(core: &mut T) -> Result<Cycles> {
 pub fn bsr_16<T: Core>(core: &mut T) -> Result<Cycles> {
     let offset = try!(core.read_imm_i16());
     let pc = pc!(core);
-    core.push_32(pc);
+    try!(core.push_32(pc));
     pc!(core) = pc!(core).wrapping_sub(2);
     core.branch_16(offset);
     Ok(Cycles(18))
@@ -1438,6 +1448,17 @@ pub fn ext_wl<T: Core>(core: &mut T) -> Result<Cycles> {
     not_z_flag!(core) = res;
     Ok(Cycles(4))
 }
+// EXTB.L (68020+)
+pub fn ext_bl<T: Core>(core: &mut T) -> Result<Cycles> {
+    let res = dy!(core) as u8 as i8 as i32 as u32;
+    dy!(core) = res;
+
+    n_flag!(core) = res >> 24;
+    v_flag!(core) = 0;
+    c_flag!(core) = 0;
+    not_z_flag!(core) = res;
+    Ok(Cycles(4))
+}
 
 // Put implementation of ILLEGAL op here
 
@@ -1457,7 +1478,7 @@ macro_rules! jump {
             // using a constant expression will optimize this check away
             if $push {
                 let pc = pc!(core);
-                core.push_32(pc);
+                try!(core.push_32(pc));
             }
             core.jump(ea);
             Ok(Cycles($cycles))
@@ -1502,15 +1523,28 @@ lea!(lea_32_pcix, index_pc, 12);
 // Put implementation of LINK ops here
 pub fn link_16<T: Core>(core: &mut T) -> Result<Cycles> {
     let sp = if ir_ay!(core) == super::STACK_POINTER_REG {
-        core.push_sp()
+        try!(core.push_sp())
     } else {
         let ay = ay!(core);
-        core.push_32(ay)
+        try!(core.push_32(ay))
     };
     ay!(core) = sp;
     sp!(core) = try!(effective_address::displacement(core, sp));
     Ok(Cycles(16))
 }
+// LINK.L (68020+)
+pub fn link_32<T: Core>(core: &mut T) -> Result<Cycles> {
+    let sp = if ir_ay!(core) == super::STACK_POINTER_REG {
+        try!(core.push_sp())
+    } else {
+        let ay = ay!(core);
+        try!(core.push_32(ay))
+    };
+    ay!(core) = sp;
+    let displacement = try!(core.read_imm_u32());
+    sp!(core) = sp.wrapping_add(displacement);
+    Ok(Cycles(6))
+}
 
 // Put implementation of LSL, LSR ops here
 macro_rules! lsr_8 {
@@ -1999,14 +2033,25 @@ move_toc!(move_16_toc_imm, imm_16, 12+4);
 macro_rules! move_frs {
     ($name:ident, dy, $cycles:expr) => (
         pub fn $name<T: Core>(core: &mut T) -> Result<Cycles> {
-            dy!(core) = mask_out_below_16!(dy!(core)) | u32::from(core.status_register());
-            Ok(Cycles($cycles))
+            // privileged on the 68010, which has MOVE from CCR instead
+            if core.model() == CpuModel::M68000 {
+                dy!(core) = mask_out_below_16!(dy!(core)) | u32::from(core.status_register());
+                Ok(Cycles($cycles))
+            } else if s_flag!(core) != 0 {
+                dy!(core) = mask_out_below_16!(dy!(core)) | u32::from(core.status_register());
+                Ok(Cycles(4))
+            } else {
+                Err(PrivilegeViolation(ir!(core), pc!(core).wrapping_sub(2)))
+            }
         });
     ($name:ident, $src:ident, $cycles:expr) => (
         pub fn $name<T: Core>(core: &mut T) -> Result<Cycles> {
   // unsigned int ea = ((m68ki_cpu.dar+8)[m68ki_cpu.ir & 7]);
   // m68ki_write_16_fc(ea, m68ki_cpu.s_flag | 1, ( m68ki_cpu.t1_flag | m68ki_cpu.t0_flag | (m68ki_cpu.s_flag << 11) | (m68ki_cpu.m_flag << 11) | m68ki_cpu.int_mask | (((m68ki_cpu.x_flag&0x100) >> 4) | ((m68ki_cpu.n_flag&0x80) >> 4) | ((!m68ki_cpu.not_z_flag) << 2) | ((m68ki_cpu.v_flag&0x80) >> 6) | ((m68ki_cpu.c_flag&0x100) >> 8))));
   // return;
+            if core.model() != CpuModel::M68000 && s_flag!(core) == 0 {
+                return Err(PrivilegeViolation(ir!(core), pc!(core).wrapping_sub(2)))
+            }
             let sr = core.status_register();
             let ea = try!(effective_address::$src(core));
             try!(core.write_data_word(ea, u32::from(sr)));
@@ -2022,6 +2067,30 @@ move_frs!(move_16_frs_ix, index_ay,            8+10);
 move_frs!(move_16_frs_aw, absolute_word,       8+8);
 move_frs!(move_16_frs_al, absolute_long,       8+12);
 
+// Put implementation of MOVE from CCR ops here (68010+)
+macro_rules! move_frc {
+    ($name:ident, dy, $cycles:expr) => (
+        pub fn $name<T: Core>(core: &mut T) -> Result<Cycles> {
+            dy!(core) = mask_out_below_16!(dy!(core)) | u32::from(core.condition_code_register());
+            Ok(Cycles($cycles))
+        });
+    ($name:ident, $src:ident, $cycles:expr) => (
+        pub fn $name<T: Core>(core: &mut T) -> Result<Cycles> {
+            let ccr = core.condition_code_register();
+            let ea = try!(effective_address::$src(core));
+            try!(core.write_data_word(ea, u32::from(ccr)));
+            Ok(Cycles($cycles))
+        })
+}
+move_frc!(move_16_frc_dn, dy, 4);
+move_frc!(move_16_frc_ai, address_indirect_ay, 8+4);
+move_frc!(move_16_frc_pi, postincrement_ay_16, 8+4);
+move_frc!(move_16_frc_pd, predecrement_ay_16,  8+6);
+move_frc!(move_16_frc_di, displacement_ay,     8+8);
+move_frc!(move_16_frc_ix, index_ay,            8+10);
+move_frc!(move_16_frc_aw, absolute_word,       8+8);
+move_frc!(move_16_frc_al, absolute_long,       8+12);
+
 // Put implementation of MOVE to SR ops here
 macro_rules! move_tos {
     ($name:ident, $src:ident, $cycles:expr) => (
@@ -2726,7 +2795,7 @@ macro_rules! pea {
     ($name:ident, $src:ident, $cycles:expr) => (
         pub fn $name<T: Core>(core: &mut T) -> Result<Cycles> {
             let ea = try!(effective_address::$src(core));
-            core.push_32(ea);
+            try!(core.push_32(ea));
             Ok(Cycles($cycles))
         });
 }
@@ -2742,7 +2811,7 @@ pea!(pea_32_pcix, index_pc, 20);
 pub fn reset<T: Core>(core: &mut T) -> Result<Cycles> {
     if s_flag!(core) != 0 {
         core.reset_external_devices();
-        Ok(Cycles(132))
+        Ok(Cycles(if core.model() == CpuModel::M68000 { 132 } else { 130 }))
     } else {
         Err(PrivilegeViolation(ir!(core), pc!(core).wrapping_sub(2)))
     }
@@ -2854,25 +2923,86 @@ roxr_16!(roxr_16_ix, ea_ay_ix_16, 18);
 roxr_16!(roxr_16_aw, ea_aw_16,    16);
 roxr_16!(roxr_16_al, ea_al_16,    20);
 
+// Put implementation of RTD ops here (68010+)
+pub fn rtd_32<T: Core>(core: &mut T) -> Result<Cycles> {
+    let new_pc = try!(core.pop_32());
+    let displacement = try!(core.read_imm_i16());
+    sp!(core) = sp!(core).wrapping_add(displacement as u32);
+    core.jump(new_pc);
+    Ok(Cycles(16))
+}
+
 // Put implementation of RTE ops here
 pub fn rte_32<T: Core>(core: &mut T) -> Result<Cycles> {
     if s_flag!(core) != 0 {
-        let new_sr = core.pop_16();
-        let new_pc = core.pop_32();
+        if core.model() == CpuModel::M68000 {
+            let new_sr = try!(core.pop_16());
+            let new_pc = try!(core.pop_32());
+            core.jump(new_pc);
+            core.sr_to_flags(new_sr);
+            core.resume_normal_processing();
+
+            return Ok(Cycles(20))
+        }
+        if core.model() == CpuModel::M68020 {
+            return rte_020(core)
+        }
+        // Only the short (format $0) frame is supported, as we cannot
+        // rerun the faulted bus cycle of a long bus fault frame
+        let sp = sp!(core);
+        let format = try!(core.read_data_word(sp.wrapping_add(6))) >> 12;
+        if format != 0 {
+            return Err(Trap(EXCEPTION_FORMAT_ERROR, 24 + 4))
+        }
+        let new_sr = try!(core.pop_16());
+        let new_pc = try!(core.pop_32());
+        sp!(core) = sp!(core).wrapping_add(2); // format/vector word
         core.jump(new_pc);
         core.sr_to_flags(new_sr);
         core.resume_normal_processing();
 
-        Ok(Cycles(20))
+        Ok(Cycles(24))
     } else {
         Err(PrivilegeViolation(ir!(core), pc!(core).wrapping_sub(2)))
     }
 }
 
+// The 68020 also unwinds the throwaway (format $1) frame of an
+// interrupt taken on the master stack, and the format $2 frame of
+// CHK, TRAPcc, TRAPV, trace and divide by zero exceptions
+fn rte_020<T: Core>(core: &mut T) -> Result<Cycles> {
+    loop {
+        let sp = sp!(core);
+        let format = try!(core.read_data_word(sp.wrapping_add(6))) >> 12;
+        let new_sr = match format {
+            0 | 2 => {
+                let new_sr = try!(core.pop_16());
+                let new_pc = try!(core.pop_32());
+                // format/vector word, and the instruction address of format $2
+                let frame_rest = if format == 0 { 2 } else { 6 };
+                sp!(core) = sp!(core).wrapping_add(frame_rest);
+                core.jump(new_pc);
+                new_sr
+            }
+            1 => {
+                let new_sr = try!(core.pop_16());
+                sp!(core) = sp!(core).wrapping_add(6);
+                // switches to the stack the interrupt was taken on
+                core.sr_to_flags(new_sr);
+                continue;
+            }
+            _ => return Err(Trap(EXCEPTION_FORMAT_ERROR, 24 + 4)),
+        };
+        core.sr_to_flags(new_sr);
+        core.resume_normal_processing();
+        return Ok(Cycles(24))
+    }
+}
+
 // Put implementation of RTR ops here
 pub fn rtr_32<T: Core>(core: &mut T) -> Result<Cycles> {
-    let new_ccr = core.pop_16();
-    let new_pc = core.pop_32();
+    let new_ccr = try!(core.pop_16());
+    let new_pc = try!(core.pop_32());
     core.jump(new_pc);
     core.ccr_to_flags(new_ccr);
     Ok(Cycles(20))
@@ -2880,11 +3010,611 @@ pub fn rtr_32<T: Core>(core: &mut T) -> Result<Cycles> {
 
 // Put implementation of RTS ops here
 pub fn rts_32<T: Core>(core: &mut T) -> Result<Cycles> {
-    let new_pc = core.pop_32();
+    let new_pc = try!(core.pop_32());
     core.jump(new_pc);
     Ok(Cycles(16))
 }
 
+// Put implementation of MOVEC ops here (68010+)
+pub fn movec_32_cr<T: Core>(core: &mut T) -> Result<Cycles> {
+    if s_flag!(core) != 0 {
+        let word2 = try!(core.read_imm_u16());
+        let value = match word2 & 0xfff {
+            0x000 => *core.sfc(),
+            0x001 => *core.dfc(),
+            0x800 => inactive_usp!(core),
+            0x801 => *core.vbr(),
+            0x002 if core.model() == CpuModel::M68020 => *core.cacr(),
+            0x802 if core.model() == CpuModel::M68020 => *core.caar(),
+            0x803 if core.model() == CpuModel::M68020 => *core.msp(),
+            0x804 if core.model() == CpuModel::M68020 => *core.isp(),
+            _ => return Err(IllegalInstruction(ir!(core), pc!(core).wrapping_sub(4))),
+        };
+        dar!(core)[(word2 >> 12) as usize] = value;
+        Ok(Cycles(12))
+    } else {
+        Err(PrivilegeViolation(ir!(core), pc!(core).wrapping_sub(2)))
+    }
+}
+pub fn movec_32_rc<T: Core>(core: &mut T) -> Result<Cycles> {
+    if s_flag!(core) != 0 {
+        let word2 = try!(core.read_imm_u16());
+        let value = dar!(core)[(word2 >> 12) as usize];
+        match word2 & 0xfff {
+            0x000 => *core.sfc() = value & 7,
+            0x001 => *core.dfc() = value & 7,
+            0x800 => inactive_usp!(core) = value,
+            0x801 => *core.vbr() = value,
+            // only the enable, freeze and clear bits of the CACR exist
+            0x002 if core.model() == CpuModel::M68020 => *core.cacr() = value & 0xf,
+            0x802 if core.model() == CpuModel::M68020 => *core.caar() = value,
+            0x803 if core.model() == CpuModel::M68020 => *core.msp() = value,
+            0x804 if core.model() == CpuModel::M68020 => *core.isp() = value,
+            _ => return Err(IllegalInstruction(ir!(core), pc!(core).wrapping_sub(4))),
+        };
+        Ok(Cycles(10))
+    } else {
+        Err(PrivilegeViolation(ir!(core), pc!(core).wrapping_sub(2)))
+    }
+}
+
+// Put implementation of MOVES ops here (68010+)
+macro_rules! moves {
+    ($name:ident, $read:ident, $write:ident, $mask:expr, $signed:ty, $dst:ident, $cycles:expr) => (
+        pub fn $name<T: Core>(core: &mut T) -> Result<Cycles> {
+            if s_flag!(core) != 0 {
+                let word2 = try!(core.read_imm_u16());
+                let ea = try!(effective_address::$dst(core));
+                let reg = (word2 >> 12) as usize;
+                if word2 & 0x0800 != 0 {
+                    // register to memory, in the DFC address space
+                    let (fc, value) = (*core.dfc(), dar!(core)[reg]);
+                    try!(core.$write(fc, ea, value & $mask));
+                } else {
+                    // memory to register, in the SFC address space
+                    let fc = *core.sfc();
+                    let value = try!(core.$read(fc, ea));
+                    dar!(core)[reg] = if reg > 7 {
+                        value as $signed as i32 as u32
+                    } else {
+                        (dar!(core)[reg] & !$mask) | value
+                    };
+                }
+                Ok(Cycles($cycles))
+            } else {
+                Err(PrivilegeViolation(ir!(core), pc!(core).wrapping_sub(2)))
+            }
+        })
+}
+moves!(moves_8_ai, read_fc_byte, write_fc_byte, 0xff, i8, address_indirect_ay, 14+4);
+moves!(moves_8_pi, read_fc_byte, write_fc_byte, 0xff, i8, postincrement_ay_8,  14+4);
+moves!(moves_8_pd, read_fc_byte, write_fc_byte, 0xff, i8, predecrement_ay_8,   14+6);
+moves!(moves_8_di, read_fc_byte, write_fc_byte, 0xff, i8, displacement_ay,     14+8);
+moves!(moves_8_ix, read_fc_byte, write_fc_byte, 0xff, i8, index_ay,            14+10);
+moves!(moves_8_aw, read_fc_byte, write_fc_byte, 0xff, i8, absolute_word,       14+8);
+moves!(moves_8_al, read_fc_byte, write_fc_byte, 0xff, i8, absolute_long,       14+12);
+
+moves!(moves_16_ai, read_fc_word, write_fc_word, 0xffff, i16, address_indirect_ay, 14+4);
+moves!(moves_16_pi, read_fc_word, write_fc_word, 0xffff, i16, postincrement_ay_16, 14+4);
+moves!(moves_16_pd, read_fc_word, write_fc_word, 0xffff, i16, predecrement_ay_16,  14+6);
+moves!(moves_16_di, read_fc_word, write_fc_word, 0xffff, i16, displacement_ay,     14+8);
+moves!(moves_16_ix, read_fc_word, write_fc_word, 0xffff, i16, index_ay,            14+10);
+moves!(moves_16_aw, read_fc_word, write_fc_word, 0xffff, i16, absolute_word,       14+8);
+moves!(moves_16_al, read_fc_word, write_fc_word, 0xffff, i16, absolute_long,       14+12);
+
+moves!(moves_32_ai, read_fc_long, write_fc_long, 0xffff_ffff, i32, address_indirect_ay, 16+8);
+moves!(moves_32_pi, read_fc_long, write_fc_long, 0xffff_ffff, i32, postincrement_ay_32, 16+8);
+moves!(moves_32_pd, read_fc_long, write_fc_long, 0xffff_ffff, i32, predecrement_ay_32,  16+10);
+moves!(moves_32_di, read_fc_long, write_fc_long, 0xffff_ffff, i32, displacement_ay,     16+12);
+moves!(moves_32_ix, read_fc_long, write_fc_long, 0xffff_ffff, i32, index_ay,            16+14);
+moves!(moves_32_aw, read_fc_long, write_fc_long, 0xffff_ffff, i32, absolute_word,       16+12);
+moves!(moves_32_al, read_fc_long, write_fc_long, 0xffff_ffff, i32, absolute_long,       16+16);
+
+// Put implementation of BKPT ops here (68010+)
+// Nothing answers the breakpoint acknowledge cycle, so the processor
+// takes the illegal instruction exception
+pub fn bkpt<T: Core>(core: &mut T) -> Result<Cycles> {
+    illegal(core)
+}
+
+// Put implementation of bit field ops here (68020+)
+// Bit fields are numbered from the most significant bit, and may start
+// at any bit offset from the base address or in the data register
+struct BitField {
+    address: Option<u32>, // None for a data register
+    reg: usize,
+    offset: i32,
+    width: u32,
+    container: u64,
+    shift: u32,
+}
+impl BitField {
+    fn mask(&self) -> u64 {
+        ((1u64 << self.width) - 1) << self.shift
+    }
+    fn spans_five_bytes(&self) -> bool {
+        self.shift < 8
+    }
+    fn value(&self) -> u32 {
+        ((self.container & self.mask()) >> self.shift) as u32
+    }
+    fn write<T: Core>(&mut self, core: &mut T, value: u32) -> Result<()> {
+        let mask = self.mask();
+        self.container = (self.container & !mask) | ((u64::from(value) << self.shift) & mask);
+        match self.address {
+            None => {
+                dar!(core)[self.reg] = (self.container as u32).rotate_right(self.offset as u32);
+                Ok(())
+            }
+            Some(address) => {
+                try!(core.write_data_long(address, (self.container >> 8) as u32));
+                if self.spans_five_bytes() {
+                    try!(core.write_data_byte(address.wrapping_add(4), self.container as u32 & 0xff));
+                }
+                Ok(())
+            }
+        }
+    }
+}
+// The offset and width come from the extension word or from data
+// registers, where the offset is signed and a width of 0 means 32
+fn bitfield_offset_width<T: Core>(core: &mut T, word2: u16) -> (i32, u32) {
+    let offset = if word2 & 0x0800 != 0 {
+        dar!(core)[((word2 >> 6) & 7) as usize] as i32
+    } else {
+        i32::from((word2 >> 6) & 31)
+    };
+    let width = if word2 & 0x0020 != 0 {
+        dar!(core)[(word2 & 7) as usize]
+    } else {
+        u32::from(word2)
+    };
+    (offset, (width.wrapping_sub(1) & 31) + 1)
+}
+fn bitfield_in_register<T: Core>(core: &mut T, word2: u16) -> BitField {
+    let (offset, width) = bitfield_offset_width(core, word2);
+    let reg = ir_dy!(core);
+    let offset = offset & 31;
+    BitField {
+        address: None, reg, offset, width,
+        container: u64::from(dar!(core)[reg].rotate_left(offset as u32)),
+        shift: 32 - width,
+    }
+}
+fn bitfield_in_memory<T: Core>(core: &mut T, word2: u16, ea: u32) -> Result<BitField> {
+    let (offset, width) = bitfield_offset_width(core, word2);
+    let address = ea.wrapping_add((offset >> 3) as u32);
+    let bit_offset = (offset & 7) as u32;
+    let mut field = BitField {
+        address: Some(address), reg: 0, offset, width,
+        container: u64::from(try!(core.read_data_long(address))) << 8,
+        shift: 40 - bit_offset - width,
+    };
+    if field.spans_five_bytes() {
+        field.container |= u64::from(try!(core.read_data_byte(address.wrapping_add(4))));
+    }
+    Ok(field)
+}
+fn bitfield_flags<T: Core>(core: &mut T, field: &BitField, value: u32) {
+    n_flag!(core) = (value >> (field.width - 1)) << 7;
+    not_z_flag!(core) = value;
+    v_flag!(core) = 0;
+    c_flag!(core) = 0;
+}
+fn bftst<T: Core>(core: &mut T, _: u16, field: &mut BitField) -> Result<()> {
+    let value = field.value();
+    bitfield_flags(core, field, value);
+    Ok(())
+}
+fn bfextu<T: Core>(core: &mut T, word2: u16, field: &mut BitField) -> Result<()> {
+    let value = field.value();
+    bitfield_flags(core, field, value);
+    dar!(core)[((word2 >> 12) & 7) as usize] = value;
+    Ok(())
+}
+fn bfexts<T: Core>(core: &mut T, word2: u16, field: &mut BitField) -> Result<()> {
+    let value = field.value();
+    bitfield_flags(core, field, value);
+    let unused = 32 - field.width;
+    dar!(core)[((word2 >> 12) & 7) as usize] = ((value << unused) as i32 >> unused) as u32;
+    Ok(())
+}
+fn bfffo<T: Core>(core: &mut T, word2: u16, field: &mut BitField) -> Result<()> {
+    let value = field.value();
+    bitfield_flags(core, field, value);
+    // an all zero field gives the offset just past it
+    let leading_zeros = value.leading_zeros() - (32 - field.width);
+    dar!(core)[((word2 >> 12) & 7) as usize] = (field.offset as u32).wrapping_add(leading_zeros);
+    Ok(())
+}
+fn bfchg<T: Core>(core: &mut T, _: u16, field: &mut BitField) -> Result<()> {
+    let value = field.value();
+    bitfield_flags(core, field, value);
+    field.write(core, !value)
+}
+fn bfclr<T: Core>(core: &mut T, _: u16, field: &mut BitField) -> Result<()> {
+    let value = field.value();
+    bitfield_flags(core, field, value);
+    field.write(core, 0)
+}
+fn bfset<T: Core>(core: &mut T, _: u16, field: &mut BitField) -> Result<()> {
+    let value = field.value();
+    bitfield_flags(core, field, value);
+    field.write(core, 0xffff_ffff)
+}
+fn bfins<T: Core>(core: &mut T, word2: u16, field: &mut BitField) -> Result<()> {
+    let value = dar!(core)[((word2 >> 12) & 7) as usize] & (field.mask() >> field.shift) as u32;
+    bitfield_flags(core, field, value);
+    field.write(core, value)
+}
+macro_rules! bitfield {
+    ($name:ident, $op:ident, dn, $cycles:expr) => (
+        pub fn $name<T: Core>(core: &mut T) -> Result<Cycles> {
+            let word2 = try!(core.read_imm_u16());
+            let mut field = bitfield_in_register(core, word2);
+            try!($op(core, word2, &mut field));
+            Ok(Cycles($cycles))
+        });
+    ($name:ident, $op:ident, $dst:ident, $cycles:expr) => (
+        pub fn $name<T: Core>(core: &mut T) -> Result<Cycles> {
+            let word2 = try!(core.read_imm_u16());
+            let ea = try!(effective_address::$dst(core));
+            let mut field = try!(bitfield_in_memory(core, word2, ea));
+            try!($op(core, word2, &mut field));
+            Ok(Cycles($cycles))
+        });
+}
+bitfield!(bftst_32_dn,   bftst, dn,                  6);
+bitfield!(bftst_32_ai,   bftst, address_indirect_ay, 17);
+bitfield!(bftst_32_di,   bftst, displacement_ay,     18);
+bitfield!(bftst_32_ix,   bftst, index_ay,            20);
+bitfield!(bftst_32_aw,   bftst, absolute_word,       17);
+bitfield!(bftst_32_al,   bftst, absolute_long,       17);
+bitfield!(bftst_32_pcdi, bftst, displacement_pc,     18);
+bitfield!(bftst_32_pcix, bftst, index_pc,            20);
+
+bitfield!(bfextu_32_dn,   bfextu, dn,                  8);
+bitfield!(bfextu_32_ai,   bfextu, address_indirect_ay, 19);
+bitfield!(bfextu_32_di,   bfextu, displacement_ay,     20);
+bitfield!(bfextu_32_ix,   bfextu, index_ay,            22);
+bitfield!(bfextu_32_aw,   bfextu, absolute_word,       19);
+bitfield!(bfextu_32_al,   bfextu, absolute_long,       19);
+bitfield!(bfextu_32_pcdi, bfextu, displacement_pc,     20);
+bitfield!(bfextu_32_pcix, bfextu, index_pc,            22);
+
+bitfield!(bfchg_32_dn, bfchg, dn,                  12);
+bitfield!(bfchg_32_ai, bfchg, address_indirect_ay, 24);
+bitfield!(bfchg_32_di, bfchg, displacement_ay,     25);
+bitfield!(bfchg_32_ix, bfchg, index_ay,            27);
+bitfield!(bfchg_32_aw, bfchg, absolute_word,       24);
+bitfield!(bfchg_32_al, bfchg, absolute_long,       24);
+
+bitfield!(bfexts_32_dn,   bfexts, dn,                  8);
+bitfield!(bfexts_32_ai,   bfexts, address_indirect_ay, 19);
+bitfield!(bfexts_32_di,   bfexts, displacement_ay,     20);
+bitfield!(bfexts_32_ix,   bfexts, index_ay,            22);
+bitfield!(bfexts_32_aw,   bfexts, absolute_word,       19);
+bitfield!(bfexts_32_al,   bfexts, absolute_long,       19);
+bitfield!(bfexts_32_pcdi, bfexts, displacement_pc,     20);
+bitfield!(bfexts_32_pcix, bfexts, index_pc,            22);
+
+bitfield!(bfclr_32_dn, bfclr, dn,                  12);
+bitfield!(bfclr_32_ai, bfclr, address_indirect_ay, 24);
+bitfield!(bfclr_32_di, bfclr, displacement_ay,     25);
+bitfield!(bfclr_32_ix, bfclr, index_ay,            27);
+bitfield!(bfclr_32_aw, bfclr, absolute_word,       24);
+bitfield!(bfclr_32_al, bfclr, absolute_long,       24);
+
+bitfield!(bfffo_32_dn,   bfffo, dn,                  18);
+bitfield!(bfffo_32_ai,   bfffo, address_indirect_ay, 32);
+bitfield!(bfffo_32_di,   bfffo, displacement_ay,     33);
+bitfield!(bfffo_32_ix,   bfffo, index_ay,            35);
+bitfield!(bfffo_32_aw,   bfffo, absolute_word,       32);
+bitfield!(bfffo_32_al,   bfffo, absolute_long,       32);
+bitfield!(bfffo_32_pcdi, bfffo, displacement_pc,     33);
+bitfield!(bfffo_32_pcix, bfffo, index_pc,            35);
+
+bitfield!(bfset_32_dn, bfset, dn,                  12);
+bitfield!(bfset_32_ai, bfset, address_indirect_ay, 24);
+bitfield!(bfset_32_di, bfset, displacement_ay,     25);
+bitfield!(bfset_32_ix, bfset, index_ay,            27);
+bitfield!(bfset_32_aw, bfset, absolute_word,       24);
+bitfield!(bfset_32_al, bfset, absolute_long,       24);
+
+bitfield!(bfins_32_dn, bfins, dn,                  10);
+bitfield!(bfins_32_ai, bfins, address_indirect_ay, 21);
+bitfield!(bfins_32_di, bfins, displacement_ay,     22);
+bitfield!(bfins_32_ix, bfins, index_ay,            24);
+bitfield!(bfins_32_aw, bfins, absolute_word,       21);
+bitfield!(bfins_32_al, bfins, absolute_long,       21);
+
+// Put implementation of CALLM and RTM ops here (68020 only)
+// Only type 0 module descriptors are supported, type 1 needs an
+// external access controller to check the access level. The module
+// stack frame holds, from the top of the stack, the descriptor type,
+// the CCR, the argument count, a reserved word, the descriptor pointer,
+// the return PC and the saved module data area pointer
+fn call_module<T: Core>(core: &mut T, argument_count: u16, ea: u32, cycles: i32) -> Result<Cycles> {
+    let descriptor = try!(core.read_data_long(ea));
+    if (descriptor >> 24) & 0x1f != 0 {
+        return Err(Trap(EXCEPTION_FORMAT_ERROR, 4))
+    }
+    let entry = try!(core.read_data_long(ea.wrapping_add(4)));
+    let data_area = try!(core.read_data_long(ea.wrapping_add(8)));
+    let reg = (try!(core.read_data_word(entry)) >> 12) as usize;
+    let saved = dar!(core)[reg];
+    let pc = pc!(core);
+    let ccr = core.condition_code_register();
+    try!(core.push_32(saved));
+    try!(core.push_32(pc));
+    try!(core.push_32(ea));
+    try!(core.push_16(0));
+    try!(core.push_16(argument_count));
+    try!(core.push_16(ccr));
+    try!(core.push_16((descriptor >> 16) as u16 & 0xff00));
+    dar!(core)[reg] = data_area;
+    core.jump(entry.wrapping_add(2));
+    Ok(Cycles(cycles))
+}
+macro_rules! callm {
+    ($name:ident, $dst:ident, $cycles:expr) => (
+        pub fn $name<T: Core>(core: &mut T) -> Result<Cycles> {
+            let argument_count = try!(core.read_imm_u16()) & 0xff;
+            let ea = try!(effective_address::$dst(core));
+            call_module(core, argument_count, ea, $cycles)
+        })
+}
+callm!(callm_32_ai,   address_indirect_ay, 64);
+callm!(callm_32_di,   displacement_ay,     65);
+callm!(callm_32_ix,   index_ay,            67);
+callm!(callm_32_aw,   absolute_word,       64);
+callm!(callm_32_al,   absolute_long,       64);
+callm!(callm_32_pcdi, displacement_pc,     65);
+callm!(callm_32_pcix, index_pc,            67);
+
+pub fn rtm_32<T: Core>(core: &mut T) -> Result<Cycles> {
+    let sp = sp!(core);
+    if (try!(core.read_data_word(sp)) >> 8) & 0x1f != 0 {
+        return Err(Trap(EXCEPTION_FORMAT_ERROR, 4))
+    }
+    let ccr = try!(core.read_data_word(sp.wrapping_add(2)));
+    let argument_count = try!(core.read_data_word(sp.wrapping_add(4))) & 0xff;
+    let new_pc = try!(core.read_data_long(sp.wrapping_add(12)));
+    let saved = try!(core.read_data_long(sp.wrapping_add(16)));
+    let reg = low_nibble!(ir!(core)) as usize;
+    sp!(core) = sp.wrapping_add(20 + argument_count);
+    dar!(core)[reg] = saved;
+    core.ccr_to_flags(ccr as u16);
+    core.jump(new_pc);
+    Ok(Cycles(19))
+}
+
+// Put implementation of CAS and CAS2 ops here (68020+)
+macro_rules! cas {
+    ($name:ident, $cmp:ident, $dst:ident, $write_op:ident, $mask:expr, $cycles:expr) => (
+        pub fn $name<T: Core>(core: &mut T) -> Result<Cycles> {
+            let word2 = try!(core.read_imm_u16());
+            let (dst, ea) = try!(operator::$dst(core));
+            let dc = (word2 & 7) as usize;
+            let compare = dar!(core)[dc];
+            let _ = common::$cmp(core, dst, compare);
+            if not_z_flag!(core) == 0 {
+                let update = dar!(core)[((word2 >> 6) & 7) as usize];
+                try!(core.$write_op(ea, update & $mask));
+                Ok(Cycles($cycles + 3))
+            } else {
+                dar!(core)[dc] = (compare & !$mask) | dst;
+                Ok(Cycles($cycles))
+            }
+        })
+}
+cas!(cas_8_ai, cmp_8, ea_ay_ai_8, write_data_byte, 0xff, 16);
+cas!(cas_8_pi, cmp_8, ea_ay_pi_8, write_data_byte, 0xff, 16);
+cas!(cas_8_pd, cmp_8, ea_ay_pd_8, write_data_byte, 0xff, 17);
+cas!(cas_8_di, cmp_8, ea_ay_di_8, write_data_byte, 0xff, 17);
+cas!(cas_8_ix, cmp_8, ea_ay_ix_8, write_data_byte, 0xff, 19);
+cas!(cas_8_aw, cmp_8, ea_aw_8,    write_data_byte, 0xff, 16);
+cas!(cas_8_al, cmp_8, ea_al_8,    write_data_byte, 0xff, 16);
+
+cas!(cas_16_ai, cmp_16, ea_ay_ai_16, write_data_word, 0xffff, 16);
+cas!(cas_16_pi, cmp_16, ea_ay_pi_16, write_data_word, 0xffff, 16);
+cas!(cas_16_pd, cmp_16, ea_ay_pd_16, write_data_word, 0xffff, 17);
+cas!(cas_16_di, cmp_16, ea_ay_di_16, write_data_word, 0xffff, 17);
+cas!(cas_16_ix, cmp_16, ea_ay_ix_16, write_data_word, 0xffff, 19);
+cas!(cas_16_aw, cmp_16, ea_aw_16,    write_data_word, 0xffff, 16);
+cas!(cas_16_al, cmp_16, ea_al_16,    write_data_word, 0xffff, 16);
+
+cas!(cas_32_ai, cmp_32, ea_ay_ai_32, write_data_long, 0xffff_ffff, 16);
+cas!(cas_32_pi, cmp_32, ea_ay_pi_32, write_data_long, 0xffff_ffff, 16);
+cas!(cas_32_pd, cmp_32, ea_ay_pd_32, write_data_long, 0xffff_ffff, 17);
+cas!(cas_32_di, cmp_32, ea_ay_di_32, write_data_long, 0xffff_ffff, 17);
+cas!(cas_32_ix, cmp_32, ea_ay_ix_32, write_data_long, 0xffff_ffff, 19);
+cas!(cas_32_aw, cmp_32, ea_aw_32,    write_data_long, 0xffff_ffff, 16);
+cas!(cas_32_al, cmp_32, ea_al_32,    write_data_long, 0xffff_ffff, 16);
+
+// Both operands are compared before anything is written, and both are
+// only updated if both compare equal
+macro_rules! cas2 {
+    ($name:ident, $cmp:ident, $read_op:ident, $write_op:ident, $mask:expr) => (
+        pub fn $name<T: Core>(core: &mut T) -> Result<Cycles> {
+            let word2 = try!(core.read_imm_u16());
+            let word3 = try!(core.read_imm_u16());
+            let ea1 = dar!(core)[(word2 >> 12) as usize];
+            let ea2 = dar!(core)[(word3 >> 12) as usize];
+            let dst1 = try!(core.$read_op(ea1));
+            let dst2 = try!(core.$read_op(ea2));
+            let (dc1, dc2) = ((word2 & 7) as usize, (word3 & 7) as usize);
+            let compare1 = dar!(core)[dc1];
+            let compare2 = dar!(core)[dc2];
+            let _ = common::$cmp(core, dst1, compare1);
+            if not_z_flag!(core) == 0 {
+                let _ = common::$cmp(core, dst2, compare2);
+                if not_z_flag!(core) == 0 {
+                    let update1 = dar!(core)[((word2 >> 6) & 7) as usize];
+                    let update2 = dar!(core)[((word3 >> 6) & 7) as usize];
+                    try!(core.$write_op(ea1, update1 & $mask));
+                    try!(core.$write_op(ea2, update2 & $mask));
+                    return Ok(Cycles(12 + 3))
+                }
+            }
+            dar!(core)[dc1] = (compare1 & !$mask) | dst1;
+            dar!(core)[dc2] = (dar!(core)[dc2] & !$mask) | dst2;
+            Ok(Cycles(12))
+        })
+}
+cas2!(cas2_16, cmp_16, read_data_word, write_data_word, 0xffff);
+cas2!(cas2_32, cmp_32, read_data_long, write_data_long, 0xffff_ffff);
+
+// Put implementation of CHK2 and CMP2 ops here (68020+)
+// Bounds where the lower is above the upper one (unsigned) only make
+// sense as signed bounds, flipping the sign bit of all three values
+// turns the unsigned comparisons into signed ones
+fn check_bounds<T: Core>(core: &mut T, word2: u16, value: u32, lower: u32, upper: u32, sign: u32, cycles: i32) -> Result<Cycles> {
+    let (value, lower, upper) = if lower > upper {
+        (value ^ sign, lower ^ sign, upper ^ sign)
+    } else {
+        (value, lower, upper)
+    };
+    let out_of_bounds = value < lower || value > upper;
+    not_z_flag!(core) = false_is_1!(value == lower || value == upper);
+    c_flag!(core) = if out_of_bounds { CFLAG_SET } else { 0 };
+    if out_of_bounds && word2 & 0x0800 != 0 {
+        Err(Trap(EXCEPTION_CHK, 40))
+    } else {
+        Ok(Cycles(cycles))
+    }
+}
+macro_rules! chk2 {
+    ($name:ident, $dst:ident, $read_op:ident, $size:expr, $signed:ty, $mask:expr, $cycles:expr) => (
+        pub fn $name<T: Core>(core: &mut T) -> Result<Cycles> {
+            let word2 = try!(core.read_imm_u16());
+            let ea = try!(effective_address::$dst(core));
+            let lower = try!(core.$read_op(ea));
+            let upper = try!(core.$read_op(ea.wrapping_add($size)));
+            let reg = (word2 >> 12) as usize;
+            if reg > 7 {
+                // address registers are compared in full, against
+                // sign-extended bounds
+                let value = dar!(core)[reg];
+                check_bounds(core, word2, value,
+                    lower as $signed as i32 as u32, upper as $signed as i32 as u32, 0x8000_0000, $cycles)
+            } else {
+                let value = dar!(core)[reg] & $mask;
+                check_bounds(core, word2, value, lower, upper, $mask ^ ($mask >> 1), $cycles)
+            }
+        })
+}
+chk2!(chk2_8_ai,   address_indirect_ay, read_data_byte, 1, i8, 0xff, 22);
+chk2!(chk2_8_di,   displacement_ay,     read_data_byte, 1, i8, 0xff, 23);
+chk2!(chk2_8_ix,   index_ay,            read_data_byte, 1, i8, 0xff, 25);
+chk2!(chk2_8_aw,   absolute_word,       read_data_byte, 1, i8, 0xff, 22);
+chk2!(chk2_8_al,   absolute_long,       read_data_byte, 1, i8, 0xff, 22);
+chk2!(chk2_8_pcdi, displacement_pc,     read_program_byte, 1, i8, 0xff, 23);
+chk2!(chk2_8_pcix, index_pc,            read_program_byte, 1, i8, 0xff, 23);
+
+chk2!(chk2_16_ai,   address_indirect_ay, read_data_word, 2, i16, 0xffff, 22);
+chk2!(chk2_16_di,   displacement_ay,     read_data_word, 2, i16, 0xffff, 23);
+chk2!(chk2_16_ix,   index_ay,            read_data_word, 2, i16, 0xffff, 25);
+chk2!(chk2_16_aw,   absolute_word,       read_data_word, 2, i16, 0xffff, 22);
+chk2!(chk2_16_al,   absolute_long,       read_data_word, 2, i16, 0xffff, 22);
+chk2!(chk2_16_pcdi, displacement_pc,     read_program_word, 2, i16, 0xffff, 23);
+chk2!(chk2_16_pcix, index_pc,            read_program_word, 2, i16, 0xffff, 23);
+
+chk2!(chk2_32_ai,   address_indirect_ay, read_data_long, 4, i32, 0xffff_ffff, 22);
+chk2!(chk2_32_di,   displacement_ay,     read_data_long, 4, i32, 0xffff_ffff, 23);
+chk2!(chk2_32_ix,   index_ay,            read_data_long, 4, i32, 0xffff_ffff, 25);
+chk2!(chk2_32_aw,   absolute_word,       read_data_long, 4, i32, 0xffff_ffff, 22);
+chk2!(chk2_32_al,   absolute_long,       read_data_long, 4, i32, 0xffff_ffff, 22);
+chk2!(chk2_32_pcdi, displacement_pc,     read_program_long, 4, i32, 0xffff_ffff, 23);
+chk2!(chk2_32_pcix, index_pc,            read_program_long, 4, i32, 0xffff_ffff, 23);
+
+// Put implementation of MULS.L, MULU.L, DIVS.L and DIVU.L ops here (68020+)
+// Bit 11 of the extension word selects signed operation, bit 10 a 64 bit
+// product or dividend in Dh:Dl
+macro_rules! mull {
+    ($name:ident, $src:ident, $cycles:expr) => (
+        pub fn $name<T: Core>(core: &mut T) -> Result<Cycles> {
+            let word2 = try!(core.read_imm_u16());
+            let src = try!(operator::$src(core));
+            common::mull_32(core, word2, src);
+            Ok(Cycles($cycles))
+        })
+}
+mull!(mull_32_dn,   dy,       43);
+mull!(mull_32_ai,   ay_ai_32, 43+4);
+mull!(mull_32_pi,   ay_pi_32, 43+4);
+mull!(mull_32_pd,   ay_pd_32, 43+5);
+mull!(mull_32_di,   ay_di_32, 43+5);
+mull!(mull_32_ix,   ay_ix_32, 43+7);
+mull!(mull_32_aw,   aw_32,    43+4);
+mull!(mull_32_al,   al_32,    43+4);
+mull!(mull_32_pcdi, pcdi_32,  43+5);
+mull!(mull_32_pcix, pcix_32,  43+7);
+mull!(mull_32_imm,  imm_32,   43+4);
+
+macro_rules! divl {
+    ($name:ident, $src:ident, $cycles:expr) => (
+        pub fn $name<T: Core>(core: &mut T) -> Result<Cycles> {
+            let word2 = try!(core.read_imm_u16());
+            let src = try!(operator::$src(core));
+            if src != 0 {
+                common::divl_32(core, word2, src);
+                Ok(Cycles($cycles))
+            } else {
+                Err(Trap(EXCEPTION_ZERO_DIVIDE, 38))
+            }
+        })
+}
+divl!(divl_32_dn,   dy,       84);
+divl!(divl_32_ai,   ay_ai_32, 84+4);
+divl!(divl_32_pi,   ay_pi_32, 84+4);
+divl!(divl_32_pd,   ay_pd_32, 84+5);
+divl!(divl_32_di,   ay_di_32, 84+5);
+divl!(divl_32_ix,   ay_ix_32, 84+7);
+divl!(divl_32_aw,   aw_32,    84+4);
+divl!(divl_32_al,   al_32,    84+4);
+divl!(divl_32_pcdi, pcdi_32,  84+5);
+divl!(divl_32_pcix, pcix_32,  84+7);
+divl!(divl_32_imm,  imm_32,   84+4);
+
+// Put implementation of PACK and UNPK ops here (68020+)
+// The adjustment is added to the unpacked digits, before packing and
+// after unpacking. Memory to memory forms read and write the low byte
+// first.
+pub fn pack_16_rr<T: Core>(core: &mut T) -> Result<Cycles> {
+    let adjustment = u32::from(try!(core.read_imm_u16()));
+    let src = dy!(core).wrapping_add(adjustment);
+    let res = ((src >> 4) & 0xf0) | (src & 0x0f);
+    dx!(core) = mask_out_below_8!(dx!(core)) | res;
+    Ok(Cycles(6))
+}
+pub fn pack_16_mm<T: Core>(core: &mut T) -> Result<Cycles> {
+    let adjustment = u32::from(try!(core.read_imm_u16()));
+    let lo = try!(operator::ay_pd_8(core));
+    let hi = try!(operator::ay_pd_8(core));
+    let src = ((hi << 8) | lo).wrapping_add(adjustment);
+    let ea = try!(effective_address::predecrement_ax_8(core));
+    try!(core.write_data_byte(ea, ((src >> 4) & 0xf0) | (src & 0x0f)));
+    Ok(Cycles(13))
+}
+pub fn unpk_16_rr<T: Core>(core: &mut T) -> Result<Cycles> {
+    let adjustment = u32::from(try!(core.read_imm_u16()));
+    let src = dy!(core);
+    let res = mask_out_above_16!((((src << 4) & 0x0f00) | (src & 0x0f)).wrapping_add(adjustment));
+    dx!(core) = mask_out_below_16!(dx!(core)) | res;
+    Ok(Cycles(8))
+}
+pub fn unpk_16_mm<T: Core>(core: &mut T) -> Result<Cycles> {
+    let adjustment = u32::from(try!(core.read_imm_u16()));
+    let src = try!(operator::ay_pd_8(core));
+    let res = (((src << 4) & 0x0f00) | (src & 0x0f)).wrapping_add(adjustment);
+    let ea = try!(effective_address::predecrement_ax_8(core));
+    try!(core.write_data_byte(ea, mask_out_above_8!(res)));
+    let ea = try!(effective_address::predecrement_ax_8(core));
+    try!(core.write_data_byte(ea, mask_out_above_8!(res >> 8)));
+    Ok(Cycles(13))
+}
+
 impl_op!(8, sbcd_8, sbcd_8_rr, dy, dx, 6);
 impl_op!(8, sbcd_8, sbcd_8_mm, ay_pd_8, ea_ax_pd_8, 18);
 
@@ -2894,7 +3624,7 @@ macro_rules! sxx_8_dn {
         pub fn $name<T: Core>(core: &mut T) -> Result<Cycles> {
             let cycles = if core.$cond() {
                 dy!(core) |= 0xff;
-                6
+                if core.model() == CpuModel::M68000 { 6 } else { 4 }
             } else {
                 dy!(core) &= 0xffff_ff00;
                 4
@@ -3420,6 +4150,69 @@ pub fn trapv<T: Core>(core: &mut T) -> Result<Cycles> {
     }
 }
 
+// Put implementation of TRAPcc ops here (68020+)
+macro_rules! trapcc {
+    ($name:ident, $cond:ident, $operand_bytes:expr) => (
+        pub fn $name<T: Core>(core: &mut T) -> Result<Cycles> {
+            // the operand is only there for the trap handler to look at,
+            // the stacked PC points past it
+            pc!(core) = pc!(core).wrapping_add($operand_bytes);
+            if core.$cond() {
+                Err(Trap(EXCEPTION_TRAPV, 34))
+            } else {
+                Ok(Cycles(4 + $operand_bytes))
+            }
+        });
+}
+trapcc!(trapt,     cond_t,  0);
+trapcc!(trapt_16,  cond_t,  2);
+trapcc!(trapt_32,  cond_t,  4);
+trapcc!(trapf,     cond_f,  0);
+trapcc!(trapf_16,  cond_f,  2);
+trapcc!(trapf_32,  cond_f,  4);
+trapcc!(traphi,    cond_hi, 0);
+trapcc!(traphi_16, cond_hi, 2);
+trapcc!(traphi_32, cond_hi, 4);
+trapcc!(trapls,    cond_ls, 0);
+trapcc!(trapls_16, cond_ls, 2);
+trapcc!(trapls_32, cond_ls, 4);
+trapcc!(trapcc,    cond_cc, 0);
+trapcc!(trapcc_16, cond_cc, 2);
+trapcc!(trapcc_32, cond_cc, 4);
+trapcc!(trapcs,    cond_cs, 0);
+trapcc!(trapcs_16, cond_cs, 2);
+trapcc!(trapcs_32, cond_cs, 4);
+trapcc!(trapne,    cond_ne, 0);
+trapcc!(trapne_16, cond_ne, 2);
+trapcc!(trapne_32, cond_ne, 4);
+trapcc!(trapeq,    cond_eq, 0);
+trapcc!(trapeq_16, cond_eq, 2);
+trapcc!(trapeq_32, cond_eq, 4);
+trapcc!(trapvc,    cond_vc, 0);
+trapcc!(trapvc_16, cond_vc, 2);
+trapcc!(trapvc_32, cond_vc, 4);
+trapcc!(trapvs,    cond_vs, 0);
+trapcc!(trapvs_16, cond_vs, 2);
+trapcc!(trapvs_32, cond_vs, 4);
+trapcc!(trappl,    cond_pl, 0);
+trapcc!(trappl_16, cond_pl, 2);
+trapcc!(trappl_32, cond_pl, 4);
+trapcc!(trapmi,    cond_mi, 0);
+trapcc!(trapmi_16, cond_mi, 2);
+trapcc!(trapmi_32, cond_mi, 4);
+trapcc!(trapge,    cond_ge, 0);
+trapcc!(trapge_16, cond_ge, 2);
+trapcc!(trapge_32, cond_ge, 4);
+trapcc!(traplt,    cond_lt, 0);
+trapcc!(traplt_16, cond_lt, 2);
+trapcc!(traplt_32, cond_lt, 4);
+trapcc!(trapgt,    cond_gt, 0);
+trapcc!(trapgt_16, cond_gt, 2);
+trapcc!(trapgt_32, cond_gt, 4);
+trapcc!(traple,    cond_le, 0);
+trapcc!(traple_16, cond_le, 2);
+trapcc!(traple_32, cond_le, 4);
+
 // Put implementation of TST ops here
 macro_rules! tst_8 {
     ($name:ident, dy, $cycles:expr) => (
@@ -3524,7 +4317,149 @@ tst_32!(tst_32_al,   al_32,     4+16);
 pub fn unlk_32<T: Core>(core: &mut T) -> Result<Cycles> {
     let ay = ay!(core);
     sp!(core) = ay;
-    ay!(core) = core.pop_32();
+    ay!(core) = try!(core.pop_32());
 
     Ok(Cycles(12))
 }
+
+// Put the 68010 timings here. The 68010 optable overrides the 68000 ops
+// whose cycle counts differ, following the 010 column of Musashi's
+// table; exceptions they raise keep their 68000 cycles, which
+// handle_trap adjusts to the model
+macro_rules! timed_010 {
+    ($($name:ident, $op:ident, $cycles:expr;)*) => ($(
+        pub fn $name<T: Core>(core: &mut T) -> Result<Cycles> {
+            $op(core).map(|_| Cycles($cycles))
+        }
+    )*)
+}
+timed_010! {
+    add_8_er_imm_010, add_8_er_imm, 8;
+    add_16_er_imm_010, add_16_er_imm, 8;
+    add_32_er_imm_010, add_32_er_imm, 14;
+    adda_16_imm_010, adda_16_imm, 12;
+    adda_32_imm_010, adda_32_imm, 14;
+    addi_32_dn_010, addi_32_dn, 14;
+    addx_32_rr_010, addx_32_rr, 6;
+    and_8_er_imm_010, and_8_er_imm, 8;
+    and_16_er_imm_010, and_16_er_imm, 8;
+    and_32_er_imm_010, and_32_er_imm, 14;
+    andi_8_toc_010, andi_8_toc, 16;
+    andi_16_tos_010, andi_16_tos, 16;
+    bclr_8_r_ai_010, bclr_8_r_ai, 14;
+    bclr_8_r_al_010, bclr_8_r_al, 22;
+    bclr_8_r_aw_010, bclr_8_r_aw, 18;
+    bclr_8_r_di_010, bclr_8_r_di, 18;
+    bclr_8_r_ix_010, bclr_8_r_ix, 20;
+    bclr_8_r_pd_010, bclr_8_r_pd, 16;
+    bclr_8_r_pi_010, bclr_8_r_pi, 14;
+    chk_16_ai_010, chk_16_ai, 12;
+    chk_16_al_010, chk_16_al, 20;
+    chk_16_aw_010, chk_16_aw, 16;
+    chk_16_dn_010, chk_16_dn, 8;
+    chk_16_di_010, chk_16_di, 16;
+    chk_16_imm_010, chk_16_imm, 12;
+    chk_16_ix_010, chk_16_ix, 18;
+    chk_16_pcdi_010, chk_16_pcdi, 16;
+    chk_16_pcix_010, chk_16_pcix, 18;
+    chk_16_pd_010, chk_16_pd, 14;
+    chk_16_pi_010, chk_16_pi, 12;
+    clr_8_ai_010, clr_8_ai, 8;
+    clr_8_al_010, clr_8_al, 14;
+    clr_8_aw_010, clr_8_aw, 12;
+    clr_8_di_010, clr_8_di, 12;
+    clr_8_ix_010, clr_8_ix, 14;
+    clr_8_pd_010, clr_8_pd, 10;
+    clr_8_pi_010, clr_8_pi, 8;
+    clr_16_ai_010, clr_16_ai, 8;
+    clr_16_al_010, clr_16_al, 14;
+    clr_16_aw_010, clr_16_aw, 12;
+    clr_16_di_010, clr_16_di, 12;
+    clr_16_ix_010, clr_16_ix, 14;
+    clr_16_pd_010, clr_16_pd, 10;
+    clr_16_pi_010, clr_16_pi, 8;
+    clr_32_ai_010, clr_32_ai, 12;
+    clr_32_al_010, clr_32_al, 20;
+    clr_32_aw_010, clr_32_aw, 16;
+    clr_32_di_010, clr_32_di, 16;
+    clr_32_ix_010, clr_32_ix, 20;
+    clr_32_pd_010, clr_32_pd, 14;
+    clr_32_pi_010, clr_32_pi, 12;
+    cmpi_32_dn_010, cmpi_32_dn, 12;
+    divs_16_ai_010, divs_16_ai, 126;
+    divs_16_al_010, divs_16_al, 134;
+    divs_16_aw_010, divs_16_aw, 130;
+    divs_16_dn_010, divs_16_dn, 122;
+    divs_16_di_010, divs_16_di, 130;
+    divs_16_imm_010, divs_16_imm, 126;
+    divs_16_ix_010, divs_16_ix, 132;
+    divs_16_pcdi_010, divs_16_pcdi, 130;
+    divs_16_pcix_010, divs_16_pcix, 132;
+    divs_16_pd_010, divs_16_pd, 128;
+    divs_16_pi_010, divs_16_pi, 126;
+    divu_16_ai_010, divu_16_ai, 112;
+    divu_16_al_010, divu_16_al, 120;
+    divu_16_aw_010, divu_16_aw, 116;
+    divu_16_dn_010, divu_16_dn, 108;
+    divu_16_di_010, divu_16_di, 116;
+    divu_16_imm_010, divu_16_imm, 112;
+    divu_16_ix_010, divu_16_ix, 118;
+    divu_16_pcdi_010, divu_16_pcdi, 116;
+    divu_16_pcix_010, divu_16_pcix, 118;
+    divu_16_pd_010, divu_16_pd, 114;
+    divu_16_pi_010, divu_16_pi, 112;
+    eor_32_dn_010, eor_32_dn, 6;
+    eori_8_toc_010, eori_8_toc, 16;
+    eori_16_tos_010, eori_16_tos, 16;
+    eori_32_dn_010, eori_32_dn, 14;
+    move_32_fru_010, move_32_fru, 6;
+    move_32_pd_an_010, move_32_pd_an, 14;
+    move_32_pd_ai_010, move_32_pd_ai, 22;
+    move_32_pd_al_010, move_32_pd_al, 30;
+    move_32_pd_aw_010, move_32_pd_aw, 26;
+    move_32_pd_dn_010, move_32_pd_dn, 14;
+    move_32_pd_di_010, move_32_pd_di, 26;
+    move_32_pd_imm_010, move_32_pd_imm, 22;
+    move_32_pd_ix_010, move_32_pd_ix, 28;
+    move_32_pd_pcdi_010, move_32_pd_pcdi, 26;
+    move_32_pd_pcix_010, move_32_pd_pcix, 28;
+    move_32_pd_pd_010, move_32_pd_pd, 24;
+    move_32_pd_pi_010, move_32_pd_pi, 22;
+    move_32_tou_010, move_32_tou, 6;
+    muls_16_ai_010, muls_16_ai, 36;
+    muls_16_al_010, muls_16_al, 44;
+    muls_16_aw_010, muls_16_aw, 40;
+    muls_16_dn_010, muls_16_dn, 32;
+    muls_16_di_010, muls_16_di, 40;
+    muls_16_imm_010, muls_16_imm, 36;
+    muls_16_ix_010, muls_16_ix, 42;
+    muls_16_pcdi_010, muls_16_pcdi, 40;
+    muls_16_pcix_010, muls_16_pcix, 42;
+    muls_16_pd_010, muls_16_pd, 38;
+    muls_16_pi_010, muls_16_pi, 36;
+    mulu_16_ai_010, mulu_16_ai, 34;
+    mulu_16_al_010, mulu_16_al, 42;
+    mulu_16_aw_010, mulu_16_aw, 38;
+    mulu_16_dn_010, mulu_16_dn, 30;
+    mulu_16_di_010, mulu_16_di, 38;
+    mulu_16_imm_010, mulu_16_imm, 34;
+    mulu_16_ix_010, mulu_16_ix, 40;
+    mulu_16_pcdi_010, mulu_16_pcdi, 38;
+    mulu_16_pcix_010, mulu_16_pcix, 40;
+    mulu_16_pd_010, mulu_16_pd, 36;
+    mulu_16_pi_010, mulu_16_pi, 34;
+    or_8_er_imm_010, or_8_er_imm, 8;
+    or_16_er_imm_010, or_16_er_imm, 8;
+    or_32_er_imm_010, or_32_er_imm, 14;
+    ori_8_toc_010, ori_8_toc, 16;
+    ori_16_tos_010, ori_16_tos, 16;
+    ori_32_dn_010, ori_32_dn, 14;
+    sub_8_er_imm_010, sub_8_er_imm, 8;
+    sub_16_er_imm_010, sub_16_er_imm, 8;
+    sub_32_er_imm_010, sub_32_er_imm, 14;
+    suba_16_imm_010, suba_16_imm, 12;
+    suba_32_imm_010, suba_32_imm, 14;
+    subi_32_dn_010, subi_32_dn, 14;
+    subq_16_an_010, subq_16_an, 4;
+    subx_32_rr_010, subx_32_rr, 6;
+}
@@ -644,7 +644,7 @@ pub const OP_EXG_32_DA: u32 = OP_EXG | EXG_DATA_ADDR;
 
 pub const OP_EXT_BW: u32 = OP_EXT | BYTE_TO_WORD;
 pub const OP_EXT_WL: u32 = OP_EXT | WORD_TO_LONG;
-// pub const OP_EXT_BL: u32 = OP_EXT | BYTE_TO_LONG; // 020+
+pub const OP_EXT_BL: u32 = OP_EXT | BYTE_TO_LONG; // 020+
 
 // Put constants for JMP here
 pub const OP_JMP_32_AI   : u32 = OP_JMP | OPER_AI;
@@ -675,6 +675,7 @@ pub const OP_LEA_32_PCIX : u32 = OP_LEA | OPER_PCIX;
 
 // Put constants for LINK here
 pub const OP_LINK_16     : u32 = OP_LINK;
+pub const OP_LINK_32     : u32 = OP_LINK32; // 020+
 
 // Put constants for LSL, LSR here
 pub const OP_LSL_8_R        : u32 = OP_SHIFT | SHIFT_LEFT  | BYTE_SIZED | LOGI_REG_SHIFT | REG_COUNT;
@@ -1071,6 +1072,16 @@ pub const OP_MOVE_16_FRS_IX   : u32 = OP_MOVE2 | MOVE_FROM_SR | OPER_IX;
 pub const OP_MOVE_16_FRS_AW   : u32 = OP_MOVE2 | MOVE_FROM_SR | OPER_AW;
 pub const OP_MOVE_16_FRS_AL   : u32 = OP_MOVE2 | MOVE_FROM_SR | OPER_AL;
 
+// Put constants for MOVE from CCR here (68010+)
+pub const OP_MOVE_16_FRC_DN  : u32 = OP_MOVE2 | MOVE_FROM_CCR | OPER_DN;
+pub const OP_MOVE_16_FRC_AI  : u32 = OP_MOVE2 | MOVE_FROM_CCR | OPER_AI;
+pub const OP_MOVE_16_FRC_PI  : u32 = OP_MOVE2 | MOVE_FROM_CCR | OPER_PI;
+pub const OP_MOVE_16_FRC_PD  : u32 = OP_MOVE2 | MOVE_FROM_CCR | OPER_PD;
+pub const OP_MOVE_16_FRC_DI  : u32 = OP_MOVE2 | MOVE_FROM_CCR | OPER_DI;
+pub const OP_MOVE_16_FRC_IX  : u32 = OP_MOVE2 | MOVE_FROM_CCR | OPER_IX;
+pub const OP_MOVE_16_FRC_AW  : u32 = OP_MOVE2 | MOVE_FROM_CCR | OPER_AW;
+pub const OP_MOVE_16_FRC_AL  : u32 = OP_MOVE2 | MOVE_FROM_CCR | OPER_AL;
+
 // Put constants for MOVE to SR here
 pub const OP_MOVE_16_TOS_DN   : u32 = OP_MOVE2 | MOVE_TO_SR | OPER_DN;
 pub const OP_MOVE_16_TOS_AI   : u32 = OP_MOVE2 | MOVE_TO_SR | OPER_AI;
@@ -1414,10 +1425,251 @@ pub const OP_ROXR_16_IX      : u32 = OP_SHIFT | SHIFT_RIGHT | WORD_SIZED | ROTX_
 pub const OP_ROXR_16_AW      : u32 = OP_SHIFT | SHIFT_RIGHT | WORD_SIZED | ROTX_MEM_SHIFT | OPER_AW;
 pub const OP_ROXR_16_AL      : u32 = OP_SHIFT | SHIFT_RIGHT | WORD_SIZED | ROTX_MEM_SHIFT | OPER_AL;
 
+pub const OP_RTD_32 : u32 = OP_RTD;
 pub const OP_RTE_32 : u32 = OP_RTE;
 pub const OP_RTR_32 : u32 = OP_RTR;
 pub const OP_RTS_32 : u32 = OP_RTS;
 
+// Put constants for 68010 MOVEC, MOVES and BKPT here
+pub const OP_MOVEC_32_CR : u32 = OP_MOVEC_CR;
+pub const OP_MOVEC_32_RC : u32 = OP_MOVEC_RC;
+
+pub const OP_MOVES_8_AI  : u32 = OP_MOVES | BYTE_SIZED | OPER_AI;
+pub const OP_MOVES_8_PI  : u32 = OP_MOVES | BYTE_SIZED | OPER_PI;
+pub const OP_MOVES_8_PD  : u32 = OP_MOVES | BYTE_SIZED | OPER_PD;
+pub const OP_MOVES_8_DI  : u32 = OP_MOVES | BYTE_SIZED | OPER_DI;
+pub const OP_MOVES_8_IX  : u32 = OP_MOVES | BYTE_SIZED | OPER_IX;
+pub const OP_MOVES_8_AW  : u32 = OP_MOVES | BYTE_SIZED | OPER_AW;
+pub const OP_MOVES_8_AL  : u32 = OP_MOVES | BYTE_SIZED | OPER_AL;
+
+pub const OP_MOVES_16_AI : u32 = OP_MOVES | WORD_SIZED | OPER_AI;
+pub const OP_MOVES_16_PI : u32 = OP_MOVES | WORD_SIZED | OPER_PI;
+pub const OP_MOVES_16_PD : u32 = OP_MOVES | WORD_SIZED | OPER_PD;
+pub const OP_MOVES_16_DI : u32 = OP_MOVES | WORD_SIZED | OPER_DI;
+pub const OP_MOVES_16_IX : u32 = OP_MOVES | WORD_SIZED | OPER_IX;
+pub const OP_MOVES_16_AW : u32 = OP_MOVES | WORD_SIZED | OPER_AW;
+pub const OP_MOVES_16_AL : u32 = OP_MOVES | WORD_SIZED | OPER_AL;
+
+pub const OP_MOVES_32_AI : u32 = OP_MOVES | LONG_SIZED | OPER_AI;
+pub const OP_MOVES_32_PI : u32 = OP_MOVES | LONG_SIZED | OPER_PI;
+pub const OP_MOVES_32_PD : u32 = OP_MOVES | LONG_SIZED | OPER_PD;
+pub const OP_MOVES_32_DI : u32 = OP_MOVES | LONG_SIZED | OPER_DI;
+pub const OP_MOVES_32_IX : u32 = OP_MOVES | LONG_SIZED | OPER_IX;
+pub const OP_MOVES_32_AW : u32 = OP_MOVES | LONG_SIZED | OPER_AW;
+pub const OP_MOVES_32_AL : u32 = OP_MOVES | LONG_SIZED | OPER_AL;
+
+// Put constants for 68020 bitfield, CALLM/RTM, CAS, CAS2, CHK2/CMP2,
+// MULL/DIVL, PACK/UNPK and TRAPcc here
+pub const OP_BFTST_32_DN    : u32 = OP_BITFIELD | BF_TST | OPER_DN;
+pub const OP_BFTST_32_AI    : u32 = OP_BITFIELD | BF_TST | OPER_AI;
+pub const OP_BFTST_32_DI    : u32 = OP_BITFIELD | BF_TST | OPER_DI;
+pub const OP_BFTST_32_IX    : u32 = OP_BITFIELD | BF_TST | OPER_IX;
+pub const OP_BFTST_32_AW    : u32 = OP_BITFIELD | BF_TST | OPER_AW;
+pub const OP_BFTST_32_AL    : u32 = OP_BITFIELD | BF_TST | OPER_AL;
+pub const OP_BFTST_32_PCDI  : u32 = OP_BITFIELD | BF_TST | OPER_PCDI;
+pub const OP_BFTST_32_PCIX  : u32 = OP_BITFIELD | BF_TST | OPER_PCIX;
+
+pub const OP_BFEXTU_32_DN   : u32 = OP_BITFIELD | BF_EXTU | OPER_DN;
+pub const OP_BFEXTU_32_AI   : u32 = OP_BITFIELD | BF_EXTU | OPER_AI;
+pub const OP_BFEXTU_32_DI   : u32 = OP_BITFIELD | BF_EXTU | OPER_DI;
+pub const OP_BFEXTU_32_IX   : u32 = OP_BITFIELD | BF_EXTU | OPER_IX;
+pub const OP_BFEXTU_32_AW   : u32 = OP_BITFIELD | BF_EXTU | OPER_AW;
+pub const OP_BFEXTU_32_AL   : u32 = OP_BITFIELD | BF_EXTU | OPER_AL;
+pub const OP_BFEXTU_32_PCDI : u32 = OP_BITFIELD | BF_EXTU | OPER_PCDI;
+pub const OP_BFEXTU_32_PCIX : u32 = OP_BITFIELD | BF_EXTU | OPER_PCIX;
+
+pub const OP_BFCHG_32_DN    : u32 = OP_BITFIELD | BF_CHG | OPER_DN;
+pub const OP_BFCHG_32_AI    : u32 = OP_BITFIELD | BF_CHG | OPER_AI;
+pub const OP_BFCHG_32_DI    : u32 = OP_BITFIELD | BF_CHG | OPER_DI;
+pub const OP_BFCHG_32_IX    : u32 = OP_BITFIELD | BF_CHG | OPER_IX;
+pub const OP_BFCHG_32_AW    : u32 = OP_BITFIELD | BF_CHG | OPER_AW;
+pub const OP_BFCHG_32_AL    : u32 = OP_BITFIELD | BF_CHG | OPER_AL;
+
+pub const OP_BFEXTS_32_DN   : u32 = OP_BITFIELD | BF_EXTS | OPER_DN;
+pub const OP_BFEXTS_32_AI   : u32 = OP_BITFIELD | BF_EXTS | OPER_AI;
+pub const OP_BFEXTS_32_DI   : u32 = OP_BITFIELD | BF_EXTS | OPER_DI;
+pub const OP_BFEXTS_32_IX   : u32 = OP_BITFIELD | BF_EXTS | OPER_IX;
+pub const OP_BFEXTS_32_AW   : u32 = OP_BITFIELD | BF_EXTS | OPER_AW;
+pub const OP_BFEXTS_32_AL   : u32 = OP_BITFIELD | BF_EXTS | OPER_AL;
+pub const OP_BFEXTS_32_PCDI : u32 = OP_BITFIELD | BF_EXTS | OPER_PCDI;
+pub const OP_BFEXTS_32_PCIX : u32 = OP_BITFIELD | BF_EXTS | OPER_PCIX;
+
+pub const OP_BFCLR_32_DN    : u32 = OP_BITFIELD | BF_CLR | OPER_DN;
+pub const OP_BFCLR_32_AI    : u32 = OP_BITFIELD | BF_CLR | OPER_AI;
+pub const OP_BFCLR_32_DI    : u32 = OP_BITFIELD | BF_CLR | OPER_DI;
+pub const OP_BFCLR_32_IX    : u32 = OP_BITFIELD | BF_CLR | OPER_IX;
+pub const OP_BFCLR_32_AW    : u32 = OP_BITFIELD | BF_CLR | OPER_AW;
+pub const OP_BFCLR_32_AL    : u32 = OP_BITFIELD | BF_CLR | OPER_AL;
+
+pub const OP_BFFFO_32_DN    : u32 = OP_BITFIELD | BF_FFO | OPER_DN;
+pub const OP_BFFFO_32_AI    : u32 = OP_BITFIELD | BF_FFO | OPER_AI;
+pub const OP_BFFFO_32_DI    : u32 = OP_BITFIELD | BF_FFO | OPER_DI;
+pub const OP_BFFFO_32_IX    : u32 = OP_BITFIELD | BF_FFO | OPER_IX;
+pub const OP_BFFFO_32_AW    : u32 = OP_BITFIELD | BF_FFO | OPER_AW;
+pub const OP_BFFFO_32_AL    : u32 = OP_BITFIELD | BF_FFO | OPER_AL;
+pub const OP_BFFFO_32_PCDI  : u32 = OP_BITFIELD | BF_FFO | OPER_PCDI;
+pub const OP_BFFFO_32_PCIX  : u32 = OP_BITFIELD | BF_FFO | OPER_PCIX;
+
+pub const OP_BFSET_32_DN    : u32 = OP_BITFIELD | BF_SET | OPER_DN;
+pub const OP_BFSET_32_AI    : u32 = OP_BITFIELD | BF_SET | OPER_AI;
+pub const OP_BFSET_32_DI    : u32 = OP_BITFIELD | BF_SET | OPER_DI;
+pub const OP_BFSET_32_IX    : u32 = OP_BITFIELD | BF_SET | OPER_IX;
+pub const OP_BFSET_32_AW    : u32 = OP_BITFIELD | BF_SET | OPER_AW;
+pub const OP_BFSET_32_AL    : u32 = OP_BITFIELD | BF_SET | OPER_AL;
+
+pub const OP_BFINS_32_DN    : u32 = OP_BITFIELD | BF_INS | OPER_DN;
+pub const OP_BFINS_32_AI    : u32 = OP_BITFIELD | BF_INS | OPER_AI;
+pub const OP_BFINS_32_DI    : u32 = OP_BITFIELD | BF_INS | OPER_DI;
+pub const OP_BFINS_32_IX    : u32 = OP_BITFIELD | BF_INS | OPER_IX;
+pub const OP_BFINS_32_AW    : u32 = OP_BITFIELD | BF_INS | OPER_AW;
+pub const OP_BFINS_32_AL    : u32 = OP_BITFIELD | BF_INS | OPER_AL;
+
+pub const OP_CALLM_32_AI    : u32 = OP_CALLM | OPER_AI;
+pub const OP_CALLM_32_DI    : u32 = OP_CALLM | OPER_DI;
+pub const OP_CALLM_32_IX    : u32 = OP_CALLM | OPER_IX;
+pub const OP_CALLM_32_AW    : u32 = OP_CALLM | OPER_AW;
+pub const OP_CALLM_32_AL    : u32 = OP_CALLM | OPER_AL;
+pub const OP_CALLM_32_PCDI  : u32 = OP_CALLM | OPER_PCDI;
+pub const OP_CALLM_32_PCIX  : u32 = OP_CALLM | OPER_PCIX;
+
+pub const OP_RTM_32         : u32 = OP_CALLM;
+
+pub const OP_CAS_8_AI       : u32 = OP_CAS | CAS_BYTE_SIZED | OPER_AI;
+pub const OP_CAS_8_PI       : u32 = OP_CAS | CAS_BYTE_SIZED | OPER_PI;
+pub const OP_CAS_8_PD       : u32 = OP_CAS | CAS_BYTE_SIZED | OPER_PD;
+pub const OP_CAS_8_DI       : u32 = OP_CAS | CAS_BYTE_SIZED | OPER_DI;
+pub const OP_CAS_8_IX       : u32 = OP_CAS | CAS_BYTE_SIZED | OPER_IX;
+pub const OP_CAS_8_AW       : u32 = OP_CAS | CAS_BYTE_SIZED | OPER_AW;
+pub const OP_CAS_8_AL       : u32 = OP_CAS | CAS_BYTE_SIZED | OPER_AL;
+
+pub const OP_CAS_16_AI      : u32 = OP_CAS | CAS_WORD_SIZED | OPER_AI;
+pub const OP_CAS_16_PI      : u32 = OP_CAS | CAS_WORD_SIZED | OPER_PI;
+pub const OP_CAS_16_PD      : u32 = OP_CAS | CAS_WORD_SIZED | OPER_PD;
+pub const OP_CAS_16_DI      : u32 = OP_CAS | CAS_WORD_SIZED | OPER_DI;
+pub const OP_CAS_16_IX      : u32 = OP_CAS | CAS_WORD_SIZED | OPER_IX;
+pub const OP_CAS_16_AW      : u32 = OP_CAS | CAS_WORD_SIZED | OPER_AW;
+pub const OP_CAS_16_AL      : u32 = OP_CAS | CAS_WORD_SIZED | OPER_AL;
+
+pub const OP_CAS_32_AI      : u32 = OP_CAS | CAS_LONG_SIZED | OPER_AI;
+pub const OP_CAS_32_PI      : u32 = OP_CAS | CAS_LONG_SIZED | OPER_PI;
+pub const OP_CAS_32_PD      : u32 = OP_CAS | CAS_LONG_SIZED | OPER_PD;
+pub const OP_CAS_32_DI      : u32 = OP_CAS | CAS_LONG_SIZED | OPER_DI;
+pub const OP_CAS_32_IX      : u32 = OP_CAS | CAS_LONG_SIZED | OPER_IX;
+pub const OP_CAS_32_AW      : u32 = OP_CAS | CAS_LONG_SIZED | OPER_AW;
+pub const OP_CAS_32_AL      : u32 = OP_CAS | CAS_LONG_SIZED | OPER_AL;
+
+pub const OP_CAS2_16        : u32 = OP_CAS | CAS_WORD_SIZED | OPER_IMM;
+pub const OP_CAS2_32        : u32 = OP_CAS | CAS_LONG_SIZED | OPER_IMM;
+
+pub const OP_CHK2_8_AI      : u32 = OP_CHK2 | CHK2_BYTE_SIZED | OPER_AI;
+pub const OP_CHK2_8_DI      : u32 = OP_CHK2 | CHK2_BYTE_SIZED | OPER_DI;
+pub const OP_CHK2_8_IX      : u32 = OP_CHK2 | CHK2_BYTE_SIZED | OPER_IX;
+pub const OP_CHK2_8_AW      : u32 = OP_CHK2 | CHK2_BYTE_SIZED | OPER_AW;
+pub const OP_CHK2_8_AL      : u32 = OP_CHK2 | CHK2_BYTE_SIZED | OPER_AL;
+pub const OP_CHK2_8_PCDI    : u32 = OP_CHK2 | CHK2_BYTE_SIZED | OPER_PCDI;
+pub const OP_CHK2_8_PCIX    : u32 = OP_CHK2 | CHK2_BYTE_SIZED | OPER_PCIX;
+
+pub const OP_CHK2_16_AI     : u32 = OP_CHK2 | CHK2_WORD_SIZED | OPER_AI;
+pub const OP_CHK2_16_DI     : u32 = OP_CHK2 | CHK2_WORD_SIZED | OPER_DI;
+pub const OP_CHK2_16_IX     : u32 = OP_CHK2 | CHK2_WORD_SIZED | OPER_IX;
+pub const OP_CHK2_16_AW     : u32 = OP_CHK2 | CHK2_WORD_SIZED | OPER_AW;
+pub const OP_CHK2_16_AL     : u32 = OP_CHK2 | CHK2_WORD_SIZED | OPER_AL;
+pub const OP_CHK2_16_PCDI   : u32 = OP_CHK2 | CHK2_WORD_SIZED | OPER_PCDI;
+pub const OP_CHK2_16_PCIX   : u32 = OP_CHK2 | CHK2_WORD_SIZED | OPER_PCIX;
+
+pub const OP_CHK2_32_AI     : u32 = OP_CHK2 | CHK2_LONG_SIZED | OPER_AI;
+pub const OP_CHK2_32_DI     : u32 = OP_CHK2 | CHK2_LONG_SIZED | OPER_DI;
+pub const OP_CHK2_32_IX     : u32 = OP_CHK2 | CHK2_LONG_SIZED | OPER_IX;
+pub const OP_CHK2_32_AW     : u32 = OP_CHK2 | CHK2_LONG_SIZED | OPER_AW;
+pub const OP_CHK2_32_AL     : u32 = OP_CHK2 | CHK2_LONG_SIZED | OPER_AL;
+pub const OP_CHK2_32_PCDI   : u32 = OP_CHK2 | CHK2_LONG_SIZED | OPER_PCDI;
+pub const OP_CHK2_32_PCIX   : u32 = OP_CHK2 | CHK2_LONG_SIZED | OPER_PCIX;
+
+pub const OP_MULL_32_DN     : u32 = OP_MULL | OPER_DN;
+pub const OP_MULL_32_AI     : u32 = OP_MULL | OPER_AI;
+pub const OP_MULL_32_PI     : u32 = OP_MULL | OPER_PI;
+pub const OP_MULL_32_PD     : u32 = OP_MULL | OPER_PD;
+pub const OP_MULL_32_DI     : u32 = OP_MULL | OPER_DI;
+pub const OP_MULL_32_IX     : u32 = OP_MULL | OPER_IX;
+pub const OP_MULL_32_AW     : u32 = OP_MULL | OPER_AW;
+pub const OP_MULL_32_AL     : u32 = OP_MULL | OPER_AL;
+pub const OP_MULL_32_PCDI   : u32 = OP_MULL | OPER_PCDI;
+pub const OP_MULL_32_PCIX   : u32 = OP_MULL | OPER_PCIX;
+pub const OP_MULL_32_IMM    : u32 = OP_MULL | OPER_IMM;
+
+pub const OP_DIVL_32_DN     : u32 = OP_DIVL | OPER_DN;
+pub const OP_DIVL_32_AI     : u32 = OP_DIVL | OPER_AI;
+pub const OP_DIVL_32_PI     : u32 = OP_DIVL | OPER_PI;
+pub const OP_DIVL_32_PD     : u32 = OP_DIVL | OPER_PD;
+pub const OP_DIVL_32_DI     : u32 = OP_DIVL | OPER_DI;
+pub const OP_DIVL_32_IX     : u32 = OP_DIVL | OPER_IX;
+pub const OP_DIVL_32_AW     : u32 = OP_DIVL | OPER_AW;
+pub const OP_DIVL_32_AL     : u32 = OP_DIVL | OPER_AL;
+pub const OP_DIVL_32_PCDI   : u32 = OP_DIVL | OPER_PCDI;
+pub const OP_DIVL_32_PCIX   : u32 = OP_DIVL | OPER_PCIX;
+pub const OP_DIVL_32_IMM    : u32 = OP_DIVL | OPER_IMM;
+
+pub const OP_PACK_16_RR     : u32 = OP_PACK | RR_MODE;
+pub const OP_PACK_16_MM     : u32 = OP_PACK | MM_MODE;
+pub const OP_UNPK_16_RR     : u32 = OP_UNPK | RR_MODE;
+pub const OP_UNPK_16_MM     : u32 = OP_UNPK | MM_MODE;
+
+pub const OP_TRAPT          : u32 = OP_CONDTRAP | IF_T | TRAPCC_NONE;
+pub const OP_TRAPT_16       : u32 = OP_CONDTRAP | IF_T | TRAPCC_16;
+pub const OP_TRAPT_32       : u32 = OP_CONDTRAP | IF_T | TRAPCC_32;
+pub const OP_TRAPF          : u32 = OP_CONDTRAP | IF_F | TRAPCC_NONE;
+pub const OP_TRAPF_16       : u32 = OP_CONDTRAP | IF_F | TRAPCC_16;
+pub const OP_TRAPF_32       : u32 = OP_CONDTRAP | IF_F | TRAPCC_32;
+pub const OP_TRAPHI         : u32 = OP_CONDTRAP | IF_HI | TRAPCC_NONE;
+pub const OP_TRAPHI_16      : u32 = OP_CONDTRAP | IF_HI | TRAPCC_16;
+pub const OP_TRAPHI_32      : u32 = OP_CONDTRAP | IF_HI | TRAPCC_32;
+pub const OP_TRAPLS         : u32 = OP_CONDTRAP | IF_LS | TRAPCC_NONE;
+pub const OP_TRAPLS_16      : u32 = OP_CONDTRAP | IF_LS | TRAPCC_16;
+pub const OP_TRAPLS_32      : u32 = OP_CONDTRAP | IF_LS | TRAPCC_32;
+pub const OP_TRAPCC         : u32 = OP_CONDTRAP | IF_CC | TRAPCC_NONE;
+pub const OP_TRAPCC_16      : u32 = OP_CONDTRAP | IF_CC | TRAPCC_16;
+pub const OP_TRAPCC_32      : u32 = OP_CONDTRAP | IF_CC | TRAPCC_32;
+pub const OP_TRAPCS         : u32 = OP_CONDTRAP | IF_CS | TRAPCC_NONE;
+pub const OP_TRAPCS_16      : u32 = OP_CONDTRAP | IF_CS | TRAPCC_16;
+pub const OP_TRAPCS_32      : u32 = OP_CONDTRAP | IF_CS | TRAPCC_32;
+pub const OP_TRAPNE         : u32 = OP_CONDTRAP | IF_NE | TRAPCC_NONE;
+pub const OP_TRAPNE_16      : u32 = OP_CONDTRAP | IF_NE | TRAPCC_16;
+pub const OP_TRAPNE_32      : u32 = OP_CONDTRAP | IF_NE | TRAPCC_32;
+pub const OP_TRAPEQ         : u32 = OP_CONDTRAP | IF_EQ | TRAPCC_NONE;
+pub const OP_TRAPEQ_16      : u32 = OP_CONDTRAP | IF_EQ | TRAPCC_16;
+pub const OP_TRAPEQ_32      : u32 = OP_CONDTRAP | IF_EQ | TRAPCC_32;
+pub const OP_TRAPVC         : u32 = OP_CONDTRAP | IF_VC | TRAPCC_NONE;
+pub const OP_TRAPVC_16      : u32 = OP_CONDTRAP | IF_VC | TRAPCC_16;
+pub const OP_TRAPVC_32      : u32 = OP_CONDTRAP | IF_VC | TRAPCC_32;
+pub const OP_TRAPVS         : u32 = OP_CONDTRAP | IF_VS | TRAPCC_NONE;
+pub const OP_TRAPVS_16      : u32 = OP_CONDTRAP | IF_VS | TRAPCC_16;
+pub const OP_TRAPVS_32      : u32 = OP_CONDTRAP | IF_VS | TRAPCC_32;
+pub const OP_TRAPPL         : u32 = OP_CONDTRAP | IF_PL | TRAPCC_NONE;
+pub const OP_TRAPPL_16      : u32 = OP_CONDTRAP | IF_PL | TRAPCC_16;
+pub const OP_TRAPPL_32      : u32 = OP_CONDTRAP | IF_PL | TRAPCC_32;
+pub const OP_TRAPMI         : u32 = OP_CONDTRAP | IF_MI | TRAPCC_NONE;
+pub const OP_TRAPMI_16      : u32 = OP_CONDTRAP | IF_MI | TRAPCC_16;
+pub const OP_TRAPMI_32      : u32 = OP_CONDTRAP | IF_MI | TRAPCC_32;
+pub const OP_TRAPGE         : u32 = OP_CONDTRAP | IF_GE | TRAPCC_NONE;
+pub const OP_TRAPGE_16      : u32 = OP_CONDTRAP | IF_GE | TRAPCC_16;
+pub const OP_TRAPGE_32      : u32 = OP_CONDTRAP | IF_GE | TRAPCC_32;
+pub const OP_TRAPLT         : u32 = OP_CONDTRAP | IF_LT | TRAPCC_NONE;
+pub const OP_TRAPLT_16      : u32 = OP_CONDTRAP | IF_LT | TRAPCC_16;
+pub const OP_TRAPLT_32      : u32 = OP_CONDTRAP | IF_LT | TRAPCC_32;
+pub const OP_TRAPGT         : u32 = OP_CONDTRAP | IF_GT | TRAPCC_NONE;
+pub const OP_TRAPGT_16      : u32 = OP_CONDTRAP | IF_GT | TRAPCC_16;
+pub const OP_TRAPGT_32      : u32 = OP_CONDTRAP | IF_GT | TRAPCC_32;
+pub const OP_TRAPLE         : u32 = OP_CONDTRAP | IF_LE | TRAPCC_NONE;
+pub const OP_TRAPLE_16      : u32 = OP_CONDTRAP | IF_LE | TRAPCC_16;
+pub const OP_TRAPLE_32      : u32 = OP_CONDTRAP | IF_LE | TRAPCC_32;
+
+pub const OP_FGEN           : u32 = OP_FPU | FPU_GENERAL;
+pub const OP_FSCC           : u32 = OP_FPU | FPU_SCC;
+pub const OP_FBCC_16        : u32 = OP_FPU | FPU_BCC_16;
+pub const OP_FBCC_32        : u32 = OP_FPU | FPU_BCC_32;
+pub const OP_FSAVE          : u32 = OP_FPU | FPU_SAVE;
+pub const OP_FRESTORE       : u32 = OP_FPU | FPU_RESTORE;
+
 pub const OP_SBCD_8_RR: u32 = OP_SBCD | BYTE_SIZED | RR_MODE;
 pub const OP_SBCD_8_MM: u32 = OP_SBCD | BYTE_SIZED | MM_MODE;
 
@@ -2364,4 +2616,76 @@ mod tests {
     fn correctly_defined_op_tst_32_ix() {
         assert_eq!(0x4ab0, OP_TST_32_IX);
     }
+    #[test]
+    fn correctly_defined_op_move_16_frc_pd() {
+        assert_eq!(0x42e0, OP_MOVE_16_FRC_PD);
+    }
+    #[test]
+    fn correctly_defined_op_moves_16_al() {
+        assert_eq!(0x0e79, OP_MOVES_16_AL);
+    }
+    #[test]
+    fn correctly_defined_op_moves_32_ix() {
+        assert_eq!(0x0eb0, OP_MOVES_32_IX);
+    }
+    #[test]
+    fn correctly_defined_op_movec_32_rc() {
+        assert_eq!(0x4e7b, OP_MOVEC_32_RC);
+    }
+    #[test]
+    fn correctly_defined_op_rtd_32() {
+        assert_eq!(0x4e74, OP_RTD_32);
+    }
+    #[test]
+    fn correctly_defined_op_ext_bl() {
+        assert_eq!(0x49c0, OP_EXT_BL);
+    }
+    #[test]
+    fn correctly_defined_op_link_32() {
+        assert_eq!(0x4808, OP_LINK_32);
+    }
+    #[test]
+    fn correctly_defined_op_bfins_32_dn() {
+        assert_eq!(0xefc0, OP_BFINS_32_DN);
+    }
+    #[test]
+    fn correctly_defined_op_bfextu_32_pcix() {
+        assert_eq!(0xe9fb, OP_BFEXTU_32_PCIX);
+    }
+    #[test]
+    fn correctly_defined_op_cas_8_ai() {
+        assert_eq!(0x0ad0, OP_CAS_8_AI);
+    }
+    #[test]
+    fn correctly_defined_op_cas2_32() {
+        assert_eq!(0x0efc, OP_CAS2_32);
+    }
+    #[test]
+    fn correctly_defined_op_chk2_32_pcdi() {
+        assert_eq!(0x04fa, OP_CHK2_32_PCDI);
+    }
+    #[test]
+    fn correctly_defined_op_callm_32_aw() {
+        assert_eq!(0x06f8, OP_CALLM_32_AW);
+    }
+    #[test]
+    fn correctly_defined_op_divl_32_imm() {
+        assert_eq!(0x4c7c, OP_DIVL_32_IMM);
+    }
+    #[test]
+    fn correctly_defined_op_unpk_16_mm() {
+        assert_eq!(0x8188, OP_UNPK_16_MM);
+    }
+    #[test]
+    fn correctly_defined_op_trapne_16() {
+        assert_eq!(0x56fa, OP_TRAPNE_16);
+    }
+    #[test]
+    fn correctly_defined_op_fbcc_32() {
+        assert_eq!(0xf2c0, OP_FBCC_32);
+    }
+    #[test]
+    fn correctly_defined_op_frestore() {
+        assert_eq!(0xf340, OP_FRESTORE);
+    }
 }
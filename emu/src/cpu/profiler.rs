@@ -0,0 +1,348 @@
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+use std::io::{BufRead, Write};
+use super::Cycles;
+
+// Cycles and executions charged to an instruction address
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PcStats {
+    pub cycles: u64,
+    pub count: u64,
+}
+
+// Cycles spent in a function, by itself and including what it called
+#[derive(Clone, Debug, PartialEq)]
+pub struct FunctionStats {
+    pub entry: u32,
+    pub name: String,
+    pub self_cycles: u64,
+    pub total_cycles: u64,
+}
+
+const JSR_MASK: u16 = 0xffc0;
+const JSR: u16 = 0x4e80;
+const BSR_MASK: u16 = 0xff00;
+const BSR: u16 = 0x6100;
+const RTD: u16 = 0x4e74;
+const RTE: u16 = 0x4e73;
+const RTS: u16 = 0x4e75;
+const RTR: u16 = 0x4e77;
+
+// A function in the call tree, as called along the path to it
+struct Node {
+    entry: u32,
+    parent: Option<usize>,
+    cycles: u64,
+}
+
+// Charges the cycles of each instruction to its address, and to the
+// stack of functions it was executed in. A function is known by the
+// address it was entered at: JSR and BSR enter one, and so does the
+// core taking an exception, at the handler; RTS, RTR, RTD and RTE
+// leave it. Code that returns by other means (such as longjmp, or an
+// exception handler that never returns) leaves the stack deeper than
+// it really is, but the cycles are still charged to the right address.
+#[derive(Default)]
+pub struct Profiler {
+    pcs: HashMap<u32, PcStats>,
+    // the entry addresses of the functions being executed, outermost
+    // first; the outermost is where profiling started
+    stack: Vec<u32>,
+    // the call tree, with the cycles spent in each node by itself; the
+    // roots are where profiling started
+    nodes: Vec<Node>,
+    // the node called at an entry address from a parent node
+    children: HashMap<(Option<usize>, u32), usize>,
+    // the node of the function being executed
+    node: usize,
+    symbols: BTreeMap<u32, String>,
+}
+
+impl Profiler {
+    pub fn new() -> Profiler {
+        Profiler::default()
+    }
+    pub fn reset(&mut self) {
+        self.pcs.clear();
+        self.stack.clear();
+        self.nodes.clear();
+        self.children.clear();
+    }
+
+    pub fn add_symbol(&mut self, address: u32, name: &str) {
+        self.symbols.insert(address, name.to_string());
+    }
+    // Reads a symbol table with a hex address and a name per line, as
+    // listed by nm (with a type letter in between) or given in a linker
+    // map. Lines that do not look like that are skipped.
+    pub fn load_symbols<R: BufRead>(&mut self, input: R) -> io::Result<usize> {
+        let mut loaded = 0;
+        for line in input.lines() {
+            let line = line?;
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let (address, name) = match fields.len() {
+                2 => (fields[0], fields[1]),
+                3 => (fields[0], fields[2]),
+                _ => continue,
+            };
+            let address = address.trim_start_matches("0x").trim_start_matches('$');
+            if let Ok(address) = u32::from_str_radix(address, 16) {
+                self.add_symbol(address, name);
+                loaded += 1;
+            }
+        }
+        Ok(loaded)
+    }
+    // The nearest symbol at or below the address, with the offset from
+    // it, or the address itself if there is none
+    pub fn symbolize(&self, address: u32) -> String {
+        match self.symbols.range(..=address).next_back() {
+            Some((&start, name)) if start == address => name.clone(),
+            Some((&start, name)) => format!("{}+{:x}", name, address - start),
+            None => format!("{:08x}", address),
+        }
+    }
+
+    // Charges an instruction, given the address it was at, its opcode if
+    // it could be fetched, the cycles it took, where it left the PC and
+    // whether an exception was taken
+    pub fn record(&mut self, pc: u32, opcode: Option<u16>, cycles: Cycles, next_pc: u32, exception: bool) {
+        let cycles = cycles.0.max(0) as u64;
+        let stats = self.pcs.entry(pc).or_default();
+        stats.cycles += cycles;
+        stats.count += 1;
+        if self.stack.is_empty() {
+            self.enter(None, pc);
+        }
+        self.nodes[self.node].cycles += cycles;
+
+        if exception {
+            self.enter(Some(self.node), next_pc);
+            return;
+        }
+        match opcode {
+            Some(op) if op & JSR_MASK == JSR || op & BSR_MASK == BSR => self.enter(Some(self.node), next_pc),
+            Some(RTS) | Some(RTR) | Some(RTD) | Some(RTE) if self.stack.len() > 1 => {
+                self.stack.pop();
+                self.node = self.nodes[self.node].parent.unwrap();
+            }
+            _ => (),
+        }
+    }
+    fn enter(&mut self, parent: Option<usize>, entry: u32) {
+        let nodes = &mut self.nodes;
+        self.node = *self.children.entry((parent, entry)).or_insert_with(|| {
+            nodes.push(Node { entry, parent, cycles: 0 });
+            nodes.len() - 1
+        });
+        self.stack.push(entry);
+    }
+    // the entry addresses on the way to a node, outermost first
+    fn path(&self, node: usize) -> Vec<u32> {
+        let mut path = Vec::new();
+        let mut node = Some(node);
+        while let Some(index) = node {
+            path.push(self.nodes[index].entry);
+            node = self.nodes[index].parent;
+        }
+        path.reverse();
+        path
+    }
+    // the call stacks instructions were charged to, with their cycles
+    fn stacks(&self) -> Vec<(Vec<u32>, u64)> {
+        (0..self.nodes.len())
+            .filter(|&node| self.nodes[node].cycles > 0)
+            .map(|node| (self.path(node), self.nodes[node].cycles))
+            .collect()
+    }
+
+    pub fn pc_stats(&self, pc: u32) -> PcStats {
+        self.pcs.get(&pc).cloned().unwrap_or_default()
+    }
+    pub fn total_cycles(&self) -> u64 {
+        self.pcs.values().map(|stats| stats.cycles).sum()
+    }
+    // the functions being executed, outermost first
+    pub fn call_stack(&self) -> &[u32] {
+        &self.stack
+    }
+
+    // the functions seen, most expensive (including callees) first
+    pub fn functions(&self) -> Vec<FunctionStats> {
+        let mut functions: HashMap<u32, (u64, u64)> = HashMap::new();
+        for (stack, cycles) in self.stacks() {
+            functions.entry(*stack.last().unwrap()).or_insert((0, 0)).0 += cycles;
+            // recursive functions only count once per stack
+            let mut seen = Vec::new();
+            for &entry in &stack {
+                if !seen.contains(&entry) {
+                    seen.push(entry);
+                    functions.entry(entry).or_insert((0, 0)).1 += cycles;
+                }
+            }
+        }
+        let mut functions: Vec<FunctionStats> = functions.into_iter()
+            .map(|(entry, (self_cycles, total_cycles))| FunctionStats {
+                entry, name: self.symbolize(entry), self_cycles, total_cycles
+            })
+            .collect();
+        functions.sort_by(|a, b| b.total_cycles.cmp(&a.total_cycles).then(a.entry.cmp(&b.entry)));
+        functions
+    }
+
+    // A line per instruction address, most expensive first:
+    //     cycles  percent      count  address symbol
+    pub fn write_flat<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        let total = self.total_cycles().max(1) as f64;
+        let mut pcs: Vec<(&u32, &PcStats)> = self.pcs.iter().collect();
+        pcs.sort_by(|a, b| b.1.cycles.cmp(&a.1.cycles).then(a.0.cmp(b.0)));
+        for (pc, stats) in pcs {
+            writeln!(out, "{:>10} {:>6.2}% {:>10}  {:08x} {}",
+                     stats.cycles, 100.0 * stats.cycles as f64 / total, stats.count, pc, self.symbolize(*pc))?;
+        }
+        Ok(())
+    }
+    // A line per function, most expensive (including callees) first:
+    //     total  percent       self  percent  function
+    pub fn write_functions<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        let total = self.total_cycles().max(1) as f64;
+        for function in self.functions() {
+            writeln!(out, "{:>10} {:>6.2}% {:>10} {:>6.2}%  {}",
+                     function.total_cycles, 100.0 * function.total_cycles as f64 / total,
+                     function.self_cycles, 100.0 * function.self_cycles as f64 / total, function.name)?;
+        }
+        Ok(())
+    }
+    // The call stacks in the collapsed format of flamegraph.pl and
+    // inferno, a line of semicolon separated functions and the cycles
+    // spent there, sorted
+    pub fn write_collapsed<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        let mut lines: Vec<String> = self.stacks().into_iter()
+            .map(|(stack, cycles)| {
+                let names: Vec<String> = stack.iter().map(|&entry| self.symbolize(entry)).collect();
+                format!("{} {}", names.join(";"), cycles)
+            })
+            .collect();
+        lines.sort();
+        for line in lines {
+            writeln!(out, "{}", line)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Profiler, PcStats};
+    use cpu::{Cycles, TestCore};
+
+    #[test]
+    fn reads_nm_style_symbol_tables() {
+        let mut profiler = Profiler::new();
+        let table = "00000100 T main\n00000200 t helper\n$300 vectors\nnot a symbol\n";
+        assert_eq!(3, profiler.load_symbols(table.as_bytes()).unwrap());
+        assert_eq!("main", profiler.symbolize(0x100));
+        assert_eq!("helper+1a", profiler.symbolize(0x21a));
+        assert_eq!("vectors", profiler.symbolize(0x300));
+        assert_eq!("00000080", profiler.symbolize(0x80));
+    }
+
+    #[test]
+    fn tracks_calls_and_returns() {
+        let mut profiler = Profiler::new();
+        profiler.record(0x100, Some(0x6100), Cycles(18), 0x200, false); // BSR
+        profiler.record(0x200, Some(0x7001), Cycles(4), 0x202, false);  // MOVEQ
+        assert_eq!(&[0x100, 0x200], profiler.call_stack());
+        profiler.record(0x202, Some(0x4e75), Cycles(16), 0x104, false); // RTS
+        assert_eq!(&[0x100], profiler.call_stack());
+        profiler.record(0x104, Some(0x4e40), Cycles(34), 0x400, true);  // TRAP #0
+        assert_eq!(&[0x100, 0x400], profiler.call_stack());
+        profiler.record(0x400, Some(0x4e73), Cycles(20), 0x106, false); // RTE
+        assert_eq!(&[0x100], profiler.call_stack());
+        assert_eq!(92, profiler.total_cycles());
+    }
+
+    #[test]
+    fn exports_collapsed_stacks() {
+        let mut profiler = Profiler::new();
+        profiler.add_symbol(0x100, "main");
+        profiler.add_symbol(0x200, "draw");
+        profiler.record(0x100, Some(0x4eb9), Cycles(20), 0x200, false); // JSR
+        profiler.record(0x200, Some(0x7001), Cycles(4), 0x202, false);
+        profiler.record(0x202, Some(0x4e75), Cycles(16), 0x106, false);
+        profiler.record(0x106, Some(0x4e71), Cycles(4), 0x108, false);
+        let mut out = Vec::new();
+        profiler.write_collapsed(&mut out).unwrap();
+        assert_eq!("main 24\nmain;draw 20\n", String::from_utf8(out).unwrap());
+        let functions = profiler.functions();
+        assert_eq!(("main", 24, 44), (functions[0].name.as_str(), functions[0].self_cycles, functions[0].total_cycles));
+        assert_eq!(("draw", 20, 20), (functions[1].name.as_str(), functions[1].self_cycles, functions[1].total_cycles));
+    }
+
+    #[test]
+    fn repeated_calls_share_a_stack() {
+        let mut profiler = Profiler::new();
+        profiler.add_symbol(0x100, "main");
+        profiler.add_symbol(0x200, "draw");
+        for &(pc, next_pc) in &[(0x100, 0x200), (0x102, 0x200)] {
+            profiler.record(pc, Some(0x6100), Cycles(18), next_pc, false); // BSR
+            profiler.record(0x200, Some(0x4e75), Cycles(16), pc + 2, false);
+        }
+        let mut out = Vec::new();
+        profiler.write_collapsed(&mut out).unwrap();
+        assert_eq!("main 36\nmain;draw 32\n", String::from_utf8(out).unwrap());
+        assert_eq!(2, profiler.nodes.len());
+    }
+
+    #[test]
+    fn core_charges_cycles_to_each_pc() {
+        // 6100 0004 is BSR.W $46, 4e71 is NOP, 7001 is MOVEQ #1, D0, 4e75 is RTS
+        let mut cpu = TestCore::new_mem(0x40, &[0x61, 0x00, 0x00, 0x04, 0x4e, 0x71, 0x70, 0x01, 0x4e, 0x75]);
+        cpu.dar[15] = 0x400;
+        cpu.profiler = Some(Profiler::new());
+        cpu.execute(18 + 4 + 16 + 4);
+        let profiler = cpu.profiler.as_ref().unwrap();
+        assert_eq!(PcStats { cycles: 18, count: 1 }, profiler.pc_stats(0x40));
+        assert_eq!(PcStats { cycles: 4, count: 1 }, profiler.pc_stats(0x46));
+        assert_eq!(PcStats { cycles: 16, count: 1 }, profiler.pc_stats(0x48));
+        assert_eq!(PcStats { cycles: 4, count: 1 }, profiler.pc_stats(0x44));
+        assert_eq!(&[0x40], profiler.call_stack());
+        let mut out = Vec::new();
+        profiler.write_flat(&mut out).unwrap();
+        let flat = String::from_utf8(out).unwrap();
+        assert!(flat.lines().next().unwrap().contains("00000040"));
+        assert_eq!(4, flat.lines().count());
+    }
+
+    #[test]
+    fn exceptions_taken_by_the_core_enter_the_handler() {
+        use ram::{AddressBus, SUPERVISOR_DATA};
+        // 4e40 is TRAP #0, with its vector pointing at $200
+        let mut cpu = TestCore::new_mem(0x40, &[0x4e, 0x40]);
+        cpu.mem.write_long(SUPERVISOR_DATA, 0x80, 0x200).unwrap();
+        cpu.dar[15] = 0x400;
+        cpu.profiler = Some(Profiler::new());
+        cpu.execute1();
+        assert_eq!(0x200, cpu.pc);
+        assert_eq!(&[0x40, 0x200], cpu.profiler.as_ref().unwrap().call_stack());
+    }
+
+    #[test]
+    fn exceptions_handled_by_the_host_are_not_calls() {
+        use cpu::{Callbacks, Exception, Result, Core};
+        struct HandleTraps;
+        impl<C: Core> Callbacks<C> for HandleTraps {
+            fn exception_callback(&mut self, _core: &mut C, ex: Exception) -> Result<Cycles> {
+                match ex {
+                    Exception::Trap(_, _) => Ok(Cycles(4)),
+                    _ => Err(ex),
+                }
+            }
+        }
+        // 4e40 is TRAP #0
+        let mut cpu = TestCore::new_mem(0x40, &[0x4e, 0x40]);
+        cpu.profiler = Some(Profiler::new());
+        cpu.execute_with_state(1, &mut HandleTraps);
+        assert_eq!(&[0x40], cpu.profiler.as_ref().unwrap().call_stack());
+    }
+}
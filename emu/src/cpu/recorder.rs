@@ -0,0 +1,252 @@
+use std::collections::VecDeque;
+use interrupts::InterruptController;
+use ram::AddressBus;
+use ram::journal::{JournalingMem, JournalEntry};
+use super::{ConfiguredCore, Cycles};
+use super::state::CpuState;
+
+struct Checkpoint {
+    step: u64,
+    state: CpuState,
+}
+
+// Records the execution of a core, one instruction (step) at a time, so
+// that it can be run backwards. The memory journal undoes the writes,
+// and the CPU state is restored from a checkpoint, taken every so many
+// steps, after which the core runs forward again to the wanted step.
+// History is bounded by the number of checkpoints kept and the capacity
+// of the journal, whichever runs out first.
+//
+// Replaying assumes the core is deterministic, which it is, but not the
+// world around it: the interrupt controller is not rewound, so pending
+// interrupts may be taken at different points the second time round.
+pub struct Recorder<T: InterruptController, A: AddressBus> {
+    pub core: ConfiguredCore<T, JournalingMem<A>>,
+    checkpoints: VecDeque<Checkpoint>,
+    interval: u64,
+    max_checkpoints: usize,
+    step: u64,
+}
+
+impl<T: InterruptController, A: AddressBus> Recorder<T, A> {
+    pub fn new(core: ConfiguredCore<T, JournalingMem<A>>, interval: u64, max_checkpoints: usize) -> Recorder<T, A> {
+        assert!(interval > 0 && max_checkpoints > 0, "need checkpoints to go back to");
+        let mut recorder = Recorder { core, checkpoints: VecDeque::new(), interval, max_checkpoints, step: 0 };
+        recorder.checkpoint();
+        recorder
+    }
+    // instructions executed since recording started
+    pub fn steps(&self) -> u64 {
+        self.step
+    }
+    // the earliest step that can still be gone back to
+    pub fn oldest_step(&self) -> Option<u64> {
+        self.checkpoints.iter().map(|c| c.step).find(|&step| self.core.mem.can_undo_to(step))
+    }
+
+    fn checkpoint(&mut self) {
+        if self.checkpoints.len() == self.max_checkpoints {
+            self.checkpoints.pop_front();
+            // what comes before the oldest checkpoint is out of reach
+            let oldest = self.checkpoints.front().map_or(self.step, |c| c.step);
+            self.core.mem.forget_to(oldest);
+        }
+        self.checkpoints.push_back(Checkpoint { step: self.step, state: self.core.save_state() });
+    }
+
+    pub fn step(&mut self) -> Cycles {
+        let pc = self.core.pc;
+        self.core.mem.set_step(self.step + 1, pc);
+        let cycles = self.core.execute1();
+        self.step += 1;
+        if self.step % self.interval == 0 {
+            self.checkpoint();
+        }
+        cycles
+    }
+    // steps until at least the given cycles have been used
+    pub fn run(&mut self, cycles: i32) -> Cycles {
+        let mut used = Cycles(0);
+        while used.0 < cycles {
+            used = used + self.step();
+        }
+        used
+    }
+
+    // Goes back (or forward) to the state after the given number of
+    // steps, unless it is beyond the recorded history
+    pub fn seek(&mut self, step: u64) -> bool {
+        if step < self.step {
+            let index = match self.checkpoints.iter().rposition(|c| c.step <= step) {
+                Some(index) => index,
+                None => return false,
+            };
+            let from = self.checkpoints[index].step;
+            if !self.core.mem.undo_to(from) {
+                return false;
+            }
+            self.core.load_state(&self.checkpoints[index].state);
+            self.checkpoints.truncate(index + 1);
+            self.step = from;
+        }
+        while self.step < step {
+            self.step();
+        }
+        true
+    }
+    pub fn step_back(&mut self) -> bool {
+        self.step > 0 && {
+            let step = self.step - 1;
+            self.seek(step)
+        }
+    }
+    // Goes back to the latest point where the instruction at pc was about
+    // to execute, searching back one checkpoint at a time. Stays put if
+    // there is none in the recorded history.
+    pub fn run_back_to(&mut self, pc: u32) -> bool {
+        let now = self.step;
+        let mut until = now;
+        let mut index = self.checkpoints.len();
+        while index > 0 {
+            index -= 1;
+            let from = self.checkpoints[index].step;
+            if from >= until {
+                continue;
+            }
+            if !self.seek(from) {
+                break;
+            }
+            let mut found = None;
+            while self.step < until {
+                if self.core.pc == pc {
+                    found = Some(self.step);
+                }
+                self.step();
+            }
+            if let Some(step) = found {
+                return self.seek(step);
+            }
+            until = from;
+        }
+        self.seek(now);
+        false
+    }
+    // the latest write to the address still in the journal
+    pub fn last_change(&self, address: u32) -> Option<JournalEntry> {
+        self.core.mem.last_change(address)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Recorder;
+    use cpu::ConfiguredCore;
+    use interrupts::AutoInterruptController;
+    use ram::{AddressBus, PagedMem, SUPERVISOR_DATA, SUPERVISOR_PROGRAM};
+    use ram::journal::JournalingMem;
+
+    // counts D0 up in a loop, storing it at $1000 each time round:
+    // 0100 moveq #0, d0
+    // 0102 addq.w #1, d0
+    // 0104 move.w d0, $1000.w
+    // 0108 bra.s $0102
+    fn recorder(interval: u64, max_checkpoints: usize, capacity: usize) -> Recorder<AutoInterruptController, PagedMem> {
+        let mut mem = PagedMem::new(0);
+        for (i, word) in [0x7000u16, 0x5240, 0x31c0, 0x1000, 0x60f8].iter().enumerate() {
+            mem.write_word(SUPERVISOR_PROGRAM, 0x100 + 2 * i as u32, u32::from(*word)).unwrap();
+        }
+        let mut core = ConfiguredCore::new_with(0x100, AutoInterruptController::new(), JournalingMem::new(mem, capacity));
+        core.processing_state = ::cpu::ProcessingState::Normal;
+        core.dar[15] = 0x800;
+        Recorder::new(core, interval, max_checkpoints)
+    }
+
+    fn counter(recorder: &mut Recorder<AutoInterruptController, PagedMem>) -> u32 {
+        recorder.core.read_data_word(0x1000).unwrap()
+    }
+
+    #[test]
+    fn steps_back_one_instruction() {
+        let mut recorder = recorder(4, 10, 100);
+        for _ in 0..12 {
+            recorder.step();
+        }
+        // every third instruction is a store, the 12th the 4th
+        assert_eq!(4, counter(&mut recorder));
+        assert!(recorder.step_back());
+        assert_eq!(11, recorder.steps());
+        assert_eq!(3, counter(&mut recorder));
+        assert_eq!(4, recorder.core.dar[0]);
+        assert_eq!(0x104, recorder.core.pc);
+        assert!(recorder.step_back());
+        assert_eq!(3, recorder.core.dar[0]);
+        assert_eq!(0x102, recorder.core.pc);
+    }
+
+    #[test]
+    fn rewound_core_runs_forward_the_same_way() {
+        let mut recorder = recorder(3, 10, 100);
+        for _ in 0..20 {
+            recorder.step();
+        }
+        let state = recorder.core.save_state();
+        assert!(recorder.seek(5));
+        assert!(recorder.seek(20));
+        assert_eq!(state, recorder.core.save_state());
+        assert_eq!(6, counter(&mut recorder));
+    }
+
+    #[test]
+    fn runs_back_to_an_earlier_pc() {
+        let mut recorder = recorder(5, 10, 100);
+        for _ in 0..14 {
+            recorder.step();
+        }
+        // at 14 steps, the next instruction is the store at $104
+        assert_eq!(0x104, recorder.core.pc);
+        assert!(recorder.run_back_to(0x100));
+        assert_eq!(0, recorder.steps());
+        assert!(recorder.seek(14));
+        assert!(recorder.run_back_to(0x104));
+        assert_eq!(11, recorder.steps());
+        assert!(!recorder.run_back_to(0x200));
+        assert_eq!(11, recorder.steps());
+    }
+
+    #[test]
+    fn finds_when_an_address_last_changed() {
+        let mut recorder = recorder(5, 10, 100);
+        for _ in 0..10 {
+            recorder.step();
+        }
+        let change = recorder.last_change(0x1001).unwrap();
+        assert_eq!((9, 0x104, SUPERVISOR_DATA), (change.step, change.pc, change.address_space));
+        assert_eq!((Some(2), 3), (change.old, change.new));
+        assert_eq!(None, recorder.last_change(0x1002));
+    }
+
+    #[test]
+    fn history_is_bounded_by_the_checkpoints() {
+        let mut recorder = recorder(4, 3, 100);
+        for _ in 0..40 {
+            recorder.step();
+        }
+        // checkpoints at 32, 36 and 40
+        assert_eq!(Some(32), recorder.oldest_step());
+        assert!(!recorder.seek(31));
+        assert!(recorder.seek(33));
+        assert_eq!(33, recorder.steps());
+    }
+
+    #[test]
+    fn history_is_bounded_by_the_journal() {
+        // with room for only two writes, the stores at steps 36 and 39
+        let mut small = recorder(4, 3, 2);
+        for _ in 0..40 {
+            small.step();
+        }
+        assert_eq!(Some(36), small.oldest_step());
+        assert!(!small.seek(32));
+        assert!(small.seek(36));
+    }
+}
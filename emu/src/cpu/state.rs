@@ -0,0 +1,407 @@
+use std::io;
+use std::io::{Read, Write};
+use super::{CpuModel, ProcessingState, BusConfig, DataBus};
+use super::fpu::{Fpu, FpuModel, Extended};
+
+const STATE_MAGIC: &[u8; 4] = b"r68c";
+// bump when the layout written by CpuState::write_to changes
+pub const STATE_VERSION: u16 = 5;
+
+// A snapshot of everything a ConfiguredCore needs to resume execution
+// exactly where it left off. The flags are kept in their internal
+// (Musashi) representation rather than as SR, so that a restored core is
+// bit-identical to the saved one.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CpuState {
+    pub pc: u32,
+    pub ir: u16,
+    pub dar: [u32; 16],
+    pub inactive_ssp: u32,
+    pub inactive_usp: u32,
+    pub s_flag: u32,
+    pub t1_flag: u32,
+    pub x_flag: u32,
+    pub c_flag: u32,
+    pub v_flag: u32,
+    pub n_flag: u32,
+    pub not_z_flag: u32,
+    pub int_mask: u32,
+    pub irq_level: u8,
+    pub prefetch_addr: u32,
+    pub prefetch_data: u32,
+    pub processing_state: ProcessingState,
+    pub model: CpuModel,
+    pub vbr: u32,
+    pub sfc: u32,
+    pub dfc: u32,
+    pub m_flag: u32,
+    pub inactive_msp: u32,
+    pub cacr: u32,
+    pub caar: u32,
+    pub fpu: Option<Fpu>,
+    pub bus: BusConfig,
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+fn read_u8<R: Read>(input: &mut R) -> io::Result<u8> {
+    let mut buf = [0u8; 1];
+    input.read_exact(&mut buf)?;
+    Ok(buf[0])
+}
+fn read_u16<R: Read>(input: &mut R) -> io::Result<u16> {
+    let mut buf = [0u8; 2];
+    input.read_exact(&mut buf)?;
+    Ok(u16::from_be_bytes(buf))
+}
+fn read_u32<R: Read>(input: &mut R) -> io::Result<u32> {
+    let mut buf = [0u8; 4];
+    input.read_exact(&mut buf)?;
+    Ok(u32::from_be_bytes(buf))
+}
+fn read_u64<R: Read>(input: &mut R) -> io::Result<u64> {
+    let mut buf = [0u8; 8];
+    input.read_exact(&mut buf)?;
+    Ok(u64::from_be_bytes(buf))
+}
+
+fn processing_state_to_u8(state: ProcessingState) -> u8 {
+    match state {
+        ProcessingState::Normal => 0,
+        ProcessingState::Group2Exception => 1,
+        ProcessingState::Group1Exception => 2,
+        ProcessingState::Group0Exception => 3,
+        ProcessingState::Stopped => 4,
+        ProcessingState::Halted => 5,
+    }
+}
+fn processing_state_from_u8(state: u8) -> io::Result<ProcessingState> {
+    Ok(match state {
+        0 => ProcessingState::Normal,
+        1 => ProcessingState::Group2Exception,
+        2 => ProcessingState::Group1Exception,
+        3 => ProcessingState::Group0Exception,
+        4 => ProcessingState::Stopped,
+        5 => ProcessingState::Halted,
+        _ => return Err(invalid_data("unknown processing state")),
+    })
+}
+fn model_to_u8(model: CpuModel) -> u8 {
+    match model {
+        CpuModel::M68000 => 0,
+        CpuModel::M68010 => 1,
+        CpuModel::M68020 => 2,
+    }
+}
+fn model_from_u8(model: u8) -> io::Result<CpuModel> {
+    Ok(match model {
+        0 => CpuModel::M68000,
+        1 => CpuModel::M68010,
+        2 => CpuModel::M68020,
+        _ => return Err(invalid_data("unknown CPU model")),
+    })
+}
+
+fn data_bus_to_u8(data_bus: DataBus) -> u8 {
+    match data_bus {
+        DataBus::Bits8 => 8,
+        DataBus::Bits16 => 16,
+        DataBus::Bits32 => 32,
+    }
+}
+fn data_bus_from_u8(data_bus: u8) -> io::Result<DataBus> {
+    Ok(match data_bus {
+        8 => DataBus::Bits8,
+        16 => DataBus::Bits16,
+        32 => DataBus::Bits32,
+        _ => return Err(invalid_data("unknown data bus width")),
+    })
+}
+
+// A byte for the FPU model, zero without an FPU, followed by its
+// registers
+fn write_fpu<W: Write>(fpu: &Option<Fpu>, out: &mut W) -> io::Result<()> {
+    let fpu = match *fpu {
+        Some(ref fpu) => fpu,
+        None => return out.write_all(&[0]),
+    };
+    out.write_all(&[match fpu.model {
+        FpuModel::M68881 => 1,
+        FpuModel::M68882 => 2,
+    }])?;
+    for reg in &fpu.fp {
+        out.write_all(&reg.sign_exp.to_be_bytes())?;
+        out.write_all(&reg.mantissa.to_be_bytes())?;
+    }
+    for value in &[fpu.fpcr, fpu.fpsr, fpu.fpiar] {
+        out.write_all(&value.to_be_bytes())?;
+    }
+    out.write_all(&[fpu.null_state as u8])
+}
+fn read_fpu<R: Read>(input: &mut R) -> io::Result<Option<Fpu>> {
+    let model = match read_u8(input)? {
+        0 => return Ok(None),
+        1 => FpuModel::M68881,
+        2 => FpuModel::M68882,
+        _ => return Err(invalid_data("unknown FPU model")),
+    };
+    let mut fpu = Fpu::new(model);
+    for reg in fpu.fp.iter_mut() {
+        let sign_exp = read_u16(input)?;
+        *reg = Extended::new(sign_exp, read_u64(input)?);
+    }
+    fpu.fpcr = read_u32(input)?;
+    fpu.fpsr = read_u32(input)?;
+    fpu.fpiar = read_u32(input)?;
+    fpu.null_state = read_u8(input)? != 0;
+    Ok(Some(fpu))
+}
+
+impl CpuState {
+    // Big-endian, preceded by a magic number and STATE_VERSION
+    pub fn write_to<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        out.write_all(STATE_MAGIC)?;
+        out.write_all(&STATE_VERSION.to_be_bytes())?;
+        out.write_all(&self.pc.to_be_bytes())?;
+        out.write_all(&self.ir.to_be_bytes())?;
+        for reg in &self.dar {
+            out.write_all(&reg.to_be_bytes())?;
+        }
+        for value in &[self.inactive_ssp, self.inactive_usp,
+                       self.s_flag, self.t1_flag, self.x_flag, self.c_flag,
+                       self.v_flag, self.n_flag, self.not_z_flag, self.int_mask] {
+            out.write_all(&value.to_be_bytes())?;
+        }
+        out.write_all(&[self.irq_level])?;
+        out.write_all(&self.prefetch_addr.to_be_bytes())?;
+        out.write_all(&self.prefetch_data.to_be_bytes())?;
+        out.write_all(&[processing_state_to_u8(self.processing_state)])?;
+        out.write_all(&[model_to_u8(self.model)])?;
+        for value in &[self.vbr, self.sfc, self.dfc,
+                       self.m_flag, self.inactive_msp, self.cacr, self.caar] {
+            out.write_all(&value.to_be_bytes())?;
+        }
+        write_fpu(&self.fpu, out)?;
+        out.write_all(&[data_bus_to_u8(self.bus.data_bus)])?;
+        out.write_all(&self.bus.address_mask.to_be_bytes())
+    }
+
+    pub fn read_from<R: Read>(input: &mut R) -> io::Result<CpuState> {
+        let mut magic = [0u8; 4];
+        input.read_exact(&mut magic)?;
+        if &magic != STATE_MAGIC {
+            return Err(invalid_data("not a CPU state"));
+        }
+        if read_u16(input)? != STATE_VERSION {
+            return Err(invalid_data("unsupported CPU state version"));
+        }
+        let pc = read_u32(input)?;
+        let ir = read_u16(input)?;
+        let mut dar = [0u32; 16];
+        for reg in dar.iter_mut() {
+            *reg = read_u32(input)?;
+        }
+        Ok(CpuState {
+            pc, ir, dar,
+            inactive_ssp: read_u32(input)?,
+            inactive_usp: read_u32(input)?,
+            s_flag: read_u32(input)?,
+            t1_flag: read_u32(input)?,
+            x_flag: read_u32(input)?,
+            c_flag: read_u32(input)?,
+            v_flag: read_u32(input)?,
+            n_flag: read_u32(input)?,
+            not_z_flag: read_u32(input)?,
+            int_mask: read_u32(input)?,
+            irq_level: read_u8(input)?,
+            prefetch_addr: read_u32(input)?,
+            prefetch_data: read_u32(input)?,
+            processing_state: processing_state_from_u8(read_u8(input)?)?,
+            model: model_from_u8(read_u8(input)?)?,
+            vbr: read_u32(input)?,
+            sfc: read_u32(input)?,
+            dfc: read_u32(input)?,
+            m_flag: read_u32(input)?,
+            inactive_msp: read_u32(input)?,
+            cacr: read_u32(input)?,
+            caar: read_u32(input)?,
+            fpu: read_fpu(input)?,
+            bus: BusConfig {
+                data_bus: data_bus_from_u8(read_u8(input)?)?,
+                address_mask: read_u32(input)?,
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::ErrorKind;
+    use super::{CpuState, STATE_MAGIC};
+    use cpu::{TestCore, ConfiguredCore, CpuModel, ProcessingState, BusConfig};
+    use cpu::fpu::{Fpu, FpuModel, Extended};
+    use interrupts::AutoInterruptController;
+    use ram::{AddressBus, PagedMem, SUPERVISOR_PROGRAM};
+
+    // 7001 is MOVEQ #1, D0
+    // 5280 is ADDQ.L #1, D0
+    // 4e72 2700 is STOP #$2700
+    fn core() -> TestCore {
+        let mut cpu = TestCore::new_mem(0x40, &[0x70, 0x01, 0x52, 0x80, 0x52, 0x80, 0x4e, 0x72, 0x27, 0x00]);
+        cpu.dar[15] = 0x400;
+        cpu.inactive_usp = 0x300;
+        cpu.sr_to_flags(0x2704);
+        cpu
+    }
+
+    #[test]
+    fn roundtrips_through_bytes() {
+        let mut cpu = core();
+        cpu.execute1();
+        cpu.irq_level = 3;
+        let state = cpu.save_state();
+        let mut bytes = Vec::new();
+        state.write_to(&mut bytes).unwrap();
+        assert_eq!(STATE_MAGIC, &bytes[0..4]);
+        assert_eq!(state, CpuState::read_from(&mut &bytes[..]).unwrap());
+    }
+
+    #[test]
+    fn restored_core_resumes_identically() {
+        let mut cpu = core();
+        cpu.execute1();
+        let state = cpu.save_state();
+
+        let mut resumed = TestCore::new_mem(0, &[]);
+        resumed.mem.copy_from(&cpu.mem);
+        resumed.load_state(&state);
+        assert_eq!(state, resumed.save_state());
+
+        cpu.execute(8 + 8 + 4);
+        resumed.execute(8 + 8 + 4);
+        assert_eq!(3, cpu.dar[0]);
+        assert_eq!(ProcessingState::Stopped, resumed.processing_state);
+        assert_eq!(cpu.save_state(), resumed.save_state());
+    }
+
+    #[test]
+    fn machine_can_be_frozen_and_resumed() {
+        let mut mem = PagedMem::new(0);
+        for (offset, word) in [0x7001u32, 0x5280, 0x5280, 0x4e72, 0x2700].iter().enumerate() {
+            mem.write_word(SUPERVISOR_PROGRAM, 0x40 + offset as u32 * 2, *word).unwrap();
+        }
+        let mut cpu = ConfiguredCore::new_with(0x40, AutoInterruptController::new(), mem);
+        cpu.processing_state = ProcessingState::Normal;
+        cpu.execute1();
+
+        let mut file = Vec::new();
+        cpu.save_state().write_to(&mut file).unwrap();
+        cpu.mem.write_snapshot(&mut file).unwrap();
+
+        let mut input = &file[..];
+        let state = CpuState::read_from(&mut input).unwrap();
+        let mem = PagedMem::read_snapshot(&mut input).unwrap();
+        let mut resumed = ConfiguredCore::new_with(0, AutoInterruptController::new(), mem);
+        resumed.load_state(&state);
+
+        cpu.execute(8);
+        resumed.execute(8);
+        assert_eq!(cpu.save_state(), resumed.save_state());
+        assert_eq!(cpu.mem.diffs().collect::<Vec<_>>(), resumed.mem.diffs().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn restores_cpu_model_and_control_registers() {
+        let mut cpu = core();
+        cpu.set_model(CpuModel::M68010);
+        cpu.vbr = 0x1000;
+        cpu.sfc = 1;
+        cpu.dfc = 5;
+        let mut bytes = Vec::new();
+        cpu.save_state().write_to(&mut bytes).unwrap();
+
+        let mut resumed = TestCore::new_mem(0, &[]);
+        resumed.load_state(&CpuState::read_from(&mut &bytes[..]).unwrap());
+        assert_eq!(CpuModel::M68010, resumed.model());
+        assert_eq!(cpu.save_state(), resumed.save_state());
+    }
+
+    #[test]
+    fn restores_68020_master_stack_pointer() {
+        let mut cpu = core();
+        cpu.set_model(CpuModel::M68020);
+        cpu.inactive_msp = 0x600;
+        cpu.sr_to_flags(0x3700);
+        cpu.cacr = 1;
+        cpu.caar = 0x40;
+        let mut bytes = Vec::new();
+        cpu.save_state().write_to(&mut bytes).unwrap();
+
+        let mut resumed = TestCore::new_mem(0, &[]);
+        resumed.load_state(&CpuState::read_from(&mut &bytes[..]).unwrap());
+        assert_eq!(0x600, resumed.dar[15]);
+        assert_eq!(0x400, resumed.ssp());
+        assert_eq!(cpu.save_state(), resumed.save_state());
+    }
+
+    #[test]
+    fn restores_fpu_registers() {
+        let mut cpu = core();
+        cpu.set_model(CpuModel::M68020);
+        let mut fpu = Fpu::new(FpuModel::M68882);
+        fpu.fp[3] = Extended::from_f64(-2.5);
+        fpu.set_fpcr(0x0430);
+        fpu.set_fpsr(0x0800_0208);
+        fpu.fpiar = 0x40;
+        fpu.null_state = false;
+        cpu.set_fpu(Some(fpu));
+        let mut bytes = Vec::new();
+        cpu.save_state().write_to(&mut bytes).unwrap();
+
+        let mut resumed = TestCore::new_mem(0, &[]);
+        resumed.load_state(&CpuState::read_from(&mut &bytes[..]).unwrap());
+        assert_eq!(cpu.fpu(), resumed.fpu());
+        assert_eq!(cpu.save_state(), resumed.save_state());
+
+        resumed.set_fpu(None);
+        resumed.load_state(&core().save_state());
+        assert_eq!(None, resumed.fpu());
+    }
+
+    #[test]
+    fn restores_bus_configuration() {
+        let mut cpu = core();
+        cpu.set_bus(BusConfig::m68008());
+        let mut bytes = Vec::new();
+        cpu.save_state().write_to(&mut bytes).unwrap();
+
+        let mut resumed = TestCore::new_mem(0, &[]);
+        resumed.load_state(&CpuState::read_from(&mut &bytes[..]).unwrap());
+        assert_eq!(BusConfig::m68008(), resumed.bus());
+    }
+
+    #[test]
+    fn rejects_other_data() {
+        let err = CpuState::read_from(&mut &b"r68m\x00\x01"[..]).unwrap_err();
+        assert_eq!(ErrorKind::InvalidData, err.kind());
+    }
+
+    #[test]
+    fn rejects_other_versions() {
+        let mut bytes = Vec::new();
+        core().save_state().write_to(&mut bytes).unwrap();
+        bytes[5] += 1;
+        let err = CpuState::read_from(&mut &bytes[..]).unwrap_err();
+        assert_eq!(ErrorKind::InvalidData, err.kind());
+    }
+
+    #[test]
+    fn rejects_truncated_state() {
+        let mut bytes = Vec::new();
+        core().save_state().write_to(&mut bytes).unwrap();
+        bytes.pop();
+        let err = CpuState::read_from(&mut &bytes[..]).unwrap_err();
+        assert_eq!(ErrorKind::UnexpectedEof, err.kind());
+    }
+}
@@ -0,0 +1,260 @@
+use std::io;
+use std::io::Write;
+use std::ops::Range;
+use super::Cycles;
+#[cfg(any(test, feature = "disassembler"))]
+use r68k_tools::{PC, memory::MemoryVec, disassembler::Disassembler};
+
+// Enough words for the longest 68020 instruction, a MOVE between two
+// memory indirect EAs with long base and outer displacements
+pub const TRACED_WORDS: usize = 11;
+
+// The state after executing one instruction, as seen by a Trace
+pub struct TraceLine {
+    pub pc: u32,                    // address of the instruction
+    pub supervisor: bool,           // mode it was executed in
+    pub ir: u16,                    // its opcode
+    pub code: [u16; TRACED_WORDS],  // the instruction words, as fetched, then zeroes
+    pub dar: [u32; 16],
+    pub sr: u16,
+    pub flags: String,
+    pub cycles: Cycles,             // including any exception processing
+}
+
+pub trait Trace {
+    // an error turns tracing off
+    fn trace(&mut self, line: &TraceLine) -> io::Result<()>;
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Mode {
+    Any,
+    Supervisor,
+    User,
+}
+
+// Writes a line per instruction to any io::Write, optionally limited to
+// instructions within an address range, or executed in a given mode:
+// 00000040 7001 -S7----- D0=00000001 ... A7=00000400   4 MOVEQ.L $01,D0
+pub struct Tracer<W: Write> {
+    out: W,
+    range: Option<Range<u32>>,
+    mode: Mode,
+    #[cfg(any(test, feature = "disassembler"))]
+    disassembler: Option<Disassembler<'static>>,
+}
+
+impl<W: Write> Tracer<W> {
+    pub fn new(out: W) -> Tracer<W> {
+        Tracer {
+            out, range: None, mode: Mode::Any,
+            #[cfg(any(test, feature = "disassembler"))]
+            disassembler: None,
+        }
+    }
+    pub fn within(self, range: Range<u32>) -> Tracer<W> {
+        Tracer { range: Some(range), ..self }
+    }
+    pub fn in_mode(self, mode: Mode) -> Tracer<W> {
+        Tracer { mode, ..self }
+    }
+    #[cfg(any(test, feature = "disassembler"))]
+    pub fn disassembling(self) -> Tracer<W> {
+        Tracer { disassembler: Some(Disassembler::new()), ..self }
+    }
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+
+    fn wanted(&self, line: &TraceLine) -> bool {
+        let in_range = self.range.as_ref().map_or(true, |range| range.contains(&line.pc));
+        let in_mode = match self.mode {
+            Mode::Any => true,
+            Mode::Supervisor => line.supervisor,
+            Mode::User => !line.supervisor,
+        };
+        in_range && in_mode
+    }
+
+    #[cfg(any(test, feature = "disassembler"))]
+    fn disassemble(&self, line: &TraceLine) -> Option<String> {
+        self.disassembler.as_ref().map(|disassembler| {
+            let mem = MemoryVec::new16(PC(line.pc), line.code.to_vec());
+            match disassembler.disassemble(PC(line.pc), &mem) {
+                Ok((_, instruction)) => format!("{}", instruction).replace('\t', " "),
+                Err(_) => "???".to_string(),
+            }
+        })
+    }
+    #[cfg(not(any(test, feature = "disassembler")))]
+    fn disassemble(&self, _line: &TraceLine) -> Option<String> {
+        None
+    }
+}
+
+impl<W: Write> Trace for Tracer<W> {
+    fn trace(&mut self, line: &TraceLine) -> io::Result<()> {
+        if !self.wanted(line) {
+            return Ok(());
+        }
+        write!(self.out, "{:08x} {:04x} {}", line.pc, line.ir, line.flags)?;
+        for (i, reg) in line.dar.iter().enumerate() {
+            write!(self.out, " {}{}={:08x}", if i < 8 {'D'} else {'A'}, i % 8, reg)?;
+        }
+        write!(self.out, " {:>3}", line.cycles.0)?;
+        if let Some(instruction) = self.disassemble(line) {
+            write!(self.out, " {}", instruction)?;
+        }
+        writeln!(self.out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::io::Write;
+    use std::sync::{Arc, Mutex};
+    use super::{Tracer, Trace, TraceLine, Mode, TRACED_WORDS};
+    use cpu::{TestCore, CpuModel};
+    use ram::{AddressBus, SUPERVISOR_DATA};
+
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl SharedBuffer {
+        fn lines(&self) -> Vec<String> {
+            String::from_utf8(self.0.lock().unwrap().clone()).unwrap().lines().map(|l| l.to_string()).collect()
+        }
+    }
+
+    struct FailingWriter;
+
+    impl Write for FailingWriter {
+        fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+            Err(io::Error::new(io::ErrorKind::Other, "full"))
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    // 7001 is MOVEQ #1, D0
+    // 46fc 0700 is MOVE #$0700, SR (dropping to user mode)
+    // 7202 is MOVEQ #2, D1
+    fn core() -> TestCore {
+        let mut cpu = TestCore::new_mem(0x40, &[0x70, 0x01, 0x46, 0xfc, 0x07, 0x00, 0x72, 0x02]);
+        cpu.dar[15] = 0x400;
+        cpu.sr_to_flags(0x2700);
+        cpu
+    }
+
+    #[test]
+    fn traces_each_instruction() {
+        let mut cpu = core();
+        let buffer = SharedBuffer::default();
+        cpu.tracer = Some(Box::new(Tracer::new(buffer.clone())));
+        cpu.execute(4 + 16 + 4);
+        let lines = buffer.lines();
+        assert_eq!(3, lines.len());
+        assert_eq!("00000040 7001 -S7----- D0=00000001 D1=00000000 D2=00000000 D3=00000000 D4=00000000 D5=00000000 D6=00000000 D7=00000000 \
+                    A0=00000000 A1=00000000 A2=00000000 A3=00000000 A4=00000000 A5=00000000 A6=00000000 A7=00000400   4", lines[0]);
+        assert!(lines[1].starts_with("00000042 46fc -U7-----"));
+        assert!(lines[1].ends_with("  16"));
+        assert!(lines[2].starts_with("00000046 7202 -U7----- D0=00000001 D1=00000002"));
+    }
+
+    #[test]
+    fn disassembles_each_instruction() {
+        let mut cpu = core();
+        let buffer = SharedBuffer::default();
+        cpu.tracer = Some(Box::new(Tracer::new(buffer.clone()).disassembling()));
+        cpu.execute(4 + 16);
+        let lines = buffer.lines();
+        assert!(lines[0].ends_with("   4 MOVEQ.L $01,D0"), lines[0].clone());
+        assert!(lines[1].ends_with("  16 MOVE.W #$0700,SR"), lines[1].clone());
+    }
+
+    #[test]
+    fn filters_on_address_range() {
+        let mut cpu = core();
+        let buffer = SharedBuffer::default();
+        cpu.tracer = Some(Box::new(Tracer::new(buffer.clone()).within(0x42..0x46)));
+        cpu.execute(4 + 16 + 4);
+        let lines = buffer.lines();
+        assert_eq!(1, lines.len());
+        assert!(lines[0].starts_with("00000042"));
+    }
+
+    #[test]
+    fn filters_on_mode() {
+        let mut cpu = core();
+        let buffer = SharedBuffer::default();
+        cpu.tracer = Some(Box::new(Tracer::new(buffer.clone()).in_mode(Mode::Supervisor)));
+        cpu.execute(4 + 16 + 4);
+        // MOVE to SR is executed in supervisor mode, even though it
+        // switches to user mode
+        assert_eq!(2, buffer.lines().len());
+
+        let mut cpu = core();
+        let buffer = SharedBuffer::default();
+        cpu.tracer = Some(Box::new(Tracer::new(buffer.clone()).in_mode(Mode::User)));
+        cpu.execute(4 + 16 + 4);
+        assert_eq!(1, buffer.lines().len());
+    }
+
+    struct Words(Arc<Mutex<Vec<[u16; TRACED_WORDS]>>>);
+
+    impl Trace for Words {
+        fn trace(&mut self, line: &TraceLine) -> io::Result<()> {
+            self.0.lock().unwrap().push(line.code);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn traces_words_as_fetched() {
+        // 31fc 1234 0044 is MOVE.W #$1234, $0044.W, overwriting its
+        // own absolute address
+        let mut cpu = TestCore::new_mem(0x40, &[0x31, 0xfc, 0x12, 0x34, 0x00, 0x44, 0x4e, 0x71]);
+        let words = Arc::new(Mutex::new(Vec::new()));
+        cpu.tracer = Some(Box::new(Words(words.clone())));
+        cpu.execute(1);
+        assert_eq!(vec![[0x31fc, 0x1234, 0x0044, 0, 0, 0, 0, 0, 0, 0, 0]], *words.lock().unwrap());
+    }
+
+    #[test]
+    fn traces_the_longest_68020_instruction_whole() {
+        // 23b0 01f3 0000 0100 0000 0010 01f3 0000 0104 0000 0000 is
+        // MOVE.L ([$100.L],$10.L), ([$104.L],0.L)
+        let code = [0x23b0, 0x01f3, 0x0000, 0x0100, 0x0000, 0x0010, 0x01f3, 0x0000, 0x0104, 0x0000, 0x0000];
+        let bytes: Vec<u8> = code.iter().flat_map(|w| vec![(w >> 8) as u8, *w as u8]).collect();
+        let mut cpu = TestCore::new_mem(0x40, &bytes);
+        cpu.set_model(CpuModel::M68020);
+        cpu.mem.write_long(SUPERVISOR_DATA, 0x100, 0x200).unwrap();
+        cpu.mem.write_long(SUPERVISOR_DATA, 0x104, 0x300).unwrap();
+        cpu.mem.write_long(SUPERVISOR_DATA, 0x210, 0xdeadbeef).unwrap();
+        let words = Arc::new(Mutex::new(Vec::new()));
+        cpu.tracer = Some(Box::new(Words(words.clone())));
+        cpu.execute(1);
+        assert_eq!(0xdeadbeef, cpu.mem.read_long(SUPERVISOR_DATA, 0x300).unwrap());
+        assert_eq!(vec![code], *words.lock().unwrap());
+    }
+
+    #[test]
+    fn write_error_turns_tracing_off() {
+        let mut cpu = core();
+        cpu.tracer = Some(Box::new(Tracer::new(FailingWriter)));
+        cpu.execute(4);
+        assert!(cpu.tracer.is_none());
+        assert_eq!(0x42, cpu.pc);
+    }
+}
@@ -0,0 +1,642 @@
+// A stub for the GDB remote serial protocol, letting gdb (or anything
+// else talking RSP) debug a core: read and write registers and memory,
+// set breakpoints and watchpoints, step, continue and interrupt.
+//
+// Registers are numbered as gdb's m68k target expects: D0-D7 are 0-7,
+// A0-A7 are 8-15, SR is 16 and PC is 17. Memory is accessed directly
+// through the AddressBus, in the data space of the current mode, so it
+// does not trigger watchpoints.
+use std::io;
+use std::str;
+use std::thread;
+use std::time::Duration;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use cpu::{ConfiguredCore, EmulateAllExceptions};
+use cpu::debugger::{StopReason, Watch, Watchpoint};
+use interrupts::InterruptController;
+use ram::{AddressBus, AddressSpace, SUPERVISOR_DATA, USER_DATA};
+
+const INTERRUPT: u8 = 0x03; // Ctrl-C
+const NUM_REGS: usize = 18;
+const SR_REG: usize = 16;
+const PC_REG: usize = 17;
+// signal numbers as gdb knows them
+const SIGINT: u8 = 2;
+const SIGTRAP: u8 = 5;
+const SIGBUS: u8 = 10;
+// cycles to run between checks for an interrupt from the client
+const CONTINUE_SLICE: i32 = 10_000;
+// how long to wait between checks while the core is stopped (by STOP),
+// rather than spinning on it
+const STOPPED_POLL: Duration = Duration::from_millis(1);
+// the largest packet we take, as told to the client, which also bounds
+// the memory read by one m packet
+const PACKET_SIZE: usize = 0x1000;
+
+// A byte stream to a debugger client, which can be polled without
+// blocking while the core is running
+pub trait Connection {
+    fn read_byte(&mut self) -> io::Result<u8>;
+    fn poll_byte(&mut self) -> io::Result<Option<u8>>;
+    fn send(&mut self, data: &[u8]) -> io::Result<()>;
+}
+
+impl Connection for TcpStream {
+    fn read_byte(&mut self) -> io::Result<u8> {
+        let mut byte = [0u8];
+        self.set_nonblocking(false)?;
+        match self.read(&mut byte)? {
+            0 => Err(io::Error::new(io::ErrorKind::UnexpectedEof, "gdb client disconnected")),
+            _ => Ok(byte[0]),
+        }
+    }
+    fn poll_byte(&mut self) -> io::Result<Option<u8>> {
+        let mut byte = [0u8];
+        self.set_nonblocking(true)?;
+        let result = match self.read(&mut byte) {
+            Ok(0) => Err(io::Error::new(io::ErrorKind::UnexpectedEof, "gdb client disconnected")),
+            Ok(_) => Ok(Some(byte[0])),
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => Ok(None),
+            Err(e) => Err(e),
+        };
+        self.set_nonblocking(false)?;
+        result
+    }
+    fn send(&mut self, data: &[u8]) -> io::Result<()> {
+        self.write_all(data)
+    }
+}
+
+// Waits for a single gdb connection on the given address (such as
+// "localhost:2345") and serves the core until gdb detaches or kills it
+pub fn serve_tcp<T: InterruptController, A: AddressBus, S: ToSocketAddrs>(core: &mut ConfiguredCore<T, A>, address: S) -> io::Result<()> {
+    let listener = TcpListener::bind(address)?;
+    let (stream, _) = listener.accept()?;
+    stream.set_nodelay(true)?;
+    GdbStub::new(stream).serve(core)
+}
+
+enum Command {
+    Reply(String),
+    Resume(String),
+    Exit(String),
+}
+
+pub struct GdbStub<C: Connection> {
+    connection: C,
+    // Z2-Z4 watchpoints as set by the client, needed to tell which kind
+    // of watchpoint was hit
+    watchpoints: Vec<Watchpoint>,
+    last_stop: String,
+}
+
+impl<C: Connection> GdbStub<C> {
+    pub fn new(connection: C) -> GdbStub<C> {
+        GdbStub { connection, watchpoints: Vec::new(), last_stop: signal(SIGTRAP) }
+    }
+
+    pub fn into_connection(self) -> C {
+        self.connection
+    }
+
+    // Handles packets until the client detaches (D) or kills (k) the
+    // target, or disconnects
+    pub fn serve<T: InterruptController, A: AddressBus>(&mut self, core: &mut ConfiguredCore<T, A>) -> io::Result<()> {
+        loop {
+            let packet = match self.read_packet() {
+                Ok(packet) => packet,
+                Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+                Err(e) => return Err(e),
+            };
+            let command = match packet {
+                Some(packet) => self.handle(core, &packet),
+                None => Command::Reply(self.last_stop.clone()), // Ctrl-C while stopped
+            };
+            match command {
+                Command::Reply(reply) => self.write_packet(&reply)?,
+                Command::Resume(reply) => {
+                    self.last_stop = reply.clone();
+                    self.write_packet(&reply)?
+                }
+                Command::Exit(reply) => {
+                    if !reply.is_empty() {
+                        self.write_packet(&reply)?;
+                    }
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    // Reads the next packet, acknowledging it, or None for an interrupt.
+    // A packet longer than PACKET_SIZE is refused without waiting for its
+    // end, skipping ahead to the next one.
+    fn read_packet(&mut self) -> io::Result<Option<String>> {
+        'packets: loop {
+            match self.connection.read_byte()? {
+                b'$' => (),
+                INTERRUPT => return Ok(None),
+                _ => continue, // acks, or line noise
+            }
+            let mut data = Vec::new();
+            let mut sum = 0u8;
+            loop {
+                match self.connection.read_byte()? {
+                    b'#' => break,
+                    _ if data.len() == PACKET_SIZE => {
+                        self.connection.send(b"-")?;
+                        continue 'packets;
+                    }
+                    byte => {
+                        sum = sum.wrapping_add(byte);
+                        data.push(byte);
+                    }
+                }
+            }
+            let checksum = [self.connection.read_byte()?, self.connection.read_byte()?];
+            let valid = String::from_utf8(checksum.to_vec()).ok()
+                .and_then(|hex| u8::from_str_radix(&hex, 16).ok()) == Some(sum);
+            if valid {
+                self.connection.send(b"+")?;
+                return Ok(Some(String::from_utf8_lossy(&data).into_owned()));
+            }
+            self.connection.send(b"-")?;
+        }
+    }
+
+    fn write_packet(&mut self, data: &str) -> io::Result<()> {
+        let sum = data.bytes().fold(0u8, |sum, byte| sum.wrapping_add(byte));
+        self.connection.send(format!("${}#{:02x}", data, sum).as_bytes())
+    }
+
+    fn handle<T: InterruptController, A: AddressBus>(&mut self, core: &mut ConfiguredCore<T, A>, packet: &str) -> Command {
+        let (command, args) = packet.split_at(if packet.is_empty() { 0 } else { 1 });
+        let reply = match command {
+            "?" => Some(self.last_stop.clone()),
+            "g" => Some((0..NUM_REGS).map(|reg| format!("{:08x}", read_register(core, reg))).collect()),
+            "G" => parse_hex_words(args).map(|values| {
+                // SR first, as changing mode swaps A7
+                if let Some(&sr) = values.get(SR_REG) {
+                    write_register(core, SR_REG, sr);
+                }
+                for (reg, &value) in values.iter().enumerate().take(NUM_REGS) {
+                    if reg != SR_REG {
+                        write_register(core, reg, value);
+                    }
+                }
+                ok()
+            }),
+            "p" => parse_hex(args).and_then(register_index)
+                .map(|reg| format!("{:08x}", read_register(core, reg))),
+            "P" => split2(args, '=').and_then(|(reg, value)| {
+                let reg = parse_hex(reg).and_then(register_index)?;
+                let value = parse_hex(value)?;
+                write_register(core, reg, value);
+                Some(ok())
+            }),
+            "m" => split2(args, ',').and_then(|(address, len)| {
+                let (address, len) = (parse_hex(address)?, parse_hex(len)?);
+                Some(read_memory(core, address, len))
+            }),
+            "M" => split2(args, ':').and_then(|(range, data)| {
+                let (address, _) = split2(range, ',')?;
+                let address = parse_hex(address)?;
+                let bytes = parse_hex_bytes(data)?;
+                Some(write_memory(core, address, &bytes))
+            }),
+            "Z" | "z" => self.handle_breakpoint(core, command == "Z", args),
+            "s" => {
+                resume_at(core, args);
+                let (_, reason) = core.step_instruction(&mut EmulateAllExceptions);
+                return Command::Resume(self.stop_reply(reason));
+            }
+            "c" => {
+                resume_at(core, args);
+                return match self.continue_until_stopped(core) {
+                    Ok(reply) => Command::Resume(reply),
+                    Err(_) => Command::Exit(String::new()),
+                }
+            }
+            "H" => Some(ok()),
+            "q" => Some(if args.starts_with("Supported") {
+                    format!("PacketSize={:x}", PACKET_SIZE)
+                } else if args == "Attached" {
+                    "1".to_string()
+                } else {
+                    String::new()
+                }),
+            "D" => return Command::Exit(ok()),
+            "k" => return Command::Exit(String::new()),
+            _ => Some(String::new()), // unsupported
+        };
+        Command::Reply(reply.unwrap_or_else(|| "E01".to_string()))
+    }
+
+    fn handle_breakpoint<T: InterruptController, A: AddressBus>(&mut self, core: &mut ConfiguredCore<T, A>, insert: bool, args: &str) -> Option<String> {
+        let mut parts = args.split(',');
+        let kind = parts.next()?;
+        let address = parse_hex(parts.next()?)?;
+        let len = parse_hex(parts.next()?)?;
+        let watch = match kind {
+            "0" | "1" => {
+                if insert {
+                    core.debugger.add_breakpoint(address);
+                } else {
+                    core.debugger.remove_breakpoint(address);
+                }
+                return Some(ok());
+            }
+            "2" => Watch::Write,
+            "3" => Watch::Read,
+            "4" => Watch::Access,
+            _ => return Some(String::new()), // unsupported
+        };
+        let watchpoint = Watchpoint::new(address, len, watch);
+        if insert {
+            core.debugger.add_watchpoint(watchpoint.clone());
+            self.watchpoints.push(watchpoint);
+        } else {
+            core.debugger.remove_watchpoint(&watchpoint);
+            self.watchpoints.retain(|w| *w != watchpoint);
+        }
+        Some(ok())
+    }
+
+    fn continue_until_stopped<T: InterruptController, A: AddressBus>(&mut self, core: &mut ConfiguredCore<T, A>) -> io::Result<String> {
+        let mut resuming = true;
+        loop {
+            // a breakpoint at the resume PC is stepped over, as gdb expects
+            let reason = if resuming {
+                resuming = false;
+                match core.step_instruction(&mut EmulateAllExceptions) {
+                    (_, StopReason::Step) => StopReason::CyclesConsumed,
+                    (_, reason) => reason,
+                }
+            } else if core.debugger.is_breakpoint(core.pc) {
+                StopReason::Breakpoint(core.pc)
+            } else {
+                core.execute_with_state(CONTINUE_SLICE, &mut EmulateAllExceptions).1
+            };
+            match reason {
+                StopReason::CyclesConsumed => (),
+                StopReason::Stopped => thread::sleep(STOPPED_POLL),
+                reason => return Ok(self.stop_reply(reason)),
+            }
+            if self.connection.poll_byte()? == Some(INTERRUPT) {
+                return Ok(signal(SIGINT));
+            }
+        }
+    }
+
+    fn stop_reply(&self, reason: StopReason) -> String {
+        match reason {
+            StopReason::Watchpoint(hit) => {
+                // the access may have started below the watched range,
+                // but is at most a long
+                let kind = self.watchpoints.iter()
+                    .find(|w| w.overlaps(hit.address, 4))
+                    .map(|w| w.watch)
+                    .unwrap_or(Watch::Access);
+                let name = match kind {
+                    Watch::Write => "watch",
+                    Watch::Read => "rwatch",
+                    Watch::Access => "awatch",
+                };
+                format!("T{:02x}{}:{:08x};", SIGTRAP, name, hit.address)
+            }
+            StopReason::Halted => signal(SIGBUS),
+            _ => signal(SIGTRAP),
+        }
+    }
+}
+
+fn ok() -> String {
+    "OK".to_string()
+}
+
+fn signal(number: u8) -> String {
+    format!("S{:02x}", number)
+}
+
+fn split2(s: &str, separator: char) -> Option<(&str, &str)> {
+    let mut parts = s.splitn(2, separator);
+    match (parts.next(), parts.next()) {
+        (Some(first), Some(second)) => Some((first, second)),
+        _ => None,
+    }
+}
+
+fn parse_hex(s: &str) -> Option<u32> {
+    u32::from_str_radix(s, 16).ok()
+}
+
+fn parse_hex_bytes(s: &str) -> Option<Vec<u8>> {
+    s.as_bytes().chunks(2)
+        .map(|hex| if hex.len() == 2 { parse_hex(str::from_utf8(hex).ok()?).map(|byte| byte as u8) } else { None })
+        .collect()
+}
+
+fn parse_hex_words(s: &str) -> Option<Vec<u32>> {
+    s.as_bytes().chunks(8)
+        .map(|hex| if hex.len() == 8 { parse_hex(str::from_utf8(hex).ok()?) } else { None })
+        .collect()
+}
+
+fn register_index(reg: u32) -> Option<usize> {
+    if (reg as usize) < NUM_REGS { Some(reg as usize) } else { None }
+}
+
+fn read_register<T: InterruptController, A: AddressBus>(core: &ConfiguredCore<T, A>, reg: usize) -> u32 {
+    match reg {
+        SR_REG => u32::from(core.status_register()),
+        PC_REG => core.pc,
+        _ => core.dar[reg],
+    }
+}
+
+fn write_register<T: InterruptController, A: AddressBus>(core: &mut ConfiguredCore<T, A>, reg: usize, value: u32) {
+    match reg {
+        SR_REG => core.sr_to_flags(value as u16),
+        PC_REG => core.jump(value),
+        _ => core.dar[reg] = value,
+    }
+}
+
+fn resume_at<T: InterruptController, A: AddressBus>(core: &mut ConfiguredCore<T, A>, args: &str) {
+    if let Some(address) = parse_hex(args) {
+        core.jump(address);
+    }
+}
+
+fn data_space<T: InterruptController, A: AddressBus>(core: &ConfiguredCore<T, A>) -> AddressSpace {
+    if core.s_flag != 0 { SUPERVISOR_DATA } else { USER_DATA }
+}
+
+fn read_memory<T: InterruptController, A: AddressBus>(core: &ConfiguredCore<T, A>, address: u32, len: u32) -> String {
+    let address_space = data_space(core);
+    let mut reply = String::new();
+    // two hex digits a byte, in a reply no longer than a packet
+    let len = len.min((PACKET_SIZE / 2) as u32);
+    for offset in 0..len {
+        match core.mem.read_byte(address_space, address.wrapping_add(offset)) {
+            Ok(byte) => reply.push_str(&format!("{:02x}", byte)),
+            Err(_) => break,
+        }
+    }
+    // a partial read is fine, but not an empty one
+    if reply.is_empty() && len > 0 { "E01".to_string() } else { reply }
+}
+
+fn write_memory<T: InterruptController, A: AddressBus>(core: &mut ConfiguredCore<T, A>, address: u32, bytes: &[u8]) -> String {
+    let address_space = data_space(core);
+    // the block cache only sees writes made by the core
+    core.flush_block_cache();
+    for (offset, &byte) in bytes.iter().enumerate() {
+        if core.mem.write_byte(address_space, address.wrapping_add(offset as u32), u32::from(byte)).is_err() {
+            return "E01".to_string();
+        }
+    }
+    ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::collections::VecDeque;
+    use std::net::{TcpListener, TcpStream};
+    use std::io::{Read, Write};
+    use std::thread;
+    use super::{Connection, GdbStub, PACKET_SIZE};
+    use cpu::TestCore;
+
+    // a client sending a fixed script, and recording the replies
+    struct ScriptedClient {
+        input: VecDeque<u8>,
+        output: Vec<u8>,
+    }
+
+    impl ScriptedClient {
+        fn new(packets: &[&str]) -> ScriptedClient {
+            let mut input = Vec::new();
+            for packet in packets {
+                if *packet == "\x03" {
+                    input.push(0x03);
+                } else {
+                    input.extend(frame(packet).bytes());
+                }
+            }
+            ScriptedClient { input: input.into_iter().collect(), output: Vec::new() }
+        }
+        fn replies(&self) -> Vec<String> {
+            let output = String::from_utf8_lossy(&self.output).into_owned();
+            output.split('$').skip(1).map(|packet| packet.split('#').next().unwrap().to_string()).collect()
+        }
+    }
+
+    impl Connection for ScriptedClient {
+        fn read_byte(&mut self) -> io::Result<u8> {
+            self.input.pop_front().ok_or_else(|| io::Error::new(io::ErrorKind::UnexpectedEof, "end of script"))
+        }
+        // the client waits for a stop reply before sending its next
+        // packet, so only an interrupt can arrive while running
+        fn poll_byte(&mut self) -> io::Result<Option<u8>> {
+            if self.input.front() == Some(&0x03) {
+                Ok(self.input.pop_front())
+            } else {
+                Ok(None)
+            }
+        }
+        fn send(&mut self, data: &[u8]) -> io::Result<()> {
+            self.output.extend_from_slice(data);
+            Ok(())
+        }
+    }
+
+    fn frame(packet: &str) -> String {
+        let sum = packet.bytes().fold(0u8, |sum, byte| sum.wrapping_add(byte));
+        format!("${}#{:02x}", packet, sum)
+    }
+
+    // 7001 is MOVEQ #1, D0
+    // 7202 is MOVEQ #2, D1
+    // 31c0 0100 is MOVE.W D0, $100.W
+    // 60fe is BRA.S to itself
+    fn core() -> TestCore {
+        let mut cpu = TestCore::new_mem(0x40, &[0x70, 0x01, 0x72, 0x02, 0x31, 0xc0, 0x01, 0x00, 0x60, 0xfe]);
+        cpu.dar[15] = 0x400;
+        cpu.sr_to_flags(0x2700);
+        cpu
+    }
+
+    fn run(cpu: &mut TestCore, packets: &[&str]) -> Vec<String> {
+        let mut stub = GdbStub::new(ScriptedClient::new(packets));
+        stub.serve(cpu).unwrap();
+        stub.into_connection().replies()
+    }
+
+    #[test]
+    fn reads_registers() {
+        let mut cpu = core();
+        cpu.dar[0] = 0x12345678;
+        cpu.dar[8] = 0xabcdef01;
+        let replies = run(&mut cpu, &["g", "p11", "p10", "p12", "k"]);
+        let regs = &replies[0];
+        assert_eq!(18 * 8, regs.len());
+        assert_eq!("12345678", &regs[0..8]);
+        assert_eq!("abcdef01", &regs[64..72]);
+        assert_eq!("00000400", &regs[120..128]);
+        assert_eq!("00002700", &regs[128..136]);
+        assert_eq!("00000040", &regs[136..144]);
+        assert_eq!(vec!["00000040", "00002700", "E01"], replies[1..].to_vec());
+    }
+
+    #[test]
+    fn writes_registers() {
+        let mut cpu = core();
+        let mut regs = String::new();
+        for reg in 0..16 {
+            regs.push_str(&format!("{:08x}", reg * 0x11));
+        }
+        regs.push_str("00000700"); // user mode
+        regs.push_str("00001000");
+        let g = format!("G{}", regs);
+        let replies = run(&mut cpu, &[&g, "P3=cafebabe", "D"]);
+        assert_eq!(vec!["OK", "OK", "OK"], replies);
+        assert_eq!(0x22, cpu.dar[2]);
+        assert_eq!(0xcafebabe, cpu.dar[3]);
+        assert_eq!(0xff, cpu.dar[15]);
+        assert_eq!(0x0700, cpu.status_register());
+        assert_eq!(0x1000, cpu.pc);
+    }
+
+    #[test]
+    fn reads_and_writes_memory() {
+        let mut cpu = core();
+        let replies = run(&mut cpu, &["m40,4", "M100,3:aabbcc", "m100,4", "k"]);
+        assert_eq!(vec!["70017202", "OK", "aabbccaa"], replies);
+    }
+
+    #[test]
+    fn written_code_is_run_from_the_block_cache() {
+        let mut cpu = core();
+        cpu.set_block_cache(true);
+        // 7005 is MOVEQ #5, D0
+        let replies = run(&mut cpu, &["s", "s", "P11=00000040", "M40,2:7005", "s", "k"]);
+        assert_eq!(vec!["S05", "S05", "OK", "OK", "S05"], replies);
+        assert_eq!(5, cpu.dar[0]);
+    }
+
+    #[test]
+    fn steps_and_reports_stop() {
+        let mut cpu = core();
+        let replies = run(&mut cpu, &["?", "s", "s", "p11", "k"]);
+        assert_eq!(vec!["S05", "S05", "S05", "00000044"], replies);
+        assert_eq!(1, cpu.dar[0]);
+        assert_eq!(2, cpu.dar[1]);
+    }
+
+    #[test]
+    fn continues_to_breakpoint() {
+        let mut cpu = core();
+        let replies = run(&mut cpu, &["Z0,44,2", "c", "p11", "z0,44,2", "k"]);
+        assert_eq!(vec!["OK", "S05", "00000044", "OK"], replies);
+        assert!(!cpu.debugger.is_breakpoint(0x44));
+    }
+
+    #[test]
+    fn continues_from_breakpoint_at_pc() {
+        let mut cpu = core();
+        let replies = run(&mut cpu, &["Z0,40,2", "Z0,42,2", "c", "p11", "k"]);
+        assert_eq!(vec!["OK", "OK", "S05", "00000042"], replies);
+    }
+
+    #[test]
+    fn continues_to_watchpoint() {
+        let mut cpu = core();
+        let replies = run(&mut cpu, &["Z2,100,2", "c", "p11", "z2,100,2", "k"]);
+        assert_eq!(vec!["OK", "T05watch:00000100;", "00000048", "OK"], replies);
+    }
+
+    #[test]
+    fn continue_is_interrupted_by_ctrl_c() {
+        let mut cpu = core();
+        let replies = run(&mut cpu, &["c", "\x03", "?", "k"]);
+        assert_eq!(vec!["S02", "S02"], replies);
+        // the script is all available at once, so the interrupt is seen
+        // right after the first instruction
+        assert_eq!(0x42, cpu.pc);
+    }
+
+    #[test]
+    fn stopped_core_is_interrupted_by_ctrl_c() {
+        // 4e72 2700 is STOP #$2700
+        let mut cpu = TestCore::new_mem(0x40, &[0x4e, 0x72, 0x27, 0x00]);
+        cpu.dar[15] = 0x400;
+        let replies = run(&mut cpu, &["c", "\x03", "k"]);
+        assert_eq!(vec!["S02"], replies);
+        assert_eq!(0x44, cpu.pc);
+    }
+
+    #[test]
+    fn answers_queries_and_ignores_unknown_packets() {
+        let mut cpu = core();
+        let replies = run(&mut cpu, &["qSupported:multiprocess+", "qAttached", "Hg0", "vMustReplyEmpty", "k"]);
+        assert_eq!(vec!["PacketSize=1000", "1", "OK", ""], replies);
+    }
+
+    #[test]
+    fn memory_reads_fit_in_a_packet() {
+        let mut cpu = core();
+        let replies = run(&mut cpu, &["m0,ffffffff", "k"]);
+        assert_eq!(PACKET_SIZE, replies[0].len());
+    }
+
+    #[test]
+    fn client_disconnecting_ends_serving() {
+        let mut stub = GdbStub::new(ScriptedClient::new(&["g"]));
+        assert!(stub.serve(&mut core()).is_ok());
+    }
+
+    #[test]
+    fn rejects_bad_checksum() {
+        let mut client = ScriptedClient::new(&["k"]);
+        client.input = b"$g#00".iter().cloned().chain(client.input.into_iter()).collect();
+        let mut stub = GdbStub::new(client);
+        stub.serve(&mut core()).unwrap();
+        assert_eq!(b"-+".to_vec(), stub.into_connection().output);
+    }
+
+    #[test]
+    fn refuses_packets_longer_than_packet_size() {
+        let mut client = ScriptedClient::new(&["k"]);
+        let long = format!("$M0,{:x}:{}#00", PACKET_SIZE, "aa".repeat(PACKET_SIZE));
+        client.input = long.bytes().chain(client.input.into_iter()).collect();
+        let mut stub = GdbStub::new(client);
+        stub.serve(&mut core()).unwrap();
+        assert_eq!(b"-+".to_vec(), stub.into_connection().output);
+    }
+
+    #[test]
+    fn serves_over_tcp() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let client = thread::spawn(move || {
+            let mut stream = TcpStream::connect(address).unwrap();
+            let mut reply = [0u8; 32];
+            stream.write_all(frame("s").as_bytes()).unwrap();
+            let mut received = Vec::new();
+            while !received.ends_with(b"#b8") {
+                let n = stream.read(&mut reply).unwrap();
+                received.extend_from_slice(&reply[..n]);
+            }
+            stream.write_all(frame("k").as_bytes()).unwrap();
+            received
+        });
+        let (stream, _) = listener.accept().unwrap();
+        let mut cpu = core();
+        GdbStub::new(stream).serve(&mut cpu).unwrap();
+        assert_eq!(b"+$S05#b8".to_vec(), client.join().unwrap());
+        assert_eq!(0x42, cpu.pc);
+    }
+}
@@ -1,16 +1,16 @@
-pub trait InterruptController
+// 'static for the same reason as AddressBus
+pub trait InterruptController: 'static
 {
     fn reset_external_devices(&mut self); // triggered by RESET instruction
     fn highest_priority(&self) -> u8;
     fn acknowledge_interrupt(&mut self, priority: u8) -> Option<u8>;
 }
 
-#[allow(dead_code)]
 pub const UNINITIALIZED_INTERRUPT: u8 = 0x0F;
 pub const SPURIOUS_INTERRUPT: u8 = 0x18;
 const AUTOVECTOR_BASE: u8 = 0x18;
 
-#[derive(Default)]
+#[derive(Clone, Default)]
 pub struct AutoInterruptController {
     level: u8
 }
@@ -45,11 +45,141 @@ impl InterruptController for AutoInterruptController {
     }
 }
 
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DeviceId(usize);
+
+struct VectoredDevice {
+    level: u8,
+    vector: Option<u8>, // None until the vector register is written
+    pending: bool,
+}
+
+// Aggregates interrupt requests from several devices that supply their
+// own vector number during the interrupt acknowledge cycle, such as the
+// 68901 MFP or 68681 DUART. Devices on the same level are daisy-chained,
+// so the first one added that has a pending request answers.
+#[derive(Default)]
+pub struct VectoredInterruptController {
+    devices: Vec<VectoredDevice>
+}
+impl VectoredInterruptController {
+    pub fn new() -> VectoredInterruptController {
+        VectoredInterruptController { devices: Vec::new() }
+    }
+
+    pub fn add_device(&mut self, level: u8) -> DeviceId
+    {
+        assert!(level > 0 && level < 8);
+        self.devices.push(VectoredDevice { level, vector: None, pending: false });
+        DeviceId(self.devices.len() - 1)
+    }
+
+    pub fn set_vector(&mut self, id: DeviceId, vector: Option<u8>) {
+        self.devices[id.0].vector = vector;
+    }
+
+    pub fn request_interrupt(&mut self, id: DeviceId) -> u8
+    {
+        self.devices[id.0].pending = true;
+        self.highest_priority()
+    }
+
+    pub fn clear_interrupt(&mut self, id: DeviceId) {
+        self.devices[id.0].pending = false;
+    }
+
+    pub fn is_pending(&self, id: DeviceId) -> bool {
+        self.devices[id.0].pending
+    }
+}
+impl InterruptController for VectoredInterruptController {
+    fn reset_external_devices(&mut self)
+    {
+        // a reset device drops its request and forgets its vector
+        for device in &mut self.devices {
+            device.pending = false;
+            device.vector = None;
+        }
+    }
+
+    fn highest_priority(&self) -> u8 {
+        self.devices.iter().filter(|device| device.pending).map(|device| device.level).max().unwrap_or(0)
+    }
+
+    fn acknowledge_interrupt(&mut self, priority: u8) -> Option<u8> {
+        match self.devices.iter_mut().find(|device| device.pending && device.level == priority) {
+            Some(device) => {
+                device.pending = false;
+                Some(device.vector.unwrap_or(UNINITIALIZED_INTERRUPT))
+            },
+            None => Some(SPURIOUS_INTERRUPT),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LineId(usize);
+
+struct Line {
+    level: u8,
+    asserted: bool,
+}
+
+// Level-sensitive, autovectored interrupt lines. Each source owns a line
+// that stays asserted until the source itself deasserts it (typically
+// when the handler reads the device's status register), so acknowledging
+// an interrupt does not clear it. Several lines may share a level, which
+// stays asserted as long as any of them is.
+#[derive(Default)]
+pub struct LevelInterruptController {
+    lines: Vec<Line>
+}
+impl LevelInterruptController {
+    pub fn new() -> LevelInterruptController {
+        LevelInterruptController { lines: Vec::new() }
+    }
+
+    pub fn add_line(&mut self, level: u8) -> LineId
+    {
+        assert!(level > 0 && level < 8);
+        self.lines.push(Line { level, asserted: false });
+        LineId(self.lines.len() - 1)
+    }
+
+    pub fn assert_line(&mut self, id: LineId) {
+        self.lines[id.0].asserted = true;
+    }
+
+    pub fn deassert_line(&mut self, id: LineId) {
+        self.lines[id.0].asserted = false;
+    }
+
+    pub fn is_asserted(&self, id: LineId) -> bool {
+        self.lines[id.0].asserted
+    }
+}
+impl InterruptController for LevelInterruptController {
+    fn reset_external_devices(&mut self)
+    {
+        for line in &mut self.lines {
+            line.asserted = false;
+        }
+    }
+
+    fn highest_priority(&self) -> u8 {
+        self.lines.iter().filter(|line| line.asserted).map(|line| line.level).max().unwrap_or(0)
+    }
+
+    fn acknowledge_interrupt(&mut self, priority: u8) -> Option<u8> {
+        Some(AUTOVECTOR_BASE + priority)
+    }
+}
 
 #[cfg(test)]
 mod tests {
     use super::{InterruptController, AutoInterruptController,
-        AUTOVECTOR_BASE};
+        VectoredInterruptController, LevelInterruptController, AUTOVECTOR_BASE,
+        UNINITIALIZED_INTERRUPT, SPURIOUS_INTERRUPT};
 
     #[test]
     fn keeps_track_of_priority() {
@@ -74,4 +204,93 @@ mod tests {
         ctrl.reset_external_devices();
         assert_eq!(0, ctrl.highest_priority());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn answers_with_device_vector() {
+        let mut ctrl = VectoredInterruptController::new();
+        let mfp = ctrl.add_device(6);
+        let duart = ctrl.add_device(4);
+        ctrl.set_vector(mfp, Some(0x40));
+        ctrl.set_vector(duart, Some(0x80));
+        ctrl.request_interrupt(duart);
+        assert_eq!(6, ctrl.request_interrupt(mfp));
+        assert_eq!(Some(0x40), ctrl.acknowledge_interrupt(6));
+        assert!(!ctrl.is_pending(mfp));
+        assert_eq!(4, ctrl.highest_priority());
+        assert_eq!(Some(0x80), ctrl.acknowledge_interrupt(4));
+        assert_eq!(0, ctrl.highest_priority());
+    }
+    #[test]
+    fn first_device_on_a_level_answers() {
+        let mut ctrl = VectoredInterruptController::new();
+        let first = ctrl.add_device(5);
+        let second = ctrl.add_device(5);
+        ctrl.set_vector(first, Some(0x50));
+        ctrl.set_vector(second, Some(0x60));
+        ctrl.request_interrupt(second);
+        ctrl.request_interrupt(first);
+        assert_eq!(Some(0x50), ctrl.acknowledge_interrupt(5));
+        assert_eq!(Some(0x60), ctrl.acknowledge_interrupt(5));
+    }
+    #[test]
+    fn answers_uninitialized_without_vector() {
+        let mut ctrl = VectoredInterruptController::new();
+        let mfp = ctrl.add_device(6);
+        ctrl.request_interrupt(mfp);
+        assert_eq!(Some(UNINITIALIZED_INTERRUPT), ctrl.acknowledge_interrupt(6));
+    }
+    #[test]
+    fn answers_spurious_when_no_device_answers() {
+        let mut ctrl = VectoredInterruptController::new();
+        let mfp = ctrl.add_device(6);
+        ctrl.set_vector(mfp, Some(0x40));
+        ctrl.request_interrupt(mfp);
+        ctrl.clear_interrupt(mfp);
+        assert_eq!(Some(SPURIOUS_INTERRUPT), ctrl.acknowledge_interrupt(6));
+    }
+    #[test]
+    fn forgets_vectors_on_external_device_reset() {
+        let mut ctrl = VectoredInterruptController::new();
+        let mfp = ctrl.add_device(6);
+        ctrl.set_vector(mfp, Some(0x40));
+        ctrl.request_interrupt(mfp);
+        ctrl.reset_external_devices();
+        assert_eq!(0, ctrl.highest_priority());
+        ctrl.request_interrupt(mfp);
+        assert_eq!(Some(UNINITIALIZED_INTERRUPT), ctrl.acknowledge_interrupt(6));
+    }
+    #[test]
+    fn keeps_line_asserted_on_ack() {
+        let mut ctrl = LevelInterruptController::new();
+        let timer = ctrl.add_line(6);
+        ctrl.assert_line(timer);
+        assert_eq!(Some(AUTOVECTOR_BASE + 6), ctrl.acknowledge_interrupt(6));
+        assert!(ctrl.is_asserted(timer));
+        assert_eq!(6, ctrl.highest_priority());
+        ctrl.deassert_line(timer);
+        assert_eq!(0, ctrl.highest_priority());
+    }
+    #[test]
+    fn keeps_shared_level_asserted_while_any_line_is() {
+        let mut ctrl = LevelInterruptController::new();
+        let serial = ctrl.add_line(3);
+        let disk = ctrl.add_line(3);
+        let timer = ctrl.add_line(2);
+        ctrl.assert_line(serial);
+        ctrl.assert_line(disk);
+        ctrl.assert_line(timer);
+        ctrl.deassert_line(serial);
+        assert_eq!(3, ctrl.highest_priority());
+        ctrl.deassert_line(disk);
+        assert_eq!(2, ctrl.highest_priority());
+    }
+    #[test]
+    fn deasserts_lines_on_external_device_reset() {
+        let mut ctrl = LevelInterruptController::new();
+        let timer = ctrl.add_line(6);
+        ctrl.assert_line(timer);
+        ctrl.reset_external_devices();
+        assert!(!ctrl.is_asserted(timer));
+        assert_eq!(0, ctrl.highest_priority());
+    }
+}
@@ -4,12 +4,15 @@ extern crate lazy_static;
 #[cfg(test)]
 extern crate itertools;
 extern crate r68k_common;
-#[cfg(test)]
+#[cfg(any(test, feature = "disassembler"))]
 extern crate r68k_tools;
 
 pub mod cpu;
 pub mod ram;
 pub mod interrupts;
+pub mod gdb;
+pub mod machine;
+pub mod runner;
 pub mod musashi;
 
 
@@ -0,0 +1,205 @@
+// A machine of several cores sharing one bus, run in turns on a single
+// thread. Time is divided into slices of a fixed number of cycles, and
+// in each slice the cores run in the order they were added, each until
+// it has caught up with the end of the slice. A core that overshoots the
+// slice with its last instruction runs that much less in the next one,
+// so the cores stay within an instruction of each other, and a run only
+// depends on the programs and the quantum, never on the host.
+//
+// A core has the bus to itself for a whole instruction, so the
+// read-modify-write cycle of TAS (and CAS/CAS2 on the 68020) is
+// indivisible: no other core can get at the operand between the read
+// and the write back. Sequences of instructions have no such guarantee,
+// as a slice can end between any two of them.
+//
+// The cores run without their block cache, which only sees the writes of
+// its own core; one turned on is turned off again in the next slice.
+use std::cell::{Ref, RefMut};
+use cpu::{ConfiguredCore, Cycles};
+use interrupts::InterruptController;
+use ram::AddressBus;
+use ram::sharedbus::SharedBus;
+
+pub struct Machine<T: InterruptController, B: AddressBus> {
+    bus: SharedBus<B>,
+    cores: Vec<ConfiguredCore<T, SharedBus<B>>>,
+    // cycles run by each core, which may be a little past the time
+    clocks: Vec<u64>,
+    quantum: i32,
+    time: u64,
+}
+
+impl<T: InterruptController, B: AddressBus> Machine<T, B> {
+    pub fn new(bus: B, quantum: i32) -> Machine<T, B> {
+        assert!(quantum > 0, "quantum must be at least one cycle");
+        Machine { bus: SharedBus::new(bus), cores: Vec::new(), clocks: Vec::new(), quantum, time: 0 }
+    }
+    // Adds a core on the shared bus, starting out in the state of a new
+    // core, so it needs a reset or setting up before it runs. Returns
+    // its index, which is also its turn within a slice.
+    pub fn add_core(&mut self, base: u32, int_ctrl: T) -> usize {
+        self.cores.push(ConfiguredCore::new_with(base, int_ctrl, self.bus.clone()));
+        // a core added later starts at the current time
+        self.clocks.push(self.time);
+        self.cores.len() - 1
+    }
+    pub fn core(&self, index: usize) -> &ConfiguredCore<T, SharedBus<B>> {
+        &self.cores[index]
+    }
+    pub fn core_mut(&mut self, index: usize) -> &mut ConfiguredCore<T, SharedBus<B>> {
+        &mut self.cores[index]
+    }
+    pub fn cores(&self) -> usize {
+        self.cores.len()
+    }
+    pub fn bus(&self) -> Ref<'_, B> {
+        self.bus.borrow()
+    }
+    pub fn bus_mut(&mut self) -> RefMut<'_, B> {
+        self.bus.borrow_mut()
+    }
+    // cycles since the machine was created, at the end of the last slice
+    pub fn time(&self) -> u64 {
+        self.time
+    }
+    // cycles the core has run, counting any overshoot of the last slice
+    pub fn clock(&self, index: usize) -> u64 {
+        self.clocks[index]
+    }
+    pub fn reset(&mut self) {
+        for core in &mut self.cores {
+            core.reset();
+        }
+    }
+
+    pub fn run_slice(&mut self) {
+        let end = self.time + self.quantum as u64;
+        for (core, clock) in self.cores.iter_mut().zip(self.clocks.iter_mut()) {
+            core.set_block_cache(false);
+            if *clock < end {
+                let Cycles(used) = core.execute((end - *clock) as i32);
+                *clock += used.max(0) as u64;
+            }
+        }
+        self.time = end;
+    }
+    // runs whole slices until at least the given number of cycles have
+    // passed
+    pub fn run(&mut self, cycles: u64) {
+        let end = self.time + cycles;
+        while self.time < end {
+            self.run_slice();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Machine;
+    use cpu::{Core, ProcessingState};
+    use interrupts::AutoInterruptController;
+    use ram::{AddressBus, PagedMem, SUPERVISOR_DATA, SUPERVISOR_PROGRAM};
+
+    fn machine(quantum: i32, code: &[u16]) -> Machine<AutoInterruptController, PagedMem> {
+        let mut mem = PagedMem::new(0);
+        // reset vectors: SSP $800, PC $100
+        mem.write_long(SUPERVISOR_PROGRAM, 0, 0x800).unwrap();
+        mem.write_long(SUPERVISOR_PROGRAM, 4, 0x100).unwrap();
+        for (i, word) in code.iter().enumerate() {
+            mem.write_word(SUPERVISOR_PROGRAM, 0x100 + 2 * i as u32, u32::from(*word)).unwrap();
+        }
+        Machine::new(mem, quantum)
+    }
+
+    // Each core increments the word at $1002 a number of times, as a
+    // load, add and store, holding a TAS lock on the byte at $1000
+    const LOCKED_INCREMENT: [u16; 14] = [
+        0x4af8, 0x1000, // loop: TAS $1000.W
+        0x6bfa,         // BMI loop
+        0x3238, 0x1002, // MOVE.W $1002.W, D1
+        0x5241,         // ADDQ.W #1, D1
+        0x31c1, 0x1002, // MOVE.W D1, $1002.W
+        0x4238, 0x1000, // CLR.B $1000.W
+        0x5340,         // SUBQ.W #1, D0
+        0x66e8,         // BNE loop
+        0x4e72, 0x2700, // STOP #$2700
+    ];
+
+    fn run_locked_increments(quantum: i32) -> Machine<AutoInterruptController, PagedMem> {
+        let mut machine = machine(quantum, &LOCKED_INCREMENT);
+        for _ in 0..3 {
+            machine.add_core(0, AutoInterruptController::new());
+        }
+        machine.reset();
+        for core in 0..3 {
+            machine.core_mut(core).dar[0] = 100;
+        }
+        machine.run(100_000);
+        machine
+    }
+
+    #[test]
+    fn cores_see_each_others_writes() {
+        // MOVE.W #$1234, $1000.W then STOP
+        let mut machine = machine(100, &[0x31fc, 0x1234, 0x1000, 0x4e72, 0x2700]);
+        let writer = machine.add_core(0, AutoInterruptController::new());
+        machine.core_mut(writer).reset();
+        machine.run_slice();
+        assert_eq!(0x1234, machine.bus().read_word(SUPERVISOR_DATA, 0x1000).unwrap());
+        let reader = machine.add_core(0, AutoInterruptController::new());
+        assert_eq!(0x1234, machine.core_mut(reader).read_data_word(0x1000).unwrap());
+    }
+
+    #[test]
+    fn cores_run_code_patched_by_another() {
+        // 7001 is MOVEQ #1, D0, followed by two NOPs and BRA.S back to it,
+        // which the second core patches to MOVEQ #2, D0 with
+        // MOVE.W #$7002, $100.W
+        let mut machine = machine(100, &[0x7001, 0x4e71, 0x4e71, 0x60f8, 0x31fc, 0x7002, 0x0100, 0x4e72, 0x2700]);
+        let runner = machine.add_core(0, AutoInterruptController::new());
+        let patcher = machine.add_core(0, AutoInterruptController::new());
+        machine.reset();
+        machine.core_mut(runner).set_block_cache(true);
+        machine.core_mut(patcher).jump(0x108);
+        machine.run_slice();
+        assert_eq!(0x7002, machine.bus().read_word(SUPERVISOR_DATA, 0x100).unwrap());
+        machine.run_slice();
+        assert_eq!(2, machine.core(runner).dar[0]);
+        assert!(machine.core(runner).block_cache().is_none());
+    }
+
+    #[test]
+    fn tas_lock_serializes_cores() {
+        // a quantum this short ends slices inside the critical section
+        let machine = run_locked_increments(7);
+        assert_eq!(300, machine.bus().read_word(SUPERVISOR_DATA, 0x1002).unwrap());
+        for core in 0..3 {
+            assert_eq!(ProcessingState::Stopped, machine.core(core).processing_state);
+        }
+    }
+
+    #[test]
+    fn runs_are_reproducible() {
+        let first = run_locked_increments(13);
+        let second = run_locked_increments(13);
+        for core in 0..3 {
+            assert_eq!(first.core(core).dar, second.core(core).dar);
+            assert_eq!(first.core(core).pc, second.core(core).pc);
+            assert_eq!(first.clock(core), second.clock(core));
+        }
+    }
+
+    #[test]
+    fn cores_stay_within_an_instruction_of_each_other() {
+        let mut machine = machine(10, &[0x4e71, 0x60fc]); // NOP, BRA.S *-2
+        machine.add_core(0, AutoInterruptController::new());
+        machine.add_core(0, AutoInterruptController::new());
+        machine.reset();
+        machine.run(1000);
+        assert_eq!(1000, machine.time());
+        for core in 0..2 {
+            let clock = machine.clock(core);
+            assert!(clock >= 1000 && clock < 1000 + 10, "core {} at {}", core, clock);
+        }
+    }
+}
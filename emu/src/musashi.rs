@@ -71,6 +71,7 @@ extern {
     fn m68k_get_reg(context: *mut libc::c_void, regnum: Register) -> u32;
     fn m68k_set_reg(regnum: Register, value: u32);
     fn m68k_set_irq(irq: u32);
+    fn m68k_set_int_ack_callback(callback: extern fn(i32) -> i32);
 }
 use ram::{AddressSpace, SUPERVISOR_PROGRAM, SUPERVISOR_DATA, USER_PROGRAM, USER_DATA, ADDRBUS_MASK};
 use ram::loggingmem::Operation;
@@ -85,6 +86,8 @@ static mut MUSASHI_MEMORY_DATA:  [u8; 1024] = [0; 1024];
 static mut MUSASHI_OPS: [Operation; 512] = [Operation::None; 512];
 static mut MUSASHI_OPCOUNT: usize = 0;
 static mut MUSASHI_ADDRESS_SPACE: AddressSpace = SUPERVISOR_PROGRAM;
+// the 68020 brings out all 32 address lines
+static mut MUSASHI_ADDRESS_MASK: u32 = ADDRBUS_MASK;
 
 unsafe fn register_op(op: Operation) {
     if MUSASHI_OPCOUNT < MUSASHI_OPS.len() {
@@ -97,7 +100,7 @@ unsafe fn register_op(op: Operation) {
 #[no_mangle]
 pub extern fn m68k_read_memory_8(address: u32) -> u32 {
     unsafe {
-        let address = address & ADDRBUS_MASK;
+        let address = address & MUSASHI_ADDRESS_MASK;
         let value = read_musashi_byte(address);
         let op = Operation::ReadByte(MUSASHI_ADDRESS_SPACE, address, value);
         register_op(op);
@@ -107,7 +110,7 @@ pub extern fn m68k_read_memory_8(address: u32) -> u32 {
 #[no_mangle]
 pub extern fn m68k_read_memory_16(address: u32) -> u32 {
     unsafe {
-        let address = address & ADDRBUS_MASK;
+        let address = address & MUSASHI_ADDRESS_MASK;
         let value =  (read_musashi_byte(address+0) as u16) << 8
                     |(read_musashi_byte(address+1) as u16) << 0;
         let op = Operation::ReadWord(MUSASHI_ADDRESS_SPACE, address, value);
@@ -175,7 +178,7 @@ unsafe fn find_musashi_location(address: u32) -> Option<usize> {
     None
 }
 unsafe fn read_musashi_byte(address: u32) -> u8 {
-    let address = address & ADDRBUS_MASK;
+    let address = address & MUSASHI_ADDRESS_MASK;
     if let Some(index) = find_musashi_location(address) {
         MUSASHI_MEMORY_DATA[index]
     } else {
@@ -183,7 +186,7 @@ unsafe fn read_musashi_byte(address: u32) -> u8 {
     }
 }
 unsafe fn write_musashi_byte(address: u32, data: u8) {
-    let address = address & ADDRBUS_MASK;
+    let address = address & MUSASHI_ADDRESS_MASK;
     let write_differs_from_initializer = data != read_initializer(address);
     if write_differs_from_initializer {
         if let Some(index) = find_musashi_location(address) {
@@ -219,6 +222,22 @@ pub extern fn cpu_irq_ack(level: i32) -> i32 {panic!("ia")}
 #[no_mangle]
 pub extern fn cpu_instr_callback() {}
 
+// Musashi's M68K_INT_ACK_AUTOVECTOR and M68K_INT_ACK_SPURIOUS
+pub const MUSASHI_INT_ACK_AUTOVECTOR: i32 = -1;
+pub const MUSASHI_INT_ACK_SPURIOUS: i32 = -2;
+static mut MUSASHI_INT_ACK_VECTOR: i32 = MUSASHI_INT_ACK_AUTOVECTOR;
+extern fn musashi_int_ack(_level: i32) -> i32 {
+    unsafe { MUSASHI_INT_ACK_VECTOR }
+}
+// Makes Musashi put the given vector on the bus during the interrupt
+// acknowledge cycle, like a vectored device would (m68k_init resets it)
+pub fn set_musashi_int_ack(vector: i32) {
+    unsafe {
+        MUSASHI_INT_ACK_VECTOR = vector;
+        m68k_set_int_ack_callback(musashi_int_ack);
+    }
+}
+
 use std::ptr;
 
 #[allow(unused_variables)]
@@ -245,7 +264,7 @@ pub fn roundtrip_register(reg: Register, value: u32) -> u32 {
     }
 }
 
-use cpu::{TestCore, Cycles};
+use cpu::{TestCore, Cycles, CpuModel};
 
 static REGS:[Register; 16] = [Register::D0, Register::D1, Register::D2, Register::D3, Register::D4, Register::D5, Register::D6, Register::D7, Register::A0, Register::A1, Register::A2, Register::A3, Register::A4, Register::A5, Register::A6, Register::A7];
 
@@ -263,8 +282,13 @@ pub fn initialize_musashi(core: &mut TestCore, memory_initializer: u32) {
     // println!("initialize_musashi {:?}", thread::current());
     unsafe {
         initialize_musashi_memory(memory_initializer);
+        MUSASHI_ADDRESS_MASK = core.model().address_mask();
         m68k_init();
-        m68k_set_cpu_type(CpuType::M68000);
+        m68k_set_cpu_type(match core.model() {
+            CpuModel::M68000 => CpuType::M68000,
+            CpuModel::M68010 => CpuType::M68010,
+            CpuModel::M68020 => CpuType::M68020,
+        });
         m68k_write_memory_32(0, core.ssp());
         m68k_write_memory_32(4, core.pc);
         m68k_pulse_reset();
@@ -278,6 +302,16 @@ pub fn initialize_musashi(core: &mut TestCore, memory_initializer: u32) {
         m68k_set_reg(Register::USP, core.usp());
         // if SR clears S_FLAG then SSP <- A7, A7 <- USP
         m68k_set_reg(Register::SR, core.status_register() as u32);
+        if core.model() != CpuModel::M68000 {
+            m68k_set_reg(Register::VBR, core.vbr);
+            m68k_set_reg(Register::SFC, core.sfc);
+            m68k_set_reg(Register::DFC, core.dfc);
+        }
+        if core.model() == CpuModel::M68020 {
+            m68k_set_reg(Register::MSP, core.msp());
+            m68k_set_reg(Register::CACR, core.cacr);
+            m68k_set_reg(Register::CAAR, core.caar);
+        }
         for (i, &reg) in REGS.iter().enumerate() {
             if i != 15 {
                 m68k_set_reg(reg, core.dar[i]);
@@ -321,6 +355,23 @@ pub fn execute1(core: &mut TestCore) -> Cycles {
             core.dar[15] = m68k_get_reg(ptr::null_mut(), Register::USP);
             core.inactive_ssp = m68k_get_reg(ptr::null_mut(), Register::ISP);
         }
+        if core.model() != CpuModel::M68000 {
+            core.vbr = m68k_get_reg(ptr::null_mut(), Register::VBR);
+            core.sfc = m68k_get_reg(ptr::null_mut(), Register::SFC);
+            core.dfc = m68k_get_reg(ptr::null_mut(), Register::DFC);
+        }
+        if core.model() == CpuModel::M68020 {
+            // A7 is the master stack pointer in supervisor mode with M set
+            let msp = m68k_get_reg(ptr::null_mut(), Register::MSP);
+            if core.s_flag > 0 && core.m_flag > 0 {
+                core.inactive_ssp = core.dar[15];
+                core.dar[15] = msp;
+            } else {
+                core.inactive_msp = msp;
+            }
+            core.cacr = m68k_get_reg(ptr::null_mut(), Register::CACR);
+            core.caar = m68k_get_reg(ptr::null_mut(), Register::CAAR);
+        }
 
         Cycles(cycle_count)
     }
@@ -350,10 +401,13 @@ mod tests {
     use ram::SUPERVISOR_PROGRAM;
     use super::MUSASHI_LOCK;
     use super::QUICKCHECK_LOCK;
-    use ram::{AddressBus};
-    use ram::loggingmem::Operation;
-    use cpu::{TestCore, EXCEPTION_ZERO_DIVIDE, EXCEPTION_CHK, Cycles};
+    use ram::{AddressBus, ADDRBUS_MASK_32};
+    use ram::loggingmem::{Operation, LoggingMem, OpsLogger};
+    use interrupts::InterruptController;
+    use cpu::{TestCore, ConfiguredCore, CpuModel, EXCEPTION_ZERO_DIVIDE, EXCEPTION_CHK, EXCEPTION_TRACE, EXCEPTION_ILLEGAL_INSTRUCTION, Cycles};
+    use cpu::ops::handlers::InstructionSetGenerator;
     use std::cmp;
+    use std::env;
 
     extern crate quickcheck;
     use self::quickcheck::*;
@@ -462,6 +516,16 @@ mod tests {
     }
 
     static mut OPCODE_UNDER_TEST: u16 = 0;
+    static mut MODEL_UNDER_TEST: CpuModel = CpuModel::M68000;
+
+    // R68K_QC_MODEL=68010 runs the 68000 tests on the 68010 instead
+    fn model_under_test(model: CpuModel) -> CpuModel {
+        match (model, env::var("R68K_QC_MODEL")) {
+            (CpuModel::M68000, Ok(ref m)) if m == "68010" => CpuModel::M68010,
+            _ => model,
+        }
+    }
+    const TRACE_BIT: u16 = 1 << 15;
 
     fn hammer_cores_even_addresses(memory_pattern: Bitpattern, rs: Vec<(Register, Bitpattern)>) -> TestResult {
         let mem_mask = (2<<24)-2; // keep even
@@ -476,6 +540,18 @@ mod tests {
         hammer_cores_with(mem_mask, memory_pattern, rs, true)
     }
 
+    lazy_static! {
+        static ref OPS_010: Vec<&'static str> = InstructionSetGenerator::<TestCore>::for_model(CpuModel::M68010).generate_with("???", |op| op.name);
+    }
+
+    // opcodes the 68010 doesn't implement
+    fn only_on_68020(opcode: u16) -> bool {
+        match OPS_010[opcode as usize] {
+            "???" | "unimplemented_1010" | "unimplemented_1111" => true,
+            _ => false,
+        }
+    }
+
     fn hammer_cores_with(mem_mask: u32, memory_pattern: Bitpattern, rs: Vec<(Register, Bitpattern)>, allow_exception: bool) -> TestResult {
         let pc = 0x140;
         let mem = unsafe {
@@ -483,6 +559,17 @@ mod tests {
         };
         let Bitpattern(memory_initializer) = memory_pattern;
         let mut musashi = TestCore::new_mem_init(pc, &mem, memory_initializer & mem_mask);
+        unsafe {
+            musashi.set_model(MODEL_UNDER_TEST);
+        }
+        if musashi.model() == CpuModel::M68020 {
+            musashi.mem = LoggingMem::with_address_mask(memory_initializer & mem_mask, OpsLogger::new(), ADDRBUS_MASK_32);
+            musashi.write_program_word(pc, u32::from(unsafe { OPCODE_UNDER_TEST })).unwrap();
+        }
+        // MOVES in user data space, as Musashi would panic on reserved
+        // function codes (see m68k_set_fc)
+        musashi.sfc = 1;
+        musashi.dfc = 1;
         const STACK_MASK:u32 = (1024-16); // keep even
         musashi.inactive_ssp = 0x128;
         musashi.inactive_usp = 0x256;
@@ -526,7 +613,8 @@ mod tests {
                 (Register::A6, Bitpattern(bp)) => musashi.dar[6+8] = bp & mem_mask,
                 (Register::A7, Bitpattern(bp)) => musashi.dar[7+8] = bp & STACK_MASK + 8,
                 (Register::USP, Bitpattern(bp)) => musashi.inactive_usp = bp & STACK_MASK + 8,
-                (Register::SR, Bitpattern(bp)) => musashi.sr_to_flags(bp as u16),
+                // keep tracing off, so each opcode is tested on its own
+                (Register::SR, Bitpattern(bp)) => musashi.sr_to_flags(bp as u16 & !TRACE_BIT),
                 _ => {
                     panic!("No idea how to set {:?}", r.0)
                 },
@@ -552,6 +640,9 @@ mod tests {
                 vector != EXCEPTION_ZERO_DIVIDE && vector != EXCEPTION_CHK
             }
         } else {true};
+        // only the 68020 specific instructions use 68020 timing, the
+        // rest keep their 68000/68010 cycles
+        let can_compare_cycles = can_compare_cycles && (musashi.model() != CpuModel::M68020 || only_on_68020(unsafe { OPCODE_UNDER_TEST }));
         if cores_equal(&musashi, &r68k) {
             if can_compare_cycles && musashi_cycles != r68k_cycles {
                 println!("Musashi {:?} but r68k {:?}", musashi_cycles, r68k_cycles);
@@ -568,9 +659,18 @@ mod tests {
     macro_rules! qc_allow_exception {
         ($opmask:ident, $opcode:ident, $fn_name:ident) => (qc!($opmask, $opcode, $fn_name, hammer_cores_allow_exception););
     }
+    macro_rules! qc_010 {
+        ($opmask:ident, $opcode:ident, $fn_name:ident) => (qc!($opmask, $opcode, $fn_name, hammer_cores_even_addresses, CpuModel::M68010););
+        ($opmask:ident, $opcode:ident, $fn_name:ident, $hammer:ident) => (qc!($opmask, $opcode, $fn_name, $hammer, CpuModel::M68010););
+    }
+    macro_rules! qc_020 {
+        ($opmask:ident, $opcode:ident, $fn_name:ident) => (qc!($opmask, $opcode, $fn_name, hammer_cores, CpuModel::M68020););
+        ($opmask:ident, $opcode:ident, $fn_name:ident, $hammer:ident) => (qc!($opmask, $opcode, $fn_name, $hammer, CpuModel::M68020););
+    }
     macro_rules! qc {
         ($opmask:ident, $opcode:ident, $fn_name:ident) => (qc!($opmask, $opcode, $fn_name, hammer_cores_even_addresses););
-        ($opmask:ident, $opcode:ident, $fn_name:ident, $hammer:ident) => (
+        ($opmask:ident, $opcode:ident, $fn_name:ident, $hammer:ident) => (qc!($opmask, $opcode, $fn_name, $hammer, CpuModel::M68000););
+        ($opmask:ident, $opcode:ident, $fn_name:ident, $hammer:ident, $model:expr) => (
         #[test]
         #[ignore]
             fn $fn_name() {
@@ -589,6 +689,7 @@ mod tests {
                     // we cannot simply use a closure either; see
                     // https://github.com/BurntSushi/quickcheck/issues/56
                     OPCODE_UNDER_TEST = opcode;
+                    MODEL_UNDER_TEST = model_under_test($model);
                 }
                 QuickCheck::new()
                 .gen(StdGen::new(rand::thread_rng(), 256))
@@ -2376,6 +2477,113 @@ mod tests {
     // Put qc for UNLK here
     qc!(MASK_OUT_Y, OP_UNLK_32, qc_unlk_32);
 
+    // 68010 mode. Musashi stacks a 68000 frame for bus and address
+    // errors also in 68010 mode, so those are not compared.
+    qc_010!(MASK_OUT_Y, OP_MOVE_16_FRC_DN, qc_010_move_16_frc_dn);
+    qc_010!(MASK_OUT_Y, OP_MOVE_16_FRC_AI, qc_010_move_16_frc_ai);
+    qc_010!(MASK_OUT_Y, OP_MOVE_16_FRC_PI, qc_010_move_16_frc_pi);
+    qc_010!(MASK_OUT_Y, OP_MOVE_16_FRC_PD, qc_010_move_16_frc_pd);
+    qc_010!(MASK_OUT_Y, OP_MOVE_16_FRC_DI, qc_010_move_16_frc_di);
+    qc_010!(MASK_OUT_Y, OP_MOVE_16_FRC_IX, qc_010_move_16_frc_ix);
+    qc_010!(MASK_EXACT, OP_MOVE_16_FRC_AW, qc_010_move_16_frc_aw);
+    qc_010!(MASK_EXACT, OP_MOVE_16_FRC_AL, qc_010_move_16_frc_al);
+
+    qc_010!(MASK_OUT_Y, OP_MOVE_16_FRS_DN, qc_010_move_16_frs_dn, hammer_cores_allow_exception);
+    qc_010!(MASK_OUT_Y, OP_MOVE_16_FRS_AI, qc_010_move_16_frs_ai, hammer_cores_allow_exception);
+    qc_010!(MASK_EXACT, OP_MOVE_16_FRS_AL, qc_010_move_16_frs_al, hammer_cores_allow_exception);
+
+    qc_010!(MASK_EXACT, OP_MOVEC_32_CR, qc_010_movec_32_cr, hammer_cores_allow_exception);
+    qc_010!(MASK_EXACT, OP_MOVEC_32_RC, qc_010_movec_32_rc, hammer_cores_allow_exception);
+
+    qc_010!(MASK_OUT_Y, OP_MOVES_8_AI, qc_010_moves_8_ai);
+    qc_010!(MASK_OUT_Y, OP_MOVES_8_PI, qc_010_moves_8_pi);
+    qc_010!(MASK_OUT_Y, OP_MOVES_8_PD, qc_010_moves_8_pd);
+    qc_010!(MASK_OUT_Y, OP_MOVES_8_DI, qc_010_moves_8_di);
+    qc_010!(MASK_OUT_Y, OP_MOVES_8_IX, qc_010_moves_8_ix);
+    qc_010!(MASK_EXACT, OP_MOVES_8_AW, qc_010_moves_8_aw);
+    qc_010!(MASK_EXACT, OP_MOVES_8_AL, qc_010_moves_8_al);
+    qc_010!(MASK_OUT_Y, OP_MOVES_16_AI, qc_010_moves_16_ai);
+    qc_010!(MASK_OUT_Y, OP_MOVES_16_PI, qc_010_moves_16_pi);
+    qc_010!(MASK_OUT_Y, OP_MOVES_16_PD, qc_010_moves_16_pd);
+    qc_010!(MASK_OUT_Y, OP_MOVES_16_DI, qc_010_moves_16_di);
+    qc_010!(MASK_OUT_Y, OP_MOVES_16_IX, qc_010_moves_16_ix);
+    qc_010!(MASK_EXACT, OP_MOVES_16_AW, qc_010_moves_16_aw);
+    qc_010!(MASK_EXACT, OP_MOVES_16_AL, qc_010_moves_16_al);
+    qc_010!(MASK_OUT_Y, OP_MOVES_32_AI, qc_010_moves_32_ai);
+    qc_010!(MASK_OUT_Y, OP_MOVES_32_PI, qc_010_moves_32_pi);
+    qc_010!(MASK_OUT_Y, OP_MOVES_32_PD, qc_010_moves_32_pd);
+    qc_010!(MASK_OUT_Y, OP_MOVES_32_DI, qc_010_moves_32_di);
+    qc_010!(MASK_OUT_Y, OP_MOVES_32_IX, qc_010_moves_32_ix);
+    qc_010!(MASK_EXACT, OP_MOVES_32_AW, qc_010_moves_32_aw);
+    qc_010!(MASK_EXACT, OP_MOVES_32_AL, qc_010_moves_32_al);
+
+    qc_010!(MASK_EXACT, OP_RTD_32, qc_010_rtd_32);
+    qc_010!(MASK_EXACT, OP_RTE_32, qc_010_rte_32, hammer_cores_allow_exception);
+
+    qc_010!(MASK_EXACT, OP_ILLEGAL, qc_010_illegal, hammer_cores_allow_exception);
+    qc_010!(MASK_LONIB, OP_TRAP, qc_010_trap, hammer_cores_allow_exception);
+    qc_010!(MASK_EXACT, OP_TRAPV, qc_010_trapv, hammer_cores_allow_exception);
+    qc_010!(MASK_LOBYTE_QUICKER, OP_BHI_8, qc_010_bhi_8, hammer_cores);
+    qc_010!(MASK_EXACT, OP_BHI_16, qc_010_bhi_16);
+    qc_010!(MASK_OUT_Y, OP_DBHI_16, qc_010_dbhi_16);
+    qc_010!(MASK_OUT_Y, OP_SHI_8_DN, qc_010_shi_8_dn);
+    qc_010!(MASK_OUT_X_Y, OP_DIVS_16_DN, qc_010_divs_16_dn);
+    qc_010!(MASK_OUT_X_Y, OP_DIVU_16_DN, qc_010_divu_16_dn);
+    qc_010!(MASK_EXACT, OP_RESET, qc_010_reset, hammer_cores);
+    // instructions with their own 68010 timing
+    qc_010!(MASK_OUT_X, OP_ADD_16_ER_IMM, qc_010_add_16_er_imm);
+    qc_010!(MASK_OUT_X, OP_SUBA_32_IMM, qc_010_suba_32_imm);
+    qc_010!(MASK_OUT_Y, OP_ORI_32_DN, qc_010_ori_32_dn);
+    qc_010!(MASK_OUT_Y, OP_CMPI_32_DN, qc_010_cmpi_32_dn);
+    qc_010!(MASK_OUT_X_Y, OP_EOR_32_DN, qc_010_eor_32_dn);
+    qc_010!(MASK_OUT_X_Y, OP_ADDX_32_RR, qc_010_addx_32_rr);
+    qc_010!(MASK_OUT_X_Y, OP_SUBQ_16_AN, qc_010_subq_16_an);
+    qc_010!(MASK_EXACT, OP_EORI_8_TOC, qc_010_eori_8_toc);
+    qc_010!(MASK_EXACT, OP_ANDI_16_TOS, qc_010_andi_16_tos);
+    qc_010!(MASK_OUT_X_Y, OP_BCLR_8_R_PD, qc_010_bclr_8_r_pd);
+    qc_010!(MASK_OUT_X_Y, OP_CHK_16_AI, qc_010_chk_16_ai, hammer_cores_allow_exception);
+    qc_010!(MASK_OUT_Y, OP_CLR_8_PI, qc_010_clr_8_pi);
+    qc_010!(MASK_OUT_Y, OP_CLR_16_IX, qc_010_clr_16_ix);
+    qc_010!(MASK_OUT_Y, OP_CLR_32_AI, qc_010_clr_32_ai);
+    qc_010!(MASK_EXACT, OP_CLR_32_AL, qc_010_clr_32_al);
+    qc_010!(MASK_OUT_X_Y, OP_DIVU_16_DI, qc_010_divu_16_di);
+    qc_010!(MASK_OUT_X_Y, OP_MULS_16_DN, qc_010_muls_16_dn);
+    qc_010!(MASK_OUT_X_Y, OP_MULU_16_PD, qc_010_mulu_16_pd);
+    qc_010!(MASK_OUT_X_Y, OP_MOVE_32_PD_DN, qc_010_move_32_pd_dn);
+    qc_010!(MASK_OUT_X, OP_MOVE_32_PD_PCDI, qc_010_move_32_pd_pcdi);
+    qc_010!(MASK_OUT_Y, OP_MOVE_32_TOU, qc_010_move_32_tou);
+    qc_010!(MASK_OUT_Y, OP_MOVE_32_FRU, qc_010_move_32_fru);
+
+    qc_020!(MASK_OUT_Y, OP_EXT_BL, qc_020_ext_bl);
+    qc_020!(MASK_OUT_Y, OP_LINK_32, qc_020_link_32);
+    qc_020!(MASK_EXACT, OP_TRAPEQ, qc_020_trapeq, hammer_cores_allow_exception);
+    qc_020!(MASK_EXACT, OP_TRAPNE_16, qc_020_trapne_16, hammer_cores_allow_exception);
+    qc_020!(MASK_EXACT, OP_TRAPMI_32, qc_020_trapmi_32, hammer_cores_allow_exception);
+    qc_020!(MASK_OUT_X_Y, OP_PACK_16_RR, qc_020_pack_16_rr);
+    qc_020!(MASK_OUT_X_Y, OP_PACK_16_MM, qc_020_pack_16_mm);
+    qc_020!(MASK_OUT_X_Y, OP_UNPK_16_RR, qc_020_unpk_16_rr);
+    qc_020!(MASK_OUT_X_Y, OP_UNPK_16_MM, qc_020_unpk_16_mm);
+    qc_020!(MASK_OUT_Y, OP_MULL_32_DN, qc_020_mull_32_dn);
+    qc_020!(MASK_OUT_Y, OP_MULL_32_AI, qc_020_mull_32_ai);
+    qc_020!(MASK_OUT_Y, OP_DIVL_32_DN, qc_020_divl_32_dn, hammer_cores_allow_exception);
+    qc_020!(MASK_EXACT, OP_DIVL_32_IMM, qc_020_divl_32_imm, hammer_cores_allow_exception);
+    qc_020!(MASK_OUT_Y, OP_CAS_8_PI, qc_020_cas_8_pi);
+    qc_020!(MASK_OUT_Y, OP_CAS_32_AI, qc_020_cas_32_ai);
+    qc_020!(MASK_EXACT, OP_CAS2_16, qc_020_cas2_16);
+    qc_020!(MASK_EXACT, OP_CAS2_32, qc_020_cas2_32);
+    qc_020!(MASK_OUT_Y, OP_CHK2_8_AI, qc_020_chk2_8_ai, hammer_cores_allow_exception);
+    qc_020!(MASK_OUT_Y, OP_CHK2_32_DI, qc_020_chk2_32_di, hammer_cores_allow_exception);
+    qc_020!(MASK_OUT_Y, OP_BFTST_32_DN, qc_020_bftst_32_dn);
+    qc_020!(MASK_OUT_Y, OP_BFEXTU_32_DN, qc_020_bfextu_32_dn);
+    qc_020!(MASK_OUT_Y, OP_BFEXTS_32_AI, qc_020_bfexts_32_ai);
+    qc_020!(MASK_OUT_Y, OP_BFCHG_32_DI, qc_020_bfchg_32_di);
+    qc_020!(MASK_OUT_Y, OP_BFFFO_32_DN, qc_020_bfffo_32_dn);
+    qc_020!(MASK_OUT_Y, OP_BFINS_32_AI, qc_020_bfins_32_ai);
+    qc_020!(MASK_OUT_X_Y, OP_LEA_32_IX, qc_020_lea_32_ix);
+    qc_020!(MASK_OUT_X_Y, OP_MOVE_32_IX_DN, qc_020_move_32_ix_dn);
+    qc_020!(MASK_EXACT, OP_MOVEC_32_CR, qc_020_movec_32_cr, hammer_cores_allow_exception);
+    qc_020!(MASK_EXACT, OP_RTE_32, qc_020_rte_32, hammer_cores_allow_exception);
+
     // OP completeness test, run once through every opcode
     const BLOCK_MASK : u32 = 0b1111_1100_0000_0000;
     const BLOCK_SIZE : u32 = 0b0000_0100_0000_0000;
@@ -2556,7 +2764,7 @@ mod tests {
             }
         })
     }
-    fn assert_all_memory_accesses_equal(r68k: &TestCore) {
+    fn assert_all_memory_accesses_equal<T: InterruptController>(r68k: &ConfiguredCore<T, LoggingMem<OpsLogger>>) {
         assert_equal(get_ops(), r68k.mem.logger.ops());
     }
     fn memory_accesses_equal_unless_exception(r68k: &TestCore) -> Option<u8> {
@@ -2586,7 +2794,7 @@ mod tests {
             None
         }
     }
-    fn cores_equal(musashi: &TestCore, r68k: &TestCore) -> bool {
+    fn cores_equal<T: InterruptController>(musashi: &TestCore, r68k: &ConfiguredCore<T, LoggingMem<OpsLogger>>) -> bool {
         core_eq!(musashi, r68k.pc);
         core_eq!(musashi, r68k.flags() ?);
         core_eq!(musashi, r68k.status_register());
@@ -2595,10 +2803,16 @@ mod tests {
         for i in (0..16).rev() {
             core_eq!(musashi, r68k.dar[i]);
         }
+        core_eq!(musashi, r68k.vbr);
+        core_eq!(musashi, r68k.sfc);
+        core_eq!(musashi, r68k.dfc);
+        core_eq!(musashi, r68k.msp());
+        core_eq!(musashi, r68k.cacr);
+        core_eq!(musashi, r68k.caar);
         true
     }
 
-    fn assert_cores_equal(musashi: &TestCore, r68k: &TestCore) {
+    fn assert_cores_equal<T: InterruptController>(musashi: &TestCore, r68k: &ConfiguredCore<T, LoggingMem<OpsLogger>>) {
         assert_all_memory_accesses_equal(r68k);
         assert!(cores_equal(musashi, r68k));
     }
@@ -2688,8 +2902,8 @@ mod tests {
         // opcodes d278,0107 is ADD.W    $0107, D1
         let mut musashi = TestCore::new_mem(0x40, &[0xd2, 0x78, 0x01, 0x07]);
         let vec3handler = 0x1F0000;
-        musashi.mem.write_long(SUPERVISOR_PROGRAM, 3*4, vec3handler);
-        musashi.mem.write_word(SUPERVISOR_PROGRAM, vec3handler, OP_NOP);
+        musashi.mem.write_long(SUPERVISOR_PROGRAM, 3*4, vec3handler).unwrap();
+        musashi.mem.write_word(SUPERVISOR_PROGRAM, vec3handler, OP_NOP).unwrap();
         musashi.dar[15] = 0x100;
         let mut r68k = musashi.clone(); // so very self-aware!
         initialize_musashi(&mut musashi, 0xaaaaaaaa);
@@ -2706,8 +2920,8 @@ mod tests {
         // d208 is ADD.B A0,D0, which is illegal
         let mut musashi = TestCore::new_mem(0x4000, &[0xd2, 08]);
         let vec4handler = 0x2F0000;
-        musashi.mem.write_long(SUPERVISOR_PROGRAM, 4*4, vec4handler);
-        musashi.mem.write_long(SUPERVISOR_PROGRAM, vec4handler, 0xd2780108);
+        musashi.mem.write_long(SUPERVISOR_PROGRAM, 4*4, vec4handler).unwrap();
+        musashi.mem.write_long(SUPERVISOR_PROGRAM, vec4handler, 0xd2780108).unwrap();
         musashi.dar[15] = 0x100;
         let mut r68k = musashi.clone(); // so very self-aware!
         initialize_musashi(&mut musashi, 0xaaaaaaaa);
@@ -2719,6 +2933,52 @@ mod tests {
         assert_cores_equal(&musashi, &r68k);
     }
 
+    // The trace tests depend on Musashi being built with M68K_EMULATE_TRACE
+    fn compare_traced(code: &[u8], sr: u16) {
+        let _mutex = MUSASHI_LOCK.lock().unwrap();
+
+        let mut musashi = TestCore::new_mem(0x4000, code);
+        let vec9handler = 0x2F0000;
+        let vec4handler = 0x1F0000;
+        musashi.mem.write_long(SUPERVISOR_PROGRAM, EXCEPTION_TRACE as u32 * 4, vec9handler).unwrap();
+        musashi.mem.write_long(SUPERVISOR_PROGRAM, EXCEPTION_ILLEGAL_INSTRUCTION as u32 * 4, vec4handler).unwrap();
+        musashi.mem.write_word(SUPERVISOR_PROGRAM, vec9handler, OP_NOP).unwrap();
+        musashi.mem.write_word(SUPERVISOR_PROGRAM, vec4handler, OP_NOP).unwrap();
+        musashi.sr_to_flags(sr);
+        musashi.dar[15] = 0x100;
+        let mut r68k = musashi.clone(); // so very self-aware!
+        initialize_musashi(&mut musashi, 0xaaaaaaaa);
+        let musashi_cycles = execute1(&mut musashi);
+        let r68k_cycles = r68k.execute1();
+
+        assert_eq!(musashi_cycles, r68k_cycles);
+        assert_cores_equal(&musashi, &r68k);
+    }
+
+    #[test]
+    fn compare_trace_exception_actions() {
+        // opcodes d278,0108 is ADD.W    $0108, D1
+        compare_traced(&[0xd2, 0x78, 0x01, 0x08], TRACE_BIT | 0x2700);
+    }
+
+    #[test]
+    fn compare_user_mode_trace_exception_actions() {
+        // opcodes d278,0108 is ADD.W    $0108, D1
+        compare_traced(&[0xd2, 0x78, 0x01, 0x08], TRACE_BIT | 0x0700);
+    }
+
+    #[test]
+    fn compare_untraced_illegal_instruction_actions() {
+        // d208 is ADD.B A0,D0, which is illegal
+        compare_traced(&[0xd2, 0x08], TRACE_BIT | 0x2700);
+    }
+
+    #[test]
+    fn compare_traced_instruction_clearing_trace_bit() {
+        // 46fc 2700 is MOVE #$2700, SR
+        compare_traced(&[0x46, 0xfc, 0x27, 0x00], TRACE_BIT | 0x2700);
+    }
+
 use std::ptr;
 use super::m68k_get_reg;
 
@@ -3020,9 +3280,9 @@ use ram::{SUPERVISOR_DATA, USER_PROGRAM, USER_DATA, ADDRBUS_MASK};
         musashi.sr_to_flags(supervisor_bit | irq_mask);
         let vec4handler = 0x2F0000;
         let autovector_base = 24;
-        musashi.mem.write_long(SUPERVISOR_PROGRAM, (autovector_base + irq) * 4 , vec4handler);
+        musashi.mem.write_long(SUPERVISOR_PROGRAM, (autovector_base + irq) * 4 , vec4handler).unwrap();
         // opcodes d278,0108 is ADD.W    $0108, D1
-        musashi.mem.write_long(SUPERVISOR_PROGRAM, vec4handler, 0xd2780108);
+        musashi.mem.write_long(SUPERVISOR_PROGRAM, vec4handler, 0xd2780108).unwrap();
         musashi.dar[15] = 0x100;
         let mut r68k = musashi.clone(); // so very self-aware!
         initialize_musashi(&mut musashi, 0xaaaaaaaa);
@@ -3043,4 +3303,96 @@ use ram::{SUPERVISOR_DATA, USER_PROGRAM, USER_DATA, ADDRBUS_MASK};
         }
         assert_cores_equal(&musashi, &r68k);
     }
+
+    use interrupts::{VectoredInterruptController, UNINITIALIZED_INTERRUPT, SPURIOUS_INTERRUPT};
+    use super::{set_musashi_int_ack, MUSASHI_INT_ACK_SPURIOUS};
+
+    #[test]
+    fn takes_device_supplied_vector() {
+        test_vectored_interrupt(Some(0x40), 0x40);
+    }
+
+    #[test]
+    fn takes_uninitialized_vector_from_device_without_vector() {
+        test_vectored_interrupt(None, UNINITIALIZED_INTERRUPT as i32);
+    }
+
+    #[test]
+    fn takes_spurious_vector_when_no_device_answers() {
+        let _mutex = MUSASHI_LOCK.lock().unwrap();
+
+        let (mut musashi, r68k) = interrupted_cores(SPURIOUS_INTERRUPT);
+        // a device that withdraws its request before the acknowledge cycle
+        // leaves the level asserted, but nobody answering
+        struct Withdrawn;
+        impl InterruptController for Withdrawn {
+            fn reset_external_devices(&mut self) {}
+            fn highest_priority(&self) -> u8 { 5 }
+            fn acknowledge_interrupt(&mut self, _priority: u8) -> Option<u8> { None }
+        }
+        let mut r68k = with_int_ctrl(&r68k, Withdrawn);
+        initialize_musashi(&mut musashi, 0xaaaaaaaa);
+        set_musashi_int_ack(MUSASHI_INT_ACK_SPURIOUS);
+        unsafe {
+            m68k_set_irq(5);
+        }
+        let musashi_cycles = execute1(&mut musashi);
+        let mut r68k_cycles = r68k.execute1();
+        if musashi_cycles > r68k_cycles {
+            r68k_cycles = r68k_cycles + r68k.execute1();
+        }
+        assert_eq!(musashi_cycles, r68k_cycles);
+        assert_cores_equal(&musashi, &r68k);
+    }
+
+    // Sets up cores at a sequence of ADD.W instructions, with a handler
+    // (also starting with an ADD.W) installed for the given vector
+    fn interrupted_cores(vector: u8) -> (TestCore, TestCore) {
+        // opcodes d278,0108 is ADD.W    $0108, D1
+        let mut musashi = TestCore::new_mem(0x4000, &[0xd2, 0x78, 0x01, 0x08, 0xd2, 0x78, 0x01, 0x08, 0xd2, 0x78, 0x01, 0x08]);
+        musashi.sr_to_flags(1 << 13);
+        let handler = 0x2F0000;
+        musashi.mem.write_long(SUPERVISOR_PROGRAM, u32::from(vector) * 4, handler).unwrap();
+        musashi.mem.write_long(SUPERVISOR_PROGRAM, handler, 0xd2780108).unwrap();
+        musashi.dar[15] = 0x100;
+        let r68k = musashi.clone();
+        (musashi, r68k)
+    }
+
+    fn with_int_ctrl<T: InterruptController>(core: &TestCore, int_ctrl: T) -> ConfiguredCore<T, LoggingMem<OpsLogger>> {
+        let mut mem = LoggingMem::new(core.mem.initializer, OpsLogger::new());
+        mem.copy_from(&core.mem);
+        let mut r68k = ConfiguredCore::new_with(core.pc, int_ctrl, mem);
+        r68k.processing_state = core.processing_state;
+        r68k.dar = core.dar;
+        r68k.inactive_ssp = core.inactive_ssp;
+        r68k.inactive_usp = core.inactive_usp;
+        r68k.sr_to_flags(core.status_register());
+        r68k
+    }
+
+    fn test_vectored_interrupt(device_vector: Option<u8>, musashi_vector: i32) {
+        let _mutex = MUSASHI_LOCK.lock().unwrap();
+
+        let (mut musashi, r68k) = interrupted_cores(musashi_vector as u8);
+        let mut int_ctrl = VectoredInterruptController::new();
+        let device = int_ctrl.add_device(5);
+        int_ctrl.set_vector(device, device_vector);
+        let mut r68k = with_int_ctrl(&r68k, int_ctrl);
+        initialize_musashi(&mut musashi, 0xaaaaaaaa);
+        set_musashi_int_ack(musashi_vector);
+        unsafe {
+            m68k_set_irq(5);
+        }
+        let musashi_cycles = execute1(&mut musashi);
+        r68k.int_ctrl.request_interrupt(device);
+        let mut r68k_cycles = r68k.execute1();
+        if musashi_cycles > r68k_cycles {
+            r68k_cycles = r68k_cycles + r68k.execute1(); // Musashi also executes the first instruction of the handler
+        }
+        assert_eq!(musashi_cycles, r68k_cycles);
+        assert!(r68k_cycles > Cycles(40));
+        assert!(!r68k.int_ctrl.is_pending(device));
+        assert_cores_equal(&musashi, &r68k);
+    }
 }
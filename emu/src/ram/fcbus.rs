@@ -0,0 +1,317 @@
+use std::cell::RefCell;
+use super::{AddressSpace, AddressBus, BusError, ADDRBUS_MASK};
+
+#[derive(Copy, Clone, Debug, Hash, Eq, PartialEq)]
+pub struct StoreId(usize);
+
+#[derive(Copy, Clone, Debug, Hash, Eq, PartialEq)]
+pub struct ProtectionId(usize);
+
+// An access refused by a protected range, as passed to the violation
+// handler. The value is that of a write, or None for a read.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Violation {
+    pub address_space: AddressSpace,
+    pub address: u32,
+    pub size: u32,
+    pub value: Option<u32>,
+}
+
+// Decides what happens to a violating access: Ok lets it through to the
+// backing store anyway, Err ends it in a bus error
+pub type ViolationHandler = Box<dyn FnMut(Violation) -> Result<(), BusError> + Send>;
+
+struct Protection {
+    id: ProtectionId,
+    start: u32,
+    len: u32,
+    // bit n set if function code n may read or write the range
+    readable: u8,
+    writable: u8,
+}
+
+impl Protection {
+    fn overlaps(&self, address: u32, bytes: u32) -> bool {
+        address.wrapping_sub(self.start) < self.len
+            || self.start.wrapping_sub(address) < bytes
+    }
+}
+
+// A bus decoding the function codes, as the MMU-less UNIX boxes of the
+// early eighties did. The rules are:
+// - each function code is routed to one of several backing stores, so
+//   user program and data can live in separate address spaces; all
+//   function codes start out on the store the bus was created with
+// - protected ranges only let the given function codes read or write
+//   them; an access touching a range it may not use is a violation
+// - a violation ends in a bus error, unless a violation handler has been
+//   installed, which decides per access
+// - changing routes or protections changes the map generation, so
+//   block caches are flushed
+pub struct FcBus<B: AddressBus> {
+    stores: Vec<B>,
+    // store index for each function code
+    routes: [usize; 8],
+    protections: Vec<Protection>,
+    next_id: usize,
+    // reads only borrow the bus immutably, but the handler is FnMut
+    on_violation: RefCell<Option<ViolationHandler>>,
+    address_mask: u32,
+    // bumped by every change of the routes and protections
+    generation: u32,
+}
+
+impl<B: AddressBus> FcBus<B> {
+    pub fn new(store: B) -> FcBus<B> {
+        FcBus::with_address_mask(store, ADDRBUS_MASK)
+    }
+    // protected ranges and accesses are masked to the bus width, the
+    // backing stores see the masked addresses
+    pub fn with_address_mask(store: B, address_mask: u32) -> FcBus<B> {
+        FcBus {
+            stores: vec![store],
+            routes: [0; 8],
+            protections: Vec::new(),
+            next_id: 0,
+            on_violation: RefCell::new(None),
+            address_mask,
+            generation: 0,
+        }
+    }
+
+    pub fn add_store(&mut self, store: B) -> StoreId {
+        self.stores.push(store);
+        StoreId(self.stores.len() - 1)
+    }
+    // the store the bus was created with
+    pub fn default_store(&self) -> StoreId {
+        StoreId(0)
+    }
+    pub fn route(&mut self, address_space: AddressSpace, store: StoreId) {
+        assert!(store.0 < self.stores.len(), "no such store");
+        self.routes[address_space.fc() as usize] = store.0;
+        self.remapped();
+    }
+    pub fn store(&self, id: StoreId) -> &B {
+        &self.stores[id.0]
+    }
+    pub fn store_mut(&mut self, id: StoreId) -> &mut B {
+        &mut self.stores[id.0]
+    }
+
+    // only let the given address spaces (function codes) read or write
+    // len bytes from start
+    pub fn protect(&mut self, start: u32, len: u32, readable: &[AddressSpace], writable: &[AddressSpace]) -> ProtectionId {
+        assert!(len > 0, "cannot protect an empty range");
+        let id = ProtectionId(self.next_id);
+        self.next_id += 1;
+        self.protections.push(Protection {
+            id, start: start & self.address_mask, len,
+            readable: AddressSpace::fc_bits(readable), writable: AddressSpace::fc_bits(writable),
+        });
+        self.remapped();
+        id
+    }
+    pub fn unprotect(&mut self, id: ProtectionId) -> bool {
+        let before = self.protections.len();
+        self.protections.retain(|p| p.id != id);
+        self.remapped();
+        before != self.protections.len()
+    }
+    pub fn on_violation(&mut self, handler: ViolationHandler) {
+        *self.on_violation.get_mut() = Some(handler);
+    }
+
+    fn remapped(&mut self) {
+        self.generation = self.generation.wrapping_add(1);
+    }
+    fn check(&self, address_space: AddressSpace, address: u32, size: u32, value: Option<u32>) -> Result<(), BusError> {
+        let fc = address_space.fc_bit();
+        let allowed = self.protections.iter()
+            .filter(|p| p.overlaps(address, size))
+            .all(|p| if value.is_some() { p.writable & fc != 0 } else { p.readable & fc != 0 });
+        if allowed {
+            return Ok(());
+        }
+        match *self.on_violation.borrow_mut() {
+            Some(ref mut handler) => handler(Violation { address_space, address, size, value }),
+            None => Err(BusError),
+        }
+    }
+    fn store_for(&self, address_space: AddressSpace) -> &B {
+        &self.stores[self.routes[address_space.fc() as usize]]
+    }
+    fn store_for_mut(&mut self, address_space: AddressSpace) -> &mut B {
+        &mut self.stores[self.routes[address_space.fc() as usize]]
+    }
+}
+
+impl<B: AddressBus> AddressBus for FcBus<B> {
+    // copies the stores between buses with the same number of them;
+    // routes and protections are left alone
+    fn copy_from(&mut self, other: &Self) {
+        for (store, other) in self.stores.iter_mut().zip(other.stores.iter()) {
+            store.copy_from(other);
+        }
+    }
+
+    fn read_byte(&self, address_space: AddressSpace, address: u32) -> Result<u32, BusError> {
+        let address = address & self.address_mask;
+        self.check(address_space, address, 1, None)?;
+        self.store_for(address_space).read_byte(address_space, address)
+    }
+
+    fn read_word(&self, address_space: AddressSpace, address: u32) -> Result<u32, BusError> {
+        let address = address & self.address_mask;
+        self.check(address_space, address, 2, None)?;
+        self.store_for(address_space).read_word(address_space, address)
+    }
+
+    fn read_long(&self, address_space: AddressSpace, address: u32) -> Result<u32, BusError> {
+        let address = address & self.address_mask;
+        self.check(address_space, address, 4, None)?;
+        self.store_for(address_space).read_long(address_space, address)
+    }
+
+    fn write_byte(&mut self, address_space: AddressSpace, address: u32, value: u32) -> Result<(), BusError> {
+        let address = address & self.address_mask;
+        self.check(address_space, address, 1, Some(value))?;
+        self.store_for_mut(address_space).write_byte(address_space, address, value)
+    }
+
+    fn write_word(&mut self, address_space: AddressSpace, address: u32, value: u32) -> Result<(), BusError> {
+        let address = address & self.address_mask;
+        self.check(address_space, address, 2, Some(value))?;
+        self.store_for_mut(address_space).write_word(address_space, address, value)
+    }
+
+    fn write_long(&mut self, address_space: AddressSpace, address: u32, value: u32) -> Result<(), BusError> {
+        let address = address & self.address_mask;
+        self.check(address_space, address, 4, Some(value))?;
+        self.store_for_mut(address_space).write_long(address_space, address, value)
+    }
+
+    fn translated(&self, address_space: AddressSpace, logical: u32, physical: u32) {
+        self.store_for(address_space).translated(address_space, logical, physical);
+    }
+
+    fn map_generation(&self) -> u32 {
+        self.stores.iter().fold(self.generation, |generation, store| generation.wrapping_add(store.map_generation()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use super::{FcBus, Violation};
+    use ram::{AddressBus, AddressSpace, BusError, PagedMem, SUPERVISOR_DATA, SUPERVISOR_PROGRAM, USER_DATA, USER_PROGRAM};
+    use cpu::ConfiguredCore;
+    use interrupts::AutoInterruptController;
+
+    const SUPERVISOR: &[AddressSpace] = &[SUPERVISOR_DATA, SUPERVISOR_PROGRAM];
+    const ALL: &[AddressSpace] = &[SUPERVISOR_DATA, SUPERVISOR_PROGRAM, USER_DATA, USER_PROGRAM];
+
+    #[test]
+    fn unprotected_bus_passes_accesses_through() {
+        let mut bus = FcBus::new(PagedMem::new(0));
+        bus.write_long(USER_DATA, 0x1000, 0x01020304).unwrap();
+        assert_eq!(0x01020304, bus.read_long(SUPERVISOR_PROGRAM, 0x1000).unwrap());
+    }
+
+    #[test]
+    fn user_write_to_supervisor_range_is_a_bus_error() {
+        let mut bus = FcBus::new(PagedMem::new(0));
+        bus.protect(0, 0x400, SUPERVISOR, SUPERVISOR);
+        assert_eq!(Ok(()), bus.write_long(SUPERVISOR_DATA, 0x100, 0x1234));
+        assert_eq!(Err(BusError), bus.write_long(USER_DATA, 0x100, 0x5678));
+        assert_eq!(Err(BusError), bus.read_word(USER_PROGRAM, 0x3fe));
+        assert_eq!(0x1234, bus.read_long(SUPERVISOR_DATA, 0x100).unwrap());
+        assert_eq!(Ok(0), bus.read_word(USER_DATA, 0x400));
+    }
+
+    #[test]
+    fn access_straddling_protected_range_is_refused() {
+        let mut bus = FcBus::new(PagedMem::new(0));
+        bus.protect(0x100, 0x100, SUPERVISOR, SUPERVISOR);
+        assert_eq!(Err(BusError), bus.read_long(USER_DATA, 0xfe));
+        assert_eq!(Err(BusError), bus.write_word(USER_DATA, 0x1ff, 0));
+        assert_eq!(Ok(0), bus.read_word(USER_DATA, 0xfe));
+    }
+
+    #[test]
+    fn read_only_range_refuses_writes() {
+        let mut bus = FcBus::new(PagedMem::new(0));
+        let rom = bus.protect(0, 0x100, ALL, &[]);
+        assert_eq!(Err(BusError), bus.write_byte(SUPERVISOR_DATA, 0x10, 1));
+        assert_eq!(Ok(0), bus.read_byte(USER_PROGRAM, 0x10));
+        assert!(bus.unprotect(rom));
+        assert_eq!(Ok(()), bus.write_byte(SUPERVISOR_DATA, 0x10, 1));
+        assert!(!bus.unprotect(rom));
+    }
+
+    #[test]
+    fn function_codes_route_to_separate_stores() {
+        let mut bus = FcBus::new(PagedMem::new(0));
+        let user_program = bus.add_store(PagedMem::new(0));
+        let user_data = bus.add_store(PagedMem::new(0));
+        bus.route(USER_PROGRAM, user_program);
+        bus.route(USER_DATA, user_data);
+        bus.write_word(USER_DATA, 0x100, 0xaaaa).unwrap();
+        bus.write_word(SUPERVISOR_DATA, 0x100, 0x5555).unwrap();
+        assert_eq!(0xaaaa, bus.read_word(USER_DATA, 0x100).unwrap());
+        assert_eq!(0x5555, bus.read_word(SUPERVISOR_PROGRAM, 0x100).unwrap());
+        assert_eq!(0, bus.read_word(USER_PROGRAM, 0x100).unwrap());
+        bus.store_mut(user_program).write_word(USER_PROGRAM, 0x100, 0x4e71).unwrap();
+        assert_eq!(0x4e71, bus.read_word(USER_PROGRAM, 0x100).unwrap());
+        assert_eq!(0xaaaa, bus.store(user_data).read_word(USER_DATA, 0x100).unwrap());
+    }
+
+    #[test]
+    fn rerouting_flushes_the_block_cache() {
+        // 7001 60fc is MOVEQ #1, D0 and BRA.S back to it, at $1000 in the
+        // default store, and 7002 60fc the same with #2 in the other
+        let store = |code| {
+            let mut store = PagedMem::new(0);
+            store.write_long(SUPERVISOR_DATA, 0, 0x800).unwrap();
+            store.write_long(SUPERVISOR_DATA, 4, 0x1000).unwrap();
+            store.write_long(SUPERVISOR_DATA, 0x1000, code).unwrap();
+            store
+        };
+        let mut bus = FcBus::new(store(0x700160fc));
+        let other = bus.add_store(store(0x700260fc));
+        let mut cpu = ConfiguredCore::new_with(0, AutoInterruptController::new(), bus);
+        cpu.set_block_cache(true);
+        cpu.reset();
+        cpu.execute(100);
+        assert_eq!(1, cpu.dar[0]);
+        cpu.mem.route(SUPERVISOR_PROGRAM, other);
+        cpu.execute(100);
+        assert_eq!(2, cpu.dar[0]);
+    }
+
+    #[test]
+    fn violation_handler_sees_refused_accesses() {
+        let mut bus = FcBus::new(PagedMem::new(0));
+        bus.protect(0, 0x400, SUPERVISOR, SUPERVISOR);
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let log = seen.clone();
+        // log the access, and let reads through
+        bus.on_violation(Box::new(move |violation| {
+            log.lock().unwrap().push(violation);
+            if violation.value.is_some() { Err(BusError) } else { Ok(()) }
+        }));
+        assert_eq!(Err(BusError), bus.write_word(USER_DATA, 0x10, 0xbeef));
+        assert_eq!(Ok(0), bus.read_byte(USER_PROGRAM, 0x20));
+        assert_eq!(vec![
+            Violation { address_space: USER_DATA, address: 0x10, size: 2, value: Some(0xbeef) },
+            Violation { address_space: USER_PROGRAM, address: 0x20, size: 1, value: None },
+        ], *seen.lock().unwrap());
+    }
+
+    #[test]
+    fn addresses_are_limited_by_the_address_bus() {
+        let mut bus = FcBus::new(PagedMem::new(0));
+        bus.protect(0x100, 0x10, SUPERVISOR, SUPERVISOR);
+        assert_eq!(Err(BusError), bus.read_byte(USER_DATA, 0xff000100));
+    }
+}
@@ -0,0 +1,302 @@
+use super::{AddressSpace, AddressBus, BusError, ADDRBUS_MASK};
+
+// Storage is allocated in large pages, on the first write of a value
+// differing from the initializer
+const PAGE_SHIFT: u32 = 16;
+const PAGE_SIZE: usize = 1 << PAGE_SHIFT;
+// but differences are tracked in chunks of the same size as the pages of
+// PagedMem, so that diffs() yields the same bytes as it would there
+const CHUNK_SIZE: usize = 16;
+const CHUNKS: usize = PAGE_SIZE / CHUNK_SIZE;
+
+struct Page {
+    bytes: Box<[u8]>,
+    // a bit per chunk written with a value differing from the initializer
+    dirty: Box<[u64]>,
+}
+
+impl Page {
+    fn new(initializer: u32) -> Page {
+        let pattern = initializer.to_be_bytes();
+        let bytes: Vec<u8> = (0..PAGE_SIZE).map(|offset| pattern[offset % 4]).collect();
+        Page { bytes: bytes.into_boxed_slice(), dirty: vec![0u64; CHUNKS / 64].into_boxed_slice() }
+    }
+    fn is_dirty(&self, chunk: usize) -> bool {
+        self.dirty[chunk / 64] & (1 << (chunk % 64)) != 0
+    }
+    fn set_dirty(&mut self, chunk: usize) {
+        self.dirty[chunk / 64] |= 1 << (chunk % 64);
+    }
+}
+
+// Memory for performance-sensitive runs, a drop-in replacement for
+// PagedMem: the same initializer and diffs(), but with storage in flat
+// 64 KiB pages found by indexing rather than hashing, so that word and
+// long accesses within a page are single big-endian loads and stores.
+// The page table has an entry per 64 KiB of the address mask, which for
+// the full 32 bit bus is 512 KiB of its own.
+pub struct FlatMem {
+    pages: Vec<Option<Box<Page>>>,
+    pub initializer: u32,
+    address_mask: u32,
+}
+
+impl FlatMem {
+    pub fn new(initializer: u32) -> FlatMem {
+        FlatMem::with_address_mask(initializer, ADDRBUS_MASK)
+    }
+    // addresses are masked to the bus width, so that memory beyond it
+    // mirrors the addressable space
+    pub fn with_address_mask(initializer: u32, address_mask: u32) -> FlatMem {
+        let pages = (0..=(address_mask >> PAGE_SHIFT)).map(|_| None).collect();
+        FlatMem { pages, initializer, address_mask }
+    }
+    pub fn address_mask(&self) -> u32 {
+        self.address_mask
+    }
+    #[allow(dead_code)]
+    fn allocated_pages(&self) -> usize {
+        self.pages.iter().filter(|page| page.is_some()).count()
+    }
+
+    fn page(&self, address: u32) -> Option<&Page> {
+        self.pages[(address >> PAGE_SHIFT) as usize].as_deref()
+    }
+    // the initializer as seen from an address, in the top bytes
+    fn initializer_at(&self, address: u32) -> u32 {
+        self.initializer.rotate_left(8 * (address % 4))
+    }
+
+    pub fn read_u8(&self, address: u32) -> u32 {
+        let address = address & self.address_mask;
+        match self.page(address) {
+            Some(page) => u32::from(page.bytes[address as usize % PAGE_SIZE]),
+            None => self.initializer_at(address) >> 24,
+        }
+    }
+    pub fn write_u8(&mut self, address: u32, value: u32) {
+        let address = address & self.address_mask;
+        let value = value as u8;
+        let differs = u32::from(value) != self.initializer_at(address) >> 24;
+        let initializer = self.initializer;
+        let slot = &mut self.pages[(address >> PAGE_SHIFT) as usize];
+        if slot.is_none() {
+            if !differs {
+                return;
+            }
+            *slot = Some(Box::new(Page::new(initializer)));
+        }
+        let page = slot.as_mut().unwrap();
+        let offset = address as usize % PAGE_SIZE;
+        page.bytes[offset] = value;
+        if differs {
+            page.set_dirty(offset / CHUNK_SIZE);
+        }
+    }
+
+    // the offset of an access of the given size, if it stays within a
+    // page and does not wrap around the address mask
+    fn offset_within_page(&self, address: u32, size: usize) -> Option<usize> {
+        let offset = (address & self.address_mask) as usize % PAGE_SIZE;
+        if offset + size <= PAGE_SIZE {
+            Some(offset)
+        } else {
+            None
+        }
+    }
+
+    pub fn diffs(&self) -> DiffIter<'_> {
+        DiffIter { mem: self, page: 0, chunk: 0, offset: 0 }
+    }
+}
+
+// The bytes of the chunks written with values differing from the
+// initializer, by address
+pub struct DiffIter<'a> {
+    mem: &'a FlatMem,
+    page: usize,
+    chunk: usize,
+    offset: usize,
+}
+impl<'a> Iterator for DiffIter<'a> {
+    type Item = (u32, u8);
+    fn next(&mut self) -> Option<(u32, u8)> {
+        while self.page < self.mem.pages.len() {
+            if let Some(ref page) = self.mem.pages[self.page] {
+                while self.chunk < CHUNKS {
+                    if page.is_dirty(self.chunk) {
+                        let offset = self.chunk * CHUNK_SIZE + self.offset;
+                        self.offset += 1;
+                        if self.offset == CHUNK_SIZE {
+                            self.offset = 0;
+                            self.chunk += 1;
+                        }
+                        return Some(((self.page << PAGE_SHIFT) as u32 + offset as u32, page.bytes[offset]));
+                    }
+                    self.chunk += 1;
+                }
+            }
+            self.page += 1;
+            self.chunk = 0;
+        }
+        None
+    }
+}
+
+impl AddressBus for FlatMem {
+    fn copy_from(&mut self, other: &Self) {
+        for (addr, byte) in other.diffs() {
+            self.write_u8(addr, u32::from(byte));
+        }
+    }
+
+    fn read_byte(&self, _address_space: AddressSpace, address: u32) -> Result<u32, BusError> {
+        Ok(self.read_u8(address))
+    }
+
+    fn read_word(&self, _address_space: AddressSpace, address: u32) -> Result<u32, BusError> {
+        let address = address & self.address_mask;
+        Ok(match (self.page(address), self.offset_within_page(address, 2)) {
+            (Some(page), Some(offset)) => u32::from(u16::from_be_bytes([page.bytes[offset], page.bytes[offset + 1]])),
+            (None, Some(_)) => self.initializer_at(address) >> 16,
+            _ => self.read_u8(address) << 8 | self.read_u8(address.wrapping_add(1)),
+        })
+    }
+
+    fn read_long(&self, _address_space: AddressSpace, address: u32) -> Result<u32, BusError> {
+        let address = address & self.address_mask;
+        Ok(match (self.page(address), self.offset_within_page(address, 4)) {
+            (Some(page), Some(offset)) => {
+                let bytes = &page.bytes[offset..offset + 4];
+                u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+            }
+            (None, Some(_)) => self.initializer_at(address),
+            _ => self.read_u8(address) << 24
+                | self.read_u8(address.wrapping_add(1)) << 16
+                | self.read_u8(address.wrapping_add(2)) << 8
+                | self.read_u8(address.wrapping_add(3)),
+        })
+    }
+
+    fn write_byte(&mut self, _address_space: AddressSpace, address: u32, value: u32) -> Result<(), BusError> {
+        self.write_u8(address, value);
+        Ok(())
+    }
+
+    fn write_word(&mut self, _address_space: AddressSpace, address: u32, value: u32) -> Result<(), BusError> {
+        let address = address & self.address_mask;
+        let differs = (value ^ (self.initializer_at(address) >> 16)) & 0xffff;
+        match (self.offset_within_page(address, 2), &mut self.pages[(address >> PAGE_SHIFT) as usize]) {
+            (Some(offset), &mut Some(ref mut page)) => {
+                page.bytes[offset..offset + 2].copy_from_slice(&(value as u16).to_be_bytes());
+                if differs & 0xff00 != 0 {
+                    page.set_dirty(offset / CHUNK_SIZE);
+                }
+                if differs & 0x00ff != 0 {
+                    page.set_dirty((offset + 1) / CHUNK_SIZE);
+                }
+            }
+            // allocating, or wrapping around
+            _ => {
+                self.write_u8(address, value >> 8);
+                self.write_u8(address.wrapping_add(1), value);
+            }
+        }
+        Ok(())
+    }
+
+    fn write_long(&mut self, _address_space: AddressSpace, address: u32, value: u32) -> Result<(), BusError> {
+        let address = address & self.address_mask;
+        let differs = value ^ self.initializer_at(address);
+        match (self.offset_within_page(address, 4), &mut self.pages[(address >> PAGE_SHIFT) as usize]) {
+            (Some(offset), &mut Some(ref mut page)) => {
+                page.bytes[offset..offset + 4].copy_from_slice(&value.to_be_bytes());
+                for i in 0..4 {
+                    if differs & (0xff00_0000 >> (8 * i)) != 0 {
+                        page.set_dirty((offset + i) / CHUNK_SIZE);
+                    }
+                }
+            }
+            _ => {
+                self.write_u8(address, value >> 24);
+                self.write_u8(address.wrapping_add(1), value >> 16);
+                self.write_u8(address.wrapping_add(2), value >> 8);
+                self.write_u8(address.wrapping_add(3), value);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FlatMem, PAGE_SIZE};
+    use ram::{AddressBus, PagedMem, SUPERVISOR_DATA, ADDRBUS_MASK, ADDRBUS_MASK_20, ADDRBUS_MASK_32};
+
+    #[test]
+    fn reads_the_initializer_until_written() {
+        let mut mem = FlatMem::new(0x01020304);
+        assert_eq!(0x03, mem.read_byte(SUPERVISOR_DATA, 0x102).unwrap());
+        assert_eq!(0x0401, mem.read_word(SUPERVISOR_DATA, 0x103).unwrap());
+        assert_eq!(0x03040102, mem.read_long(SUPERVISOR_DATA, 0x102).unwrap());
+        mem.write_long(SUPERVISOR_DATA, 0x100, 0xdeadbeef).unwrap();
+        assert_eq!(0xbeef0102, mem.read_long(SUPERVISOR_DATA, 0x102).unwrap());
+        assert_eq!(0x03040102, mem.read_long(SUPERVISOR_DATA, 0x10002).unwrap());
+    }
+
+    #[test]
+    fn allocates_only_on_writes_differing_from_the_initializer() {
+        let mut mem = FlatMem::new(0x01020304);
+        mem.write_long(SUPERVISOR_DATA, 0x1000, 0x01020304).unwrap();
+        mem.write_word(SUPERVISOR_DATA, 0x1003, 0x0401).unwrap();
+        assert_eq!(0, mem.allocated_pages());
+        mem.write_byte(SUPERVISOR_DATA, 0x1002, 0x99).unwrap();
+        assert_eq!(1, mem.allocated_pages());
+        assert_eq!(None, mem.diffs().find(|&(address, _)| address >= 0x1010));
+    }
+
+    #[test]
+    fn accesses_across_pages_and_around_the_address_mask() {
+        let mut mem = FlatMem::new(0);
+        let edge = PAGE_SIZE as u32 - 2;
+        mem.write_long(SUPERVISOR_DATA, edge, 0x91929394).unwrap();
+        assert_eq!(0x91929394, mem.read_long(SUPERVISOR_DATA, edge).unwrap());
+        assert_eq!(0x9394, mem.read_word(SUPERVISOR_DATA, edge + 2).unwrap());
+        mem.write_long(SUPERVISOR_DATA, ADDRBUS_MASK - 1, 0xa1a2a3a4).unwrap();
+        assert_eq!(0xa3a4, mem.read_word(SUPERVISOR_DATA, 0).unwrap());
+        assert_eq!(0xa1a2a3a4, mem.read_long(SUPERVISOR_DATA, ADDRBUS_MASK - 1).unwrap());
+        let mut mem = FlatMem::with_address_mask(0, ADDRBUS_MASK_20);
+        mem.write_byte(SUPERVISOR_DATA, 0x0010_0000, 0x55).unwrap();
+        assert_eq!(0x55, mem.read_byte(SUPERVISOR_DATA, 0).unwrap());
+        let mut mem = FlatMem::with_address_mask(0, ADDRBUS_MASK_32);
+        mem.write_word(SUPERVISOR_DATA, 0xffff_fffe, 0x0102).unwrap();
+        assert_eq!(0x0102, mem.read_word(SUPERVISOR_DATA, 0xffff_fffe).unwrap());
+        assert_eq!(0, mem.read_byte(SUPERVISOR_DATA, 0x00ff_fffe).unwrap());
+    }
+
+    #[test]
+    fn behaves_like_paged_mem() {
+        let mut paged = PagedMem::new(0xaaaa_aaaa);
+        let mut flat = FlatMem::new(0xaaaa_aaaa);
+        // a pseudo-random mix of accesses, some writing the initializer
+        let mut seed = 12345u32;
+        for _ in 0..5000 {
+            seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+            let address = seed >> 4 & 0x0003_ffff | (seed & 1) << 23;
+            let value = if seed & 0x300 == 0 { 0xaaaa_aaaa } else { seed.rotate_left(7) };
+            match seed >> 29 {
+                0 | 1 => { paged.write_byte(SUPERVISOR_DATA, address, value).unwrap(); flat.write_byte(SUPERVISOR_DATA, address, value).unwrap(); }
+                2 | 3 => { paged.write_word(SUPERVISOR_DATA, address, value).unwrap(); flat.write_word(SUPERVISOR_DATA, address, value).unwrap(); }
+                4 | 5 => { paged.write_long(SUPERVISOR_DATA, address, value).unwrap(); flat.write_long(SUPERVISOR_DATA, address, value).unwrap(); }
+                _ => assert_eq!(paged.read_long(SUPERVISOR_DATA, address).unwrap(), flat.read_long(SUPERVISOR_DATA, address).unwrap()),
+            }
+        }
+        assert_eq!(paged.diffs().collect::<Vec<_>>(), flat.diffs().collect::<Vec<_>>());
+        // copies leave out chunks that were written back to the initializer
+        let mut paged_copy = PagedMem::new(0xaaaa_aaaa);
+        paged_copy.copy_from(&paged);
+        let mut flat_copy = FlatMem::new(0xaaaa_aaaa);
+        flat_copy.copy_from(&flat);
+        assert_eq!(paged_copy.diffs().collect::<Vec<_>>(), flat_copy.diffs().collect::<Vec<_>>());
+    }
+}
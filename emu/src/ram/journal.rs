@@ -0,0 +1,202 @@
+use std::collections::VecDeque;
+use super::{AddressSpace, AddressBus, BusError};
+
+// A write as recorded in the journal: what was there before, and by the
+// instruction at pc, the step-th one executed
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct JournalEntry {
+    pub step: u64,
+    pub pc: u32,
+    pub address_space: AddressSpace,
+    pub address: u32,
+    pub size: u32,
+    // None if the old contents could not be read back
+    pub old: Option<u32>,
+    pub new: u32,
+}
+
+impl JournalEntry {
+    pub fn covers(&self, address: u32) -> bool {
+        address.wrapping_sub(self.address) < self.size
+    }
+}
+
+// Wraps a bus, keeping a journal of the writes to it, so that they can be
+// undone. The old contents are read before each write, so this should
+// wrap memory, not devices with side effects on read. The journal holds
+// at most the given number of writes, forgetting the oldest ones.
+pub struct JournalingMem<B: AddressBus> {
+    bus: B,
+    entries: VecDeque<JournalEntry>,
+    capacity: usize,
+    // tags for the writes that follow
+    step: u64,
+    pc: u32,
+    // the latest step of which writes have been forgotten
+    forgotten: Option<u64>,
+}
+
+impl<B: AddressBus> JournalingMem<B> {
+    pub fn new(bus: B, capacity: usize) -> JournalingMem<B> {
+        JournalingMem { bus, entries: VecDeque::new(), capacity, step: 0, pc: 0, forgotten: None }
+    }
+    pub fn bus(&self) -> &B {
+        &self.bus
+    }
+    // writes through this reference bypass the journal
+    pub fn bus_mut(&mut self) -> &mut B {
+        &mut self.bus
+    }
+    pub fn set_step(&mut self, step: u64, pc: u32) {
+        self.step = step;
+        self.pc = pc;
+    }
+    pub fn entries(&self) -> &VecDeque<JournalEntry> {
+        &self.entries
+    }
+    pub fn forgotten(&self) -> Option<u64> {
+        self.forgotten
+    }
+    // whether the writes after the given step are all still known
+    pub fn can_undo_to(&self, step: u64) -> bool {
+        self.forgotten.map_or(true, |forgotten| forgotten <= step)
+    }
+    // the latest write touching the address, if still in the journal
+    pub fn last_change(&self, address: u32) -> Option<JournalEntry> {
+        self.entries.iter().rev().find(|entry| entry.covers(address)).cloned()
+    }
+
+    // Puts back the old contents written after the given step, latest
+    // first, and drops them from the journal
+    pub fn undo_to(&mut self, step: u64) -> bool {
+        if !self.can_undo_to(step) {
+            return false;
+        }
+        while self.entries.back().map_or(false, |entry| entry.step > step) {
+            let entry = self.entries.pop_back().unwrap();
+            if let Some(old) = entry.old {
+                // it could be written before, so it can be written again
+                let _ = match entry.size {
+                    1 => self.bus.write_byte(entry.address_space, entry.address, old),
+                    2 => self.bus.write_word(entry.address_space, entry.address, old),
+                    _ => self.bus.write_long(entry.address_space, entry.address, old),
+                };
+            }
+        }
+        true
+    }
+    // drops the writes up to the given step, which can no longer be undone
+    pub fn forget_to(&mut self, step: u64) {
+        while self.entries.front().map_or(false, |entry| entry.step <= step) {
+            self.forget_oldest();
+        }
+    }
+    fn forget_oldest(&mut self) {
+        if let Some(entry) = self.entries.pop_front() {
+            self.forgotten = Some(self.forgotten.map_or(entry.step, |forgotten| forgotten.max(entry.step)));
+        }
+    }
+
+    fn record(&mut self, address_space: AddressSpace, address: u32, size: u32, old: Option<u32>, new: u32) {
+        if self.capacity == 0 {
+            self.forgotten = Some(self.step);
+            return;
+        }
+        if self.entries.len() == self.capacity {
+            self.forget_oldest();
+        }
+        self.entries.push_back(JournalEntry { step: self.step, pc: self.pc, address_space, address, size, old, new });
+    }
+}
+
+impl<B: AddressBus> AddressBus for JournalingMem<B> {
+    fn copy_from(&mut self, other: &Self) {
+        self.bus.copy_from(&other.bus);
+    }
+
+    fn read_byte(&self, address_space: AddressSpace, address: u32) -> Result<u32, BusError> {
+        self.bus.read_byte(address_space, address)
+    }
+
+    fn read_word(&self, address_space: AddressSpace, address: u32) -> Result<u32, BusError> {
+        self.bus.read_word(address_space, address)
+    }
+
+    fn read_long(&self, address_space: AddressSpace, address: u32) -> Result<u32, BusError> {
+        self.bus.read_long(address_space, address)
+    }
+
+    fn write_byte(&mut self, address_space: AddressSpace, address: u32, value: u32) -> Result<(), BusError> {
+        let old = self.bus.read_byte(address_space, address).ok();
+        self.bus.write_byte(address_space, address, value)?;
+        self.record(address_space, address, 1, old, value & 0xff);
+        Ok(())
+    }
+
+    fn write_word(&mut self, address_space: AddressSpace, address: u32, value: u32) -> Result<(), BusError> {
+        let old = self.bus.read_word(address_space, address).ok();
+        self.bus.write_word(address_space, address, value)?;
+        self.record(address_space, address, 2, old, value & 0xffff);
+        Ok(())
+    }
+
+    fn write_long(&mut self, address_space: AddressSpace, address: u32, value: u32) -> Result<(), BusError> {
+        let old = self.bus.read_long(address_space, address).ok();
+        self.bus.write_long(address_space, address, value)?;
+        self.record(address_space, address, 4, old, value);
+        Ok(())
+    }
+
+    fn translated(&self, address_space: AddressSpace, logical: u32, physical: u32) {
+        self.bus.translated(address_space, logical, physical)
+    }
+
+    fn map_generation(&self) -> u32 {
+        self.bus.map_generation()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{JournalingMem, JournalEntry};
+    use ram::{AddressBus, PagedMem, SUPERVISOR_DATA, USER_DATA};
+
+    #[test]
+    fn records_old_and_new_contents() {
+        let mut mem = JournalingMem::new(PagedMem::new(0), 10);
+        mem.set_step(3, 0x100);
+        mem.write_long(USER_DATA, 0x20, 0x11223344).unwrap();
+        mem.write_byte(USER_DATA, 0x21, 0xff).unwrap();
+        assert_eq!(Some(JournalEntry { step: 3, pc: 0x100, address_space: USER_DATA, address: 0x21, size: 1, old: Some(0x22), new: 0xff }),
+                   mem.last_change(0x21));
+        assert_eq!(Some(0x20), mem.last_change(0x23).map(|entry| entry.address));
+        assert_eq!(None, mem.last_change(0x24));
+    }
+
+    #[test]
+    fn undoes_writes_after_a_step() {
+        let mut mem = JournalingMem::new(PagedMem::new(0), 10);
+        mem.set_step(1, 0);
+        mem.write_word(SUPERVISOR_DATA, 0x10, 0x1111).unwrap();
+        mem.set_step(2, 0);
+        mem.write_word(SUPERVISOR_DATA, 0x10, 0x2222).unwrap();
+        mem.set_step(3, 0);
+        mem.write_word(SUPERVISOR_DATA, 0x10, 0x3333).unwrap();
+        assert!(mem.undo_to(1));
+        assert_eq!(0x1111, mem.read_word(SUPERVISOR_DATA, 0x10).unwrap());
+        assert_eq!(1, mem.entries().len());
+    }
+
+    #[test]
+    fn forgets_the_oldest_writes_when_full() {
+        let mut mem = JournalingMem::new(PagedMem::new(0), 2);
+        for step in 1..4 {
+            mem.set_step(step, 0);
+            mem.write_byte(SUPERVISOR_DATA, 0x10, step as u32).unwrap();
+        }
+        assert_eq!(Some(1), mem.forgotten());
+        assert!(!mem.undo_to(0));
+        assert!(mem.undo_to(1));
+        assert_eq!(1, mem.read_byte(SUPERVISOR_DATA, 0x10).unwrap());
+    }
+}
@@ -1,5 +1,6 @@
 use std::cell::RefCell;
-use super::{AddressSpace, AddressBus, ADDRBUS_MASK};
+use std::sync::{Arc, Mutex};
+use super::{AddressSpace, AddressBus, BusError, ADDRBUS_MASK};
 use ram::pagedmem::{PagedMem, DiffIter};
 
 #[derive(Copy, Clone, PartialEq)]
@@ -11,6 +12,8 @@ pub enum Operation {
     WriteByte(AddressSpace, u32, u32),
     WriteWord(AddressSpace, u32, u32),
     WriteLong(AddressSpace, u32, u32),
+    // logical and physical address of the access that follows
+    Translate(AddressSpace, u32, u32),
 }
 use std::fmt;
 impl fmt::Debug for Operation {
@@ -23,6 +26,7 @@ impl fmt::Debug for Operation {
             Operation::WriteByte(aspace, addr, byte) => write!(f, "WriteByte{:?} @{:06x} <= {:02x}", aspace, addr, byte),
             Operation::WriteWord(aspace, addr, word) => write!(f, "WriteWord{:?} @{:06x} <= {:04x}", aspace, addr, word),
             Operation::WriteLong(aspace, addr, long) => write!(f, "WriteLong{:?} @{:06x} <= {:08x}", aspace, addr, long),
+            Operation::Translate(aspace, logical, physical) => write!(f, "Translate{:?} @{:06x} => @{:06x}", aspace, logical, physical),
         }
     }
 }
@@ -57,6 +61,41 @@ impl OpsLogging for OpsLogger {
     }
 }
 
+// An OpsLogger that can be shared between threads, so that a core
+// running on another thread can be logged (through an Arc) while the
+// host keeps a handle to the log
+#[derive(Default)]
+pub struct SyncOpsLogger {
+    log: Mutex<Vec<Operation>>,
+}
+
+impl SyncOpsLogger {
+    pub fn new() -> SyncOpsLogger {
+        SyncOpsLogger { log: Mutex::new(Vec::new()) }
+    }
+    pub fn ops(&self) -> Vec<Operation>
+    {
+        self.log.lock().unwrap().clone()
+    }
+    pub fn len(&self) -> usize {
+        self.log.lock().unwrap().len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.log.lock().unwrap().is_empty()
+    }
+}
+impl OpsLogging for SyncOpsLogger {
+    fn log(&self, op: Operation) {
+        self.log.lock().unwrap().push(op);
+    }
+}
+
+impl<T: OpsLogging> OpsLogging for Arc<T> {
+    fn log(&self, op: Operation) {
+        (**self).log(op)
+    }
+}
+
 pub struct LoggingMem<T: OpsLogging> {
     pub logger: T,
     mem: PagedMem,
@@ -66,7 +105,13 @@ pub struct LoggingMem<T: OpsLogging> {
 
 impl<T: OpsLogging> LoggingMem<T> {
     pub fn new(initializer: u32, logger: T) -> LoggingMem<T> {
-        LoggingMem { logger, mem: PagedMem::new(initializer), initializer }
+        LoggingMem::with_address_mask(initializer, logger, ADDRBUS_MASK)
+    }
+    pub fn with_address_mask(initializer: u32, logger: T, address_mask: u32) -> LoggingMem<T> {
+        LoggingMem { logger, mem: PagedMem::with_address_mask(initializer, address_mask), initializer }
+    }
+    pub fn address_mask(&self) -> u32 {
+        self.mem.address_mask()
     }
     pub fn read_u8(&self, address: u32) -> u32 {
         self.mem.read_u8(address)
@@ -80,59 +125,68 @@ impl<T: OpsLogging> LoggingMem<T> {
     }
 }
 
-impl<T: OpsLogging> AddressBus for LoggingMem<T> {
+impl<T: OpsLogging + 'static> AddressBus for LoggingMem<T> {
     fn copy_from(&mut self, other: &Self) {
         for (addr, byte) in other.diffs() {
             self.write_u8(addr, u32::from(byte));
         }
     }
 
-    fn read_byte(&self, address_space: AddressSpace, address: u32) -> u32 {
+    fn read_byte(&self, address_space: AddressSpace, address: u32) -> Result<u32, BusError> {
         let value = self.read_u8(address);
-        self.logger.log(Operation::ReadByte(address_space, address & ADDRBUS_MASK, value as u8));
-        value
+        self.logger.log(Operation::ReadByte(address_space, address & self.address_mask(), value as u8));
+        Ok(value)
     }
 
-    fn read_word(&self, address_space: AddressSpace, address: u32) -> u32 {
+    fn read_word(&self, address_space: AddressSpace, address: u32) -> Result<u32, BusError> {
         let value = (self.read_u8(address) << 8
                     |self.read_u8(address.wrapping_add(1))) as u32;
-        self.logger.log(Operation::ReadWord(address_space, address & ADDRBUS_MASK, value as u16));
-        value
+        self.logger.log(Operation::ReadWord(address_space, address & self.address_mask(), value as u16));
+        Ok(value)
     }
 
-    fn read_long(&self, address_space: AddressSpace, address: u32) -> u32 {
+    fn read_long(&self, address_space: AddressSpace, address: u32) -> Result<u32, BusError> {
         let value = (self.read_u8(address) << 24
                     |self.read_u8(address.wrapping_add(1)) << 16
                     |self.read_u8(address.wrapping_add(2)) <<  8
                     |self.read_u8(address.wrapping_add(3))) as u32;
-        self.logger.log(Operation::ReadLong(address_space, address & ADDRBUS_MASK, value));
-        value
+        self.logger.log(Operation::ReadLong(address_space, address & self.address_mask(), value));
+        Ok(value)
     }
 
-    fn write_byte(&mut self, address_space: AddressSpace, address: u32, value: u32) {
-        self.logger.log(Operation::WriteByte(address_space, address & ADDRBUS_MASK, value));
+    fn write_byte(&mut self, address_space: AddressSpace, address: u32, value: u32) -> Result<(), BusError> {
+        self.logger.log(Operation::WriteByte(address_space, address & self.address_mask(), value));
         self.write_u8(address, value);
+        Ok(())
     }
 
-    fn write_word(&mut self, address_space: AddressSpace, address: u32, value: u32) {
-        self.logger.log(Operation::WriteWord(address_space, address & ADDRBUS_MASK, value));
+    fn write_word(&mut self, address_space: AddressSpace, address: u32, value: u32) -> Result<(), BusError> {
+        self.logger.log(Operation::WriteWord(address_space, address & self.address_mask(), value));
         self.write_u8(address, value >> 8);
         self.write_u8(address.wrapping_add(1), value);
+        Ok(())
     }
 
-    fn write_long(&mut self, address_space: AddressSpace, address: u32, value: u32) {
-        self.logger.log(Operation::WriteLong(address_space, address & ADDRBUS_MASK, value));
+    fn write_long(&mut self, address_space: AddressSpace, address: u32, value: u32) -> Result<(), BusError> {
+        self.logger.log(Operation::WriteLong(address_space, address & self.address_mask(), value));
         self.write_u8(address, value >> 24);
         self.write_u8(address.wrapping_add(1), value >> 16);
         self.write_u8(address.wrapping_add(2), value >>  8);
         self.write_u8(address.wrapping_add(3), value);
+        Ok(())
+    }
+
+    fn translated(&self, address_space: AddressSpace, logical: u32, physical: u32) {
+        self.logger.log(Operation::Translate(address_space, logical, physical & self.address_mask()));
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{LoggingMem, AddressBus, OpsLogger, Operation};
-    use ram::{SUPERVISOR_DATA, SUPERVISOR_PROGRAM, USER_DATA, USER_PROGRAM, ADDRBUS_MASK};
+    use std::sync::Arc;
+    use std::thread;
+    use super::{LoggingMem, AddressBus, OpsLogger, SyncOpsLogger, Operation};
+    use ram::{SUPERVISOR_DATA, SUPERVISOR_PROGRAM, USER_DATA, USER_PROGRAM, ADDRBUS_MASK, ADDRBUS_MASK_32};
 
     #[test]
     fn read_byte_is_logged() {
@@ -181,23 +235,40 @@ mod tests {
         do_write_long_is_logged(0xFF000180);
     }
 
+    #[test]
+    fn full_32_bit_addresses_are_logged() {
+        let mut mem = LoggingMem::with_address_mask(0x01020304, OpsLogger::new(), ADDRBUS_MASK_32);
+        mem.write_byte(USER_DATA, 0xFF000180, 0x42).unwrap();
+        assert_eq!(0x42, mem.read_byte(USER_DATA, 0xFF000180).unwrap());
+        assert_eq!(0x01, mem.read_byte(USER_DATA, 0x180).unwrap());
+        assert_eq!(Operation::WriteByte(USER_DATA, 0xFF000180, 0x42), mem.logger.ops()[0]);
+    }
+
+    #[test]
+    fn shared_logger_sees_accesses_from_another_thread() {
+        let logger = Arc::new(SyncOpsLogger::new());
+        let mut mem = LoggingMem::new(0, logger.clone());
+        thread::spawn(move || mem.write_word(USER_DATA, 0x100, 0x1234).unwrap()).join().unwrap();
+        assert_eq!(vec![Operation::WriteWord(USER_DATA, 0x100, 0x1234)], logger.ops());
+    }
+
     fn do_read_byte_is_logged(address: u32) {
         let mem = LoggingMem::new(0x01020304, OpsLogger::new());
-        mem.read_byte(SUPERVISOR_DATA, address);
+        mem.read_byte(SUPERVISOR_DATA, address).unwrap();
         assert!(mem.logger.len() > 0);
         assert_eq!(Operation::ReadByte(SUPERVISOR_DATA, address & ADDRBUS_MASK, 0x01), mem.logger.ops()[0]);
     }
 
     fn do_read_word_is_logged(address: u32) {
         let mem = LoggingMem::new(0x01020304, OpsLogger::new());
-        mem.read_word(SUPERVISOR_PROGRAM, address);
+        mem.read_word(SUPERVISOR_PROGRAM, address).unwrap();
         assert!(mem.logger.len() > 0);
         assert_eq!(Operation::ReadWord(SUPERVISOR_PROGRAM, address & ADDRBUS_MASK, 0x0102), mem.logger.ops()[0]);
     }
 
     fn do_read_long_is_logged(address: u32) {
         let mem = LoggingMem::new(0x01020304, OpsLogger::new());
-        mem.read_long(USER_DATA, address);
+        mem.read_long(USER_DATA, address).unwrap();
         assert!(mem.logger.len() > 0);
         assert_eq!(Operation::ReadLong(USER_DATA, address & ADDRBUS_MASK, 0x01020304), mem.logger.ops()[0]);
     }
@@ -205,7 +276,7 @@ mod tests {
     fn do_write_byte_is_logged(address: u32) {
         let mut mem = LoggingMem::new(0x01020304, OpsLogger::new());
         let pattern = 0xAAAA7777;
-        mem.write_byte(USER_PROGRAM, address, pattern);
+        mem.write_byte(USER_PROGRAM, address, pattern).unwrap();
         assert!(mem.logger.len() > 0);
         assert_eq!(Operation::WriteByte(USER_PROGRAM, address & ADDRBUS_MASK, pattern), mem.logger.ops()[0]);
     }
@@ -213,7 +284,7 @@ mod tests {
     fn do_write_word_is_logged(address: u32) {
         let mut mem = LoggingMem::new(0x01020304, OpsLogger::new());
         let pattern = 0xAAAA7777;
-        mem.write_word(SUPERVISOR_PROGRAM, address, pattern);
+        mem.write_word(SUPERVISOR_PROGRAM, address, pattern).unwrap();
         assert!(mem.logger.len() > 0);
         assert_eq!(Operation::WriteWord(SUPERVISOR_PROGRAM, address & ADDRBUS_MASK, pattern), mem.logger.ops()[0]);
     }
@@ -221,7 +292,7 @@ mod tests {
     fn do_write_long_is_logged(address: u32) {
         let mut mem = LoggingMem::new(0x01020304, OpsLogger::new());
         let pattern = 0xAAAA7777;
-        mem.write_long(USER_DATA, address, pattern);
+        mem.write_long(USER_DATA, address, pattern).unwrap();
         assert!(mem.logger.len() > 0);
         assert_eq!(Operation::WriteLong(USER_DATA, address & ADDRBUS_MASK, pattern), mem.logger.ops()[0]);
     }
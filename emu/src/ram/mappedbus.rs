@@ -0,0 +1,612 @@
+use std::cell::{Cell, RefCell};
+use super::{AddressSpace, AddressBus, BusError, ADDRBUS_MASK, SUPERVISOR_PROGRAM, USER_PROGRAM};
+
+// A memory mapped peripheral. Offsets are relative to the start of the
+// region the device is mapped at (after mirroring). Only byte accesses
+// need to be implemented; by default word and long accesses are split
+// into byte accesses, most significant byte first, as an 8-bit
+// peripheral on the 68000 bus would see them.
+pub trait Device {
+    fn read_byte(&mut self, address_space: AddressSpace, offset: u32) -> Result<u32, BusError>;
+    fn write_byte(&mut self, address_space: AddressSpace, offset: u32, value: u32) -> Result<(), BusError>;
+
+    fn read_word(&mut self, address_space: AddressSpace, offset: u32) -> Result<u32, BusError> {
+        let hi = self.read_byte(address_space, offset)?;
+        let lo = self.read_byte(address_space, offset.wrapping_add(1))?;
+        Ok(hi << 8 | lo)
+    }
+    fn read_long(&mut self, address_space: AddressSpace, offset: u32) -> Result<u32, BusError> {
+        let hi = self.read_word(address_space, offset)?;
+        let lo = self.read_word(address_space, offset.wrapping_add(2))?;
+        Ok(hi << 16 | lo)
+    }
+    fn write_word(&mut self, address_space: AddressSpace, offset: u32, value: u32) -> Result<(), BusError> {
+        self.write_byte(address_space, offset, (value >> 8) & 0xff)?;
+        self.write_byte(address_space, offset.wrapping_add(1), value & 0xff)
+    }
+    fn write_long(&mut self, address_space: AddressSpace, offset: u32, value: u32) -> Result<(), BusError> {
+        self.write_word(address_space, offset, (value >> 16) & 0xffff)?;
+        self.write_word(address_space, offset.wrapping_add(2), value & 0xffff)
+    }
+}
+
+// What happens on an access to an address not covered by any region
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Unmapped {
+    // nothing answers, so the access ends in a bus error
+    BusError,
+    // reads return the given byte pattern, writes are ignored
+    OpenBus(u8),
+}
+
+#[derive(Copy, Clone, Debug, Hash, Eq, PartialEq)]
+pub struct RegionId(usize);
+
+// What happens to a write to ROM
+#[derive(Copy, Clone, Debug, Hash, Eq, PartialEq)]
+pub enum RomWrites {
+    // it is dropped, as on a real bus where nothing drives the ROM
+    Ignore,
+    // it is dropped, but kept in the log of rom_writes
+    Log,
+    // it ends in a bus error
+    BusError,
+}
+
+// A write dropped by a ROM region with the Log policy
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct RomWrite {
+    pub region: RegionId,
+    pub address_space: AddressSpace,
+    pub address: u32,
+    pub size: u32,
+    pub value: u32,
+}
+
+enum Backing {
+    Ram(Vec<u8>),
+    Rom(Vec<u8>, RomWrites),
+    // devices may have side effects on read, but AddressBus reads
+    // only borrow the bus immutably
+    Device(RefCell<Box<dyn Device + Send>>),
+}
+
+struct Region {
+    id: RegionId,
+    start: u32,
+    // size of the address range covered, which is larger than len for
+    // mirrored regions
+    size: u32,
+    // size of the backing RAM, ROM or device register window
+    len: u32,
+    // bit n set if function code n is decoded by this region
+    fcs: u8,
+    backing: Backing,
+    // the copy at 0 of a boot ROM, until shadowed out
+    overlay: bool,
+}
+
+const ALL_FCS: u8 = 0b0110_0110;
+
+impl Region {
+    fn covers(&self, address_space: AddressSpace, address: u32) -> bool {
+        self.fcs & address_space.fc_bit() != 0
+            && address >= self.start && address - self.start < self.size
+    }
+    fn fits(&self, address: u32, bytes: u32) -> bool {
+        bytes <= self.size && address - self.start <= self.size - bytes
+    }
+    fn read(&self, address_space: AddressSpace, address: u32, bytes: u32) -> Result<u32, BusError> {
+        let offset = (address - self.start) % self.len;
+        match self.backing {
+            Backing::Ram(ref mem) | Backing::Rom(ref mem, _) =>
+                Ok((0..bytes).fold(0, |value, i| value << 8 | u32::from(mem[((offset + i) % self.len) as usize]))),
+            Backing::Device(ref device) => {
+                let mut device = device.borrow_mut();
+                match bytes {
+                    1 => device.read_byte(address_space, offset),
+                    2 => device.read_word(address_space, offset),
+                    _ => device.read_long(address_space, offset),
+                }
+            }
+        }
+    }
+    fn write(&mut self, address_space: AddressSpace, address: u32, bytes: u32, value: u32) -> Result<(), BusError> {
+        let offset = (address - self.start) % self.len;
+        match self.backing {
+            Backing::Ram(ref mut mem) => {
+                for i in 0..bytes {
+                    let shift = 8 * (bytes - 1 - i);
+                    mem[((offset + i) % self.len) as usize] = (value >> shift) as u8;
+                }
+                Ok(())
+            }
+            Backing::Rom(_, RomWrites::BusError) => Err(BusError),
+            Backing::Rom(..) => Ok(()),
+            Backing::Device(ref mut device) => {
+                let device = device.get_mut();
+                match bytes {
+                    1 => device.write_byte(address_space, offset, value & 0xff),
+                    2 => device.write_word(address_space, offset, value & 0xffff),
+                    _ => device.write_long(address_space, offset, value),
+                }
+            }
+        }
+    }
+}
+
+// A bus combining RAM, ROM and device regions. The rules are:
+// - an access is routed to the most recently mapped region that covers
+//   its address and decodes its function code, so a later mapping
+//   overlays earlier ones, and unmapping it uncovers them again
+// - a region may cover a larger address range than its contents, which
+//   are then mirrored (repeated) over the range
+// - accesses not covered by any region are handled by the Unmapped rule
+// - a word or long access straddling two regions is split into byte
+//   accesses, each routed on its own
+// - writes to ROM are ignored, logged or end in a bus error, as set per
+//   region
+//
+// Many 68000 machines boot with their ROM overlaid at 0, for the reset
+// vectors, and shadow it out once running from its real address, to
+// uncover what is mapped beneath; map_boot_rom sets that up.
+pub struct MappedBus {
+    regions: Vec<Region>,
+    unmapped: Unmapped,
+    next_id: usize,
+    address_mask: u32,
+    // the boot ROM, whose first program fetch ends the overlay
+    boot: Option<RegionId>,
+    overlaid: Cell<bool>,
+    logged: Vec<RomWrite>,
+    generation: Cell<u32>,
+}
+
+impl MappedBus {
+    pub fn new(unmapped: Unmapped) -> MappedBus {
+        MappedBus::with_address_mask(unmapped, ADDRBUS_MASK)
+    }
+    // region starts and accesses are masked to the bus width, so the
+    // regions of a 24 bit bus mirror over the 32 bit address space
+    pub fn with_address_mask(unmapped: Unmapped, address_mask: u32) -> MappedBus {
+        MappedBus {
+            regions: Vec::new(), unmapped, next_id: 0, address_mask,
+            boot: None, overlaid: Cell::new(false), logged: Vec::new(), generation: Cell::new(0)
+        }
+    }
+
+    fn map(&mut self, start: u32, len: u32, backing: Backing) -> RegionId {
+        assert!(len > 0, "cannot map an empty region");
+        let id = RegionId(self.next_id);
+        self.next_id += 1;
+        self.regions.push(Region { id, start: start & self.address_mask, size: len, len, fcs: ALL_FCS, backing, overlay: false });
+        self.remapped();
+        id
+    }
+    pub fn map_ram(&mut self, start: u32, len: u32) -> RegionId {
+        self.map(start, len, Backing::Ram(vec![0; len as usize]))
+    }
+    pub fn map_rom(&mut self, start: u32, image: &[u8]) -> RegionId {
+        self.map(start, image.len() as u32, Backing::Rom(image.to_vec(), RomWrites::Ignore))
+    }
+    // Maps a boot ROM at start, and overlaid at 0 as well, so the reset
+    // vectors are read from it. The overlay is shadowed out by the first
+    // program fetch from start on, or by end_overlay.
+    pub fn map_boot_rom(&mut self, start: u32, image: &[u8]) -> RegionId {
+        let id = self.map_rom(start, image);
+        let overlay = self.map_rom(0, image);
+        self.region_mut(overlay).overlay = true;
+        self.boot = Some(id);
+        self.overlaid.set(true);
+        id
+    }
+    pub fn map_device(&mut self, start: u32, len: u32, device: Box<dyn Device + Send>) -> RegionId {
+        self.map(start, len, Backing::Device(RefCell::new(device)))
+    }
+
+    fn region_mut(&mut self, id: RegionId) -> &mut Region {
+        self.regions.iter_mut().find(|r| r.id == id).expect("no such region")
+    }
+    // repeat the contents of the region over size bytes of address space
+    pub fn mirror(&mut self, id: RegionId, size: u32) {
+        let region = self.region_mut(id);
+        assert!(size >= region.len, "mirror cannot be smaller than the region");
+        region.size = size;
+        self.remapped();
+    }
+    // only decode the given address spaces (function codes), leaving
+    // accesses in other spaces to underlying regions, or unmapped
+    pub fn restrict(&mut self, id: RegionId, address_spaces: &[AddressSpace]) {
+        let region = self.region_mut(id);
+        region.fcs = AddressSpace::fc_bits(address_spaces);
+        self.remapped();
+    }
+    // what happens to writes to a ROM region, and its boot overlay
+    pub fn set_rom_writes(&mut self, id: RegionId, on_write: RomWrites) {
+        let boot = self.boot == Some(id);
+        for region in self.regions.iter_mut().filter(|r| r.id == id || (boot && r.overlay)) {
+            match region.backing {
+                Backing::Rom(_, ref mut writes) => *writes = on_write,
+                _ => panic!("not a ROM region"),
+            }
+        }
+    }
+    pub fn unmap(&mut self, id: RegionId) -> bool {
+        let before = self.regions.len();
+        self.regions.retain(|r| r.id != id);
+        if self.boot == Some(id) {
+            self.boot = None;
+            self.end_overlay();
+        }
+        self.remapped();
+        before != self.regions.len()
+    }
+
+    pub fn is_overlaid(&self) -> bool {
+        self.overlaid.get()
+    }
+    pub fn end_overlay(&mut self) {
+        self.regions.retain(|r| !r.overlay);
+        self.overlaid.set(false);
+        self.remapped();
+    }
+    pub fn rom_writes(&self) -> &[RomWrite] {
+        &self.logged
+    }
+    pub fn take_rom_writes(&mut self) -> Vec<RomWrite> {
+        ::std::mem::take(&mut self.logged)
+    }
+
+    fn remapped(&self) {
+        self.generation.set(self.generation.get().wrapping_add(1));
+    }
+    fn find(&self, address_space: AddressSpace, address: u32) -> Option<usize> {
+        let overlaid = self.overlaid.get();
+        self.regions.iter().rposition(|r| (overlaid || !r.overlay) && r.covers(address_space, address))
+    }
+    fn read(&self, address_space: AddressSpace, address: u32, bytes: u32) -> Result<u32, BusError> {
+        let address = address & self.address_mask;
+        if self.overlaid.get() && (address_space == SUPERVISOR_PROGRAM || address_space == USER_PROGRAM) {
+            let boot = self.regions.iter().find(|r| Some(r.id) == self.boot);
+            if boot.map_or(false, |r| r.covers(address_space, address)) {
+                self.overlaid.set(false);
+                self.remapped();
+            }
+        }
+        match self.find(address_space, address) {
+            Some(index) if self.regions[index].fits(address, bytes) =>
+                self.regions[index].read(address_space, address, bytes),
+            Some(_) => {
+                let mut value = 0;
+                for i in 0..bytes {
+                    value = value << 8 | self.read(address_space, address.wrapping_add(i), 1)?;
+                }
+                Ok(value)
+            }
+            None => match self.unmapped {
+                Unmapped::BusError => Err(BusError),
+                Unmapped::OpenBus(pattern) =>
+                    Ok((0..bytes).fold(0, |value, _| value << 8 | u32::from(pattern))),
+            }
+        }
+    }
+    fn write(&mut self, address_space: AddressSpace, address: u32, bytes: u32, value: u32) -> Result<(), BusError> {
+        let address = address & self.address_mask;
+        match self.find(address_space, address) {
+            Some(index) if self.regions[index].fits(address, bytes) => {
+                let region = &mut self.regions[index];
+                region.write(address_space, address, bytes, value)?;
+                if let Backing::Rom(_, RomWrites::Log) = region.backing {
+                    // writes to the overlay are put down to the boot ROM
+                    let id = if region.overlay { self.boot.unwrap() } else { region.id };
+                    self.logged.push(RomWrite { region: id, address_space, address, size: bytes, value });
+                }
+                Ok(())
+            }
+            Some(_) => {
+                for i in 0..bytes {
+                    let shift = 8 * (bytes - 1 - i);
+                    self.write(address_space, address.wrapping_add(i), 1, (value >> shift) & 0xff)?;
+                }
+                Ok(())
+            }
+            None => match self.unmapped {
+                Unmapped::BusError => Err(BusError),
+                Unmapped::OpenBus(_) => Ok(()),
+            }
+        }
+    }
+}
+
+impl AddressBus for MappedBus {
+    // copies RAM contents between identically mapped buses; ROM and
+    // device state is left alone
+    fn copy_from(&mut self, other: &Self) {
+        for (region, other) in self.regions.iter_mut().zip(other.regions.iter()) {
+            if let (Backing::Ram(mem), Backing::Ram(other_mem)) = (&mut region.backing, &other.backing) {
+                if mem.len() == other_mem.len() {
+                    mem.copy_from_slice(other_mem);
+                }
+            }
+        }
+    }
+
+    fn read_byte(&self, address_space: AddressSpace, address: u32) -> Result<u32, BusError> {
+        self.read(address_space, address, 1)
+    }
+
+    fn read_word(&self, address_space: AddressSpace, address: u32) -> Result<u32, BusError> {
+        self.read(address_space, address, 2)
+    }
+
+    fn read_long(&self, address_space: AddressSpace, address: u32) -> Result<u32, BusError> {
+        self.read(address_space, address, 4)
+    }
+
+    fn write_byte(&mut self, address_space: AddressSpace, address: u32, value: u32) -> Result<(), BusError> {
+        self.write(address_space, address, 1, value & 0xff)
+    }
+
+    fn write_word(&mut self, address_space: AddressSpace, address: u32, value: u32) -> Result<(), BusError> {
+        self.write(address_space, address, 2, value & 0xffff)
+    }
+
+    fn write_long(&mut self, address_space: AddressSpace, address: u32, value: u32) -> Result<(), BusError> {
+        self.write(address_space, address, 4, value)
+    }
+
+    fn map_generation(&self) -> u32 {
+        self.generation.get()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use super::{MappedBus, Device, Unmapped, RomWrites, RomWrite};
+    use cpu::ConfiguredCore;
+    use interrupts::AutoInterruptController;
+    use ram::{AddressBus, AddressSpace, BusError, SUPERVISOR_DATA, SUPERVISOR_PROGRAM, USER_DATA, USER_PROGRAM, ADDRBUS_MASK_32};
+
+    // a device with a single status register, cleared on read, and a
+    // log of the accesses it has seen
+    struct StatusDevice {
+        status: u32,
+        log: Arc<Mutex<Vec<(u32, Option<u32>)>>>,
+    }
+
+    impl Device for StatusDevice {
+        fn read_byte(&mut self, _address_space: AddressSpace, offset: u32) -> Result<u32, BusError> {
+            self.log.lock().unwrap().push((offset, None));
+            if offset == 0 {
+                let status = self.status;
+                self.status = 0;
+                Ok(status)
+            } else {
+                Ok(0xff)
+            }
+        }
+        fn write_byte(&mut self, _address_space: AddressSpace, offset: u32, value: u32) -> Result<(), BusError> {
+            self.log.lock().unwrap().push((offset, Some(value)));
+            if offset == 0 {
+                self.status = value;
+            }
+            Ok(())
+        }
+    }
+
+    fn status_device() -> (Box<StatusDevice>, Arc<Mutex<Vec<(u32, Option<u32>)>>>) {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        (Box::new(StatusDevice { status: 0x80, log: log.clone() }), log)
+    }
+
+    #[test]
+    fn reads_and_writes_ram() {
+        let mut bus = MappedBus::new(Unmapped::BusError);
+        bus.map_ram(0x1000, 0x100);
+        bus.write_long(SUPERVISOR_DATA, 0x1010, 0x01020304).unwrap();
+        assert_eq!(0x01020304, bus.read_long(USER_DATA, 0x1010).unwrap());
+        assert_eq!(0x0203, bus.read_word(SUPERVISOR_PROGRAM, 0x1011).unwrap());
+        assert_eq!(0x04, bus.read_byte(USER_PROGRAM, 0x1013).unwrap());
+    }
+
+    #[test]
+    fn unmapped_access_is_a_bus_error() {
+        let mut bus = MappedBus::new(Unmapped::BusError);
+        bus.map_ram(0x1000, 0x100);
+        assert_eq!(Err(BusError), bus.read_byte(SUPERVISOR_DATA, 0x0fff));
+        assert_eq!(Err(BusError), bus.write_word(SUPERVISOR_DATA, 0x1100, 0));
+    }
+
+    #[test]
+    fn unmapped_access_can_see_open_bus() {
+        let mut bus = MappedBus::new(Unmapped::OpenBus(0xff));
+        assert_eq!(0xffffffff, bus.read_long(SUPERVISOR_DATA, 0x2000).unwrap());
+        assert_eq!(Ok(()), bus.write_word(SUPERVISOR_DATA, 0x2000, 0));
+    }
+
+    #[test]
+    fn writes_to_rom_are_ignored() {
+        let mut bus = MappedBus::new(Unmapped::BusError);
+        bus.map_rom(0, &[0x12, 0x34, 0x56, 0x78]);
+        bus.write_word(SUPERVISOR_DATA, 0, 0xffff).unwrap();
+        assert_eq!(0x12345678, bus.read_long(SUPERVISOR_PROGRAM, 0).unwrap());
+    }
+
+    #[test]
+    fn writes_to_rom_follow_its_policy() {
+        let mut bus = MappedBus::new(Unmapped::BusError);
+        bus.map_rom(0x1000, &[0x12, 0x34]);
+        let logged = bus.map_rom(0x2000, &[0x56, 0x78]);
+        bus.set_rom_writes(logged, RomWrites::Log);
+        let guarded = bus.map_rom(0x3000, &[0x9a, 0xbc]);
+        bus.set_rom_writes(guarded, RomWrites::BusError);
+        assert_eq!(Ok(()), bus.write_word(SUPERVISOR_DATA, 0x1000, 0));
+        assert_eq!(Ok(()), bus.write_byte(SUPERVISOR_DATA, 0x2001, 0xff));
+        assert_eq!(Err(BusError), bus.write_word(SUPERVISOR_DATA, 0x3000, 0));
+        assert_eq!(0x1234, bus.read_word(SUPERVISOR_DATA, 0x1000).unwrap());
+        assert_eq!(0x5678, bus.read_word(SUPERVISOR_DATA, 0x2000).unwrap());
+        assert_eq!(0x9abc, bus.read_word(SUPERVISOR_DATA, 0x3000).unwrap());
+        assert_eq!(vec![RomWrite { region: logged, address_space: SUPERVISOR_DATA, address: 0x2001, size: 1, value: 0xff }],
+                   bus.take_rom_writes());
+    }
+
+    // SSP $800, PC $fc0008, and 21fc 1122 3344 0000 is MOVE.L #$11223344, $0.W
+    const BOOT_IMAGE: [u8; 16] = [0x00, 0x00, 0x08, 0x00, 0x00, 0xfc, 0x00, 0x08,
+                                  0x21, 0xfc, 0x11, 0x22, 0x33, 0x44, 0x00, 0x00];
+
+    #[test]
+    fn boot_rom_is_shadowed_out_once_run_from() {
+        let mut bus = MappedBus::new(Unmapped::BusError);
+        bus.map_ram(0, 0x1000);
+        let boot = bus.map_boot_rom(0xfc0000, &BOOT_IMAGE);
+        bus.set_rom_writes(boot, RomWrites::BusError);
+        let mut cpu = ConfiguredCore::new_with(0, AutoInterruptController::new(), bus);
+        cpu.reset();
+        assert_eq!((0x800, 0xfc0008), (cpu.dar[15], cpu.pc));
+        assert!(cpu.mem.is_overlaid());
+        cpu.execute1();
+        assert!(!cpu.mem.is_overlaid());
+        // the write went to the RAM uncovered at 0
+        assert_eq!(0x11223344, cpu.read_data_long(0).unwrap());
+        assert_eq!(0x21fc, cpu.mem.read_word(SUPERVISOR_PROGRAM, 0xfc0008).unwrap());
+    }
+
+    #[test]
+    fn end_of_overlay_flushes_the_block_cache() {
+        // the overlay runs 4ef9 00fc 000e, JMP $fc000e.L, at 8, from where
+        // 4ef8 0008 is JMP $0008.W, into the RAM uncovered by then, which
+        // holds 7005 60fe, MOVEQ #5, D0 and BRA.S to itself
+        let image = [0x00, 0x00, 0x08, 0x00, 0x00, 0x00, 0x00, 0x08,
+                     0x4e, 0xf9, 0x00, 0xfc, 0x00, 0x0e, 0x4e, 0xf8, 0x00, 0x08];
+        let mut bus = MappedBus::new(Unmapped::BusError);
+        bus.map_ram(0, 0x1000);
+        bus.write_long(SUPERVISOR_DATA, 8, 0x700560fe).unwrap();
+        bus.map_boot_rom(0xfc0000, &image);
+        let mut cpu = ConfiguredCore::new_with(0, AutoInterruptController::new(), bus);
+        cpu.set_block_cache(true);
+        cpu.reset();
+        cpu.execute1();
+        assert_eq!(0xfc000e, cpu.pc);
+        cpu.execute1();
+        assert!(!cpu.mem.is_overlaid());
+        cpu.execute1();
+        assert_eq!(5, cpu.dar[0]);
+    }
+
+    #[test]
+    fn overlay_can_be_ended_by_hand() {
+        let mut bus = MappedBus::new(Unmapped::BusError);
+        bus.map_ram(0, 0x1000);
+        bus.map_boot_rom(0x8000, &[0x12, 0x34]);
+        bus.write_word(SUPERVISOR_DATA, 0, 0x5678).unwrap();
+        assert_eq!(0x1234, bus.read_word(SUPERVISOR_DATA, 0).unwrap());
+        bus.end_overlay();
+        bus.write_word(SUPERVISOR_DATA, 0, 0x5678).unwrap();
+        assert_eq!(0x5678, bus.read_word(SUPERVISOR_DATA, 0).unwrap());
+        assert_eq!(0x1234, bus.read_word(SUPERVISOR_DATA, 0x8000).unwrap());
+    }
+
+    #[test]
+    fn mirrored_region_repeats_contents() {
+        let mut bus = MappedBus::new(Unmapped::BusError);
+        let ram = bus.map_ram(0x10000, 0x100);
+        let generation = bus.map_generation();
+        bus.mirror(ram, 0x1000);
+        assert_ne!(generation, bus.map_generation());
+        bus.write_word(SUPERVISOR_DATA, 0x10010, 0xabcd).unwrap();
+        assert_eq!(0xabcd, bus.read_word(SUPERVISOR_DATA, 0x10110).unwrap());
+        assert_eq!(0xabcd, bus.read_word(SUPERVISOR_DATA, 0x10f10).unwrap());
+        assert_eq!(Err(BusError), bus.read_word(SUPERVISOR_DATA, 0x11010));
+        // long access wrapping around the end of the mirrored contents
+        bus.write_long(SUPERVISOR_DATA, 0x100fe, 0x11223344).unwrap();
+        assert_eq!(0x3344, bus.read_word(SUPERVISOR_DATA, 0x10000).unwrap());
+    }
+
+    #[test]
+    fn later_region_overlays_earlier() {
+        let mut bus = MappedBus::new(Unmapped::BusError);
+        bus.map_ram(0, 0x1000);
+        bus.write_long(SUPERVISOR_DATA, 0, 0xaaaaaaaa).unwrap();
+        bus.write_long(SUPERVISOR_DATA, 4, 0xaaaaaaaa).unwrap();
+        let rom = bus.map_rom(0, &[0x00, 0x00, 0x04, 0x00]);
+        assert_eq!(0x400, bus.read_long(SUPERVISOR_PROGRAM, 0).unwrap());
+        // outside the overlay, the RAM is still visible
+        assert_eq!(0xaaaa, bus.read_word(SUPERVISOR_DATA, 0x4).unwrap());
+        assert!(bus.unmap(rom));
+        assert_eq!(0xaaaaaaaa, bus.read_long(SUPERVISOR_PROGRAM, 0).unwrap());
+        assert!(!bus.unmap(rom));
+    }
+
+    #[test]
+    fn access_straddling_regions_is_split() {
+        let mut bus = MappedBus::new(Unmapped::BusError);
+        bus.map_rom(0, &[0x11, 0x22]);
+        bus.map_ram(2, 2);
+        bus.write_long(SUPERVISOR_DATA, 0, 0xffff3344).unwrap();
+        assert_eq!(0x11223344, bus.read_long(SUPERVISOR_DATA, 0).unwrap());
+    }
+
+    #[test]
+    fn restricted_region_only_decodes_given_spaces() {
+        let mut bus = MappedBus::new(Unmapped::BusError);
+        let ram = bus.map_ram(0, 0x100);
+        bus.restrict(ram, &[SUPERVISOR_DATA, SUPERVISOR_PROGRAM]);
+        assert_eq!(Ok(()), bus.write_byte(SUPERVISOR_DATA, 0, 1));
+        assert_eq!(Err(BusError), bus.write_byte(USER_DATA, 0, 1));
+        assert_eq!(Err(BusError), bus.read_byte(USER_PROGRAM, 0));
+    }
+
+    #[test]
+    fn device_sees_offsets_and_read_side_effects() {
+        let mut bus = MappedBus::new(Unmapped::BusError);
+        let (device, log) = status_device();
+        let dev = bus.map_device(0xff0000, 2, device);
+        bus.mirror(dev, 0x10);
+        assert_eq!(0x80, bus.read_byte(SUPERVISOR_DATA, 0xff0000).unwrap());
+        // reading the status register cleared it
+        assert_eq!(0x00, bus.read_byte(SUPERVISOR_DATA, 0xff0004).unwrap());
+        bus.write_word(SUPERVISOR_DATA, 0xff0002, 0x4142).unwrap();
+        assert_eq!(0x41ff, bus.read_word(SUPERVISOR_DATA, 0xff0000).unwrap());
+        assert_eq!(vec![(0, None), (0, None), (0, Some(0x41)), (1, Some(0x42)), (0, None), (1, None)], *log.lock().unwrap());
+    }
+
+    #[test]
+    fn addresses_are_limited_by_the_address_bus() {
+        let mut bus = MappedBus::new(Unmapped::BusError);
+        bus.map_ram(0x180, 0x10);
+        bus.write_byte(SUPERVISOR_DATA, 0xff000180, 0x42).unwrap();
+        assert_eq!(0x42, bus.read_byte(SUPERVISOR_DATA, 0x180).unwrap());
+    }
+
+    #[test]
+    fn full_32_bit_bus_decodes_all_address_lines() {
+        let mut bus = MappedBus::with_address_mask(Unmapped::BusError, ADDRBUS_MASK_32);
+        bus.map_ram(0xff000180, 0x10);
+        bus.write_byte(SUPERVISOR_DATA, 0xff000180, 0x42).unwrap();
+        assert_eq!(0x42, bus.read_byte(SUPERVISOR_DATA, 0xff000180).unwrap());
+        assert_eq!(Err(BusError), bus.read_byte(SUPERVISOR_DATA, 0x180));
+    }
+
+    #[test]
+    fn region_can_reach_the_top_of_a_32_bit_bus() {
+        let mut bus = MappedBus::with_address_mask(Unmapped::BusError, ADDRBUS_MASK_32);
+        let ram = bus.map_ram(0, 0x100);
+        bus.mirror(ram, 0xffff_ffff);
+        bus.write_long(SUPERVISOR_DATA, 0xffff_fff8, 0x11223344).unwrap();
+        assert_eq!(0x11223344, bus.read_long(SUPERVISOR_DATA, 0xffff_fff8).unwrap());
+        // the last byte is left out of the mirror
+        assert_eq!(Err(BusError), bus.read_long(SUPERVISOR_DATA, 0xffff_fffc));
+    }
+
+    #[test]
+    fn copy_from_copies_ram_only() {
+        let mut bus = MappedBus::new(Unmapped::BusError);
+        bus.map_ram(0, 0x10);
+        let mut other = MappedBus::new(Unmapped::BusError);
+        other.map_ram(0, 0x10);
+        other.write_long(SUPERVISOR_DATA, 4, 0xdeadbeef).unwrap();
+        bus.copy_from(&other);
+        assert_eq!(0xdeadbeef, bus.read_long(SUPERVISOR_DATA, 4).unwrap());
+    }
+}
@@ -0,0 +1,481 @@
+use std::cell::{Cell, RefCell};
+use super::{AddressSpace, AddressBus, BusError, SUPERVISOR_DATA, ADDRBUS_MASK};
+
+pub const DESCRIPTORS: usize = 32;
+
+// Register offsets from the base the MMU is mapped at, laid out after
+// the MC68451's. The address space table has a byte for each function
+// code at the odd addresses, the accumulator is where descriptors are
+// assembled before being loaded, and loaded descriptors are read back.
+pub const REG_ADDRESS_SPACE_TABLE: u32 = 0x00;
+pub const REG_LOGICAL_BASE: u32 = 0x20;
+pub const REG_LOGICAL_MASK: u32 = 0x22;
+pub const REG_PHYSICAL_BASE: u32 = 0x24;
+pub const REG_ADDRESS_SPACE_NUMBER: u32 = 0x26;
+pub const REG_SEGMENT_STATUS: u32 = 0x27;
+pub const REG_ADDRESS_SPACE_MASK: u32 = 0x28;
+pub const REG_DESCRIPTOR_POINTER: u32 = 0x29;
+// reading these copies the descriptor selected by the descriptor
+// pointer to the accumulator, or loads the accumulator into it
+pub const REG_READ_DESCRIPTOR: u32 = 0x3d;
+pub const REG_LOAD_DESCRIPTOR: u32 = 0x3f;
+pub const REGISTERS_SIZE: u32 = 0x40;
+
+// segment status bits
+pub const SEGMENT_USED: u8 = 0x80;
+pub const SEGMENT_MODIFIED: u8 = 0x10;
+pub const SEGMENT_WRITE_PROTECTED: u8 = 0x04;
+pub const SEGMENT_ENABLED: u8 = 0x01;
+
+// A segment descriptor. The base addresses and mask cover address bits
+// 23-8, so segments are a power of two of 256 byte pages. A logical
+// address is in the segment if it matches the logical base in the bits
+// set in the mask, and the address space number of its function code
+// matches in the bits set in the address space mask.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Descriptor {
+    pub logical_base: u16,
+    pub logical_mask: u16,
+    pub physical_base: u16,
+    pub address_space_number: u8,
+    pub address_space_mask: u8,
+    pub status: u8,
+}
+
+impl Descriptor {
+    fn matches(&self, asn: u8, page: u16) -> bool {
+        self.status & SEGMENT_ENABLED != 0
+            && (asn ^ self.address_space_number) & self.address_space_mask == 0
+            && (page ^ self.logical_base) & self.logical_mask == 0
+    }
+    fn translate(&self, page: u16) -> u16 {
+        self.physical_base & self.logical_mask | page & !self.logical_mask
+    }
+    fn read_byte(&self, offset: u32) -> u8 {
+        match offset {
+            0 => (self.logical_base >> 8) as u8,
+            1 => self.logical_base as u8,
+            2 => (self.logical_mask >> 8) as u8,
+            3 => self.logical_mask as u8,
+            4 => (self.physical_base >> 8) as u8,
+            5 => self.physical_base as u8,
+            6 => self.address_space_number,
+            7 => self.status,
+            _ => self.address_space_mask,
+        }
+    }
+    fn write_byte(&mut self, offset: u32, value: u8) {
+        match offset {
+            0 => self.logical_base = self.logical_base & 0x00ff | u16::from(value) << 8,
+            1 => self.logical_base = self.logical_base & 0xff00 | u16::from(value),
+            2 => self.logical_mask = self.logical_mask & 0x00ff | u16::from(value) << 8,
+            3 => self.logical_mask = self.logical_mask & 0xff00 | u16::from(value),
+            4 => self.physical_base = self.physical_base & 0x00ff | u16::from(value) << 8,
+            5 => self.physical_base = self.physical_base & 0xff00 | u16::from(value),
+            6 => self.address_space_number = value,
+            7 => self.status = value,
+            _ => self.address_space_mask = value,
+        }
+    }
+}
+
+struct State {
+    // address space number for each function code; the MC68451 has
+    // another eight entries for DMA devices, unused here
+    address_space_table: [u8; 16],
+    descriptors: [Descriptor; DESCRIPTORS],
+    accumulator: Descriptor,
+    descriptor_pointer: u8,
+}
+
+// An MMU in the style of the MC68451, translating the logical addresses
+// of the CPU to physical addresses on the bus it wraps, per segment:
+// - an access is translated by the lowest numbered enabled descriptor
+//   matching its address and address space; no match is a translation
+//   fault, and so is a write to a write protected segment, both ending
+//   in a bus error
+// - translated segments are marked used, and modified when written
+// - long accesses crossing a page boundary are split into bytes,
+//   each translated on its own
+// - the registers are seen in supervisor data space at the given base,
+//   untranslated, and can be programmed from the host too
+// - after reset, descriptor 0 maps all of memory one-to-one, so the
+//   boot code runs untranslated until it programs the MMU
+// - loading descriptors and address space numbers changes the map
+//   generation, so block caches are flushed
+pub struct Mmu<B: AddressBus> {
+    bus: B,
+    registers: u32,
+    // reads only borrow the bus immutably, but mark segments used, and
+    // register reads can load descriptors
+    state: RefCell<State>,
+    // bumped by every change to the translation
+    generation: Cell<u32>,
+}
+
+impl<B: AddressBus> Mmu<B> {
+    pub fn new(bus: B, registers: u32) -> Mmu<B> {
+        let mut mmu = Mmu {
+            bus,
+            registers: registers & ADDRBUS_MASK,
+            state: RefCell::new(State {
+                address_space_table: [0; 16],
+                descriptors: [Descriptor::default(); DESCRIPTORS],
+                accumulator: Descriptor::default(),
+                descriptor_pointer: 0,
+            }),
+            generation: Cell::new(0),
+        };
+        mmu.reset();
+        mmu
+    }
+    pub fn reset(&mut self) {
+        let state = self.state.get_mut();
+        state.address_space_table = [0; 16];
+        state.descriptors = [Descriptor::default(); DESCRIPTORS];
+        state.descriptors[0].status = SEGMENT_ENABLED;
+        state.accumulator = Descriptor::default();
+        state.descriptor_pointer = 0;
+        self.retranslated();
+    }
+    pub fn bus(&self) -> &B {
+        &self.bus
+    }
+    pub fn bus_mut(&mut self) -> &mut B {
+        &mut self.bus
+    }
+
+    pub fn descriptor(&self, index: usize) -> Descriptor {
+        self.state.borrow().descriptors[index]
+    }
+    pub fn load_descriptor(&mut self, index: usize, descriptor: Descriptor) {
+        self.state.get_mut().descriptors[index] = descriptor;
+        self.retranslated();
+    }
+    pub fn address_space_number(&self, address_space: AddressSpace) -> u8 {
+        self.state.borrow().address_space_table[address_space.fc() as usize]
+    }
+    pub fn set_address_space_number(&mut self, address_space: AddressSpace, asn: u8) {
+        self.state.get_mut().address_space_table[address_space.fc() as usize] = asn;
+        self.retranslated();
+    }
+    fn retranslated(&self) {
+        self.generation.set(self.generation.get().wrapping_add(1));
+    }
+
+    fn find(&self, address_space: AddressSpace, address: u32) -> Option<usize> {
+        let state = self.state.borrow();
+        let asn = state.address_space_table[address_space.fc() as usize];
+        let page = (address >> 8) as u16;
+        state.descriptors.iter().position(|d| d.matches(asn, page))
+    }
+    // The physical address a logical one maps to, without marking the
+    // segment used, as a debugger would want
+    pub fn translate(&self, address_space: AddressSpace, address: u32) -> Option<u32> {
+        let address = address & ADDRBUS_MASK;
+        self.find(address_space, address).map(|index| {
+            let page = self.state.borrow().descriptors[index].translate((address >> 8) as u16);
+            u32::from(page) << 8 | address & 0xff
+        })
+    }
+    fn translate_access(&self, address_space: AddressSpace, address: u32, write: bool) -> Result<u32, BusError> {
+        let index = self.find(address_space, address).ok_or(BusError)?;
+        let mut state = self.state.borrow_mut();
+        let descriptor = &mut state.descriptors[index];
+        if write && descriptor.status & SEGMENT_WRITE_PROTECTED != 0 {
+            return Err(BusError);
+        }
+        descriptor.status |= SEGMENT_USED | if write { SEGMENT_MODIFIED } else { 0 };
+        let physical = u32::from(descriptor.translate((address >> 8) as u16)) << 8 | address & 0xff;
+        self.bus.translated(address_space, address, physical);
+        Ok(physical)
+    }
+
+    fn is_register(&self, address_space: AddressSpace, address: u32) -> bool {
+        address_space == SUPERVISOR_DATA && address.wrapping_sub(self.registers) < REGISTERS_SIZE
+    }
+    fn read_register(&self, offset: u32) -> u32 {
+        let mut state = self.state.borrow_mut();
+        let value = match offset {
+            REG_ADDRESS_SPACE_TABLE ..= 0x1f if offset & 1 == 1 => state.address_space_table[offset as usize / 2],
+            REG_LOGICAL_BASE ..= REG_ADDRESS_SPACE_MASK => state.accumulator.read_byte(offset - REG_LOGICAL_BASE),
+            REG_DESCRIPTOR_POINTER => state.descriptor_pointer,
+            REG_READ_DESCRIPTOR => {
+                let index = state.descriptor_pointer as usize % DESCRIPTORS;
+                state.accumulator = state.descriptors[index];
+                0
+            }
+            REG_LOAD_DESCRIPTOR => {
+                let index = state.descriptor_pointer as usize % DESCRIPTORS;
+                state.descriptors[index] = state.accumulator;
+                self.retranslated();
+                0
+            }
+            _ => 0,
+        };
+        u32::from(value)
+    }
+    fn write_register(&mut self, offset: u32, value: u32) {
+        let state = self.state.get_mut();
+        let value = value as u8;
+        match offset {
+            REG_ADDRESS_SPACE_TABLE ..= 0x1f if offset & 1 == 1 => {
+                state.address_space_table[offset as usize / 2] = value;
+                self.retranslated();
+            }
+            REG_LOGICAL_BASE ..= REG_ADDRESS_SPACE_MASK => state.accumulator.write_byte(offset - REG_LOGICAL_BASE, value),
+            REG_DESCRIPTOR_POINTER => state.descriptor_pointer = value,
+            _ => (),
+        }
+    }
+
+    fn read(&self, address_space: AddressSpace, address: u32, bytes: u32) -> Result<u32, BusError> {
+        let address = address & ADDRBUS_MASK;
+        if self.is_register(address_space, address) {
+            return Ok((0..bytes).fold(0, |value, i| value << 8 | self.read_register(address - self.registers + i)));
+        }
+        if (address & 0xff) + bytes > 0x100 {
+            let mut value = 0;
+            for i in 0..bytes {
+                value = value << 8 | self.read(address_space, address.wrapping_add(i), 1)?;
+            }
+            return Ok(value);
+        }
+        let physical = self.translate_access(address_space, address, false)?;
+        match bytes {
+            1 => self.bus.read_byte(address_space, physical),
+            2 => self.bus.read_word(address_space, physical),
+            _ => self.bus.read_long(address_space, physical),
+        }
+    }
+    fn write(&mut self, address_space: AddressSpace, address: u32, bytes: u32, value: u32) -> Result<(), BusError> {
+        let address = address & ADDRBUS_MASK;
+        if self.is_register(address_space, address) {
+            for i in 0..bytes {
+                let offset = address - self.registers + i;
+                self.write_register(offset, value >> (8 * (bytes - 1 - i)));
+            }
+            return Ok(());
+        }
+        if (address & 0xff) + bytes > 0x100 {
+            for i in 0..bytes {
+                let shift = 8 * (bytes - 1 - i);
+                self.write(address_space, address.wrapping_add(i), 1, (value >> shift) & 0xff)?;
+            }
+            return Ok(());
+        }
+        let physical = self.translate_access(address_space, address, true)?;
+        match bytes {
+            1 => self.bus.write_byte(address_space, physical, value),
+            2 => self.bus.write_word(address_space, physical, value),
+            _ => self.bus.write_long(address_space, physical, value),
+        }
+    }
+}
+
+impl<B: AddressBus> AddressBus for Mmu<B> {
+    // copies the memory behind the MMU, but not its descriptors
+    fn copy_from(&mut self, other: &Self) {
+        self.bus.copy_from(&other.bus);
+    }
+
+    fn read_byte(&self, address_space: AddressSpace, address: u32) -> Result<u32, BusError> {
+        self.read(address_space, address, 1)
+    }
+
+    fn read_word(&self, address_space: AddressSpace, address: u32) -> Result<u32, BusError> {
+        self.read(address_space, address, 2)
+    }
+
+    fn read_long(&self, address_space: AddressSpace, address: u32) -> Result<u32, BusError> {
+        self.read(address_space, address, 4)
+    }
+
+    fn write_byte(&mut self, address_space: AddressSpace, address: u32, value: u32) -> Result<(), BusError> {
+        self.write(address_space, address, 1, value & 0xff)
+    }
+
+    fn write_word(&mut self, address_space: AddressSpace, address: u32, value: u32) -> Result<(), BusError> {
+        self.write(address_space, address, 2, value & 0xffff)
+    }
+
+    fn write_long(&mut self, address_space: AddressSpace, address: u32, value: u32) -> Result<(), BusError> {
+        self.write(address_space, address, 4, value)
+    }
+
+    fn map_generation(&self) -> u32 {
+        self.generation.get().wrapping_add(self.bus.map_generation())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Mmu, Descriptor, SEGMENT_ENABLED, SEGMENT_USED, SEGMENT_MODIFIED, SEGMENT_WRITE_PROTECTED,
+                REG_ADDRESS_SPACE_TABLE, REG_LOGICAL_BASE, REG_PHYSICAL_BASE, REG_LOGICAL_MASK,
+                REG_SEGMENT_STATUS, REG_ADDRESS_SPACE_NUMBER, REG_ADDRESS_SPACE_MASK,
+                REG_DESCRIPTOR_POINTER, REG_LOAD_DESCRIPTOR, REG_READ_DESCRIPTOR};
+    use ram::{AddressBus, BusError, PagedMem, SUPERVISOR_DATA, SUPERVISOR_PROGRAM, USER_DATA, USER_PROGRAM};
+    use ram::loggingmem::{LoggingMem, OpsLogger, Operation};
+    use cpu::ConfiguredCore;
+    use interrupts::AutoInterruptController;
+
+    const REGISTERS: u32 = 0xff_f000;
+
+    // descriptor 0 only maps address space 0, left to the supervisor
+    fn supervisor_only<B: AddressBus>(mut mmu: Mmu<B>) -> Mmu<B> {
+        let mut identity = mmu.descriptor(0);
+        identity.address_space_mask = 0xff;
+        mmu.load_descriptor(0, identity);
+        mmu
+    }
+
+    fn mmu() -> Mmu<PagedMem> {
+        supervisor_only(Mmu::new(PagedMem::new(0), REGISTERS))
+    }
+
+    // a 4 KB segment for address space 1
+    fn user_segment(logical: u32, physical: u32) -> Descriptor {
+        Descriptor {
+            logical_base: (logical >> 8) as u16,
+            logical_mask: 0xfff0,
+            physical_base: (physical >> 8) as u16,
+            address_space_number: 1,
+            address_space_mask: 0xff,
+            status: SEGMENT_ENABLED,
+        }
+    }
+
+    #[test]
+    fn maps_one_to_one_after_reset() {
+        let mut mmu = Mmu::new(PagedMem::new(0), REGISTERS);
+        mmu.write_long(USER_DATA, 0x1234, 0xdeadbeef).unwrap();
+        assert_eq!(0xdeadbeef, mmu.read_long(SUPERVISOR_PROGRAM, 0x1234).unwrap());
+        assert_eq!(0xdeadbeef, mmu.bus().read_long(USER_DATA, 0x1234).unwrap());
+    }
+
+    #[test]
+    fn translates_per_address_space() {
+        let mut mmu = mmu();
+        mmu.load_descriptor(1, user_segment(0x0000, 0x4_0000));
+        mmu.set_address_space_number(USER_DATA, 1);
+        mmu.set_address_space_number(USER_PROGRAM, 1);
+        mmu.write_word(USER_DATA, 0x0100, 0x1234).unwrap();
+        assert_eq!(0x1234, mmu.bus().read_word(USER_DATA, 0x4_0100).unwrap());
+        assert_eq!(0x1234, mmu.read_word(USER_PROGRAM, 0x0100).unwrap());
+        // the supervisor still sees physical memory through descriptor 0
+        assert_eq!(0, mmu.read_word(SUPERVISOR_DATA, 0x0100).unwrap());
+        assert_eq!(Some(0x4_0fff), mmu.translate(USER_DATA, 0x0fff));
+    }
+
+    #[test]
+    fn unmapped_address_is_a_translation_fault() {
+        let mut mmu = mmu();
+        mmu.load_descriptor(1, user_segment(0x0000, 0x4_0000));
+        mmu.set_address_space_number(USER_DATA, 1);
+        assert_eq!(Err(BusError), mmu.read_byte(USER_DATA, 0x1000));
+        assert_eq!(Err(BusError), mmu.write_byte(USER_DATA, 0x1000, 0));
+        assert_eq!(None, mmu.translate(USER_DATA, 0x1000));
+    }
+
+    #[test]
+    fn write_to_write_protected_segment_faults() {
+        let mut mmu = mmu();
+        let mut segment = user_segment(0x0000, 0x4_0000);
+        segment.status |= SEGMENT_WRITE_PROTECTED;
+        mmu.load_descriptor(1, segment);
+        mmu.set_address_space_number(USER_PROGRAM, 1);
+        assert_eq!(Ok(0), mmu.read_word(USER_PROGRAM, 0x10));
+        assert_eq!(Err(BusError), mmu.write_word(USER_PROGRAM, 0x10, 0x4e71));
+    }
+
+    #[test]
+    fn marks_segments_used_and_modified() {
+        let mut mmu = mmu();
+        mmu.load_descriptor(1, user_segment(0x0000, 0x4_0000));
+        mmu.set_address_space_number(USER_DATA, 1);
+        mmu.read_byte(USER_DATA, 0x10).unwrap();
+        assert_eq!(SEGMENT_ENABLED | SEGMENT_USED, mmu.descriptor(1).status);
+        mmu.write_byte(USER_DATA, 0x10, 1).unwrap();
+        assert_eq!(SEGMENT_ENABLED | SEGMENT_USED | SEGMENT_MODIFIED, mmu.descriptor(1).status);
+    }
+
+    #[test]
+    fn long_access_across_pages_is_translated_per_byte() {
+        let mut mmu = mmu();
+        mmu.load_descriptor(1, user_segment(0x0000, 0x4_0000));
+        mmu.load_descriptor(2, user_segment(0x1000, 0x8_0000));
+        mmu.set_address_space_number(USER_DATA, 1);
+        mmu.write_long(USER_DATA, 0x0ffe, 0x11223344).unwrap();
+        assert_eq!(0x1122, mmu.bus().read_word(USER_DATA, 0x4_0ffe).unwrap());
+        assert_eq!(0x3344, mmu.bus().read_word(USER_DATA, 0x8_0000).unwrap());
+        assert_eq!(0x11223344, mmu.read_long(USER_DATA, 0x0ffe).unwrap());
+    }
+
+    #[test]
+    fn is_programmed_through_its_registers() {
+        let mut mmu = mmu();
+        // accumulator: $1000-$1fff in address space 2 at $20000
+        mmu.write_word(SUPERVISOR_DATA, REGISTERS + REG_LOGICAL_BASE, 0x0010).unwrap();
+        mmu.write_word(SUPERVISOR_DATA, REGISTERS + REG_LOGICAL_MASK, 0xfff0).unwrap();
+        mmu.write_word(SUPERVISOR_DATA, REGISTERS + REG_PHYSICAL_BASE, 0x0200).unwrap();
+        mmu.write_byte(SUPERVISOR_DATA, REGISTERS + REG_ADDRESS_SPACE_NUMBER, 2).unwrap();
+        mmu.write_byte(SUPERVISOR_DATA, REGISTERS + REG_SEGMENT_STATUS, u32::from(SEGMENT_ENABLED)).unwrap();
+        mmu.write_byte(SUPERVISOR_DATA, REGISTERS + REG_ADDRESS_SPACE_MASK, 0xff).unwrap();
+        mmu.write_byte(SUPERVISOR_DATA, REGISTERS + REG_DESCRIPTOR_POINTER, 3).unwrap();
+        mmu.read_byte(SUPERVISOR_DATA, REGISTERS + REG_LOAD_DESCRIPTOR).unwrap();
+        // function code 1 (user data) uses address space 2
+        mmu.write_byte(SUPERVISOR_DATA, REGISTERS + REG_ADDRESS_SPACE_TABLE + 2 * 1 + 1, 2).unwrap();
+        assert_eq!(2, mmu.read_word(SUPERVISOR_DATA, REGISTERS + REG_ADDRESS_SPACE_TABLE + 2).unwrap());
+        assert_eq!(Some(0x2_0abc), mmu.translate(USER_DATA, 0x1abc));
+
+        mmu.write_long(SUPERVISOR_DATA, REGISTERS + REG_LOGICAL_BASE, 0).unwrap();
+        mmu.read_byte(SUPERVISOR_DATA, REGISTERS + REG_READ_DESCRIPTOR).unwrap();
+        assert_eq!(0x0010_fff0, mmu.read_long(SUPERVISOR_DATA, REGISTERS + REG_LOGICAL_BASE).unwrap());
+        // the registers are not in user space
+        assert_eq!(None, mmu.translate(USER_DATA, REGISTERS));
+    }
+
+    #[test]
+    fn logging_mem_sees_logical_and_physical_addresses() {
+        let mut mmu = supervisor_only(Mmu::new(LoggingMem::new(0, OpsLogger::new()), REGISTERS));
+        mmu.load_descriptor(1, user_segment(0x0000, 0x4_0000));
+        mmu.set_address_space_number(USER_DATA, 1);
+        mmu.write_word(USER_DATA, 0x0100, 0x1234).unwrap();
+        assert_eq!(vec![
+            Operation::Translate(USER_DATA, 0x0100, 0x4_0100),
+            Operation::WriteWord(USER_DATA, 0x4_0100, 0x1234),
+        ], mmu.bus().logger.ops());
+    }
+
+    #[test]
+    fn reloading_a_descriptor_flushes_the_block_cache() {
+        // 7001 60fc is MOVEQ #1, D0 and BRA.S back to it, at $1000, and
+        // 7002 60fc the same with #2, at $11000
+        let mut mem = PagedMem::new(0);
+        mem.write_long(SUPERVISOR_DATA, 0, 0x800).unwrap();
+        mem.write_long(SUPERVISOR_DATA, 4, 0x1000).unwrap();
+        mem.write_long(SUPERVISOR_DATA, 0x1000, 0x700160fc).unwrap();
+        mem.write_long(SUPERVISOR_DATA, 0x11000, 0x700260fc).unwrap();
+        let mut cpu = ConfiguredCore::new_with(0, AutoInterruptController::new(), Mmu::new(mem, REGISTERS));
+        cpu.set_block_cache(true);
+        cpu.reset();
+        cpu.execute(100);
+        assert_eq!(1, cpu.dar[0]);
+
+        // the first 64 KB moved up by 64 KB, from the host
+        let mut moved = cpu.mem.descriptor(0);
+        moved.logical_mask = 0xff00;
+        moved.physical_base = 0x0100;
+        cpu.mem.load_descriptor(0, moved);
+        cpu.execute(100);
+        assert_eq!(2, cpu.dar[0]);
+
+        // and back, through the registers
+        cpu.mem.write_byte(SUPERVISOR_DATA, REGISTERS + REG_DESCRIPTOR_POINTER, 0).unwrap();
+        cpu.mem.read_byte(SUPERVISOR_DATA, REGISTERS + REG_READ_DESCRIPTOR).unwrap();
+        cpu.mem.write_word(SUPERVISOR_DATA, REGISTERS + REG_PHYSICAL_BASE, 0).unwrap();
+        cpu.mem.read_byte(SUPERVISOR_DATA, REGISTERS + REG_LOAD_DESCRIPTOR).unwrap();
+        cpu.execute(100);
+        assert_eq!(1, cpu.dar[0]);
+    }
+}
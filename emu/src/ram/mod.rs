@@ -1,9 +1,23 @@
+pub mod fcbus;
+pub mod flatmem;
+pub mod journal;
 pub mod loggingmem;
+pub mod mappedbus;
+pub mod mmu;
 pub mod pagedmem;
+pub mod sanitizer;
+pub mod sharedbus;
 pub use self::pagedmem::PagedMem;
+pub use self::flatmem::FlatMem;
 // The m68k had a 24 bit external address bus with
 // (2^24 bytes = ) 16 MB addressable space
 pub const ADDRBUS_MASK: u32 = 0x00ff_ffff;
+// while the 68020 brings out all 32 address lines (4 GB)
+pub const ADDRBUS_MASK_32: u32 = 0xffff_ffff;
+// and the 68008 only 20 (1 MB) in its 48-pin package, or 22 (4 MB) in
+// the 52-pin one
+pub const ADDRBUS_MASK_20: u32 = 0x000f_ffff;
+pub const ADDRBUS_MASK_22: u32 = 0x003f_ffff;
 
 #[derive(Copy, Clone, Hash, Eq, PartialEq)]
 pub struct AddressSpace(Mode, Segment);
@@ -17,6 +31,24 @@ impl AddressSpace {
             SUPERVISOR_PROGRAM => 6,
         }
     }
+    // function codes 0, 3 and 4 are reserved, 7 is CPU space
+    pub fn from_fc(fc: u32) -> Option<AddressSpace> {
+        match fc {
+            1 => Some(USER_DATA),
+            2 => Some(USER_PROGRAM),
+            5 => Some(SUPERVISOR_DATA),
+            6 => Some(SUPERVISOR_PROGRAM),
+            _ => None,
+        }
+    }
+    // bit n set for function code n, so that a set of address spaces
+    // fits a byte
+    pub fn fc_bit(self) -> u8 {
+        1 << self.fc()
+    }
+    pub fn fc_bits(address_spaces: &[AddressSpace]) -> u8 {
+        address_spaces.iter().fold(0, |fcs, &space| fcs | space.fc_bit())
+    }
 }
 use std::fmt;
 impl fmt::Debug for AddressSpace {
@@ -41,13 +73,31 @@ pub const SUPERVISOR_DATA: AddressSpace = AddressSpace(Mode::Supervisor, Segment
 pub const USER_PROGRAM: AddressSpace = AddressSpace(Mode::User, Segment::Program);
 pub const USER_DATA: AddressSpace = AddressSpace(Mode::User, Segment::Data);
 
-pub trait AddressBus {
+// Returned by a memory implementation refusing an access, such as to an
+// unmapped or protected address. The CPU turns it into a bus error
+// exception, as it knows the details needed for the stack frame.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct BusError;
+
+// buses are 'static so that cores can be told apart by type, to share
+// their instruction sets
+pub trait AddressBus: 'static {
     fn copy_from(&mut self, other: &Self);
-    fn read_byte(&self, address_space: AddressSpace, address: u32) -> u32;
-    fn read_word(&self, address_space: AddressSpace, address: u32) -> u32;
-    fn read_long(&self, address_space: AddressSpace, address: u32) -> u32;
-    fn write_byte(&mut self, address_space: AddressSpace, address: u32, value: u32);
-    fn write_word(&mut self, address_space: AddressSpace, address: u32, value: u32);
-    fn write_long(&mut self, address_space: AddressSpace, address: u32, value: u32);
+    fn read_byte(&self, address_space: AddressSpace, address: u32) -> Result<u32, BusError>;
+    fn read_word(&self, address_space: AddressSpace, address: u32) -> Result<u32, BusError>;
+    fn read_long(&self, address_space: AddressSpace, address: u32) -> Result<u32, BusError>;
+    fn write_byte(&mut self, address_space: AddressSpace, address: u32, value: u32) -> Result<(), BusError>;
+    fn write_word(&mut self, address_space: AddressSpace, address: u32, value: u32) -> Result<(), BusError>;
+    fn write_long(&mut self, address_space: AddressSpace, address: u32, value: u32) -> Result<(), BusError>;
+    // Called by a translating layer, such as the MMU, before it passes
+    // on an access to the given physical address, so that a logging
+    // implementation can relate it to the logical address of the CPU
+    fn translated(&self, _address_space: AddressSpace, _logical: u32, _physical: u32) {}
+    // Changes whenever what is mapped where changes other than by a
+    // write, such as when a boot ROM overlay ends on its own, so that a
+    // core can tell when its block cache is stale
+    fn map_generation(&self) -> u32 {
+        0
+    }
 }
 
@@ -1,15 +1,17 @@
 use std::collections::HashMap;
-use super::{AddressSpace, AddressBus, ADDRBUS_MASK};
+use std::io;
+use std::io::{Read, Write};
+use super::{AddressSpace, AddressBus, BusError, ADDRBUS_MASK};
 
 const PAGE_SIZE: u32 = 16; // 16 bytes page size
 const ADDR_MASK: u32 = PAGE_SIZE - 1; // all ones
-const PAGE_MASK: u32 = ADDRBUS_MASK ^ ADDR_MASK;
 
 type Page = Vec<u8>;
 
 pub struct PagedMem {
     pages: HashMap<u32, Page>,
     pub initializer: u32,
+    address_mask: u32,
 }
 
 impl PagedMem {
@@ -17,8 +19,11 @@ impl PagedMem {
     fn allocated_pages(&self) -> usize {
         self.pages.len()
     }
+    fn page_mask(&self) -> u32 {
+        self.address_mask ^ ADDR_MASK
+    }
     fn new_page_is_needed(&self, address: u32, value_to_write: u8) -> bool {
-        let pageno = address & PAGE_MASK;
+        let pageno = address & self.page_mask();
         let write_differs_from_initializer = value_to_write as u8 != self.read_initializer(address);
         !self.pages.contains_key(&pageno) && write_differs_from_initializer
     }
@@ -26,7 +31,7 @@ impl PagedMem {
     // only if we are going to need to write an interesting value to it
     // i.e. one that differs from the initializer
     fn page_if_needed(&mut self, address: u32, value_to_write: u8) -> Option<&mut Page> {
-        let pageno = address & PAGE_MASK;
+        let pageno = address & self.page_mask();
         if self.new_page_is_needed(address, value_to_write) {
             self.create_initialized_page(pageno);
         }
@@ -51,7 +56,7 @@ impl PagedMem {
         ((self.initializer >> shift) & 0xFF) as u8
     }
     pub fn read_u8(&self, address: u32) -> u32 {
-        let pageno = address & PAGE_MASK;
+        let pageno = address & self.page_mask();
         if let Some(page) = self.pages.get(&pageno) {
             let index = (address & ADDR_MASK) as usize;
             u32::from(page[index])
@@ -73,9 +78,75 @@ impl PagedMem {
     }
 }
 
+const SNAPSHOT_MAGIC: &[u8; 4] = b"r68m";
+const SNAPSHOT_VERSION: u16 = 2;
+
+fn read_u32<R: Read>(input: &mut R) -> io::Result<u32> {
+    let mut buf = [0u8; 4];
+    input.read_exact(&mut buf)?;
+    Ok(u32::from_be_bytes(buf))
+}
+
 impl PagedMem {
     pub fn new(initializer: u32) -> PagedMem {
-        PagedMem { pages: HashMap::new(), initializer }
+        PagedMem::with_address_mask(initializer, ADDRBUS_MASK)
+    }
+    // addresses are masked to the bus width, so that memory beyond it
+    // mirrors the addressable space
+    pub fn with_address_mask(initializer: u32, address_mask: u32) -> PagedMem {
+        PagedMem { pages: HashMap::new(), initializer, address_mask }
+    }
+    pub fn address_mask(&self) -> u32 {
+        self.address_mask
+    }
+
+    // A binary snapshot of the memory contents: the initializer and
+    // address mask followed by the differing bytes as (address, length,
+    // bytes) runs, big-endian
+    pub fn write_snapshot<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        out.write_all(SNAPSHOT_MAGIC)?;
+        out.write_all(&SNAPSHOT_VERSION.to_be_bytes())?;
+        out.write_all(&self.initializer.to_be_bytes())?;
+        out.write_all(&self.address_mask.to_be_bytes())?;
+        let mut runs: Vec<(u32, Vec<u8>)> = Vec::new();
+        for (address, byte) in self.diffs() {
+            match runs.last_mut() {
+                Some(&mut (start, ref mut bytes)) if start + bytes.len() as u32 == address =>
+                    bytes.push(byte),
+                _ => runs.push((address, vec![byte])),
+            }
+        }
+        out.write_all(&(runs.len() as u32).to_be_bytes())?;
+        for (start, bytes) in runs {
+            out.write_all(&start.to_be_bytes())?;
+            out.write_all(&(bytes.len() as u32).to_be_bytes())?;
+            out.write_all(&bytes)?;
+        }
+        Ok(())
+    }
+
+    pub fn read_snapshot<R: Read>(input: &mut R) -> io::Result<PagedMem> {
+        let mut header = [0u8; 6];
+        input.read_exact(&mut header)?;
+        if &header[0..4] != SNAPSHOT_MAGIC || header[4..6] != SNAPSHOT_VERSION.to_be_bytes() {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "not a supported memory snapshot"));
+        }
+        let initializer = read_u32(input)?;
+        let address_mask = read_u32(input)?;
+        let mut mem = PagedMem::with_address_mask(initializer, address_mask);
+        for _ in 0..read_u32(input)? {
+            let start = read_u32(input)?;
+            let len = read_u32(input)?;
+            if start & !address_mask != 0 || u64::from(start) + u64::from(len) > u64::from(address_mask) + 1 {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "memory snapshot run out of range"));
+            }
+            let mut bytes = vec![0u8; len as usize];
+            input.read_exact(&mut bytes)?;
+            for (offset, byte) in bytes.iter().enumerate() {
+                mem.write_u8(start + offset as u32, u32::from(*byte));
+            }
+        }
+        Ok(mem)
     }
 }
 
@@ -106,68 +177,71 @@ impl AddressBus for PagedMem {
         }
     }
 
-    fn read_byte(&self, _address_space: AddressSpace, address: u32) -> u32 {
-        self.read_u8(address)
+    fn read_byte(&self, _address_space: AddressSpace, address: u32) -> Result<u32, BusError> {
+        Ok(self.read_u8(address))
     }
 
-    fn read_word(&self, _address_space: AddressSpace, address: u32) -> u32 {
-        (self.read_u8(address) << 8
-        |self.read_u8(address.wrapping_add(1))) as u32
+    fn read_word(&self, _address_space: AddressSpace, address: u32) -> Result<u32, BusError> {
+        Ok((self.read_u8(address) << 8
+        |self.read_u8(address.wrapping_add(1))) as u32)
     }
 
-    fn read_long(&self, _address_space: AddressSpace, address: u32) -> u32 {
-        (self.read_u8(address) << 24
+    fn read_long(&self, _address_space: AddressSpace, address: u32) -> Result<u32, BusError> {
+        Ok((self.read_u8(address) << 24
         |self.read_u8(address.wrapping_add(1)) << 16
         |self.read_u8(address.wrapping_add(2)) <<  8
-        |self.read_u8(address.wrapping_add(3))) as u32
+        |self.read_u8(address.wrapping_add(3))) as u32)
     }
 
-    fn write_byte(&mut self, _address_space: AddressSpace, address: u32, value: u32) {
+    fn write_byte(&mut self, _address_space: AddressSpace, address: u32, value: u32) -> Result<(), BusError> {
         self.write_u8(address, value);
+        Ok(())
     }
 
-    fn write_word(&mut self, _address_space: AddressSpace, address: u32, value: u32) {
+    fn write_word(&mut self, _address_space: AddressSpace, address: u32, value: u32) -> Result<(), BusError> {
         self.write_u8(address, value >>  8);
         self.write_u8(address.wrapping_add(1), value);
+        Ok(())
     }
 
-    fn write_long(&mut self, _address_space: AddressSpace, address: u32, value: u32) {
+    fn write_long(&mut self, _address_space: AddressSpace, address: u32, value: u32) -> Result<(), BusError> {
         self.write_u8(address, value >> 24);
         self.write_u8(address.wrapping_add(1), value >> 16);
         self.write_u8(address.wrapping_add(2), value >>  8);
         self.write_u8(address.wrapping_add(3), value);
+        Ok(())
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::{AddressBus, PagedMem, PAGE_SIZE};
-    use ram::{SUPERVISOR_DATA, SUPERVISOR_PROGRAM, USER_DATA, USER_PROGRAM, ADDRBUS_MASK};
+    use ram::{SUPERVISOR_DATA, SUPERVISOR_PROGRAM, USER_DATA, USER_PROGRAM, ADDRBUS_MASK, ADDRBUS_MASK_32};
 
     #[test]
     fn read_initialized_memory() {
         let mem = PagedMem::new(0x01020304);
         for v in 0..256 {
-            assert_eq!(0x01, mem.read_byte(SUPERVISOR_DATA, 4*v+0));
-            assert_eq!(0x02, mem.read_byte(SUPERVISOR_DATA, 4*v+1));
-            assert_eq!(0x03, mem.read_byte(SUPERVISOR_DATA, 4*v+2));
-            assert_eq!(0x04, mem.read_byte(SUPERVISOR_DATA, 4*v+3));
+            assert_eq!(0x01, mem.read_byte(SUPERVISOR_DATA, 4*v+0).unwrap());
+            assert_eq!(0x02, mem.read_byte(SUPERVISOR_DATA, 4*v+1).unwrap());
+            assert_eq!(0x03, mem.read_byte(SUPERVISOR_DATA, 4*v+2).unwrap());
+            assert_eq!(0x04, mem.read_byte(SUPERVISOR_DATA, 4*v+3).unwrap());
         }
         for v in 0..256 {
-            assert_eq!(0x0102, mem.read_word(SUPERVISOR_DATA, 4*v+0));
-            assert_eq!(0x0203, mem.read_word(SUPERVISOR_DATA, 4*v+1));
-            assert_eq!(0x0304, mem.read_word(SUPERVISOR_DATA, 4*v+2));
+            assert_eq!(0x0102, mem.read_word(SUPERVISOR_DATA, 4*v+0).unwrap());
+            assert_eq!(0x0203, mem.read_word(SUPERVISOR_DATA, 4*v+1).unwrap());
+            assert_eq!(0x0304, mem.read_word(SUPERVISOR_DATA, 4*v+2).unwrap());
             if 4*v+3 < 1023 {
-                assert_eq!(0x0401, mem.read_word(SUPERVISOR_DATA, 4*v+3));
+                assert_eq!(0x0401, mem.read_word(SUPERVISOR_DATA, 4*v+3).unwrap());
             }
         }
         for v in 0..255 {
-            assert_eq!(0x01020304, mem.read_long(SUPERVISOR_DATA, 4*v+0));
-            assert_eq!(0x02030401, mem.read_long(SUPERVISOR_DATA, 4*v+1));
-            assert_eq!(0x03040102, mem.read_long(SUPERVISOR_DATA, 4*v+2));
-            assert_eq!(0x04010203, mem.read_long(SUPERVISOR_DATA, 4*v+3));
+            assert_eq!(0x01020304, mem.read_long(SUPERVISOR_DATA, 4*v+0).unwrap());
+            assert_eq!(0x02030401, mem.read_long(SUPERVISOR_DATA, 4*v+1).unwrap());
+            assert_eq!(0x03040102, mem.read_long(SUPERVISOR_DATA, 4*v+2).unwrap());
+            assert_eq!(0x04010203, mem.read_long(SUPERVISOR_DATA, 4*v+3).unwrap());
         }
-        assert_eq!(0x01020304, mem.read_long(SUPERVISOR_DATA, 4*255));
+        assert_eq!(0x01020304, mem.read_long(SUPERVISOR_DATA, 4*255).unwrap());
     }
 
     #[test]
@@ -175,9 +249,9 @@ mod tests {
         let mut mem = PagedMem::new(0x01020304);
         let pattern = 0xAAAA7777;
         let address = 128;
-        assert!(pattern != mem.read_long(SUPERVISOR_DATA, address));
-        mem.write_long(SUPERVISOR_DATA, address, pattern);
-        assert_eq!(pattern, mem.read_long(SUPERVISOR_DATA, address));
+        assert!(pattern != mem.read_long(SUPERVISOR_DATA, address).unwrap());
+        mem.write_long(SUPERVISOR_DATA, address, pattern).unwrap();
+        assert_eq!(pattern, mem.read_long(SUPERVISOR_DATA, address).unwrap());
     }
 
     #[test]
@@ -185,9 +259,9 @@ mod tests {
         let mut mem = PagedMem::new(0x01020304);
         let pattern = 0xAAAA7777;
         let address = 128;
-        assert!(pattern != mem.read_word(SUPERVISOR_DATA, address));
-        mem.write_word(SUPERVISOR_DATA, address, pattern);
-        assert_eq!(pattern & 0xFFFF, mem.read_word(SUPERVISOR_DATA, address));
+        assert!(pattern != mem.read_word(SUPERVISOR_DATA, address).unwrap());
+        mem.write_word(SUPERVISOR_DATA, address, pattern).unwrap();
+        assert_eq!(pattern & 0xFFFF, mem.read_word(SUPERVISOR_DATA, address).unwrap());
     }
 
     #[test]
@@ -195,9 +269,9 @@ mod tests {
         let mut mem = PagedMem::new(0x01020304);
         let pattern = 0xAAAA7777;
         let address = 128;
-        assert!(pattern != mem.read_byte(SUPERVISOR_DATA, address));
-        mem.write_byte(SUPERVISOR_DATA, address, pattern);
-        assert_eq!(pattern & 0xFF, mem.read_byte(SUPERVISOR_DATA, address));
+        assert!(pattern != mem.read_byte(SUPERVISOR_DATA, address).unwrap());
+        mem.write_byte(SUPERVISOR_DATA, address, pattern).unwrap();
+        assert_eq!(pattern & 0xFF, mem.read_byte(SUPERVISOR_DATA, address).unwrap());
     }
 
     #[test]
@@ -205,16 +279,16 @@ mod tests {
         let mut mem = PagedMem::new(0x01020304);
         let pattern = 0xAAAA7777;
         let address = 128;
-        assert!(pattern != mem.read_long(SUPERVISOR_DATA, address));
-        assert!(pattern != mem.read_long(SUPERVISOR_PROGRAM, address));
-        assert!(pattern != mem.read_long(USER_DATA, address));
-        assert!(pattern != mem.read_long(USER_PROGRAM, address));
-        mem.write_long(SUPERVISOR_DATA, address, pattern);
+        assert!(pattern != mem.read_long(SUPERVISOR_DATA, address).unwrap());
+        assert!(pattern != mem.read_long(SUPERVISOR_PROGRAM, address).unwrap());
+        assert!(pattern != mem.read_long(USER_DATA, address).unwrap());
+        assert!(pattern != mem.read_long(USER_PROGRAM, address).unwrap());
+        mem.write_long(SUPERVISOR_DATA, address, pattern).unwrap();
 
-        assert_eq!(pattern, mem.read_long(SUPERVISOR_DATA, address));
-        assert_eq!(pattern, mem.read_long(SUPERVISOR_PROGRAM, address));
-        assert_eq!(pattern, mem.read_long(USER_DATA, address));
-        assert_eq!(pattern, mem.read_long(USER_PROGRAM, address));
+        assert_eq!(pattern, mem.read_long(SUPERVISOR_DATA, address).unwrap());
+        assert_eq!(pattern, mem.read_long(SUPERVISOR_PROGRAM, address).unwrap());
+        assert_eq!(pattern, mem.read_long(USER_DATA, address).unwrap());
+        assert_eq!(pattern, mem.read_long(USER_PROGRAM, address).unwrap());
     }
 
     #[test]
@@ -226,24 +300,24 @@ mod tests {
         // no pages allocated
         assert_eq!(0, mem.allocated_pages());
         // no pages allocated after read
-        mem.read_long(SUPERVISOR_DATA, address);
+        mem.read_long(SUPERVISOR_DATA, address).unwrap();
         // no pages allocated after read of different page
-        mem.read_long(SUPERVISOR_DATA, address + PAGE_SIZE * 10);
+        mem.read_long(SUPERVISOR_DATA, address + PAGE_SIZE * 10).unwrap();
         assert_eq!(0, mem.allocated_pages());
         // one page allocated after write
-        mem.write_long(SUPERVISOR_DATA, address, data);
+        mem.write_long(SUPERVISOR_DATA, address, data).unwrap();
         assert_eq!(1, mem.allocated_pages());
         // no more pages allocated after more writing on same page
-        mem.write_long(SUPERVISOR_DATA, address + 1, data);
+        mem.write_long(SUPERVISOR_DATA, address + 1, data).unwrap();
         assert_eq!(1, mem.allocated_pages());
         // an additional page allocated after writing on new page
-        mem.write_long(SUPERVISOR_DATA, address + PAGE_SIZE * 10, data);
+        mem.write_long(SUPERVISOR_DATA, address + PAGE_SIZE * 10, data).unwrap();
         assert_eq!(2, mem.allocated_pages());
         // no additional pages allocated after reading over new page boundary
-        mem.read_long(SUPERVISOR_DATA, address + 4*PAGE_SIZE - 2);
+        mem.read_long(SUPERVISOR_DATA, address + 4*PAGE_SIZE - 2).unwrap();
         assert_eq!(2, mem.allocated_pages());
         // two additional pages allocated after writing over new page boundary
-        mem.write_long(SUPERVISOR_DATA, address + 4*PAGE_SIZE - 2, data);
+        mem.write_long(SUPERVISOR_DATA, address + 4*PAGE_SIZE - 2, data).unwrap();
         assert_eq!(4, mem.allocated_pages());
     }
 
@@ -253,23 +327,23 @@ mod tests {
         let data = 0x01020304;
         let mut mem = PagedMem::new(data);
         for offset in 0..PAGE_SIZE/4 {
-            mem.write_long(SUPERVISOR_DATA, 4*offset, data);
+            mem.write_long(SUPERVISOR_DATA, 4*offset, data).unwrap();
         }
-        mem.write_byte(SUPERVISOR_DATA, 0, 0x1);
-        mem.write_byte(SUPERVISOR_DATA, 1, 0x2);
-        mem.write_byte(SUPERVISOR_DATA, 2, 0x3);
-        mem.write_byte(SUPERVISOR_DATA, 3, 0x4);
+        mem.write_byte(SUPERVISOR_DATA, 0, 0x1).unwrap();
+        mem.write_byte(SUPERVISOR_DATA, 1, 0x2).unwrap();
+        mem.write_byte(SUPERVISOR_DATA, 2, 0x3).unwrap();
+        mem.write_byte(SUPERVISOR_DATA, 3, 0x4).unwrap();
 
-        mem.write_word(SUPERVISOR_DATA, 3, 0x0401);
+        mem.write_word(SUPERVISOR_DATA, 3, 0x0401).unwrap();
 
         // no pages allocated
         assert_eq!(0, mem.allocated_pages());
         // but as soon as we write something different
-        mem.write_byte(SUPERVISOR_DATA, 2, 0x2);
+        mem.write_byte(SUPERVISOR_DATA, 2, 0x2).unwrap();
         // a page is allocated
         assert_eq!(1, mem.allocated_pages());
         // we don't need to allocate a second page if we overwrite existing data
-        mem.write_byte(SUPERVISOR_DATA, 2, 0x99);
+        mem.write_byte(SUPERVISOR_DATA, 2, 0x99).unwrap();
         assert_eq!(1, mem.allocated_pages());
     }
 
@@ -284,8 +358,8 @@ mod tests {
     fn can_extract_diffs()
     {
         let mut mem = PagedMem::new(0x01020304);
-        mem.write_byte(SUPERVISOR_DATA, PAGE_SIZE * 10, 0x91);
-        mem.write_byte(SUPERVISOR_DATA, PAGE_SIZE * 20, 0x92);
+        mem.write_byte(SUPERVISOR_DATA, PAGE_SIZE * 10, 0x91).unwrap();
+        mem.write_byte(SUPERVISOR_DATA, PAGE_SIZE * 20, 0x92).unwrap();
         assert_eq!(2, mem.allocated_pages());
         let diffs: Vec<(u32, u8)> = mem.diffs().collect();
         assert_eq!((PAGE_SIZE * 10, 0x91), diffs[0]);
@@ -296,8 +370,8 @@ mod tests {
     fn extracts_two_full_pages_of_diffs()
     {
         let mut mem = PagedMem::new(0x01020304);
-        mem.write_byte(SUPERVISOR_DATA, PAGE_SIZE * 10, 0x91);
-        mem.write_byte(SUPERVISOR_DATA, PAGE_SIZE * 20, 0x92);
+        mem.write_byte(SUPERVISOR_DATA, PAGE_SIZE * 10, 0x91).unwrap();
+        mem.write_byte(SUPERVISOR_DATA, PAGE_SIZE * 20, 0x92).unwrap();
 
         assert_eq!(PAGE_SIZE as usize * mem.allocated_pages(), mem.diffs().count());
     }
@@ -305,24 +379,24 @@ mod tests {
     #[test]
     fn cross_address_bus_boundary_byte_access() {
         let mut mem = PagedMem::new(0x01020304);
-        mem.write_byte(SUPERVISOR_DATA, ADDRBUS_MASK, 0x91);
-        assert_eq!(0x91, mem.read_byte(SUPERVISOR_DATA, ADDRBUS_MASK));
-        mem.write_byte(SUPERVISOR_DATA, ADDRBUS_MASK+1, 0x92);
-        assert_eq!(0x92, mem.read_byte(SUPERVISOR_DATA, 0));
+        mem.write_byte(SUPERVISOR_DATA, ADDRBUS_MASK, 0x91).unwrap();
+        assert_eq!(0x91, mem.read_byte(SUPERVISOR_DATA, ADDRBUS_MASK).unwrap());
+        mem.write_byte(SUPERVISOR_DATA, ADDRBUS_MASK+1, 0x92).unwrap();
+        assert_eq!(0x92, mem.read_byte(SUPERVISOR_DATA, 0).unwrap());
     }
 
     #[test]
     fn cross_address_bus_boundary_word_access() {
         let mut mem = PagedMem::new(0x01020304);
-        mem.write_word(SUPERVISOR_DATA, ADDRBUS_MASK+1, 0x9192);
-        assert_eq!(0x9192, mem.read_word(SUPERVISOR_DATA, 0));
+        mem.write_word(SUPERVISOR_DATA, ADDRBUS_MASK+1, 0x9192).unwrap();
+        assert_eq!(0x9192, mem.read_word(SUPERVISOR_DATA, 0).unwrap());
     }
 
     #[test]
     fn cross_address_bus_boundary_long_access() {
         let mut mem = PagedMem::new(0x01020304);
-        mem.write_long(SUPERVISOR_DATA, ADDRBUS_MASK-1, 0x91929394);
-        assert_eq!(0x91929394, mem.read_long(SUPERVISOR_DATA, ADDRBUS_MASK-1));
+        mem.write_long(SUPERVISOR_DATA, ADDRBUS_MASK-1, 0x91929394).unwrap();
+        assert_eq!(0x91929394, mem.read_long(SUPERVISOR_DATA, ADDRBUS_MASK-1).unwrap());
     }
 
     #[test]
@@ -330,8 +404,8 @@ mod tests {
         let mut mem = PagedMem::new(0x01020304);
 
         let addr = u32::max_value()-1;
-        mem.write_word(SUPERVISOR_DATA, addr, 0x9192);
-        assert_eq!(0x9192, mem.read_word(SUPERVISOR_DATA, addr));
+        mem.write_word(SUPERVISOR_DATA, addr, 0x9192).unwrap();
+        assert_eq!(0x9192, mem.read_word(SUPERVISOR_DATA, addr).unwrap());
     }
 
     #[test]
@@ -339,7 +413,56 @@ mod tests {
         let mut mem = PagedMem::new(0x01020304);
 
         let addr = u32::max_value()-1;
-        mem.write_long(SUPERVISOR_DATA, addr, 0x91929394);
-        assert_eq!(0x91929394, mem.read_long(SUPERVISOR_DATA, addr));
+        mem.write_long(SUPERVISOR_DATA, addr, 0x91929394).unwrap();
+        assert_eq!(0x91929394, mem.read_long(SUPERVISOR_DATA, addr).unwrap());
+    }
+
+    #[test]
+    fn snapshot_roundtrips_contents() {
+        let mut mem = PagedMem::new(0x01020304);
+        mem.write_long(SUPERVISOR_DATA, 0x100, 0xdeadbeef).unwrap();
+        mem.write_long(SUPERVISOR_DATA, 0x104, 0xcafebabe).unwrap();
+        mem.write_byte(SUPERVISOR_DATA, ADDRBUS_MASK, 0x55).unwrap();
+        let mut bytes = Vec::new();
+        mem.write_snapshot(&mut bytes).unwrap();
+        let restored = PagedMem::read_snapshot(&mut &bytes[..]).unwrap();
+        assert_eq!(0x01020304, restored.initializer);
+        assert_eq!(mem.diffs().collect::<Vec<_>>(), restored.diffs().collect::<Vec<_>>());
+        assert_eq!(0xcafebabe, restored.read_long(SUPERVISOR_DATA, 0x104).unwrap());
+    }
+
+    #[test]
+    fn snapshot_of_untouched_memory_has_no_runs() {
+        let mut bytes = Vec::new();
+        PagedMem::new(0).write_snapshot(&mut bytes).unwrap();
+        assert_eq!(4 + 2 + 4 + 4 + 4, bytes.len());
+    }
+
+    #[test]
+    fn rejects_snapshot_run_beyond_address_bus() {
+        let mut bytes = Vec::new();
+        PagedMem::new(0).write_snapshot(&mut bytes).unwrap();
+        bytes[17] = 1; // one run
+        bytes.extend_from_slice(&[0x00, 0xff, 0xff, 0xff, 0x00, 0x00, 0x00, 0x02, 0x01, 0x02]);
+        assert!(PagedMem::read_snapshot(&mut &bytes[..]).is_err());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn accepts_snapshot_run_ending_at_top_of_32_bit_bus() {
+        let mut mem = PagedMem::with_address_mask(0, ADDRBUS_MASK_32);
+        mem.write_word(SUPERVISOR_DATA, 0xffff_fffe, 0x0102).unwrap();
+        let mut bytes = Vec::new();
+        mem.write_snapshot(&mut bytes).unwrap();
+        let restored = PagedMem::read_snapshot(&mut &bytes[..]).unwrap();
+        assert_eq!(ADDRBUS_MASK_32, restored.address_mask());
+        assert_eq!(0x0102, restored.read_word(SUPERVISOR_DATA, 0xffff_fffe).unwrap());
+    }
+
+    #[test]
+    fn full_32_bit_bus_does_not_mirror() {
+        let mut mem = PagedMem::with_address_mask(0, ADDRBUS_MASK_32);
+        mem.write_byte(SUPERVISOR_DATA, 0x0100_0000, 0x91).unwrap();
+        assert_eq!(0x00, mem.read_byte(SUPERVISOR_DATA, 0x0000_0000).unwrap());
+        assert_eq!(0x91, mem.read_byte(SUPERVISOR_DATA, 0x0100_0000).unwrap());
+    }
+}
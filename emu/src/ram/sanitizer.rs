@@ -0,0 +1,411 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use cpu::{self, ConfiguredCore, Callbacks, BeforeInstruction, Flow, Cycles, Exception};
+use interrupts::InterruptController;
+use super::{AddressSpace, AddressBus, BusError, SUPERVISOR_PROGRAM, USER_PROGRAM};
+
+// a stack pointer moving further than this within an instruction is taken
+// to be switched to another stack, not to push or pop
+const STACK_SWITCH: u32 = 0x1000;
+
+const PAGE_SHIFT: u32 = 16;
+const PAGE_WORDS: usize = (1 << PAGE_SHIFT) / 64;
+
+#[derive(Copy, Clone, Debug, Hash, Eq, PartialEq)]
+pub enum Finding {
+    // data read from, or code fetched from, bytes never written
+    UninitializedRead,
+    // a write to bytes fetched as program before
+    SelfModifyingWrite,
+    // a push or pop outside the configured stacks
+    StackOutOfBounds,
+}
+
+// A suspicious access, made by the instruction at pc
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Report {
+    pub finding: Finding,
+    pub pc: u32,
+    pub address_space: AddressSpace,
+    pub address: u32,
+    pub size: u32,
+}
+
+pub type ReportHandler = Box<dyn FnMut(Report) + Send>;
+
+// one bit per byte, in lazily allocated pages
+#[derive(Clone, Default)]
+struct Bits {
+    pages: HashMap<u32, Box<[u64; PAGE_WORDS]>>,
+}
+
+impl Bits {
+    fn get(&self, address: u32) -> bool {
+        let offset = (address & ((1 << PAGE_SHIFT) - 1)) as usize;
+        self.pages.get(&(address >> PAGE_SHIFT))
+            .map_or(false, |page| page[offset / 64] & (1 << (offset % 64)) != 0)
+    }
+    fn set(&mut self, address: u32, value: bool) {
+        let offset = (address & ((1 << PAGE_SHIFT) - 1)) as usize;
+        if !value && !self.pages.contains_key(&(address >> PAGE_SHIFT)) {
+            return;
+        }
+        let page = self.pages.entry(address >> PAGE_SHIFT).or_insert_with(|| Box::new([0; PAGE_WORDS]));
+        if value {
+            page[offset / 64] |= 1 << (offset % 64);
+        } else {
+            page[offset / 64] &= !(1 << (offset % 64));
+        }
+    }
+    fn any(&self, address: u32, size: u32) -> bool {
+        (0..size).any(|i| self.get(address.wrapping_add(i)))
+    }
+    fn all(&self, address: u32, size: u32) -> bool {
+        (0..size).all(|i| self.get(address.wrapping_add(i)))
+    }
+    fn set_range(&mut self, address: u32, size: u32, value: bool) {
+        for i in 0..size {
+            self.set(address.wrapping_add(i), value);
+        }
+    }
+}
+
+#[derive(Default)]
+struct State {
+    // the instruction making the accesses, and its stack pointer
+    pc: u32,
+    sp: u32,
+    started: bool,
+    // false once it has ended, when program reads are the opcode fetch
+    // of the next one, held until it begins
+    running: bool,
+    fetches: Vec<(AddressSpace, u32, u32)>,
+    // its data accesses, checked against the stacks once its SP is known
+    accesses: Vec<(AddressSpace, u32, u32)>,
+    reports: Vec<Report>,
+    on_report: Option<ReportHandler>,
+}
+
+impl State {
+    fn report(&mut self, finding: Finding, address_space: AddressSpace, address: u32, size: u32) {
+        let report = Report { finding, pc: self.pc, address_space, address, size };
+        match self.on_report {
+            Some(ref mut handler) => handler(report),
+            None => self.reports.push(report),
+        }
+    }
+}
+
+// Wraps a bus, keeping track of which bytes were written and which were
+// fetched as program, to report reads of uninitialized memory, code that
+// modifies itself and stack accesses outside the bounds given. Accesses
+// are still passed on, so the program runs as it would without it.
+//
+// Writes bypassing the sanitizer (through bus_mut, or by a device) leave
+// bytes uninitialized; use mark_initialized for those. Code is fetched by
+// the long, so a write right after the last instruction run can be taken
+// for self-modifying. An instruction's stack accesses are checked once
+// the next instruction begins, and its stack pointer is known.
+pub struct SanitizingMem<B: AddressBus> {
+    bus: B,
+    written: RefCell<Bits>,
+    fetched: RefCell<Bits>,
+    // the configured stacks, from low up to but not including high
+    stacks: Vec<(u32, u32)>,
+    state: RefCell<State>,
+}
+
+impl<B: AddressBus> SanitizingMem<B> {
+    pub fn new(bus: B) -> SanitizingMem<B> {
+        SanitizingMem {
+            bus, written: RefCell::new(Bits::default()), fetched: RefCell::new(Bits::default()),
+            stacks: Vec::new(), state: RefCell::new(State::default())
+        }
+    }
+    pub fn bus(&self) -> &B {
+        &self.bus
+    }
+    // writes through this reference leave the bytes uninitialized
+    pub fn bus_mut(&mut self) -> &mut B {
+        &mut self.bus
+    }
+    pub fn mark_initialized(&mut self, start: u32, len: u32) {
+        self.written.get_mut().set_range(start, len, true);
+    }
+    // Adds a stack, from low up to but not including high. Once there is
+    // one, pushes and pops outside all of them are reported.
+    pub fn add_stack(&mut self, low: u32, high: u32) {
+        self.stacks.push((low, high));
+    }
+    // Passes the reports to the handler as they are made, instead of
+    // collecting them
+    pub fn on_report(&mut self, handler: ReportHandler) {
+        self.state.get_mut().on_report = Some(handler);
+    }
+    pub fn reports(&self) -> Vec<Report> {
+        self.state.borrow().reports.clone()
+    }
+    pub fn take_reports(&mut self) -> Vec<Report> {
+        ::std::mem::take(&mut self.state.get_mut().reports)
+    }
+    // Tells which instruction makes the accesses that follow, given its
+    // address and the active stack pointer, once its opcode is fetched.
+    // Run the core with Sanitize callbacks to have this called.
+    pub fn begin_instruction(&mut self, pc: u32, sp: u32) {
+        self.check_stack(sp);
+        let state = self.state.get_mut();
+        state.pc = pc;
+        state.sp = sp;
+        state.started = true;
+        state.running = true;
+        let written = self.written.get_mut();
+        let fetched = self.fetched.get_mut();
+        for (address_space, address, size) in ::std::mem::take(&mut state.fetches) {
+            Self::fetch(written, fetched, state, address_space, address, size);
+        }
+    }
+    pub fn end_instruction(&mut self) {
+        self.state.get_mut().running = false;
+    }
+
+    fn is_program(address_space: AddressSpace) -> bool {
+        address_space == SUPERVISOR_PROGRAM || address_space == USER_PROGRAM
+    }
+
+    // a program read by the instruction at state.pc
+    fn fetch(written: &Bits, fetched: &mut Bits, state: &mut State, address_space: AddressSpace, address: u32, size: u32) {
+        // the prefetch reads ahead, so only code of which nothing was
+        // written is sure to be run uninitialized
+        if !written.any(address, size) {
+            state.report(Finding::UninitializedRead, address_space, address, size);
+        }
+        // and does not read behind the instruction
+        let skipped = state.pc.wrapping_sub(address);
+        if skipped < size {
+            fetched.set_range(state.pc, size - skipped, true);
+        } else {
+            fetched.set_range(address, size, true);
+        }
+    }
+    fn read(&self, address_space: AddressSpace, address: u32, size: u32) {
+        let mut state = self.state.borrow_mut();
+        if Self::is_program(address_space) {
+            if state.started && !state.running {
+                state.fetches.push((address_space, address, size));
+            } else {
+                Self::fetch(&self.written.borrow(), &mut self.fetched.borrow_mut(), &mut state, address_space, address, size);
+            }
+        } else {
+            if !self.written.borrow().all(address, size) {
+                state.report(Finding::UninitializedRead, address_space, address, size);
+            }
+            if state.started {
+                state.accesses.push((address_space, address, size));
+            }
+        }
+    }
+    fn write(&mut self, address_space: AddressSpace, address: u32, size: u32) {
+        let state = self.state.get_mut();
+        let fetched = self.fetched.get_mut();
+        if fetched.any(address, size) {
+            state.report(Finding::SelfModifyingWrite, address_space, address, size);
+            // once is enough, until it is run again
+            fetched.set_range(address, size, false);
+        }
+        self.written.get_mut().set_range(address, size, true);
+        if state.started && !Self::is_program(address_space) {
+            state.accesses.push((address_space, address, size));
+        }
+    }
+    // checks the accesses of the instruction just run against the stacks,
+    // given its stack pointer when done
+    fn check_stack(&mut self, sp: u32) {
+        let stacks = &self.stacks;
+        let state = self.state.get_mut();
+        let accesses = ::std::mem::take(&mut state.accesses);
+        if !state.started || stacks.is_empty() {
+            return;
+        }
+        let (low, high) = (state.sp.min(sp), state.sp.max(sp));
+        if high - low > STACK_SWITCH {
+            return;
+        }
+        for (address_space, address, size) in accesses {
+            let pushed_or_popped = address >= low && address < high;
+            let on_stack = stacks.iter().any(|&(l, h)| address >= l && address.wrapping_add(size) <= h);
+            if pushed_or_popped && !on_stack {
+                state.report(Finding::StackOutOfBounds, address_space, address, size);
+            }
+        }
+    }
+}
+
+impl<B: AddressBus> AddressBus for SanitizingMem<B> {
+    // copies the memory behind the sanitizer, but not what it tracks
+    fn copy_from(&mut self, other: &Self) {
+        self.bus.copy_from(&other.bus);
+    }
+
+    fn read_byte(&self, address_space: AddressSpace, address: u32) -> Result<u32, BusError> {
+        let value = self.bus.read_byte(address_space, address)?;
+        self.read(address_space, address, 1);
+        Ok(value)
+    }
+
+    fn read_word(&self, address_space: AddressSpace, address: u32) -> Result<u32, BusError> {
+        let value = self.bus.read_word(address_space, address)?;
+        self.read(address_space, address, 2);
+        Ok(value)
+    }
+
+    fn read_long(&self, address_space: AddressSpace, address: u32) -> Result<u32, BusError> {
+        let value = self.bus.read_long(address_space, address)?;
+        self.read(address_space, address, 4);
+        Ok(value)
+    }
+
+    fn write_byte(&mut self, address_space: AddressSpace, address: u32, value: u32) -> Result<(), BusError> {
+        self.bus.write_byte(address_space, address, value)?;
+        self.write(address_space, address, 1);
+        Ok(())
+    }
+
+    fn write_word(&mut self, address_space: AddressSpace, address: u32, value: u32) -> Result<(), BusError> {
+        self.bus.write_word(address_space, address, value)?;
+        self.write(address_space, address, 2);
+        Ok(())
+    }
+
+    fn write_long(&mut self, address_space: AddressSpace, address: u32, value: u32) -> Result<(), BusError> {
+        self.bus.write_long(address_space, address, value)?;
+        self.write(address_space, address, 4);
+        Ok(())
+    }
+
+    fn translated(&self, address_space: AddressSpace, logical: u32, physical: u32) {
+        self.bus.translated(address_space, logical, physical)
+    }
+
+    fn map_generation(&self) -> u32 {
+        self.bus.map_generation()
+    }
+}
+
+// Callbacks for a core on a SanitizingMem, telling it which instruction
+// makes the accesses, and passing everything on to the wrapped callbacks
+pub struct Sanitize<S>(pub S);
+
+impl<T, B, S> Callbacks<ConfiguredCore<T, SanitizingMem<B>>> for Sanitize<S>
+    where T: InterruptController, B: AddressBus, S: Callbacks<ConfiguredCore<T, SanitizingMem<B>>>
+{
+    fn exception_callback(&mut self, core: &mut ConfiguredCore<T, SanitizingMem<B>>, ex: Exception) -> cpu::Result<Cycles> {
+        self.0.exception_callback(core, ex)
+    }
+    fn before_instruction(&mut self, core: &mut ConfiguredCore<T, SanitizingMem<B>>, pc: u32, opcode: u16) -> BeforeInstruction {
+        let sp = core.dar[15];
+        core.mem.begin_instruction(pc, sp);
+        self.0.before_instruction(core, pc, opcode)
+    }
+    fn after_instruction(&mut self, core: &mut ConfiguredCore<T, SanitizingMem<B>>, cycles: Cycles) -> Flow {
+        core.mem.end_instruction();
+        self.0.after_instruction(core, cycles)
+    }
+    fn reset_instruction(&mut self, core: &mut ConfiguredCore<T, SanitizingMem<B>>) -> Flow {
+        self.0.reset_instruction(core)
+    }
+    fn stopped(&mut self, core: &mut ConfiguredCore<T, SanitizingMem<B>>) {
+        self.0.stopped(core)
+    }
+    fn halted(&mut self, core: &mut ConfiguredCore<T, SanitizingMem<B>>) {
+        self.0.halted(core)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SanitizingMem, Sanitize, Finding, Report};
+    use cpu::{ConfiguredCore, ProcessingState, EmulateAllExceptions};
+    use interrupts::AutoInterruptController;
+    use ram::{AddressBus, PagedMem, SUPERVISOR_DATA, SUPERVISOR_PROGRAM};
+    use std::sync::{Arc, Mutex};
+
+    type Core = ConfiguredCore<AutoInterruptController, SanitizingMem<PagedMem>>;
+
+    fn core(code: &[u16]) -> Core {
+        let mut mem = SanitizingMem::new(PagedMem::new(0xaaaa_aaaa));
+        // followed by NOPs, so that the prefetch reads written memory
+        for (i, word) in code.iter().chain(&[0x4e71; 4]).enumerate() {
+            mem.write_word(SUPERVISOR_PROGRAM, 0x100 + 2 * i as u32, u32::from(*word)).unwrap();
+        }
+        let mut cpu = ConfiguredCore::new_with(0x100, AutoInterruptController::new(), mem);
+        cpu.processing_state = ProcessingState::Normal;
+        cpu.dar[15] = 0x800;
+        cpu
+    }
+
+    fn run(cpu: &mut Core, cycles: i32) {
+        cpu.execute_with_state(cycles, &mut Sanitize(EmulateAllExceptions));
+    }
+
+    fn report(finding: Finding, pc: u32, address: u32, size: u32) -> Report {
+        Report { finding, pc, address_space: SUPERVISOR_DATA, address, size }
+    }
+
+    #[test]
+    fn reports_reads_of_uninitialized_memory() {
+        // 3038 2000 is MOVE.W $2000.W, D0 and 3038 2002 MOVE.W $2002.W, D0
+        let mut cpu = core(&[0x3038, 0x2000, 0x3038, 0x2002]);
+        cpu.write_data_word(0x2002, 0x1234).unwrap();
+        run(&mut cpu, 1);
+        run(&mut cpu, 1);
+        assert_eq!(vec![report(Finding::UninitializedRead, 0x100, 0x2000, 2)], cpu.mem.take_reports());
+        assert_eq!(0x1234, cpu.dar[0]);
+    }
+
+    #[test]
+    fn reports_writes_to_code_run_before() {
+        // 0100 nop
+        // 0102 nop
+        // 0104 move.w #$4e75, $0100.w
+        let mut cpu = core(&[0x4e71, 0x4e71, 0x31fc, 0x4e75, 0x0100]);
+        run(&mut cpu, 3 * 4 + 12);
+        cpu.write_data_word(0x10e, 0).unwrap();
+        assert_eq!(vec![report(Finding::SelfModifyingWrite, 0x104, 0x100, 2)], cpu.mem.take_reports());
+    }
+
+    #[test]
+    fn code_jumped_over_is_not_taken_for_run() {
+        // 0100 bra.s $0106
+        // 0102 nop
+        // 0104 nop
+        // 0106 nop
+        let mut cpu = core(&[0x6004, 0x4e71, 0x4e71, 0x4e71]);
+        run(&mut cpu, 10 + 4);
+        assert_eq!(0x108, cpu.pc);
+        // fetched with the opcode at $0106, but never run
+        cpu.write_data_word(0x104, 0).unwrap();
+        assert!(cpu.mem.take_reports().is_empty());
+    }
+
+    #[test]
+    fn reports_pushes_outside_the_stack() {
+        // 3f00 is MOVE.W D0, -(A7)
+        let mut cpu = core(&[0x3f00, 0x3f00]);
+        cpu.mem.add_stack(0x700, 0x800);
+        cpu.dar[15] = 0x702;
+        run(&mut cpu, 8 + 8 + 4);
+        assert_eq!(vec![report(Finding::StackOutOfBounds, 0x102, 0x6fe, 2)], cpu.mem.take_reports());
+    }
+
+    #[test]
+    fn passes_reports_to_a_handler() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        // 4a79 0000 3000 is TST.W $3000.L
+        let mut cpu = core(&[0x4a79, 0x0000, 0x3000]);
+        let handler_seen = seen.clone();
+        cpu.mem.on_report(Box::new(move |report| handler_seen.lock().unwrap().push(report)));
+        run(&mut cpu, 1);
+        assert_eq!(vec![report(Finding::UninitializedRead, 0x100, 0x3000, 2)], *seen.lock().unwrap());
+        assert!(cpu.mem.reports().is_empty());
+    }
+}
@@ -0,0 +1,97 @@
+use std::cell::{Ref, RefCell, RefMut};
+use std::rc::Rc;
+use super::{AddressSpace, AddressBus, BusError};
+
+// A handle to a bus shared by several cores. Clones refer to the same
+// bus, so each core can own one as its memory. Accesses borrow the bus
+// only for their duration, which is fine as long as the cores run on
+// one thread, taking turns.
+pub struct SharedBus<B: AddressBus> {
+    bus: Rc<RefCell<B>>,
+}
+
+impl<B: AddressBus> SharedBus<B> {
+    pub fn new(bus: B) -> SharedBus<B> {
+        SharedBus { bus: Rc::new(RefCell::new(bus)) }
+    }
+    pub fn borrow(&self) -> Ref<'_, B> {
+        self.bus.borrow()
+    }
+    pub fn borrow_mut(&self) -> RefMut<'_, B> {
+        self.bus.borrow_mut()
+    }
+    pub fn is_same_bus(&self, other: &SharedBus<B>) -> bool {
+        Rc::ptr_eq(&self.bus, &other.bus)
+    }
+}
+
+impl<B: AddressBus> Clone for SharedBus<B> {
+    fn clone(&self) -> SharedBus<B> {
+        SharedBus { bus: self.bus.clone() }
+    }
+}
+
+impl<B: AddressBus> AddressBus for SharedBus<B> {
+    // copying a bus onto itself is a no-op
+    fn copy_from(&mut self, other: &Self) {
+        if !self.is_same_bus(other) {
+            self.bus.borrow_mut().copy_from(&other.bus.borrow());
+        }
+    }
+
+    fn read_byte(&self, address_space: AddressSpace, address: u32) -> Result<u32, BusError> {
+        self.bus.borrow().read_byte(address_space, address)
+    }
+
+    fn read_word(&self, address_space: AddressSpace, address: u32) -> Result<u32, BusError> {
+        self.bus.borrow().read_word(address_space, address)
+    }
+
+    fn read_long(&self, address_space: AddressSpace, address: u32) -> Result<u32, BusError> {
+        self.bus.borrow().read_long(address_space, address)
+    }
+
+    fn write_byte(&mut self, address_space: AddressSpace, address: u32, value: u32) -> Result<(), BusError> {
+        self.bus.borrow_mut().write_byte(address_space, address, value)
+    }
+
+    fn write_word(&mut self, address_space: AddressSpace, address: u32, value: u32) -> Result<(), BusError> {
+        self.bus.borrow_mut().write_word(address_space, address, value)
+    }
+
+    fn write_long(&mut self, address_space: AddressSpace, address: u32, value: u32) -> Result<(), BusError> {
+        self.bus.borrow_mut().write_long(address_space, address, value)
+    }
+
+    fn translated(&self, address_space: AddressSpace, logical: u32, physical: u32) {
+        self.bus.borrow().translated(address_space, logical, physical)
+    }
+
+    fn map_generation(&self) -> u32 {
+        self.bus.borrow().map_generation()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SharedBus;
+    use ram::{AddressBus, PagedMem, SUPERVISOR_DATA};
+
+    #[test]
+    fn clones_share_the_bus() {
+        let mut bus = SharedBus::new(PagedMem::new(0));
+        let other = bus.clone();
+        bus.write_long(SUPERVISOR_DATA, 0x100, 0xdeadbeef).unwrap();
+        assert_eq!(0xdeadbeef, other.read_long(SUPERVISOR_DATA, 0x100).unwrap());
+        assert!(bus.is_same_bus(&other));
+    }
+
+    #[test]
+    fn copy_from_itself_is_a_no_op() {
+        let mut bus = SharedBus::new(PagedMem::new(0));
+        bus.write_word(SUPERVISOR_DATA, 0x100, 0x1234).unwrap();
+        let other = bus.clone();
+        bus.copy_from(&other);
+        assert_eq!(0x1234, bus.read_word(SUPERVISOR_DATA, 0x100).unwrap());
+    }
+}
@@ -0,0 +1,306 @@
+// Runs many independent machines on a pool of threads. Time advances in
+// slices of a fixed number of cycles, and every machine runs until it
+// has caught up with the end of each slice, running that much less in
+// the next one if its last instruction overshot. As the machines share
+// nothing, each one sees exactly the same calls whichever thread runs
+// it, so the results do not depend on the number of threads.
+//
+// The machines must be Send and own what they use ('static), which a
+// ConfiguredCore is when its interrupt controller and memory are. A
+// Machine of several cores is not, as its cores share their bus within
+// a thread.
+use std::mem;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread::{self, JoinHandle};
+use cpu::{ConfiguredCore, Cycles};
+use interrupts::InterruptController;
+use ram::AddressBus;
+
+// Something the runner can run for a number of cycles, returning the
+// cycles actually used, which may be a few more
+pub trait Runnable: Send + 'static {
+    fn run_for(&mut self, cycles: i32) -> i32;
+}
+
+impl<T: InterruptController + Send + 'static, A: AddressBus + Send + 'static> Runnable for ConfiguredCore<T, A> {
+    fn run_for(&mut self, cycles: i32) -> i32 {
+        let Cycles(used) = self.execute(cycles);
+        used
+    }
+}
+
+// The machines of one thread, and the cycles run by each, which may be
+// a little past the time
+struct Batch<M> {
+    machines: Vec<M>,
+    clocks: Vec<u64>,
+}
+
+impl<M> Default for Batch<M> {
+    fn default() -> Batch<M> {
+        Batch { machines: Vec::new(), clocks: Vec::new() }
+    }
+}
+
+impl<M: Runnable> Batch<M> {
+    // runs the machines through the slices following start
+    fn run(&mut self, start: u64, slices: u64, slice: u64) {
+        for (machine, clock) in self.machines.iter_mut().zip(self.clocks.iter_mut()) {
+            let mut end = start;
+            for _ in 0..slices {
+                end += slice;
+                if *clock < end {
+                    *clock += machine.run_for((end - *clock) as i32).max(0) as u64;
+                }
+            }
+        }
+    }
+}
+
+// A thread running the batches sent to it, and sending them back,
+// until the runner hangs up
+struct Worker<M> {
+    // a batch, and the start time and number of slices to run it for
+    jobs: Option<Sender<(Batch<M>, u64, u64)>>,
+    done: Receiver<Batch<M>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl<M: Runnable> Worker<M> {
+    fn new(slice: u64) -> Worker<M> {
+        let (jobs, next_job) = channel::<(Batch<M>, u64, u64)>();
+        let (finished, done) = channel();
+        let thread = thread::spawn(move || {
+            for (mut batch, start, slices) in next_job {
+                batch.run(start, slices, slice);
+                if finished.send(batch).is_err() {
+                    break;
+                }
+            }
+        });
+        Worker { jobs: Some(jobs), done, thread: Some(thread) }
+    }
+}
+
+impl<M> Drop for Worker<M> {
+    fn drop(&mut self) {
+        self.jobs = None;
+        if let Some(thread) = self.thread.take() {
+            // a panic in a machine has been reported by run already
+            let _ = thread.join();
+        }
+    }
+}
+
+// The machines are dealt out to the threads in turn, which start when
+// the runner is created and keep their machines between runs, so the
+// runner is cheap to run a slice at a time.
+pub struct Runner<M: Runnable> {
+    // machine i is machine i / threads of batch i % threads
+    batches: Vec<Batch<M>>,
+    workers: Vec<Worker<M>>,
+    len: usize,
+    slice: i32,
+    time: u64,
+}
+
+impl<M: Runnable> Runner<M> {
+    pub fn new(threads: usize, slice: i32) -> Runner<M> {
+        assert!(threads > 0, "need at least one thread");
+        assert!(slice > 0, "slice must be at least one cycle");
+        Runner {
+            batches: (0..threads).map(|_| Batch::default()).collect(),
+            workers: (0..threads).map(|_| Worker::new(slice as u64)).collect(),
+            len: 0, slice, time: 0,
+        }
+    }
+    // a machine added later starts at the current time
+    pub fn add(&mut self, machine: M) -> usize {
+        let index = self.len;
+        let batch = &mut self.batches[index % self.workers.len()];
+        batch.machines.push(machine);
+        batch.clocks.push(self.time);
+        self.len += 1;
+        index
+    }
+    fn batch(&self, index: usize) -> (&Batch<M>, usize) {
+        assert!(index < self.len, "no such machine");
+        let threads = self.workers.len();
+        (&self.batches[index % threads], index / threads)
+    }
+    pub fn machine(&self, index: usize) -> &M {
+        let (batch, i) = self.batch(index);
+        &batch.machines[i]
+    }
+    pub fn machine_mut(&mut self, index: usize) -> &mut M {
+        assert!(index < self.len, "no such machine");
+        let threads = self.workers.len();
+        &mut self.batches[index % threads].machines[index / threads]
+    }
+    pub fn len(&self) -> usize {
+        self.len
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+    pub fn into_machines(mut self) -> Vec<M> {
+        let threads = self.workers.len();
+        let mut batches: Vec<_> = mem::take(&mut self.batches).into_iter().map(|b| b.machines.into_iter()).collect();
+        (0..self.len).map(|i| batches[i % threads].next().unwrap()).collect()
+    }
+    // cycles since the runner was created, at the end of the last slice
+    pub fn time(&self) -> u64 {
+        self.time
+    }
+    pub fn clock(&self, index: usize) -> u64 {
+        let (batch, i) = self.batch(index);
+        batch.clocks[i]
+    }
+
+    pub fn run_slice(&mut self) {
+        let slice = self.slice as u64;
+        self.run(slice);
+    }
+    // Runs whole slices until at least the given number of cycles have
+    // passed. Each thread runs its machines through all of them, and
+    // the threads only meet again at the end, as no machine waits for
+    // another.
+    pub fn run(&mut self, cycles: u64) {
+        let slice = self.slice as u64;
+        let slices = (cycles + slice - 1) / slice;
+        let start = self.time;
+        self.time += slices * slice;
+        if slices == 0 {
+            return;
+        }
+        let sent: Vec<bool> = self.batches.iter_mut().zip(&self.workers).map(|(batch, worker)| {
+            if batch.machines.is_empty() {
+                return false;
+            }
+            worker.jobs.as_ref().unwrap().send((mem::take(batch), start, slices)).expect("runner thread panicked");
+            true
+        }).collect();
+        for ((batch, worker), sent) in self.batches.iter_mut().zip(&self.workers).zip(sent) {
+            if sent {
+                *batch = worker.done.recv().expect("runner thread panicked");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Runner, Runnable};
+    use cpu::ConfiguredCore;
+    use interrupts::AutoInterruptController;
+    use ram::{AddressBus, PagedMem, SUPERVISOR_PROGRAM};
+    use ram::loggingmem::{LoggingMem, SyncOpsLogger};
+    use std::collections::HashSet;
+    use std::sync::Arc;
+    use std::thread::{self, ThreadId};
+
+    type Vm = ConfiguredCore<AutoInterruptController, PagedMem>;
+
+    fn assert_send<T: Send>() {}
+
+    #[test]
+    fn cores_can_be_sent_between_threads() {
+        assert_send::<Vm>();
+        assert_send::<ConfiguredCore<AutoInterruptController, LoggingMem<Arc<SyncOpsLogger>>>>();
+    }
+
+    // each machine sums the numbers from its D0 down to 1 into D1,
+    // forever, in a loop of its own length
+    fn vm(n: u32) -> Vm {
+        let mut mem = PagedMem::new(0);
+        let code: [u16; 7] = [
+            0x7200,         // loop: MOVEQ #0, D1
+            0x2400,         // MOVE.L D0, D2
+            0xd282,         // sum: ADD.L D2, D1
+            0x5382,         // SUBQ.L #1, D2
+            0x66fa,         // BNE sum
+            0x5283,         // ADDQ.L #1, D3
+            0x60f2,         // BRA loop
+        ];
+        for (i, word) in code.iter().enumerate() {
+            mem.write_word(SUPERVISOR_PROGRAM, 0x100 + 2 * i as u32, u32::from(*word)).unwrap();
+        }
+        let mut core = ConfiguredCore::new_with(0x100, AutoInterruptController::new(), mem);
+        core.processing_state = ::cpu::ProcessingState::Normal;
+        core.dar[0] = n;
+        core.dar[15] = 0x800;
+        core
+    }
+
+    fn run_vms(threads: usize) -> Runner<Vm> {
+        let mut runner = Runner::new(threads, 1000);
+        for n in 1..20 {
+            runner.add(vm(n));
+        }
+        runner.run(50_000);
+        runner
+    }
+
+    #[test]
+    fn results_do_not_depend_on_the_threads() {
+        let single = run_vms(1);
+        for threads in &[2, 3, 8, 32] {
+            let pooled = run_vms(*threads);
+            for i in 0..single.len() {
+                assert_eq!(single.machine(i).dar, pooled.machine(i).dar);
+                assert_eq!(single.machine(i).pc, pooled.machine(i).pc);
+                assert_eq!(single.clock(i), pooled.clock(i));
+            }
+        }
+    }
+
+    #[test]
+    fn machines_run_for_the_given_cycles() {
+        let runner = run_vms(4);
+        assert_eq!(50_000, runner.time());
+        for i in 0..runner.len() {
+            let machine = runner.machine(i);
+            // D1 is the sum of 1..n once a loop has completed
+            let n = machine.dar[0];
+            assert!(machine.dar[3] > 0);
+            assert!(runner.clock(i) >= 50_000 && runner.clock(i) < 50_000 + 20);
+            assert!(machine.dar[1] <= n * (n + 1) / 2);
+        }
+    }
+
+    // records the threads it is run on
+    struct ThreadRecorder(HashSet<ThreadId>);
+
+    impl Runnable for ThreadRecorder {
+        fn run_for(&mut self, cycles: i32) -> i32 {
+            self.0.insert(thread::current().id());
+            cycles
+        }
+    }
+
+    #[test]
+    fn threads_are_kept_between_runs() {
+        let mut runner = Runner::new(2, 100);
+        runner.add(ThreadRecorder(HashSet::new()));
+        runner.add(ThreadRecorder(HashSet::new()));
+        for _ in 0..10 {
+            runner.run_slice();
+        }
+        let machines = runner.into_machines();
+        assert_eq!(1, machines[0].0.len());
+        assert_eq!(1, machines[1].0.len());
+        assert!(machines[0].0 != machines[1].0);
+    }
+
+    #[test]
+    fn machine_added_later_starts_at_current_time() {
+        let mut runner = Runner::new(2, 100);
+        runner.add(vm(3));
+        runner.run_slice();
+        let late = runner.add(vm(3));
+        runner.run_slice();
+        assert_eq!(200, runner.time());
+        assert!(runner.clock(late) >= 200 && runner.clock(late) < 200 + 20);
+        assert!(runner.machine(late).dar[3] <= runner.machine(0).dar[3]);
+    }
+}